@@ -8,7 +8,7 @@ use utils::term::Term;
 
 use super::peer;
 use super::Blockchain;
-use cardano::{self, block::{RawBlock}};
+use cardano::{self, block::{RawBlock, EpochId}};
 
 /// function to create and initialize a given new blockchain
 ///
@@ -21,24 +21,28 @@ use cardano::{self, block::{RawBlock}};
 /// structure `Config`).
 ///
 pub fn new( mut term: Term
-          , root_dir: PathBuf
+          , blockchains_dir: PathBuf
           , name: String
           , config: Config
           )
 {
-    let blockchain = Blockchain::new(root_dir, name.clone(), config);
+    let blockchain = Blockchain::new(blockchains_dir, name.clone(), config);
     blockchain.save();
 
     term.success(&format!("local blockchain `{}' created.\n", &name)).unwrap();
 }
 
 pub fn list( mut term: Term
-           , root_dir: PathBuf
+           , blockchains_dir: PathBuf
            , detailed: bool
            )
 {
-    let blockchains_dir = super::config::blockchains_directory(&root_dir);
-    for entry in ::std::fs::read_dir(blockchains_dir).unwrap() {
+    let entries = match ::std::fs::read_dir(&blockchains_dir) {
+        // no blockchain has been created yet
+        Err(ref err) if err.kind() == ::std::io::ErrorKind::NotFound => return,
+        entries => entries.unwrap(),
+    };
+    for entry in entries {
         let entry = entry.unwrap();
         if ! entry.file_type().unwrap().is_dir() {
             term.warn(&format!("unexpected file in blockchains directory: {:?}", entry.path())).unwrap();
@@ -48,7 +52,7 @@ pub fn list( mut term: Term
             panic!("invalid utf8... {:?}", err)
         });
 
-        let blockchain = Blockchain::load(root_dir.clone(), name);
+        let blockchain = Blockchain::load(blockchains_dir.clone(), name);
 
         term.info(&blockchain.name).unwrap();
         if detailed {
@@ -69,11 +73,11 @@ pub fn list( mut term: Term
 }
 
 pub fn destroy( mut term: Term
-              , root_dir: PathBuf
+              , blockchains_dir: PathBuf
               , name: String
               )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = Blockchain::load(blockchains_dir, name);
 
     writeln!(term, "You are about to destroy the local blockchain {}.
 This means that all the blocks downloaded will be deleted and that the attached
@@ -100,13 +104,13 @@ wallets won't be able to interact with this blockchain.",
 /// anything more than the genesis block.
 ///
 pub fn remote_add( mut term: Term
-                 , root_dir: PathBuf
+                 , blockchains_dir: PathBuf
                  , name: String
                  , remote_alias: String
                  , remote_endpoint: String
                  )
 {
-    let mut blockchain = Blockchain::load(root_dir, name);
+    let mut blockchain = Blockchain::load(blockchains_dir, name);
     blockchain.add_peer(remote_alias.clone(), remote_endpoint);
     blockchain.save();
 
@@ -119,25 +123,31 @@ pub fn remote_add( mut term: Term
 /// such as the tag pointing to the remote's tip.
 ///
 pub fn remote_rm( mut term: Term
-                , root_dir: PathBuf
+                , blockchains_dir: PathBuf
                 , name: String
                 , remote_alias: String
                 )
 {
-    let mut blockchain = Blockchain::load(root_dir, name);
+    let mut blockchain = Blockchain::load(blockchains_dir, name);
     blockchain.remove_peer(remote_alias.clone());
     blockchain.save();
 
     term.success(&format!("remote `{}' node removed from blockchain `{}'\n", remote_alias, blockchain.name)).unwrap();
 }
 
+/// fetch the blocks of the given peers (all of them if none is given),
+/// reporting the progress as JSON objects if `json` is set
 pub fn remote_fetch( mut term: Term
-                   , root_dir: PathBuf
+                   , blockchains_dir: PathBuf
                    , name: String
                    , peers: Vec<String>
+                   , json: bool
                    )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    // only the progress entries on the standard output
+    if json { term.messages_to_stderr() }
+
+    let blockchain = Blockchain::load(blockchains_dir, name);
 
     for np in blockchain.peers() {
         if peers.is_empty() || peers.contains(&np.name().to_owned()) {
@@ -145,7 +155,7 @@ pub fn remote_fetch( mut term: Term
 
             let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
 
-            peer.connect(&mut term).unwrap().sync(&mut term);
+            peer.connect(&mut term).unwrap().sync(&mut term, json);
         }
     }
 }
@@ -158,12 +168,12 @@ pub enum RemoteDetail {
 }
 
 pub fn remote_ls( mut term: Term
-                , root_dir: PathBuf
+                , blockchains_dir: PathBuf
                 , name: String
                 , detailed: RemoteDetail
                 )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = Blockchain::load(blockchains_dir, name);
 
     for np in blockchain.peers() {
         let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
@@ -219,12 +229,12 @@ fn format_duration(duration: ::std::time::Duration) -> String {
 }
 
 pub fn log( mut term: Term
-          , root_dir: PathBuf
+          , blockchains_dir: PathBuf
           , name: String
           , from: Option<String>
           )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = Blockchain::load(blockchains_dir, name);
 
     let from = if let Some(hash_hex) = from {
         let hash = super::config::parse_block_hash(&mut term, &hash_hex);
@@ -247,12 +257,12 @@ pub fn log( mut term: Term
 }
 
 pub fn forward( mut term: Term
-              , root_dir: PathBuf
+              , blockchains_dir: PathBuf
               , name: String
               , to: Option<String>
               )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = Blockchain::load(blockchains_dir, name);
 
     let hash = if let Some(hash_hex) = to {
         let hash = super::config::parse_block_hash(&mut term, &hash_hex);
@@ -285,11 +295,15 @@ pub fn forward( mut term: Term
 }
 
 pub fn pull( mut term: Term
-           , root_dir: PathBuf
+           , blockchains_dir: PathBuf
            , name: String
+           , json: bool
            )
 {
-    let blockchain = Blockchain::load(root_dir.clone(), name.clone());
+    // only the progress entries on the standard output
+    if json { term.messages_to_stderr() }
+
+    let blockchain = Blockchain::load(blockchains_dir.clone(), name.clone());
 
     for np in blockchain.peers() {
         if ! np.is_native() { continue; }
@@ -297,10 +311,10 @@ pub fn pull( mut term: Term
 
         let peer = peer::Peer::prepare(&blockchain, np.name().to_owned());
 
-        peer.connect(&mut term).unwrap().sync(&mut term);
+        peer.connect(&mut term).unwrap().sync(&mut term, json);
     }
 
-    forward(term, root_dir, name, None)
+    forward(term, blockchains_dir, name, None)
 }
 
 fn get_block(mut term: &mut Term, blockchain: &Blockchain, hash_str: &str) -> RawBlock
@@ -327,23 +341,94 @@ fn get_block(mut term: &mut Term, blockchain: &Blockchain, hash_str: &str) -> Ra
 }
 
 pub fn cat( mut term: Term
-          , root_dir: PathBuf
+          , blockchains_dir: PathBuf
           , name: String
           , hash_str: &str
           , no_parse: bool
           , debug: bool
           )
 {
-    let blockchain = Blockchain::load(root_dir.clone(), name.clone());
+    let blockchain = Blockchain::load(blockchains_dir.clone(), name.clone());
     let rblk = get_block(&mut term, &blockchain, hash_str);
 
+    print_block(term, rblk, hash_str, no_parse, debug)
+}
+
+// the hash of the block at the given height in the local blockchain, or
+// `None` if the local tip is below it
+fn local_block_by_height(blockchain: &Blockchain, height: u64) -> Option<cardano::block::HeaderHash> {
+    let (tip, is_genesis) = blockchain.load_tip();
+    if is_genesis { return None }
+
+    // going back from the tip, the epoch boundary blocks come before the
+    // main block of the same difficulty: keep the last block found
+    let mut found = None;
+    for block in storage::block::iter::ReverseIter::from(&blockchain.storage, tip.hash).unwrap() {
+        let header = block.get_header();
+        let difficulty = u64::from(header.difficulty());
+        if difficulty < height { break }
+        if difficulty == height { found = Some(header.compute_hash()) }
+    }
+    found
+}
+
+/// print the block at the given height
+///
+/// The block is read from the local blockchain if it has been synced up
+/// to that height, otherwise it is downloaded from the first native peer
+/// serving it, walking through the headers of the peer from the local tip.
+/// This fails if the height is above the tip of every peer.
+pub fn cat_height( mut term: Term
+                 , blockchains_dir: PathBuf
+                 , name: String
+                 , height: u64
+                 , no_parse: bool
+                 , debug: bool
+                 )
+{
+    let blockchain = Blockchain::load(blockchains_dir.clone(), name.clone());
+    let what = format!("at height {}", height);
+
+    if let Some(hash) = local_block_by_height(&blockchain, height) {
+        let rblk = get_block(&mut term, &blockchain, &hash.to_string());
+        return print_block(term, rblk, &what, no_parse, debug);
+    }
+
+    let (tip, is_genesis) = blockchain.load_tip();
+    let checkpoint = if is_genesis { blockchain.config.genesis_prev.clone() } else { tip.hash };
+    for np in blockchain.peers() {
+        if ! np.is_native() { continue; }
+        term.info(&format!("block {} not in the local blockchain, fetching it from peer: {}\n", what, np.name())).unwrap();
+
+        let mut peer = match peer::Peer::prepare(&blockchain, np.name().to_owned()).connect(&mut term) {
+            Ok(peer) => peer,
+            Err(()) => continue,
+        };
+        match peer.get_block_by_height(&checkpoint, height) {
+            Some(Ok(rblk)) => return print_block(term, rblk, &what, no_parse, debug),
+            Some(Err(err)) => term.warn(&format!("Unable to fetch the block {} from peer {}: {:?}\n", what, np.name(), err)).unwrap(),
+            None => {},
+        }
+    }
+
+    term.error(&format!("no block {} found locally nor on the peers\n", what)).unwrap();
+    ::std::process::exit(1);
+}
+
+fn print_block(mut term: Term, rblk: RawBlock, what: &str, no_parse: bool, debug: bool) {
     if no_parse {
         ::std::io::stdout().write(rblk.as_ref()).unwrap();
         ::std::io::stdout().flush().unwrap();
     } else {
         use utils::pretty::Pretty;
 
-        let blk = rblk.decode().unwrap();
+        let blk = match rblk.decode() {
+            Ok(blk) => blk,
+            Err(err) => {
+                term.error(&format!("Block {} is invalid: {}\n", what, err)).unwrap();
+                ::std::process::exit(1);
+            }
+        };
         if debug {
             writeln!(term, "{:#?}", blk).unwrap();
         } else {
@@ -353,11 +438,11 @@ pub fn cat( mut term: Term
 }
 
 pub fn status( mut term: Term
-         , root_dir: PathBuf
+         , blockchains_dir: PathBuf
          , name: String
          )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = Blockchain::load(blockchains_dir, name);
 
     term.warn("Blockchain:\n").unwrap();
     {
@@ -378,6 +463,16 @@ pub fn status( mut term: Term
         term.simply("   * local tip date:  ").unwrap();
         term.success(&format!("{}", tip.date)).unwrap();
         term.simply("\n").unwrap();
+        match blockchain.storage.disk_usage() {
+            Ok(size) => {
+                term.simply("   * block store:     ").unwrap();
+                term.info(&format!("{} bytes", size)).unwrap();
+                term.simply("\n").unwrap();
+            },
+            Err(err) => {
+                term.warn(&format!("   * block store:     cannot compute its size: {}\n", err)).unwrap();
+            }
+        }
     }
 
     term.warn("Peers:\n").unwrap();
@@ -410,12 +505,12 @@ pub fn status( mut term: Term
 }
 
 pub fn verify_block( mut term: Term
-                   , root_dir: PathBuf
+                   , blockchains_dir: PathBuf
                    , name: String
                    , hash_str: &str
                    )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = Blockchain::load(blockchains_dir, name);
     let hash = super::config::parse_block_hash(&mut term, &hash_str);
     let rblk = get_block(&mut term, &blockchain, hash_str);
     match rblk.decode() {
@@ -443,11 +538,11 @@ pub fn verify_block( mut term: Term
 }
 
 pub fn verify_chain( mut term: Term
-                   , root_dir: PathBuf
+                   , blockchains_dir: PathBuf
                    , name: String
                    )
 {
-    let blockchain = Blockchain::load(root_dir, name);
+    let blockchain = Blockchain::load(blockchains_dir, name);
 
     let mut bad_blocks = 0;
     let mut nr_blocks = 0;
@@ -456,7 +551,14 @@ pub fn verify_chain( mut term: Term
         nr_blocks += 1;
         // FIXME: inefficient - the iterator has already decoded the block.
         let rblk = rblk.unwrap();
-        let blk = rblk.decode().unwrap();
+        let blk = match rblk.decode() {
+            Ok(blk) => blk,
+            Err(err) => {
+                bad_blocks += 1;
+                term.error(&format!("Block number {} cannot be decoded: {}\n", nr_blocks, err)).unwrap();
+                continue;
+            }
+        };
         let hash = blk.get_header().compute_hash();
         writeln!(term, "block {} {}", hash, blk.get_header().get_blockdate()).unwrap();
         match cardano::block::verify_block(blockchain.config.protocol_magic, &hash, &blk) {
@@ -479,3 +581,42 @@ pub fn verify_chain( mut term: Term
     term.success(&format!("All {} blocks are valid", nr_blocks)).unwrap();
     term.simply("\n").unwrap();
 }
+
+/// rebuild the indexes of the blockchain's storage from the pack files
+pub fn reindex( mut term: Term
+              , blockchains_dir: PathBuf
+              , name: String
+              )
+{
+    let mut blockchain = Blockchain::load(blockchains_dir, name);
+
+    let reindexed = blockchain.storage.reindex().unwrap();
+    for packhash in reindexed.iter() {
+        writeln!(term, "pack {} reindexed", packhash).unwrap();
+    }
+
+    term.success(&format!("{} packs reindexed\n", reindexed.len())).unwrap();
+}
+
+pub fn prune( mut term: Term
+            , blockchains_dir: PathBuf
+            , name: String
+            , epoch: EpochId
+            )
+{
+    let mut blockchain = Blockchain::load(blockchains_dir, name);
+
+    let pruned = match blockchain.storage.prune_epochs_before(epoch) {
+        Err(storage::Error::EpochNotPacked(unpacked)) => {
+            term.error(&format!("epoch {} is not packed yet, its blocks may still be rolled back\n", unpacked)).unwrap();
+            ::std::process::exit(1)
+        },
+        Err(err) => panic!("{:?}", err),
+        Ok(pruned) => pruned,
+    };
+
+    term.success(&format!("{} epochs pruned, the blocks are stored from epoch {}\n", pruned, blockchain.storage.first_epoch())).unwrap();
+    if pruned > 0 {
+        term.warn("the wallets not synced past these epochs can not be synced with this blockchain anymore\n").unwrap();
+    }
+}
@@ -1,27 +1,55 @@
 //! local blockchain configuration related functions and tools
 //!
 
-use std::path::PathBuf;
+use std::{fs, path::PathBuf};
 use utils::term::Term;
 
 /// this is the name of the directory where the blockchains'
 /// blocks, epochs and tags will lie.
 pub const BLOCKCHAINS_DIRECTORY : &'static str = "blockchains";
 
+/// environment variable giving the directory of the blockchains, as the
+/// `--storage-dir` option does.
+pub const BLOCKCHAINS_DIRECTORY_ENVIRONMENT : &'static str = "CARDANO_CLI_STORAGE_DIR";
+
+/// the directory of the blockchains: the given storage directory if any
+/// (see `BLOCKCHAINS_DIRECTORY_ENVIRONMENT`), `BLOCKCHAINS_DIRECTORY` in
+/// the given _cardano-cli_ directory otherwise.
 pub fn blockchains_directory( root_dir: &PathBuf
+                            , storage_dir: Option<PathBuf>
                             ) -> PathBuf
 {
-    root_dir.join(BLOCKCHAINS_DIRECTORY)
+    match storage_dir {
+        Some(dir) => dir,
+        None => root_dir.join(BLOCKCHAINS_DIRECTORY)
+    }
 }
 
 /// handy function to define where to find the blockchains related
-/// functions in a given _cardano-cli_ directory.
+/// functions in a given blockchains' directory (see
+/// `blockchains_directory`).
 ///
-pub fn directory( root_dir: PathBuf
+pub fn directory( blockchains_dir: PathBuf
                 , name: &str
                 ) -> PathBuf
 {
-    root_dir.join(BLOCKCHAINS_DIRECTORY).join(name)
+    blockchains_dir.join(name)
+}
+
+/// check the blockchains' directory can be written to, creating it if
+/// needed, rather than failing in the middle of a command.
+///
+/// Only the commands writing to the storage check it: the read only ones
+/// do not create the directory.
+pub fn check_writable(term: &mut Term, blockchains_dir: &PathBuf) {
+    let probe = blockchains_dir.join(".cardano-cli-write-test");
+    let writable = fs::create_dir_all(blockchains_dir)
+        .and_then(|_| fs::File::create(&probe))
+        .and_then(|_| fs::remove_file(&probe));
+    if let Err(err) = writable {
+        term.error(&format!("storage directory `{}' is not writable: {}\n", blockchains_dir.display(), err)).unwrap();
+        ::std::process::exit(1)
+    }
 }
 
 /// function to check if the given string is a valid block hash
@@ -1,7 +1,8 @@
 #[derive(Debug)]
 pub enum Error {
     IoError(::std::io::Error),
-    CborError(::cbor_event::Error)
+    CborError(::cbor_event::Error),
+    BlockDecodeError(::cardano::block::BlockDecodeError),
 }
 impl From<::std::io::Error> for Error {
     fn from(e: ::std::io::Error) -> Self { Error::IoError(e) }
@@ -9,5 +10,8 @@ impl From<::std::io::Error> for Error {
 impl From<::cbor_event::Error> for Error {
     fn from(e: ::cbor_event::Error) -> Self { Error::CborError(e) }
 }
+impl From<::cardano::block::BlockDecodeError> for Error {
+    fn from(e: ::cardano::block::BlockDecodeError) -> Self { Error::BlockDecodeError(e) }
+}
 
 pub type Result<T> = ::std::result::Result<T, Error>;
@@ -130,7 +130,10 @@ impl<'a> Iterator for Iter<'a> {
                 match block {
                     Err(err) => return Some(Err(err)),
                     Ok(raw_block) => {
-                        let hh = raw_block.decode().unwrap().get_header().compute_hash();
+                        let hh = match raw_block.decode() {
+                            Ok(block) => block.get_header().compute_hash(),
+                            Err(err) => return Some(Err(err.into())),
+                        };
                         let end = &hh == &self.starting_from;
                         next = Some(Ok(raw_block));
                         self.last_known_block_hash = Some(hh);
@@ -158,7 +161,10 @@ impl<'a> Iterator for Iter<'a> {
                 },
                 Some(Err(err)) => Some(Err(err)),
                 Some(Ok(raw_block)) => {
-                    let hh = raw_block.decode().unwrap().get_header().compute_hash();
+                    let hh = match raw_block.decode() {
+                        Ok(block) => block.get_header().compute_hash(),
+                        Err(err) => return Some(Err(err.into())),
+                    };
                     self.last_known_block_hash = Some(hh);
                     Some(Ok(raw_block))
                 }
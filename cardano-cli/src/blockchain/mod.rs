@@ -12,6 +12,19 @@ use cardano::block;
 
 pub const LOCAL_BLOCKCHAIN_TIP_TAG : &'static str = "tip";
 
+/// the networks there is a configuration template for
+pub const NETWORKS : [&'static str; 3] = ["mainnet", "staging", "testnet"];
+
+/// the configuration template of the given network, if known
+pub fn network_config(network: &str) -> Option<Config> {
+    match network {
+        "mainnet" => Some(Config::mainnet()),
+        "staging" => Some(Config::staging()),
+        "testnet" => Some(Config::testnet()),
+        _ => None,
+    }
+}
+
 /// handy structure to use to manage and orginise a blockchain
 ///
 pub struct Blockchain {
@@ -22,9 +35,10 @@ pub struct Blockchain {
     pub config: Config,
 }
 impl Blockchain {
-    /// create the new blockhain with the given setting
-    pub fn new(root_dir: PathBuf, name: String, config: Config) -> Self {
-        let dir = config::directory(root_dir, &name);
+    /// create the new blockhain with the given setting, in the given
+    /// blockchains' directory (see `config::blockchains_directory`)
+    pub fn new(blockchains_dir: PathBuf, name: String, config: Config) -> Self {
+        let dir = config::directory(blockchains_dir, &name);
         let storage_config = StorageConfig::new(&dir);
 
         let storage = Storage::init(&storage_config).unwrap();
@@ -55,9 +69,9 @@ impl Blockchain {
         ::std::fs::remove_dir_all(self.dir)
     }
 
-    /// load the blockchain
-    pub fn load(root_dir: PathBuf, name: String) -> Self {
-        let dir = config::directory(root_dir, &name);
+    /// load the blockchain from the given blockchains' directory
+    pub fn load(blockchains_dir: PathBuf, name: String) -> Self {
+        let dir = config::directory(blockchains_dir, &name);
         let storage_config = StorageConfig::new(&dir);
         let storage = Storage::init(&storage_config).unwrap();
 
@@ -137,6 +151,29 @@ impl Blockchain {
         tag::write_hash(&self.storage, &LOCAL_BLOCKCHAIN_TIP_TAG, hh);
     }
 
+    /// find the epoch boundary block of the given epoch in the local storage
+    ///
+    /// returns `None` if the epoch has not been downloaded.
+    pub fn epoch_boundary(&self, epoch: block::EpochId) -> Option<block::HeaderHash> {
+        // the epoch boundary block is the first reference of a packed epoch
+        if let Ok(mut refpack) = ::storage::epoch::epoch_open_packref(&self.storage.config, epoch) {
+            if let Ok(Some(hash)) = refpack.getref_at_index(0) {
+                return Some(block::HeaderHash::from(hash));
+            }
+        }
+
+        // otherwise the epoch's blocks are still loose, walk back from the tip
+        let mut hash = self.load_tip().0.hash;
+        loop {
+            let header = ::storage::block_read(&self.storage, hash.bytes())?.decode().ok()?.get_header();
+            match header.get_blockdate() {
+                block::BlockDate::Genesis(e) if e == epoch => return Some(hash),
+                date => if date.get_epochid() < epoch { return None }
+            }
+            hash = header.get_previous_header();
+        }
+    }
+
     pub fn iter<'a>(&'a self, from: block::HeaderHash, to: block::HeaderHash) -> iter::Result<iter::Iter<'a>> {
         iter::Iter::new(&self.storage, from, to)
     }
@@ -1,12 +1,98 @@
 
 use exe_common;
 use exe_common::network::{api::Api, api::BlockRef};
-use cardano::{block::{BlockDate, EpochId, HeaderHash}, tx::{TxAux}};
+use cardano::{block::{BlockDate, EpochId, HeaderHash, RawBlock, SLOTS_PER_EPOCH}, tx::{TxAux}};
 use utils::term::Term;
 use storage::{self, tag};
 use std::ops::Deref;
-use std::time::SystemTime;
-use std::mem;
+use std::time::{Duration, Instant, SystemTime};
+use std::{io::Write, mem};
+use indicatif::ProgressBar;
+
+/// the progress of the download is reported at most this often
+const PROGRESS_INTERVAL : Duration = Duration::from_secs(1);
+
+/// the progress of a download, as output in JSON by `SyncProgress`
+#[derive(Serialize)]
+struct SyncProgressEntry {
+    epoch: EpochId,
+    /// `None` for the genesis block of the epoch
+    slot: Option<u16>,
+    blocks: u64,
+    blocks_per_sec: f64,
+    slots_to_tip: u64,
+    /// `None` until enough has been downloaded to tell
+    eta_secs: Option<u64>,
+}
+
+/// report the progress of the download of the blocks up to the tip of
+/// the peer, on a progress bar or as JSON objects (one per line)
+///
+/// The ETA assumes the slots still to download are as full as the ones
+/// downloaded so far, the peer only gives the date of its tip.
+struct SyncProgress {
+    bar: Option<ProgressBar>,
+    from: u64,
+    to: u64,
+    start: Instant,
+    reported: Option<Instant>,
+    blocks: u64,
+}
+impl SyncProgress {
+    fn new(term: &Term, from: &BlockDate, to: &BlockDate, json: bool) -> Self {
+        let from = from.slot_number(SLOTS_PER_EPOCH);
+        let to = to.slot_number(SLOTS_PER_EPOCH);
+        let bar = if json { None } else { Some(term.progress_bar(to.saturating_sub(from))) };
+        SyncProgress { bar, from, to, start: Instant::now(), reported: None, blocks: 0 }
+    }
+
+    /// account for a downloaded block, reporting the progress if it has
+    /// not been for `PROGRESS_INTERVAL`
+    fn block(&mut self, term: &mut Term, date: &BlockDate) {
+        self.blocks += 1;
+        let now = Instant::now();
+        match self.reported {
+            Some(reported) if now.duration_since(reported) < PROGRESS_INTERVAL => {},
+            _ => {
+                self.reported = Some(now);
+                self.report(term, date);
+            }
+        }
+    }
+
+    fn finish(self, term: &mut Term, date: Option<&BlockDate>) {
+        if let Some(date) = date { self.report(term, date); }
+        if let Some(bar) = self.bar { bar.finish(); }
+    }
+
+    fn report(&self, term: &mut Term, date: &BlockDate) {
+        let done = date.slot_number(SLOTS_PER_EPOCH).saturating_sub(self.from);
+        let remaining = self.to.saturating_sub(self.from + done);
+        let elapsed = self.start.elapsed();
+        let elapsed = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+        let blocks_per_sec = if elapsed > 0.0 { self.blocks as f64 / elapsed } else { 0.0 };
+        let eta_secs = if done > 0 { Some((elapsed * remaining as f64 / done as f64) as u64) } else { None };
+        let slot = match date {
+            BlockDate::Genesis(_) => None,
+            BlockDate::Normal(slot) => Some(slot.slotid),
+        };
+
+        match &self.bar {
+            Some(bar) => {
+                bar.set_position(done);
+                let slot = slot.map(|slot| format!(" slot {}", slot)).unwrap_or_default();
+                bar.set_message(&format!("epoch {}{}, {} blocks ({:.1} blocks/s) ", date.get_epochid(), slot, self.blocks, blocks_per_sec));
+            },
+            None => {
+                let entry = SyncProgressEntry {
+                    epoch: date.get_epochid(), slot, blocks: self.blocks,
+                    blocks_per_sec, slots_to_tip: remaining, eta_secs,
+                };
+                writeln!(term, "{}", ::serde_json::to_string(&entry).unwrap()).unwrap();
+            }
+        }
+    }
+}
 
 pub struct ConnectedPeer<'a> {
     peer: Peer<'a>,
@@ -27,11 +113,33 @@ impl<'a> ConnectedPeer<'a> {
         }
     }
 
+    /// download the block at the given height from the peer, walking
+    /// through its headers from the checkpoint. Only the native peers
+    /// support it, `None` is returned for the others.
+    pub fn get_block_by_height(&mut self, checkpoint: &HeaderHash, height: u64) -> Option<exe_common::network::Result<RawBlock>> {
+        match self.connection {
+            exe_common::network::Peer::Native(ref mut pool) => Some(pool.get_block_by_height(checkpoint, height)),
+            exe_common::network::Peer::Http(_) => None,
+        }
+    }
+
+    /// find the hash of the epoch boundary block of the given epoch on the
+    /// peer, walking through its headers from the checkpoint. Only the
+    /// native peers support it, `None` is returned for the others.
+    pub fn get_epoch_boundary(&mut self, checkpoint: &HeaderHash, epoch: EpochId) -> Option<exe_common::network::Result<HeaderHash>> {
+        match self.connection {
+            exe_common::network::Peer::Native(ref mut pool) => Some(pool.get_epoch_boundary(checkpoint, epoch)),
+            exe_common::network::Peer::Http(_) => None,
+        }
+    }
+
     pub fn send_txaux(mut self, txaux: TxAux) {
         let sent = self.connection.send_transaction(txaux).unwrap();
     }
 
-    pub fn sync(mut self, term: &mut Term) -> Peer<'a> {
+    /// download the blocks from our tip up to the peer's, reporting the
+    /// progress on a progress bar or, with `json`, as JSON objects
+    pub fn sync(mut self, term: &mut Term, json: bool) -> Peer<'a> {
         // recover and print the TIP of the network
         let tip = self.query_tip();
 
@@ -147,13 +255,21 @@ impl<'a> ConnectedPeer<'a> {
         }
 
 
-        // initialisation of the progress bar:
-        let count = tip.date - best_tip.0.date;
-        let pbr = term.progress_bar(count as u64);
+        let mut progress = SyncProgress::new(term, &best_tip.0.date, &tip.date, json);
+        let mut last_date = None;
+        let mut next_block = exe_common::sync::NextBlock::new(&best_tip.0, best_tip.1);
+        let mut chain_error = None;
         connection.get_blocks(&best_tip.0, best_tip.1, &tip, &mut |block_hash, block, block_raw| {
+            // ignore the blocks following an invalid one
+            if chain_error.is_some() { return }
+            if let Err(err) = next_block.accept(block_hash, &block.get_header().get_previous_header()) {
+                chain_error = Some(err);
+                return
+            }
+
             let date = block.get_header().get_blockdate();
-            pbr.inc(1);
-            pbr.set_message(&format!("downloading epoch {} -> ", date.get_epochid()));
+            progress.block(term, &date);
+            last_date = Some(date.clone());
 
             // Flush the previous epoch (if any).
             if date.is_genesis() {
@@ -188,13 +304,18 @@ impl<'a> ConnectedPeer<'a> {
 
             last_block = Some(block_hash.clone());
         }).unwrap();
-        pbr.finish();
+        progress.finish(term, last_date.as_ref());
 
         // Update the tip tag to point to the most recent block.
         if let Some(block_hash) = last_block {
             peer.save_peer_local_tip(&block_hash);
         }
 
+        if let Some(err) = chain_error {
+            term.error(&format!("the peer sent blocks not linking to our chain: {:?}\n", err)).unwrap();
+            ::std::process::exit(1);
+        }
+
         peer
     }
 }
@@ -297,7 +418,6 @@ impl<'a> Peer<'a> {
 mod internal {
     use storage::{self, block_read};
     use cardano::block::{EpochId, HeaderHash};
-    use cardano::util::{hex};
     use std::time::{SystemTime, Duration};
 
     fn duration_print(d: Duration) -> String {
@@ -369,7 +489,7 @@ mod internal {
         storage::epoch::epoch_create(&storage.config, &packhash, epoch_id);
 
         info!( "=> pack {} written for epoch {} in {}"
-             , hex::encode(&packhash[..])
+             , packhash
              , epoch_id, duration_print(epoch_time_elapsed)
              );
     }
@@ -6,7 +6,7 @@ pub fn command_address( mut term: Term
                       , address: String
                       )
 {
-    let bytes = match base58::decode(&address) {
+    let bytes = match base58::decode_lenient(&address) {
         Err(err) => {
             term.error(&format!("Invalid Address, should be encoded in base58\n")).unwrap();
             term.error(&format!("{}\n", err)).unwrap();
@@ -12,6 +12,7 @@ extern crate indicatif;
 extern crate serde_derive;
 extern crate serde;
 extern crate serde_yaml;
+extern crate serde_json;
 extern crate rand;
 #[macro_use]
 extern crate log;
@@ -25,6 +25,7 @@ fn main() {
         .arg(global_quiet_definition())
         .arg(global_color_definition())
         .arg(global_rootdir_definition(&default_root_dir))
+        .arg(global_storagedir_definition())
 
         .subcommand(blockchain_commands_definition())
         .subcommand(wallet_commands_definition())
@@ -35,11 +36,12 @@ fn main() {
     let mut term = term::Term::new(configure_terminal(&matches));
 
     let root_dir = global_rootdir_match(&default_root_dir, &matches);
+    let blockchains_dir = blockchain::config::blockchains_directory(&root_dir, global_storagedir_match(&matches));
 
     match matches.subcommand() {
-        (BLOCKCHAIN_COMMAND, Some(matches))  => { subcommand_blockchain(term, root_dir, matches) },
-        (WALLET_COMMAND, Some(matches))      => { subcommand_wallet(term, root_dir, matches) },
-        (TRANSACTION_COMMAND, Some(matches)) => { subcommand_transaction(term, root_dir, matches) },
+        (BLOCKCHAIN_COMMAND, Some(matches))  => { subcommand_blockchain(term, blockchains_dir, matches) },
+        (WALLET_COMMAND, Some(matches))      => { subcommand_wallet(term, root_dir, blockchains_dir, matches) },
+        (TRANSACTION_COMMAND, Some(matches)) => { subcommand_transaction(term, root_dir, blockchains_dir, matches) },
         (DEBUG_COMMAND, Some(matches))       => { subcommand_debug(term, root_dir, matches) },
         _ => {
             term.error(matches.usage()).unwrap();
@@ -79,6 +81,18 @@ fn global_rootdir_match<'a>(default: &'a PathBuf, matches: &ArgMatches<'a>) -> P
     }
 }
 
+fn global_storagedir_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("STORAGE_DIR")
+        .long("storage-dir")
+        .takes_value(true)
+        .value_name("PATH")
+        .help("the directory of the blockchains' storage for this run, instead of the one in the root directory")
+        .env(blockchain::config::BLOCKCHAINS_DIRECTORY_ENVIRONMENT)
+}
+fn global_storagedir_match<'a>(matches: &ArgMatches<'a>) -> Option<PathBuf> {
+    matches.value_of("STORAGE_DIR").map(PathBuf::from)
+}
+
 fn global_quiet_definition<'a, 'b>() -> Arg<'a, 'b> {
     Arg::with_name("QUIET")
         .long("quiet")
@@ -168,6 +182,11 @@ fn blockchain_argument_remote_alias_definition<'a, 'b>() -> Arg<'a,'b> {
         .help("Alias given to a remote node.")
         .required(true)
 }
+fn blockchain_argument_fetch_json_definition<'a, 'b>() -> Arg<'a,'b> {
+    Arg::with_name("FETCH_JSON")
+        .help("report the progress of the download as JSON objects, one per line and at most one per second, instead of a progress bar. The other messages are written to the standard error.")
+        .long("json")
+}
 fn blockchain_argument_remote_alias_match<'a>(matches: &ArgMatches<'a>) -> String {
     match matches.value_of("BLOCKCHAIN_REMOTE_ALIAS") {
         Some(r) => { r.to_owned() },
@@ -191,56 +210,61 @@ fn blockchain_argument_template_definition<'a, 'b>() -> Arg<'a, 'b> {
         .value_name("TEMPLATE")
         .help("the template for the new blockchain")
         .required(false)
-        .possible_values(&["mainnet", "staging", "testnet"])
+        .possible_values(&blockchain::NETWORKS)
         .default_value("mainnet")
 }
 fn blockchain_argument_template_match<'a>(matches: &ArgMatches<'a>)
     -> blockchain::Config
 {
-    match matches.value_of("BLOCKCHAIN_TEMPLATE") {
+    match matches.value_of("BLOCKCHAIN_TEMPLATE").and_then(blockchain::network_config) {
         None => blockchain::Config::mainnet(),
-        Some("mainnet") => blockchain::Config::mainnet(),
-        Some("staging") => blockchain::Config::staging(),
-        Some("testnet") => blockchain::Config::testnet(),
-        Some(&_) => {
-            // this should not be reachable as clap is handling
-            // checking the value against all possible value
-            unreachable!()
-        }
+        Some(config) => config,
     }
 }
 
-fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
+fn subcommand_blockchain<'a>(mut term: term::Term, blockchains_dir: PathBuf, matches: &ArgMatches<'a>) {
+    // the commands writing to the storage, the read only ones do not
+    // create the blockchains' directory
+    match matches.subcommand_name() {
+        Some("new") | Some("remote-add") | Some("remote-rm") | Some("remote-fetch") | Some("forward")
+            | Some("pull") | Some("destroy") | Some("reindex") | Some("prune") => {
+            blockchain::config::check_writable(&mut term, &blockchains_dir);
+        },
+        _ => {}
+    }
+
     match matches.subcommand() {
         ("list", Some(matches)) => {
             let detailed = matches.is_present("LIST_DETAILS");
 
-            blockchain::commands::list(term, root_dir, detailed);
+            blockchain::commands::list(term, blockchains_dir, detailed);
         },
         ("new", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
             let net_config = blockchain_argument_template_match(&matches);
 
-            blockchain::commands::new(term, root_dir, name, net_config);
+            blockchain::commands::new(term, blockchains_dir, name, net_config);
         },
         ("remote-add", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
             let alias = blockchain_argument_remote_alias_match(&matches);
             let endpoint = blockchain_argument_remote_endpoint_match(&matches);
 
-            blockchain::commands::remote_add(term, root_dir, name, alias, endpoint);
+            blockchain::commands::remote_add(term, blockchains_dir, name, alias, endpoint);
         },
         ("remote-rm", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
             let alias = blockchain_argument_remote_alias_match(&matches);
 
-            blockchain::commands::remote_rm(term, root_dir, name, alias);
+            blockchain::commands::remote_rm(term, blockchains_dir, name, alias);
         },
         ("remote-fetch", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
             let peers = values_t!(matches, "BLOCKCHAIN_REMOTE_ALIAS", String).unwrap_or_else(|_| Vec::new());
 
-            blockchain::commands::remote_fetch(term, root_dir, name, peers);
+            let json = matches.is_present("FETCH_JSON");
+
+            blockchain::commands::remote_fetch(term, blockchains_dir, name, peers, json);
         },
         ("remote-ls", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
@@ -254,52 +278,68 @@ fn subcommand_blockchain<'a>(mut term: term::Term, root_dir: PathBuf, matches: &
                 blockchain::commands::RemoteDetail::Short
             };
 
-            blockchain::commands::remote_ls(term, root_dir, name, detailed);
+            blockchain::commands::remote_ls(term, blockchains_dir, name, detailed);
         },
         ("forward", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
             let opt_hash = matches.value_of("FORWARD_TO_BLOCK").map(|s| s.to_owned());
 
-            blockchain::commands::forward(term, root_dir, name, opt_hash);
+            blockchain::commands::forward(term, blockchains_dir, name, opt_hash);
         },
         ("pull", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
 
-            blockchain::commands::pull(term, root_dir, name);
+            let json = matches.is_present("FETCH_JSON");
+
+            blockchain::commands::pull(term, blockchains_dir, name, json);
         },
         ("cat", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
-            let hash = matches.value_of("HASH_BLOCK").unwrap();
             let no_parse = matches.is_present("BLOCK_NO_PARSE");
             let debug = matches.is_present("DEBUG");
 
-            blockchain::commands::cat(term, root_dir, name, hash, no_parse, debug);
+            if matches.is_present("BLOCK_HEIGHT") {
+                let height = value_t!(matches, "BLOCK_HEIGHT", u64).unwrap_or_else(|e| e.exit());
+                blockchain::commands::cat_height(term, blockchains_dir, name, height, no_parse, debug);
+            } else {
+                let hash = matches.value_of("HASH_BLOCK").unwrap();
+                blockchain::commands::cat(term, blockchains_dir, name, hash, no_parse, debug);
+            }
         },
         ("status", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
 
-            blockchain::commands::status(term, root_dir, name);
+            blockchain::commands::status(term, blockchains_dir, name);
         },
         ("destroy", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
 
-            blockchain::commands::destroy(term, root_dir, name);
+            blockchain::commands::destroy(term, blockchains_dir, name);
         },
         ("log", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
             let hash = matches.value_of("HASH_BLOCK").map(|s| s.to_owned());
 
-            blockchain::commands::log(term, root_dir, name, hash);
+            blockchain::commands::log(term, blockchains_dir, name, hash);
         },
         ("verify-block", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
             let hash = matches.value_of("HASH_BLOCK").unwrap();
 
-            blockchain::commands::verify_block(term, root_dir, name, hash);
+            blockchain::commands::verify_block(term, blockchains_dir, name, hash);
         },
         ("verify", Some(matches)) => {
             let name = blockchain_argument_name_match(&matches);
-            blockchain::commands::verify_chain(term, root_dir, name);
+            blockchain::commands::verify_chain(term, blockchains_dir, name);
+        },
+        ("reindex", Some(matches)) => {
+            let name = blockchain_argument_name_match(&matches);
+            blockchain::commands::reindex(term, blockchains_dir, name);
+        },
+        ("prune", Some(matches)) => {
+            let name = blockchain_argument_name_match(&matches);
+            let epoch = value_t!(matches, "BEFORE_EPOCH", u64).unwrap_or_else(|e| e.exit());
+            blockchain::commands::prune(term, blockchains_dir, name, epoch);
         },
         _ => {
             term.error(matches.usage()).unwrap();
@@ -343,6 +383,7 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .multiple(true) // we want to accept multiple aliases here too
                 .required(false) // we allow user not to set any values here
             )
+            .arg(blockchain_argument_fetch_json_definition())
         )
         .subcommand(SubCommand::with_name("remote-ls")
             .about("List all the remote nodes of the given blockchain")
@@ -378,6 +419,7 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
         .subcommand(SubCommand::with_name("pull")
             .about("handy command to `remote-fetch' and `forward' the local blockchain.")
             .arg(blockchain_argument_name_definition())
+            .arg(blockchain_argument_fetch_json_definition())
         )
         .subcommand(SubCommand::with_name("gc")
             .about("run garbage collection of lose blocks. This function might be a bit slow to run but it will free some disk space.")
@@ -388,9 +430,16 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .arg(blockchain_argument_name_definition())
             .arg(Arg::with_name("HASH_BLOCK")
                 .value_name("HASH")
-                .required(true)
+                .required_unless("BLOCK_HEIGHT")
+                .conflicts_with("BLOCK_HEIGHT")
                 .help("The block hash to open.")
             )
+            .arg(Arg::with_name("BLOCK_HEIGHT")
+                .long("height")
+                .value_name("HEIGHT")
+                .takes_value(true)
+                .help("open the block at the given height instead, downloading it from the peers if the local blockchain is not synced that far.")
+            )
             .arg(Arg::with_name("BLOCK_NO_PARSE")
                 .long("no-parse")
                 .help("don't parse the block, flush the bytes direct to the standard output (not subject to `--quiet' option)")
@@ -430,6 +479,20 @@ fn blockchain_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .about("verify all blocks in the chain")
             .arg(blockchain_argument_name_definition())
         )
+        .subcommand(SubCommand::with_name("reindex")
+            .about("rebuild the indexes of the local blockchain's storage from the packed blocks, useful if the indexes are inconsistent (e.g. after a crash)")
+            .arg(blockchain_argument_name_definition())
+        )
+        .subcommand(SubCommand::with_name("prune")
+            .about("delete the blocks of the oldest epochs of the local blockchain, keeping the UTxO set at the end of these epochs. Only the packed (stable) epochs can be deleted.")
+            .arg(blockchain_argument_name_definition())
+            .arg(Arg::with_name("BEFORE_EPOCH")
+                .long("before-epoch")
+                .value_name("EPOCH")
+                .required(true)
+                .help("delete the epochs before this one")
+            )
+        )
 }
 
 /* ------------------------------------------------------------------------- *
@@ -561,7 +624,24 @@ fn wallet_argument_daedalus_seed_match<'a>(matches: &ArgMatches<'a>) -> bool {
 
 const WALLET_COMMAND : &'static str = "wallet";
 
-fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
+// only the wallet commands reading the local blockchain take `--network`:
+// sync, status, log, statement, utxos, audit-utxo and scan-address. The
+// others (e.g. address, next-address) only use the wallet's keys or
+// configuration, and the transaction commands use the blockchain given
+// to them or the ones the wallets are attached to.
+fn wallet_argument_network_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("WALLET_NETWORK")
+        .long("network")
+        .value_name("NETWORK")
+        .takes_value(true)
+        .possible_values(&blockchain::NETWORKS)
+        .help("use the local blockchain of the given network (created if needed) instead of the attached one, for this command only: the wallet's configuration and log are left unchanged. Only the commands reading the local blockchain take this option.")
+}
+fn wallet_argument_network_match<'a>(matches: &ArgMatches<'a>) -> Option<String> {
+    matches.value_of("WALLET_NETWORK").map(|s| s.to_owned())
+}
+
+fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, blockchains_dir: PathBuf, matches: &ArgMatches<'a>) {
     match matches.subcommand() {
         ("create", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
@@ -598,56 +678,133 @@ fn subcommand_wallet<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgM
         ("address", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
             let account = value_t!(matches, "ACCOUNT_INDEX", u32).unwrap_or_else(|e| e.exit());
-            let index   = value_t!(matches, "ADDRESS_INDEX", u32).unwrap_or_else(|e| e.exit());
+            let index   = if matches.is_present("ADDRESS_INDEX") {
+                Some(value_t!(matches, "ADDRESS_INDEX", u32).unwrap_or_else(|e| e.exit()))
+            } else { None };
             let is_internal = matches.is_present("INTERNAL_ADDRESS");
+            let count = value_t!(matches, "ADDRESS_COUNT", u32).unwrap_or_else(|e| e.exit());
+            let output = matches.value_of("ADDRESS_OUTPUT").map(PathBuf::from);
+            let json = matches.is_present("ADDRESS_JSON");
+            let force = matches.is_present("ADDRESS_FORCE");
+            let hardened = matches.values_of("HARDENED_LEVELS").map(|levels| levels.map(String::from).collect());
+            let no_cache = matches.is_present("ADDRESS_NO_CACHE");
+
+            wallet::commands::address(term, root_dir, name, account, is_internal, index, count, output, json, force, hardened, no_cache);
+        },
+        ("derive", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let path = value_t!(matches, "DERIVATION_PATH", String).unwrap_or_else(|e| e.exit());
+
+            wallet::commands::derive(term, root_dir, name, path);
+        },
+        ("next-address", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let account = value_t!(matches, "ACCOUNT_INDEX", u32).unwrap_or_else(|e| e.exit());
 
-            wallet::commands::address(term, root_dir, name, account, is_internal, index);
+            wallet::commands::next_address(term, root_dir, name, account);
+        },
+        ("scan-address", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let address = value_t!(matches, "SCANNED_ADDRESS", cardano::address::ExtendedAddr).unwrap_or_else(|e| e.exit());
+            let network = wallet_argument_network_match(&matches);
+
+            wallet::commands::scan_address(term, root_dir, blockchains_dir, name, network, address);
         },
         ("attach", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
             let blockchain = blockchain_argument_name_match(&matches);
 
-            wallet::commands::attach(term, root_dir, name, blockchain);
+            wallet::commands::attach(term, root_dir, blockchains_dir, name, blockchain);
         },
         ("detach", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
 
-            wallet::commands::detach(term, root_dir, name);
+            wallet::commands::detach(term, root_dir, blockchains_dir, name);
         },
         ("sync", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
+            let network = wallet_argument_network_match(&matches);
+
+            let epochs = if matches.is_present("SYNC_EPOCHS") {
+                Some(value_t!(matches, "SYNC_EPOCHS", u32).unwrap_or_else(|e| e.exit()))
+            } else {
+                None
+            };
 
-            wallet::commands::sync(term, root_dir, name);
+            wallet::commands::sync(term, root_dir, blockchains_dir, name, network, epochs);
         },
         ("status", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
+            let network = wallet_argument_network_match(&matches);
 
-            wallet::commands::status(term, root_dir, name);
+            let refresh = matches.is_present("REFRESH");
+            let json = matches.is_present("STATUS_JSON");
+
+            wallet::commands::status(term, root_dir, blockchains_dir, name, network, refresh, json);
         },
         ("log", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
+            let network = wallet_argument_network_match(&matches);
 
-            wallet::commands::log(term, root_dir, name, false);
+            wallet::commands::log(term, root_dir, blockchains_dir, name, network, false);
         },
         ("utxos", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
+            let network = wallet_argument_network_match(&matches);
+
+            let account = if matches.is_present("ACCOUNT_INDEX") {
+                Some(value_t!(matches, "ACCOUNT_INDEX", u32).unwrap_or_else(|e| e.exit()))
+            } else { None };
+            let json = matches.is_present("UTXOS_JSON");
+            let sort_by_amount = matches.is_present("UTXOS_SORT_BY_AMOUNT");
 
-            wallet::commands::utxos(term, root_dir, name);
+            wallet::commands::utxos(term, root_dir, blockchains_dir, name, network, account, json, sort_by_amount);
+        },
+        ("audit-utxo", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let network = wallet_argument_network_match(&matches);
+
+            wallet::commands::audit_utxo(term, root_dir, blockchains_dir, name, network);
         },
         ("statement", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
+            let network = wallet_argument_network_match(&matches);
 
-            wallet::commands::log(term, root_dir, name, true);
+            wallet::commands::log(term, root_dir, blockchains_dir, name, network, true);
         },
         ("destroy", Some(matches)) => {
             let name = wallet_argument_name_match(&matches);
 
             wallet::commands::destroy(term, root_dir, name);
         },
+        ("export-keys", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let output = value_t!(matches, "EXPORT_OUTPUT", String).unwrap_or_else(|e| e.exit());
+
+            wallet::commands::export_keys(term, root_dir, name, output.into());
+        },
+        ("import-keys", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let input = value_t!(matches, "IMPORT_INPUT", String).unwrap_or_else(|e| e.exit());
+
+            wallet::commands::import_keys(term, root_dir, name, input.into());
+        },
+        ("dump-config", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+
+            wallet::commands::dump_config(term, root_dir, name);
+        },
+        ("set", Some(matches)) => {
+            let name = wallet_argument_name_match(&matches);
+            let key = value_t!(matches, "CONFIG_KEY", String).unwrap_or_else(|e| e.exit());
+            let value = value_t!(matches, "CONFIG_VALUE", String).unwrap_or_else(|e| e.exit());
+
+            wallet::commands::set(term, root_dir, blockchains_dir, name, key, value);
+        },
         ("list", Some(matches)) => {
             let detailed = matches.is_present("WALLET_LIST_DETAILED");
 
-            wallet::commands::list(term, root_dir, detailed);
+            wallet::commands::list(term, root_dir, blockchains_dir, detailed);
         },
         _ => {
             term.error(matches.usage()).unwrap();
@@ -692,12 +849,99 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .about("delete all data associated to the given wallet.")
             .arg(wallet_argument_name_definition())
         )
+        .subcommand(SubCommand::with_name("export-keys")
+            .about("export the wallet's private key, encrypted with a new passphrase, for backup or migration")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("EXPORT_OUTPUT")
+                .help("the file to write the encrypted keys to, it must not exist already")
+                .long("out")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+            )
+        )
+        .subcommand(SubCommand::with_name("import-keys")
+            .about("create a new wallet from keys exported with `export-keys'")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("IMPORT_INPUT")
+                .help("the file to read the encrypted keys from")
+                .long("in")
+                .value_name("FILE")
+                .takes_value(true)
+                .required(true)
+            )
+        )
         .subcommand(SubCommand::with_name("address")
             .about("create a new address")
             .arg(wallet_argument_name_definition())
             .arg(Arg::with_name("ACCOUNT_INDEX").required(true))
-            .arg(Arg::with_name("ADDRESS_INDEX").required(true))
-            .arg(Arg::with_name("INTERNAL_ADDRESS").long("internal"))
+            .arg(Arg::with_name("ADDRESS_INDEX")
+                .help("the index of the address, the next unused change address if omitted with `--change'")
+                .required_unless("INTERNAL_ADDRESS")
+            )
+            .arg(Arg::with_name("INTERNAL_ADDRESS")
+                .help("create an internal address, i.e. a change address: an address receiving the remainder of the inputs spent in a transaction.")
+                .long("internal")
+                .visible_alias("change")
+            )
+            .arg(Arg::with_name("ADDRESS_COUNT")
+                .help("number of addresses to create, at consecutive indexes starting from the address index")
+                .long("count")
+                .value_name("COUNT")
+                .takes_value(true)
+                .default_value("1")
+            )
+            .arg(Arg::with_name("ADDRESS_OUTPUT")
+                .help("write the addresses to the given file instead of printing them")
+                .long("out")
+                .value_name("FILE")
+                .takes_value(true)
+            )
+            .arg(Arg::with_name("ADDRESS_JSON")
+                .help("output the addresses, with their account and index, in JSON instead of one per line")
+                .long("json")
+            )
+            .arg(Arg::with_name("ADDRESS_FORCE")
+                .help("overwrite the output file without asking for confirmation if it already exists")
+                .long("force")
+                .requires("ADDRESS_OUTPUT")
+            )
+            .arg(Arg::with_name("HARDENED_LEVELS")
+                .help("the levels of the derivation path to derive hardened, for BIP44 wallets only. Defaults to `account', as BIP44 does. The account level is always hardened. Addresses derived with other hardened levels are not found when syncing the wallet.")
+                .long("hardened")
+                .value_name("LEVELS")
+                .takes_value(true)
+                .use_delimiter(true)
+                .possible_values(&["account", "change", "index"])
+            )
+            .arg(Arg::with_name("ADDRESS_NO_CACHE")
+                .help("derive the addresses again instead of using the ones cached from the previous calls, e.g. to verify the cached ones. The cache is left untouched.")
+                .long("no-cache")
+            )
+        )
+        .subcommand(SubCommand::with_name("next-address")
+            .about("print the next external address not used nor printed yet, to receive funds. Each call prints a new address.")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("ACCOUNT_INDEX").required(true))
+        )
+        .subcommand(SubCommand::with_name("scan-address")
+            .about("tell if the given address belongs to the wallet (searching the BIP44 addresses up to the gap limit), printing its derivation path and UTxOs if it does")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("SCANNED_ADDRESS")
+                .help("the address to look for, in base58")
+                .value_name("ADDRESS")
+                .required(true)
+            )
+            .arg(wallet_argument_network_definition())
+        )
+        .subcommand(SubCommand::with_name("derive")
+            .about("derive the wallet's root key along an arbitrary BIP32 derivation path and print the derived keys and address")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("DERIVATION_PATH")
+                .help("the derivation path from the root key, e.g. m/1852'/1815'/0'/0/0. Levels followed by `'' (or `h') are derived hardened.")
+                .value_name("PATH")
+                .required(true)
+            )
         )
         .subcommand(SubCommand::with_name("attach")
             .about("Attach the existing wallet to the existing local blockchain. Detach first to attach to an other blockchain.")
@@ -708,6 +952,23 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .about("detach the wallet from its associated blockchain")
             .arg(wallet_argument_name_definition())
         )
+        .subcommand(SubCommand::with_name("dump-config")
+            .about("print the configuration of the given wallet")
+            .arg(wallet_argument_name_definition())
+        )
+        .subcommand(SubCommand::with_name("set")
+            .about("update a value of the wallet configuration")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("CONFIG_KEY")
+                .help("the configuration key to update")
+                .possible_values(&["attached_blockchain", "derivation_scheme"])
+                .required(true)
+            )
+            .arg(Arg::with_name("CONFIG_VALUE")
+                .help("the new value")
+                .required(true)
+            )
+        )
         .subcommand(SubCommand::with_name("sync")
             .about("synchronize the wallet with the attached blockchain")
             .arg(Arg::with_name("DRY_RUN")
@@ -720,23 +981,58 @@ fn wallet_commands_definition<'a, 'b>() -> App<'a, 'b> {
                 .value_name("HASH")
                 .takes_value(true)
             )
+            .arg(Arg::with_name("SYNC_EPOCHS")
+                .help("only sync from the start of the epoch N epochs before the epoch of the local blockchain's tip (0 syncs the epoch of the tip only), looked up on the peers if the local blockchain does not have it, the funds received before are not accounted: the balance will be incomplete.")
+                .long("epochs")
+                .value_name("N")
+                .takes_value(true)
+            )
             .arg(wallet_argument_name_definition())
+            .arg(wallet_argument_network_definition())
         )
         .subcommand(SubCommand::with_name("status")
             .about("print some status information from the given wallet (funds, transactions...)")
             .arg(wallet_argument_name_definition())
+            .arg(wallet_argument_network_definition())
+            .arg(Arg::with_name("REFRESH")
+                .long("refresh")
+                .help("recompute the balance from the whole wallet log instead of using the cached balance")
+            )
+            .arg(Arg::with_name("STATUS_JSON")
+                .long("json")
+                .help("print the status as a JSON object instead")
+            )
         )
         .subcommand(SubCommand::with_name("statement")
             .about("print the wallet statement")
             .arg(wallet_argument_name_definition())
+            .arg(wallet_argument_network_definition())
         )
         .subcommand(SubCommand::with_name("log")
             .about("print the wallet logs")
             .arg(wallet_argument_name_definition())
+            .arg(wallet_argument_network_definition())
         )
         .subcommand(SubCommand::with_name("utxos")
-            .about("print the wallet's available funds")
+            .about("print the wallet's available funds: the UTxOs, with their amount and address")
+            .arg(wallet_argument_name_definition())
+            .arg(Arg::with_name("ACCOUNT_INDEX")
+                .help("only print the UTxOs of the addresses of the given account")
+            )
+            .arg(wallet_argument_network_definition())
+            .arg(Arg::with_name("UTXOS_JSON")
+                .help("output the UTxOs in JSON instead of one per line")
+                .long("json")
+            )
+            .arg(Arg::with_name("UTXOS_SORT_BY_AMOUNT")
+                .help("print the UTxOs by decreasing amount")
+                .long("sort-by-amount")
+            )
+        )
+        .subcommand(SubCommand::with_name("audit-utxo")
+            .about("rebuild the wallet's UTxOs from the blocks of the blockchain and report the differences with the wallet's")
             .arg(wallet_argument_name_definition())
+            .arg(wallet_argument_network_definition())
         )
 }
 
@@ -774,6 +1070,22 @@ impl TransactionCmd {
     }
 }
 
+fn transaction_argument_coin_selection_definition<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("COIN_SELECTION")
+        .help("the strategy to select the inputs: the first inputs that match, the largest inputs first (fewer inputs, smaller fee), or random inputs with a change close to the payment.")
+        .long("coin-selection")
+        .takes_value(true)
+        .possible_values(&["first-match-first", "largest-first", "random-improve"])
+        .default_value("first-match-first")
+}
+fn transaction_argument_coin_selection_match<'a>(matches: &ArgMatches<'a>) -> cardano::fee::SelectionPolicy {
+    match matches.value_of("COIN_SELECTION") {
+        Some("first-match-first") => cardano::fee::SelectionPolicy::FirstMatchFirst,
+        Some("largest-first")     => cardano::fee::SelectionPolicy::LargestFirst,
+        Some("random-improve")    => cardano::fee::SelectionPolicy::RandomImprove(rand::random()),
+        _ => unreachable!() // default is "first-match-first"
+    }
+}
 fn transaction_argument_name_definition<'a, 'b>() -> Arg<'a,'b> {
     Arg::with_name("TRANSACTION_ID")
         .help("the transaction staging identifier")
@@ -827,11 +1139,11 @@ fn transaction_argument_output_match<'a>(matches: &ArgMatches<'a>) -> Option<(ca
     Some((address, coin))
 }
 
-fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches: &ArgMatches<'a>) {
+fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, blockchains_dir: PathBuf, matches: &ArgMatches<'a>) {
     match matches.subcommand() {
         ("new", Some(matches)) => {
             let blockchain = blockchain_argument_name_match(&matches);
-            transaction::commands::new(term, root_dir, blockchain);
+            transaction::commands::new(term, root_dir, blockchains_dir, blockchain);
         },
         ("list", _) => {
             transaction::commands::list(term, root_dir);
@@ -852,8 +1164,10 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
         ("send", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
             let blockchain = blockchain_argument_name_match(&matches);
+            let dry_run = matches.is_present("DRY_RUN");
+            let allow_no_change = matches.is_present("ALLOW_NO_CHANGE");
 
-            transaction::commands::send(term, root_dir, id, blockchain);
+            transaction::commands::send(term, root_dir, blockchains_dir, id, blockchain, dry_run, allow_no_change);
         },
         ("finalize", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
@@ -863,13 +1177,13 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
         ("sign", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
 
-            transaction::commands::sign(term, root_dir, id);
+            transaction::commands::sign(term, root_dir, blockchains_dir, id);
         },
         ("add-input", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
             let input = transaction_argument_input_match(&matches);
 
-            transaction::commands::add_input(term, root_dir, id, input);
+            transaction::commands::add_input(term, root_dir, blockchains_dir, id, input);
         },
         ("add-output", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
@@ -886,8 +1200,9 @@ fn subcommand_transaction<'a>(mut term: term::Term, root_dir: PathBuf, matches:
         ("input-select", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
             let wallets = values_t!(matches, "WALLET_NAME", wallet::WalletName).unwrap_or_else(|e| e.exit());
+            let selection_policy = transaction_argument_coin_selection_match(&matches);
 
-            transaction::commands::input_select(term, root_dir, id, wallets);
+            transaction::commands::input_select(term, root_dir, blockchains_dir, id, wallets, selection_policy);
         }
         ("rm-output", Some(matches)) => {
             let id = transaction_argument_name_match(&matches);
@@ -958,6 +1273,14 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .arg(blockchain_argument_name_definition()
                 .help("The blockchain the send the transaction too (will contact the peers of this blockchain)")
             )
+            .arg(Arg::with_name("DRY_RUN")
+                .help("print the transaction and its fee without sending it to the peers.")
+                .long("dry-run")
+            )
+            .arg(Arg::with_name("ALLOW_NO_CHANGE")
+                .help("send the transaction even if it has a change address but no output to it, the remainder of the inputs being paid as fee.")
+                .long("allow-no-change")
+            )
         )
         .subcommand(SubCommand::with_name(TransactionCmd::Finalize.as_string())
             .about("Finalize a staging transaction")
@@ -968,6 +1291,7 @@ fn transaction_commands_definition<'a, 'b>() -> App<'a, 'b> {
             .about("Select input automatically using a wallet (or a set of wallets), and a input selection algorithm")
             .arg(transaction_argument_name_definition())
             .arg(Arg::with_name("WALLET_NAME").required(true).multiple(true).help("wallet name to use for the selection"))
+            .arg(transaction_argument_coin_selection_definition())
         )
         .subcommand(SubCommand::with_name(TransactionCmd::AddChange.as_string())
             .about("Add a change address to a transaction")
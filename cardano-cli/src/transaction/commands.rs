@@ -3,16 +3,18 @@ use utils::term::{Term, style::{Style}};
 use super::core::{self, StagingId, StagingTransaction};
 use super::super::blockchain::{Blockchain};
 use super::super::wallet::{Wallets, Wallet, self, WalletName};
-use cardano::{tx::{TxId, TxIn, TxInWitness}, coin::{Coin, sum_coins}, address::{ExtendedAddr}, fee::{LinearFee, FeeAlgorithm}};
+use cardano::{tx::{TxId, TxIn, TxInWitness}, coin::{Coin, sum_coins}, address::{ExtendedAddr}, config::ProtocolMagic, txutils};
+use cardano::fee::{LinearFee, FeeAlgorithm, SelectionAlgorithm, SelectionPolicy};
 use cardano::tx;
 
 /// function to create a new empty transaction
 pub fn new( mut term: Term
           , root_dir: PathBuf
+          , blockchains_dir: PathBuf
           , blockchain: String
           )
 {
-    let blockchain = Blockchain::load(root_dir.clone(), blockchain);
+    let blockchain = Blockchain::load(blockchains_dir, blockchain);
 
     let staging = match StagingTransaction::new(root_dir, blockchain.config.protocol_magic) {
         Err(err) => {
@@ -25,7 +27,7 @@ pub fn new( mut term: Term
         Ok(st) => st
     };
 
-    writeln!(term, "Staging file successfully created: {}", style!(staging.id()));
+    writeln!(term, "Staging file successfully created: {}", style!(staging.id())).unwrap();
 }
 
 pub fn list( mut term: Term
@@ -65,19 +67,118 @@ pub fn destroy( mut term: Term
     }
 }
 
-/// function to create a new empty transaction
+/// send the transaction to the native peers of the given blockchain
+///
+/// If the staging transaction has no inputs yet, they are selected among
+/// the UTxOs of all the wallets, the remainder going to the change
+/// address; if it is not signed yet, its inputs are signed with the
+/// wallets owning them. The fee is checked against the blockchain's fee
+/// algorithm before signing.
+///
+/// With `dry_run`, the transaction is built and signed the same way but
+/// in memory only: the staging transaction is left unchanged and nothing
+/// is sent to the peers.
+///
+/// If the transaction has a change address but no output to it, the whole
+/// remainder of the inputs would be paid as fee: this is refused unless
+/// `allow_no_change` is set (a dry run only warns).
 pub fn send( mut term: Term
            , root_dir: PathBuf
+           , blockchains_dir: PathBuf
            , id_str: &str
            , blockchain: String
+           , dry_run: bool
+           , allow_no_change: bool
            )
 {
-    let staging = load_staging(&mut term, root_dir.clone(), id_str);
-    let blockchain = Blockchain::load(root_dir.clone(), blockchain);
+    let mut staging = load_staging(&mut term, root_dir.clone(), id_str);
+    let blockchain = Blockchain::load(blockchains_dir.clone(), blockchain);
+    let fee_alg = blockchain.config.linear_fee;
+
+    // the operations completing the staging transaction, only saved to
+    // it if the transaction is actually sent
+    let mut transaction = staging.transaction().clone();
+    let mut operations = Vec::new();
+
+    if transaction.inputs().is_empty() {
+        let wallets = Wallets::load(root_dir.clone()).unwrap().into_iter().map(|(name, _)| name).collect();
+        let (selection, _) = select_inputs(&mut term, root_dir.clone(), blockchains_dir.clone(), &transaction, wallets, SelectionPolicy::default(), &fee_alg);
+        for operation in selection {
+            transaction.update_with(operation.clone());
+            operations.push(operation);
+        }
+    }
 
-    let txaux = staging.to_tx_aux();
+    let input_total = sum_coins(transaction.inputs().iter().map(|x| x.expected_value)).unwrap();
+    let output_total = transaction.to_tx_aux().tx.get_output_total().unwrap();
+    let estimated_fee = fee_alg.fee_for(transaction.inputs().len(), transaction.to_tx_aux().tx.outputs.len(), 0);
+    let fake_witnesses : Vec<TxInWitness> = iter::repeat(TxInWitness::fake()).take(transaction.inputs().len()).collect();
+    let required_fee = fee_alg.calculate_for_txaux_component(&transaction.to_tx_aux().tx, &fake_witnesses).unwrap().to_coin();
+    let required : u64 = u64::from(output_total) + u64::from(required_fee);
+    if u64::from(input_total) < required {
+        term.error(&format!("insufficient funds: the inputs provide {} but the outputs and the fee require {}\n", input_total, required)).unwrap();
+        ::std::process::exit(1);
+    }
+    let fee = u64::from(input_total) - u64::from(output_total);
+    writeln!(term, "fee: {} (required: {}, estimated from the number of inputs and outputs: {})", style!(fee), required_fee, estimated_fee).unwrap();
+
+    let change_outputs : Vec<core::Output> = transaction.outputs().iter()
+        .filter(|output| transaction.changes().iter().any(|change| change.address == output.address))
+        .cloned()
+        .collect();
+    if transaction.has_change() && change_outputs.is_empty() && fee > u64::from(required_fee) {
+        term.warn(&format!("the transaction has no change output, the remainder of the inputs is paid as fee: {}\n", fee)).unwrap();
+        if ! dry_run && ! allow_no_change {
+            term.error("not sending the transaction, use --allow-no-change to send it anyway\n").unwrap();
+            ::std::process::exit(1);
+        }
+    }
+
+    if transaction.signature().is_empty() {
+        for witness in sign_inputs(&mut term, root_dir.clone(), blockchains_dir.clone(), &transaction, staging.protocol_magic) {
+            let operation = core::Operation::Signature(witness);
+            transaction.update_with(operation.clone());
+            operations.push(operation);
+        }
+    }
+    if transaction.signature().len() != transaction.inputs().len() {
+        term.error(&format!("the transaction has {} signatures for {} inputs\n", transaction.signature().len(), transaction.inputs().len())).unwrap();
+        ::std::process::exit(1);
+    }
+
+    let txaux = transaction.to_tx_aux();
+
+    writeln!(term, "transaction id: {}", style!(txaux.tx.id())).unwrap();
+    writeln!(term, "input total: {}", style!(input_total)).unwrap();
+    writeln!(term, "output total: {}", style!(output_total)).unwrap();
+    if change_outputs.is_empty() {
+        writeln!(term, "change: none").unwrap();
+    }
+    for output in change_outputs {
+        writeln!(term, "change: {} to {}", style!(output.amount), style!(output.address)).unwrap();
+    }
+
+    if dry_run {
+        writeln!(term, "{}", txaux).unwrap();
+        term.info("dry run, the transaction has not been sent\n").unwrap();
+        return;
+    }
+
+    for operation in operations {
+        let saved = match operation {
+            core::Operation::AddInput(input) => staging.add_input(input),
+            core::Operation::AddOutput(output) => staging.add_output(output),
+            core::Operation::Signature(witness) => staging.add_signature(witness),
+            operation => panic!("unexpected operation {:?}", operation),
+        };
+        if let Err(err) = saved {
+            error!("Error while updating the staging transaction: {:?}", err);
+            term.error("Cannot update the staging transaction\n").unwrap();
+            ::std::process::exit(1);
+        }
+    }
 
-    writeln!(term, "sending transaction {}", style!(txaux.tx.id()));
+    writeln!(term, "sending transaction {}", style!(txaux.tx.id())).unwrap();
 
     for np in blockchain.peers() {
         if ! np.is_native() { continue; }
@@ -90,48 +191,13 @@ pub fn send( mut term: Term
 
 pub fn sign( mut term: Term
            , root_dir: PathBuf
+           , blockchains_dir: PathBuf
            , id_str: &str
            )
 {
-    let mut signatures = Vec::new();
     let mut staging = load_staging(&mut term, root_dir.clone(), id_str);
 
-    let mut wallets = BTreeMap::new();
-    for (name, wallet) in Wallets::load(root_dir.clone()).unwrap() {
-        let state = wallet::utils::create_wallet_state_from_logs(&mut term, &wallet, root_dir.clone(), wallet::state::lookup::accum::Accum::default());
-        wallets.insert(name, (wallet, state));
-    }
-
-    let txid = staging.to_tx_aux().tx.id();
-    let protocol_magic = staging.protocol_magic;
-
-    // TODO: ignore already signed inputs
-    for input in staging.transaction().inputs() {
-        let txin = input.extract_txin();
-        let mut signature = None;
-        for (name, (wallet, state)) in wallets.iter() {
-            if let Some(utxo) = state.utxos.get(&txin) {
-                term.info(
-                    &format!(
-                        "signing input {}.{} ({})\n",
-                        style!(input.transaction_id),
-                        style!(input.index_in_transaction),
-                        style!(name)
-                    )
-                ).unwrap();
-
-                signature = Some(wallet::utils::wallet_sign_tx(
-                    &mut term, wallet, protocol_magic, &txid, &utxo.credited_addressing
-                ));
-            }
-        }
-
-        if let Some(signature) = signature {
-            signatures.push(signature);
-        } else {
-            panic!("cannot sign input {:#?}", input)
-        }
-    }
+    let signatures = sign_inputs(&mut term, root_dir, blockchains_dir, staging.transaction(), staging.protocol_magic);
 
     for signature in signatures {
         staging.add_signature(signature).unwrap();
@@ -175,6 +241,7 @@ pub fn status( mut term: Term
 
 pub fn add_input( mut term: Term
                 , root_dir: PathBuf
+                , blockchains_dir: PathBuf
                 , id_str: &str
                 , input: Option<(TxId, u32, Option<Coin>)>
                 )
@@ -189,7 +256,7 @@ pub fn add_input( mut term: Term
     let input = if let Some(input) = input {
         match input.2 {
             None => {
-                find_input_in_all_utxos(&mut term, root_dir.clone(), input.0, input.1)
+                find_input_in_all_utxos(&mut term, root_dir.clone(), blockchains_dir, input.0, input.1)
             },
             Some(v) => {
                 core::Input {
@@ -382,19 +449,18 @@ pub fn import( mut term: Term
     let staging = StagingTransaction::import(root_dir, import).unwrap();
     writeln!(&mut term, "Staging transaction `{}' successfully imported",
         style!(staging.id())
-    );
+    ).unwrap();
 }
 
 pub fn input_select( mut term: Term
                    , root_dir: PathBuf
+                   , blockchains_dir: PathBuf
                    , id_str: &str
                    , wallets: Vec<WalletName>
+                   , selection_policy: SelectionPolicy
                    )
 {
-    use ::cardano::{fee::{self, SelectionAlgorithm}, txutils};
-
-    let alg = fee::LinearFee::default();
-    let selection_policy = fee::SelectionPolicy::default();
+    let alg = wallets_linear_fee(&mut term, root_dir.clone(), blockchains_dir.clone(), &wallets);
 
     let mut staging = load_staging(&mut term, root_dir.clone(), id_str);
 
@@ -403,42 +469,19 @@ pub fn input_select( mut term: Term
         ::std::process::exit(1);
     }
 
-    if ! staging.transaction().has_change() {
-        term.error("cannot select inputs if no change").unwrap();
-        ::std::process::exit(1);
-    }
-
-    let change_address = staging.transaction().changes()[0].address.clone();
-    let output_policy = txutils::OutputPolicy::One(change_address.clone());
-
-    let outputs = staging.transaction().outputs().iter().map(|output| {
-        output.into()
-    }).collect::<Vec<_>>();
-    let inputs = list_input_inputs(&mut term, root_dir.clone(), wallets);
-
-    let result = alg.compute(
-        selection_policy,
-        inputs.iter(),
-        outputs.iter(),
-        &output_policy
-    );
-    let (_, selected_inputs, change) = match result {
-        Err(err) => { panic!("error {:#?}", err) },
-        Ok(v) => v
-    };
+    let (operations, change) = select_inputs(&mut term, root_dir, blockchains_dir, staging.transaction(), wallets, selection_policy, &alg);
 
     if change != Coin::zero() {
+        let change_address = staging.transaction().changes()[0].address.clone();
         term.info(&format!("using the change address: {} with value {}", change_address, change)).unwrap();
-        // add/remove the output change
-        staging.add_output(core::Output { address : change_address, amount: change }).unwrap();
     }
 
-    for input in selected_inputs {
-        staging.add_input(core::Input {
-            transaction_id: input.ptr.id,
-            index_in_transaction: input.ptr.index,
-            expected_value: input.value.value
-        }).unwrap()
+    for operation in operations {
+        match operation {
+            core::Operation::AddInput(input) => staging.add_input(input).unwrap(),
+            core::Operation::AddOutput(output) => staging.add_output(output).unwrap(),
+            operation => panic!("unexpected operation {:?}", operation),
+        }
     }
 }
 
@@ -465,10 +508,115 @@ fn load_staging(term: &mut Term, root_dir: PathBuf, id_str: &str) -> StagingTran
 
 // ----------------------------------- helpers ---------------------------------
 
-fn find_input_in_all_utxos(term: &mut Term, root_dir: PathBuf, txid: TxId, index: u32) -> core::Input {
+/// select inputs for the outputs of the given transaction among the UTxOs
+/// of the given wallets, the remainder going to the transaction's change
+/// address. Returns the operations adding the change output (if there is
+/// a remainder) and the selected inputs, and the value of the remainder.
+fn select_inputs( term: &mut Term
+                , root_dir: PathBuf
+                , blockchains_dir: PathBuf
+                , transaction: &core::Transaction
+                , wallets: Vec<WalletName>
+                , selection_policy: SelectionPolicy
+                , alg: &LinearFee
+                ) -> (Vec<core::Operation>, Coin)
+{
+    if ! transaction.has_change() {
+        term.error("cannot select inputs if no change\n").unwrap();
+        ::std::process::exit(1);
+    }
+
+    let change_address = transaction.changes()[0].address.clone();
+    let output_policy = txutils::OutputPolicy::One(change_address.clone());
+
+    let outputs = transaction.outputs().iter().map(|output| {
+        output.into()
+    }).collect::<Vec<_>>();
+    let inputs = list_input_inputs(term, root_dir, blockchains_dir, wallets);
+
+    let result = alg.compute(
+        selection_policy,
+        inputs.iter(),
+        outputs.iter(),
+        &output_policy
+    );
+    let (_, selected_inputs, change) = match result {
+        Err(err) => {
+            term.error(&format!("cannot select the inputs: {}\n", err)).unwrap();
+            ::std::process::exit(1);
+        },
+        Ok(v) => v
+    };
+
+    let mut operations = Vec::new();
+    if change != Coin::zero() {
+        operations.push(core::Operation::AddOutput(core::Output { address : change_address, amount: change }));
+    }
+    for input in selected_inputs {
+        operations.push(core::Operation::AddInput(core::Input {
+            transaction_id: input.ptr.id,
+            index_in_transaction: input.ptr.index,
+            expected_value: input.value.value
+        }));
+    }
+
+    (operations, change)
+}
+
+/// sign the inputs of the given transaction with the wallets the spent
+/// UTxOs belong to
+fn sign_inputs( term: &mut Term
+              , root_dir: PathBuf
+              , blockchains_dir: PathBuf
+              , transaction: &core::Transaction
+              , protocol_magic: ProtocolMagic
+              ) -> Vec<TxInWitness>
+{
+    let mut signatures = Vec::new();
+
+    let mut wallets = BTreeMap::new();
+    for (name, wallet) in Wallets::load(root_dir.clone()).unwrap() {
+        let state = wallet::utils::create_wallet_state_from_logs(term, &wallet, blockchains_dir.clone(), wallet::state::lookup::accum::Accum::default());
+        wallets.insert(name, (wallet, state));
+    }
+
+    let txid = transaction.to_tx_aux().tx.id();
+
+    // TODO: ignore already signed inputs
+    for input in transaction.inputs() {
+        let txin = input.extract_txin();
+        let mut signature = None;
+        for (name, (wallet, state)) in wallets.iter() {
+            if let Some(utxo) = state.utxos.get(&txin) {
+                term.info(
+                    &format!(
+                        "signing input {}.{} ({})\n",
+                        style!(input.transaction_id),
+                        style!(input.index_in_transaction),
+                        style!(name)
+                    )
+                ).unwrap();
+
+                signature = Some(wallet::utils::wallet_sign_tx(
+                    term, wallet, protocol_magic, &txid, &utxo.credited_addressing
+                ));
+            }
+        }
+
+        if let Some(signature) = signature {
+            signatures.push(signature);
+        } else {
+            panic!("cannot sign input {:#?}", input)
+        }
+    }
+
+    signatures
+}
+
+fn find_input_in_all_utxos(term: &mut Term, root_dir: PathBuf, blockchains_dir: PathBuf, txid: TxId, index: u32) -> core::Input {
     let txin = TxIn { id: txid, index: index };
     for (_, wallet) in Wallets::load(root_dir.clone()).unwrap() {
-        let state = wallet::utils::create_wallet_state_from_logs(term, &wallet, root_dir.clone(), wallet::state::lookup::accum::Accum::default());
+        let state = wallet::utils::create_wallet_state_from_logs(term, &wallet, blockchains_dir.clone(), wallet::state::lookup::accum::Accum::default());
 
         if let Some(utxo) = state.utxos.get(&txin) {
             let txin = utxo.extract_txin();
@@ -484,11 +632,32 @@ fn find_input_in_all_utxos(term: &mut Term, root_dir: PathBuf, txid: TxId, index
     ::std::process::exit(1);
 }
 
-fn list_input_inputs(term: &mut Term, root_dir: PathBuf, wallets: Vec<WalletName>) -> Vec<::cardano::txutils::Input<ExtendedAddr>> {
+/// the fee algorithm of the blockchain the given wallets are attached to
+///
+/// Fails if the wallets are attached to different blockchains, their
+/// UTxOs could not be spent in the same transaction.
+fn wallets_linear_fee(term: &mut Term, root_dir: PathBuf, blockchains_dir: PathBuf, wallets: &[WalletName]) -> LinearFee {
+    let mut attached = None;
+    for wallet in wallets {
+        let wallet = Wallet::load(root_dir.clone(), wallet.clone());
+        let blockchain = wallet.config.attached_blockchain.clone();
+        match attached {
+            Some(ref name) if Some(name) != blockchain.as_ref() => {
+                term.error(&format!("the wallets are attached to different blockchains: `{}' and `{}'\n", name, blockchain.unwrap_or("none".to_owned()))).unwrap();
+                ::std::process::exit(1);
+            },
+            Some(_) => {},
+            None => attached = blockchain,
+        }
+    }
+    wallet::utils::load_attached_blockchain(term, blockchains_dir, attached).config.linear_fee
+}
+
+fn list_input_inputs(term: &mut Term, root_dir: PathBuf, blockchains_dir: PathBuf, wallets: Vec<WalletName>) -> Vec<::cardano::txutils::Input<ExtendedAddr>> {
     let mut inputs = Vec::new();
     for wallet in wallets {
         let wallet = Wallet::load(root_dir.clone(), wallet);
-        let state = wallet::utils::create_wallet_state_from_logs(term, &wallet, root_dir.clone(), wallet::state::lookup::accum::Accum::default());
+        let state = wallet::utils::create_wallet_state_from_logs(term, &wallet, blockchains_dir.clone(), wallet::state::lookup::accum::Accum::default());
 
         inputs.extend(state.utxos.iter().map(|(_, utxo)| {
             let txin = utxo.extract_txin();
@@ -6,6 +6,7 @@ use rand;
 use std::{io::{Write, Read}, iter::repeat};
 
 use cryptoxide::{pbkdf2::pbkdf2, chacha20poly1305::ChaCha20Poly1305, sha2::Sha512, hmac::Hmac};
+use cardano::util::securemem;
 
 const PASSWORD_DERIVATION_ITERATIONS : u32 = 10_000;
 const SALT_SIZE  : usize = 16;
@@ -37,6 +38,7 @@ pub fn encrypt(password: &Password, data: &[u8]) -> Vec<u8> {
 
     password_to_key(password, salt, &mut key);
     let mut ctx = ChaCha20Poly1305::new(&key[..], &nonce[..], &[]);
+    securemem::zero(&mut key);
 
     ctx.encrypt(data, &mut encrypted[0..len], &mut tag);
     encrypted.extend_from_slice(&tag[..]);
@@ -58,9 +60,11 @@ pub fn decrypt(password: &Password, data: &[u8]) -> Option<Vec<u8>> {
 
     password_to_key(password, salt, &mut key);
     let mut ctx = ChaCha20Poly1305::new(&key[..], &nonce[..], &[]);
+    securemem::zero(&mut key);
     if ctx.decrypt(&reader[0..len], &mut bytes[..], &reader[len..]) {
         Some(bytes)
     } else {
+        securemem::zero(&mut bytes);
         None
     }
 }
@@ -46,7 +46,10 @@ pub struct Term {
 
     pub style: Style,
 
-    pub term: console::Term
+    pub term: console::Term,
+
+    // where `success`, `info`, `warn` and `error` write to
+    messages: console::Term,
 }
 impl Term {
     pub fn new(config: Config) -> Self {
@@ -56,9 +59,17 @@ impl Term {
         }
 
         let term = console::Term::stdout();
+        let messages = console::Term::stdout();
         let style  = Style::new(&config.color);
 
-        Term { config, term, style }
+        Term { config, term, style, messages }
+    }
+
+    /// write the messages (`success`, `info`, `warn` and `error`) to the
+    /// standard error from now on, so the standard output only has what
+    /// is written with `simply` or `write!` (e.g. one JSON object per line)
+    pub fn messages_to_stderr(&mut self) {
+        self.messages = console::Term::stderr();
     }
 
     pub fn progress_bar(&self, count: u64) -> indicatif::ProgressBar {
@@ -66,7 +77,7 @@ impl Term {
         pb.enable_steady_tick(100);
         pb.set_style(
             indicatif::ProgressStyle::default_bar()
-                .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
+                .template("{spinner:.green} {msg}[{elapsed_precise}] [{bar:40.cyan/blue}] {pos}/{len} ({eta})")
                 .progress_chars("#>-")
         );
         pb
@@ -125,16 +136,16 @@ impl Term {
         write!(self, "{}", msg)
     }
     pub fn success(&mut self, msg: &str) -> io::Result<()> {
-        write!(&mut self.term, "{}", self.style.success.apply_to(msg))
+        write!(&mut self.messages, "{}", self.style.success.apply_to(msg))
     }
     pub fn info(&mut self, msg: &str) -> io::Result<()> {
-        write!(&mut self.term, "{}", self.style.info.apply_to(msg))
+        write!(&mut self.messages, "{}", self.style.info.apply_to(msg))
     }
     pub fn warn(&mut self, msg: &str) -> io::Result<()> {
-        write!(&mut self.term, "{}", self.style.warning.apply_to(msg))
+        write!(&mut self.messages, "{}", self.style.warning.apply_to(msg))
     }
     pub fn error(&mut self, msg: &str) -> io::Result<()> {
-        write!(&mut self.term, "{}", self.style.error.apply_to(msg))
+        write!(&mut self.messages, "{}", self.style.error.apply_to(msg))
     }
 }
 impl ::std::ops::Deref for Term {
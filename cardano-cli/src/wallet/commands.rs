@@ -1,10 +1,11 @@
-use super::config::{encrypt_primary_key, Config, HDWalletModel};
+use super::config::{self, encrypt_primary_key, decrypt_primary_key, Config, HDWalletModel};
+use super::export::{ExportedKeys};
 use super::{WalletName, Wallet, Wallets};
-use super::state::{lookup};
+use super::state::{lookup::{self, sequentialindex::Hardening}};
 use super::utils::{*};
 
-use std::{path::PathBuf, io::Write};
-use cardano::{hdwallet::{self, DerivationScheme}, wallet, bip::bip39};
+use std::{path::PathBuf, fs, io::Write};
+use cardano::{hdwallet::{self, DerivationScheme}, wallet, bip::{bip32::DerivationPath, bip39}, address::ExtendedAddr, coin::Coin};
 use rand::random;
 
 use utils::{term::{Term, style::{Style}}, prompt};
@@ -13,6 +14,7 @@ use blockchain::{self, Blockchain};
 
 pub fn list( mut term: Term
            , root_dir: PathBuf
+           , blockchains_dir: PathBuf
            , detailed: bool
            )
 {
@@ -20,7 +22,7 @@ pub fn list( mut term: Term
     for (_, wallet) in wallets {
         let detail = if detailed {
             if let Some(blk_name) = &wallet.config.attached_blockchain {
-                let state = create_wallet_state_from_logs(&mut term, &wallet, root_dir.clone(), lookup::accum::Accum::default());
+                let state = create_wallet_state_from_logs(&mut term, &wallet, blockchains_dir.clone(), lookup::accum::Accum::default());
 
                 let total = state.total().unwrap();
 
@@ -52,11 +54,7 @@ pub fn new<D>( mut term: Term
              )
     where D: bip39::dictionary::Language
 {
-    let config = Config {
-        attached_blockchain: None,
-        derivation_scheme: derivation_scheme,
-        hdwallet_model: wallet_scheme
-    };
+    let config = Config::new(derivation_scheme, wallet_scheme);
 
     // 1. generate the mnemonics
 
@@ -111,11 +109,7 @@ pub fn recover<D>( mut term: Term
                  )
     where D: bip39::dictionary::Language
 {
-    let config = Config {
-        attached_blockchain: None,
-        derivation_scheme: derivation_scheme,
-        hdwallet_model: wallet_scheme
-    };
+    let config = Config::new(derivation_scheme, wallet_scheme);
 
     // 1. generate the mnemonics
     term.info("enter your mnemonics\n").unwrap();
@@ -196,6 +190,7 @@ new transactions will be by recovering the wallet with the mnemonic words.",
 
 pub fn attach( mut term: Term
              , root_dir: PathBuf
+             , blockchains_dir: PathBuf
              , name: WalletName
              , blockchain_name: String
              )
@@ -210,13 +205,13 @@ pub fn attach( mut term: Term
     }
 
     // 2. check the blockchain exists
-    let blockchain_dir = blockchain::config::directory(root_dir.clone(), &blockchain_name);
+    let blockchain_dir = blockchain::config::directory(blockchains_dir.clone(), &blockchain_name);
     if let Err(err) = ::std::fs::read_dir(blockchain_dir) {
         term.error(&format!("Blockchain `{}' does not exists or you do not have user permissions\n", blockchain_name)).unwrap();
         term.error(&format!("   |-> {}\n", err)).unwrap();
         ::std::process::exit(2);
     }
-    let _ = Blockchain::load(root_dir, blockchain_name.clone());
+    let _ = Blockchain::load(blockchains_dir, blockchain_name.clone());
 
     // 3. save the attached wallet
     wallet.config.attached_blockchain = Some(blockchain_name);
@@ -227,6 +222,7 @@ pub fn attach( mut term: Term
 
 pub fn detach( mut term: Term
              , root_dir: PathBuf
+             , blockchains_dir: PathBuf
              , name: WalletName
              )
 {
@@ -236,7 +232,7 @@ pub fn detach( mut term: Term
     // 1. get the wallet's blockchain
     let _ = load_attached_blockchain(
         &mut term,
-        root_dir,
+        blockchains_dir,
         ::std::mem::replace(&mut wallet.config.attached_blockchain, None)
     );
 
@@ -248,15 +244,322 @@ pub fn detach( mut term: Term
     term.success("Wallet successfully attached to blockchain.\n").unwrap()
 }
 
+/// export the root private key of the wallet to the given file
+///
+/// the key is encrypted with a passphrase asked to the user, distinct
+/// from the wallet's spending password. There is no way to export the
+/// key unencrypted.
+pub fn export_keys( mut term: Term
+                  , root_dir: PathBuf
+                  , name: WalletName
+                  , output: PathBuf
+                  )
+{
+    // load the wallet
+    let wallet = Wallet::load(root_dir, name);
+
+    if output.exists() {
+        term.error(&format!("File `{}' already exists, refusing to overwrite it\n", output.display())).unwrap();
+        ::std::process::exit(1);
+    }
+
+    // 1. retrieve the private key
+    term.info("Enter the wallet password.\n").unwrap();
+    let password = term.password("wallet password: ").unwrap();
+    let xprv = match decrypt_primary_key(password.as_bytes(), &wallet.encrypted_key) {
+        Ok(xprv) => xprv,
+        Err(err) => {
+            term.error(&format!("Cannot retrieve the wallet private key: {:?}\n", err)).unwrap();
+            ::std::process::exit(1);
+        }
+    };
+
+    // 2. encrypt it with the export passphrase
+    term.info("Set the export passphrase. It will be needed to import the keys.\n").unwrap();
+    let export_password = term.new_password("export passphrase", "confirm export passphrase", "passphrase mismatch").unwrap();
+    if export_password.is_empty() {
+        term.error("The export passphrase cannot be empty\n").unwrap();
+        ::std::process::exit(1);
+    }
+    let exported = ExportedKeys::new(export_password.as_bytes(), &xprv, &wallet.config);
+
+    // 3. write the exported keys, without overwriting an existing file
+    let mut file = match fs::OpenOptions::new().write(true).create_new(true).open(&output) {
+        Ok(file) => file,
+        Err(err) => {
+            term.error(&format!("Cannot create `{}': {}\n", output.display(), err)).unwrap();
+            ::std::process::exit(1);
+        }
+    };
+    ::serde_yaml::to_writer(&mut file, &exported).unwrap();
+
+    term.success(&format!("wallet `{}' keys exported to `{}'.\n", &wallet.name, output.display())).unwrap();
+}
+
+/// create a new wallet from the keys exported with `export_keys`
+pub fn import_keys( mut term: Term
+                  , root_dir: PathBuf
+                  , name: WalletName
+                  , input: PathBuf
+                  )
+{
+    if config::directory(root_dir.clone(), &name.as_dirname()).exists() {
+        term.error(&format!("Wallet `{}' already exists\n", name)).unwrap();
+        ::std::process::exit(1);
+    }
+
+    // 1. read the exported keys
+    let exported : ExportedKeys = match fs::File::open(&input).map_err(|e| e.to_string())
+        .and_then(|file| ::serde_yaml::from_reader(file).map_err(|e| e.to_string()))
+    {
+        Ok(exported) => exported,
+        Err(err) => {
+            term.error(&format!("Cannot read the exported keys from `{}': {}\n", input.display(), err)).unwrap();
+            ::std::process::exit(1);
+        }
+    };
+
+    // 2. decrypt the private key
+    term.info("Enter the export passphrase.\n").unwrap();
+    let export_password = term.password("export passphrase: ").unwrap();
+    let xprv = match exported.decrypt(export_password.as_bytes()) {
+        Ok(xprv) => xprv,
+        Err(err) => {
+            term.error(&format!("Cannot retrieve the exported private key: {:?}\n", err)).unwrap();
+            ::std::process::exit(1);
+        }
+    };
+
+    let config = exported.config();
+
+    // create the root public key
+    let public_key = match config.hdwallet_model {
+        HDWalletModel::BIP44 => None,
+        HDWalletModel::RandomIndex2Levels => Some(xprv.public()),
+    };
+
+    // 3. encrypt the private key
+    term.info("Set a wallet password. This is for local usage only, allows you to protect your cached private key and prevent from creating non desired transactions.\n").unwrap();
+    let password = term.new_password("spending password", "confirm spending password", "password mismatch").unwrap();
+    let encrypted_xprv = encrypt_primary_key(password.as_bytes(), &xprv);
+
+    // 4. create the wallet
+    let wallet = Wallet::new(root_dir, name, config, encrypted_xprv, public_key);
+
+    // 5. save the wallet
+    wallet.save();
+
+    term.success(&format!("wallet `{}' successfully imported.\n", &wallet.name)).unwrap();
+}
+
+/// use the local blockchain of the given network (one of
+/// `blockchain::NETWORKS`) instead of the attached one, for this command
+/// only. The local blockchain is named after the network, it is created
+/// from the network's template if it does not exist yet.
+///
+/// The wallet's log only makes sense for the blockchain it has been
+/// synced with: the override is refused if the wallet has a log already.
+///
+/// Returns true if the wallet now uses another blockchain than the
+/// attached one, in which case nothing must be saved to the wallet.
+fn override_network( term: &mut Term
+                   , blockchains_dir: PathBuf
+                   , wallet: &mut Wallet
+                   , network: Option<String>
+                   ) -> bool
+{
+    let network = match network {
+        None => return false,
+        Some(network) => network,
+    };
+    if wallet.config.attached_blockchain.as_ref() == Some(&network) {
+        return false;
+    }
+
+    if wallet.has_log().unwrap() {
+        term.error(&format!("Wallet has been synced with blockchain `{}', it cannot be used with the network `{}'\n", wallet.config.attached_blockchain.as_ref().map(|s| s.as_str()).unwrap_or("?"), network)).unwrap();
+        ::std::process::exit(1);
+    }
+
+    let net_config = match blockchain::network_config(&network) {
+        None => {
+            term.error(&format!("Unknown network `{}'\n", network)).unwrap();
+            ::std::process::exit(1);
+        },
+        Some(net_config) => net_config,
+    };
+    let blockchain = if blockchain::config::directory(blockchains_dir.clone(), &network).exists() {
+        Blockchain::load(blockchains_dir, network.clone())
+    } else {
+        blockchain::config::check_writable(term, &blockchains_dir);
+        term.info(&format!("creating the local blockchain `{}'\n", network)).unwrap();
+        Blockchain::new(blockchains_dir, network.clone(), net_config.clone())
+    };
+    if blockchain.config.protocol_magic != net_config.protocol_magic || blockchain.config.genesis != net_config.genesis {
+        term.error(&format!("The local blockchain `{}' is not a blockchain of the network `{}'\n", blockchain.name, network)).unwrap();
+        ::std::process::exit(1);
+    }
+
+    wallet.config.attached_blockchain = Some(network);
+    true
+}
+
+/// print the wallet's configuration, as saved on the disk, in pretty JSON
+pub fn dump_config( mut term: Term
+                  , root_dir: PathBuf
+                  , name: WalletName
+                  )
+{
+    // load the wallet
+    let wallet = Wallet::load(root_dir, name);
+
+    writeln!(term, "{}", ::serde_json::to_string_pretty(&wallet.config).unwrap()).unwrap();
+}
+
+/// update one value of the wallet's configuration
+///
+/// only the values that can be safely changed are supported, and
+/// the values that would invalidate the wallet log cannot be changed
+/// once the wallet has been synced (detach the wallet first).
+pub fn set( mut term: Term
+          , root_dir: PathBuf
+          , blockchains_dir: PathBuf
+          , name: WalletName
+          , key: String
+          , value: String
+          )
+{
+    // load the wallet
+    let mut wallet = Wallet::load(root_dir.clone(), name);
+    let has_log = wallet.has_log().unwrap();
+
+    match key.as_str() {
+        "attached_blockchain" => {
+            if has_log && wallet.config.attached_blockchain.as_ref() != Some(&value) {
+                term.error(&format!("Wallet has been synced with blockchain `{}', detach it first\n", wallet.config.attached_blockchain.as_ref().unwrap())).unwrap();
+                ::std::process::exit(1);
+            }
+
+            let blockchain_dir = blockchain::config::directory(blockchains_dir.clone(), &value);
+            if let Err(err) = ::std::fs::read_dir(blockchain_dir) {
+                term.error(&format!("Blockchain `{}' does not exists or you do not have user permissions\n", value)).unwrap();
+                term.error(&format!("   |-> {}\n", err)).unwrap();
+                ::std::process::exit(2);
+            }
+            let _ = Blockchain::load(blockchains_dir, value.clone());
+
+            wallet.config.attached_blockchain = Some(value);
+        },
+        "derivation_scheme" => {
+            let derivation_scheme = match value.as_str() {
+                "v1" => DerivationScheme::V1,
+                "v2" => DerivationScheme::V2,
+                _ => {
+                    term.error(&format!("Invalid derivation scheme `{}', expected `v1' or `v2'\n", value)).unwrap();
+                    ::std::process::exit(1);
+                }
+            };
+            if has_log && wallet.config.derivation_scheme != derivation_scheme {
+                term.error("Wallet has already been synced, detach it first\n").unwrap();
+                ::std::process::exit(1);
+            }
+
+            wallet.config.derivation_scheme = derivation_scheme;
+        },
+        _ => {
+            term.error(&format!("Unknown or read-only configuration key `{}'\n", key)).unwrap();
+            term.error("   |-> supported keys: attached_blockchain, derivation_scheme\n").unwrap();
+            ::std::process::exit(1);
+        }
+    }
+
+    wallet.save();
+
+    term.success("Wallet configuration successfully updated.\n").unwrap()
+}
+
+/// the status of a wallet, as output in JSON
+#[derive(Serialize, Default)]
+struct WalletStatus {
+    present: bool,
+    blockchain: Option<String>,
+    protocol_magic: Option<u32>,
+    accounts: Option<usize>,
+    balance: Option<Coin>,
+    synced_block_hash: Option<String>,
+    synced_block_date: Option<String>,
+    synced_block_height: Option<u64>,
+    blockchain_storage_size: Option<u64>,
+}
+
+/// print a summary of the wallet and of the state it has been synced to
+///
+/// Everything is computed from the local files: no connection to the
+/// network is made.
 pub fn status( mut term: Term
              , root_dir: PathBuf
+             , blockchains_dir: PathBuf
              , name: WalletName
+             , network: Option<String>
+             , refresh: bool
+             , json: bool
              )
 {
+    if ! Wallet::exists(root_dir.clone(), &name) {
+        if json {
+            let status = WalletStatus::default();
+            term.simply(&format!("{}\n", ::serde_json::to_string_pretty(&status).unwrap())).unwrap();
+            return;
+        }
+        term.error(&format!("No wallet named `{}'\n", name.as_dirname())).unwrap();
+        ::std::process::exit(1);
+    }
+
     // load the wallet
-    let wallet = Wallet::load(root_dir.clone(), name);
+    let mut wallet = Wallet::load(root_dir.clone(), name);
+    override_network(&mut term, blockchains_dir.clone(), &mut wallet, network);
+
+    let mut status = WalletStatus { present: true, .. WalletStatus::default() };
+
+    let blockchain = match &wallet.config.attached_blockchain {
+        None => None,
+        Some(blk_name) => Some(Blockchain::load(blockchains_dir.clone(), blk_name.clone())),
+    };
+
+    status.accounts = match wallet.config.hdwallet_model {
+        HDWalletModel::BIP44 => Some(used_accounts(&wallet).len()),
+        HDWalletModel::RandomIndex2Levels => None,
+    };
+
+    if let Some(blockchain) = &blockchain {
+        status.blockchain = Some(blockchain.name.clone());
+        status.protocol_magic = Some(*blockchain.config.protocol_magic);
+        status.blockchain_storage_size = match blockchain.storage.disk_usage() {
+            Ok(size) => Some(size),
+            Err(err) => {
+                term.warn(&format!("cannot compute the blockchain storage size: {}\n", err)).unwrap();
+                None
+            }
+        };
+
+        let balance = wallet_balance(&wallet, refresh);
+        status.balance = Some(balance.balance);
+        if let Some(ptr) = balance.ptr {
+            let hash = ptr.latest_known_hash.clone();
+            status.synced_block_hash = Some(format!("{}", hash));
+            status.synced_block_date = Some(format!("{}", ptr.latest_block_date()));
+            status.synced_block_height = ::storage::block_read(&blockchain.storage, hash.bytes())
+                .and_then(|raw| raw.decode().ok())
+                .map(|block| block.get_header().difficulty().into());
+        }
+    }
+
+    if json {
+        term.simply(&format!("{}\n", ::serde_json::to_string_pretty(&status).unwrap())).unwrap();
+        return;
+    }
 
-    if let Some(ref blk_name) = &wallet.config.attached_blockchain {
+    if let Some(ref blk_name) = &status.blockchain {
         term.simply("Wallet ").unwrap();
         term.warn(&format!("{}", &wallet.name)).unwrap();
         term.simply(" on blockchain ").unwrap();
@@ -268,112 +571,543 @@ pub fn status( mut term: Term
         return;
     }
 
+    if let Some(protocol_magic) = status.protocol_magic {
+        term.simply(" * protocol magic ").unwrap();
+        term.warn(&format!("{}", protocol_magic)).unwrap();
+        term.simply("\n").unwrap();
+    }
+    if let Some(size) = status.blockchain_storage_size {
+        term.simply(" * blockchain storage size ").unwrap();
+        term.warn(&format!("{} bytes", size)).unwrap();
+        term.simply("\n").unwrap();
+    }
     term.simply(" * wallet model ").unwrap();
     term.warn(&format!("{:?}", &wallet.config.hdwallet_model)).unwrap();
     term.simply("\n").unwrap();
     term.simply(" * derivation scheme ").unwrap();
     term.warn(&format!("{:?}", &wallet.config.derivation_scheme)).unwrap();
     term.simply("\n").unwrap();
+    if let Some(accounts) = status.accounts {
+        term.simply(" * accounts in use ").unwrap();
+        term.warn(&format!("{}", accounts)).unwrap();
+        term.simply("\n").unwrap();
+    }
 
-    let state = create_wallet_state_from_logs(&mut term, &wallet, root_dir, lookup::accum::Accum::default());
-
-    let total = state.total().unwrap();
-
-    term.simply(" * balance ").unwrap();
-    term.success(&format!(" {}", total)).unwrap();
-    term.simply("\n").unwrap();
-    term.simply(" * synced to block ").unwrap();
-    term.warn(&format!(" {} ({})", state.ptr.latest_known_hash, state.ptr.latest_addr.unwrap())).unwrap();
+    if let Some(balance) = status.balance {
+        term.simply(" * balance ").unwrap();
+        term.success(&format!(" {}", balance)).unwrap();
+        term.simply("\n").unwrap();
+    }
+    match (&status.synced_block_hash, &status.synced_block_date) {
+        (Some(hash), Some(date)) => {
+            term.simply(" * synced to block ").unwrap();
+            term.warn(&format!(" {} ({})", hash, date)).unwrap();
+            if let Some(height) = status.synced_block_height {
+                term.simply(" at height ").unwrap();
+                term.warn(&format!("{}", height)).unwrap();
+            }
+        },
+        _ => {
+            term.warn(" * wallet not synced yet").unwrap();
+        }
+    }
     term.simply("\n").unwrap();
 }
 
 pub fn log( mut term: Term
           , root_dir: PathBuf
+          , blockchains_dir: PathBuf
           , name: WalletName
+          , network: Option<String>
           , pretty: bool
           )
 {
     // load the wallet
-    let wallet = Wallet::load(root_dir.clone(), name);
+    let mut wallet = Wallet::load(root_dir.clone(), name);
+    override_network(&mut term, blockchains_dir.clone(), &mut wallet, network);
 
-    let mut state = create_wallet_state_from_logs(&mut term, &wallet, root_dir, lookup::accum::Accum::default());
+    let mut state = create_wallet_state_from_logs(&mut term, &wallet, blockchains_dir, lookup::accum::Accum::default());
 
     display_wallet_state_logs(&mut term, &wallet, &mut state, pretty);
 }
 
+/// print the wallet's UTxOs, as of the last `sync`: only the ones of the
+/// given account if any, the largest first if `sort_by_amount` is set
 pub fn utxos( mut term: Term
             , root_dir: PathBuf
+            , blockchains_dir: PathBuf
             , name: WalletName
+            , network: Option<String>
+            , account: Option<u32>
+            , json: bool
+            , sort_by_amount: bool
             )
 {
     // load the wallet
-    let wallet = Wallet::load(root_dir.clone(), name);
+    let mut wallet = Wallet::load(root_dir.clone(), name);
+    override_network(&mut term, blockchains_dir.clone(), &mut wallet, network);
 
-    let state = create_wallet_state_from_logs(&mut term, &wallet, root_dir, lookup::accum::Accum::default());
+    let state = create_wallet_state_from_logs(&mut term, &wallet, blockchains_dir, lookup::accum::Accum::default());
 
-    display_wallet_state_utxos(&mut term, state);
+    let mut utxos : Vec<_> = state.utxos.into_iter().map(|(_, utxo)| utxo).filter(|utxo| {
+        match (account, &utxo.credited_addressing) {
+            (None, _) => true,
+            (Some(account), lookup::Address::Bip44(addressing)) => addressing.account.get_account_number() == account,
+            (Some(account), lookup::Address::RIndex(addressing)) => addressing.0 == account,
+            (Some(_), lookup::Address::Unknown(_)) => false,
+        }
+    }).collect();
+    if sort_by_amount {
+        utxos.sort_by(|a, b| b.credited_value.cmp(&a.credited_value));
+    }
+
+    display_wallet_utxos(&mut term, utxos, json);
 }
 
 pub fn sync( mut term: Term
            , root_dir: PathBuf
+           , blockchains_dir: PathBuf
            , name: WalletName
+           , network: Option<String>
+           , epochs: Option<u32>
            )
 
 {
     // 0. load the wallet
-    let wallet = Wallet::load(root_dir.clone(), name);
+    let mut wallet = Wallet::load(root_dir.clone(), name);
+    let overridden = override_network(&mut term, blockchains_dir.clone(), &mut wallet, network);
 
     // 1. get the wallet's blockchain
-    let blockchain = load_attached_blockchain(&mut term, root_dir.clone(), wallet.config.attached_blockchain.clone());
+    let blockchain = load_attached_blockchain(&mut term, blockchains_dir.clone(), wallet.config.attached_blockchain.clone());
 
     match wallet.config.hdwallet_model {
         HDWalletModel::BIP44 => {
             let mut lookup_struct = load_bip44_lookup_structure(&mut term, &wallet);
             lookup_struct.prepare_next_account().unwrap();
-            let mut state = create_wallet_state_from_logs(&mut term, &wallet, root_dir.clone(), lookup_struct);
+            let mut state = create_wallet_state_from_logs(&mut term, &wallet, blockchains_dir.clone(), lookup_struct);
+            if let Some(epochs) = epochs {
+                skip_to_recent_epochs(&mut term, &blockchain, &mut state, epochs);
+            }
 
-            update_wallet_state_with_utxos(&mut term, &wallet, &blockchain, &mut state);
+            update_wallet_state_with_utxos(&mut term, &wallet, &blockchain, &mut state, ! overridden);
         },
         HDWalletModel::RandomIndex2Levels => {
             let lookup_struct = load_randomindex_lookup_structure(&mut term, &wallet);
-            let mut state = create_wallet_state_from_logs(&mut term, &wallet, root_dir.clone(), lookup_struct);
+            let mut state = create_wallet_state_from_logs(&mut term, &wallet, blockchains_dir.clone(), lookup_struct);
+            if let Some(epochs) = epochs {
+                skip_to_recent_epochs(&mut term, &blockchain, &mut state, epochs);
+            }
 
-            update_wallet_state_with_utxos(&mut term, &wallet, &blockchain, &mut state);
+            update_wallet_state_with_utxos(&mut term, &wallet, &blockchain, &mut state, ! overridden);
         },
     };
 }
 
+
+/// check the wallet's UTxOs against the ones rebuilt from the blocks of
+/// the attached blockchain, to catch errors of the incremental `sync`
+pub fn audit_utxo( mut term: Term
+                 , root_dir: PathBuf
+                 , blockchains_dir: PathBuf
+                 , name: WalletName
+                 , network: Option<String>
+                 )
+{
+    // 0. load the wallet
+    let mut wallet = Wallet::load(root_dir.clone(), name);
+    override_network(&mut term, blockchains_dir.clone(), &mut wallet, network);
+
+    // 1. get the wallet's blockchain
+    let blockchain = load_attached_blockchain(&mut term, blockchains_dir.clone(), wallet.config.attached_blockchain.clone());
+
+    let discrepancies = match wallet.config.hdwallet_model {
+        HDWalletModel::BIP44 => {
+            let mut lookup_struct = load_bip44_lookup_structure(&mut term, &wallet);
+            lookup_struct.prepare_next_account().unwrap();
+            let state = create_wallet_state_from_logs(&mut term, &wallet, blockchains_dir, lookup_struct);
+            audit_wallet_state_utxos(&mut term, &blockchain, state)
+        },
+        HDWalletModel::RandomIndex2Levels => {
+            let lookup_struct = load_randomindex_lookup_structure(&mut term, &wallet);
+            let state = create_wallet_state_from_logs(&mut term, &wallet, blockchains_dir, lookup_struct);
+            audit_wallet_state_utxos(&mut term, &blockchain, state)
+        },
+    };
+
+    if discrepancies == 0 {
+        term.success("the wallet's UTxOs match the blockchain\n").unwrap();
+    } else {
+        term.error(&format!("{} discrepancies between the wallet's UTxOs and the blockchain\n", discrepancies)).unwrap();
+        ::std::process::exit(1);
+    }
+}
+
+/// an address created by `address`, as output in JSON
+#[derive(Serialize)]
+struct AddressEntry {
+    account: u32,
+    internal: bool,
+    index: u32,
+    address: ExtendedAddr,
+}
+
+/// print the addresses of the given account, from the given index
+///
+/// In a BIP44 wallet, the change addresses are the internal addresses
+/// of an account: the addresses receiving the remainder of the inputs
+/// spent in a transaction. If `is_internal` is set and no index is given,
+/// the addresses start from the next change address not used yet by the
+/// account.
+///
+/// With an `output` file, the addresses are written to it instead, asking
+/// for confirmation before overwriting an existing file unless `force`
+/// is set.
+///
+/// `hardened` lists the levels of a BIP44 derivation path to derive
+/// hardened (`account`, `change` or `index`), BIP44 requires the account
+/// to be one of them.
+///
+/// The derived addresses are cached alongside the wallet, they are not
+/// derived again (nor is the wallet password asked) when they are all
+/// cached already, unless `no_cache` is set.
 pub fn address( mut term: Term
               , root_dir: PathBuf
               , name: WalletName
               , account: u32
               , is_internal: bool
-              , index: u32
+              , index: Option<u32>
+              , count: u32
+              , output: Option<PathBuf>
+              , json: bool
+              , force: bool
+              , hardened: Option<Vec<String>>
+              , no_cache: bool
               )
 {
     // load the wallet
     let wallet = Wallet::load(root_dir.clone(), name);
 
-    let addr = match wallet.config.hdwallet_model {
-        HDWalletModel::BIP44 => {
-            let mut lookup_struct = load_bip44_lookup_structure(&mut term, &wallet);
-            let account = match ::cardano::bip::bip44::Account::new(account) {
-                Err(err) => panic!("{:#?}", err),
-                Ok(account) => account
-            };
-            let change = if is_internal { account.internal().unwrap() } else { account.external().unwrap() };
-            let addressing = match change.index(index) {
-                Err(err) => panic!("{:#?}", err),
+    let hardening = match (hardened, &wallet.config.hdwallet_model) {
+        (None, _) => Hardening::default(),
+        (Some(_), HDWalletModel::RandomIndex2Levels) => {
+            term.error("random index wallets always derive their addresses hardened, `--hardened' is for BIP44 wallets only\n").unwrap();
+            ::std::process::exit(1);
+        },
+        (Some(levels), HDWalletModel::BIP44) => {
+            if ! levels.iter().any(|level| level == "account") {
+                term.error("BIP44 requires the account level to be hardened, add `account' to the hardened levels\n").unwrap();
+                ::std::process::exit(1);
+            }
+            Hardening {
+                change: levels.iter().any(|level| level == "change"),
+                index: levels.iter().any(|level| level == "index"),
+            }
+        }
+    };
+    if hardening != Hardening::default() {
+        term.warn("addresses with hardened change or index levels are not standard BIP44, the wallet will not find the funds they receive\n").unwrap();
+    }
+
+    let index = match (index, &wallet.config.hdwallet_model) {
+        (Some(index), _) => index,
+        (None, HDWalletModel::BIP44) => {
+            let index = next_unused_index(&wallet, account, ::cardano::wallet::bip44::AddrType::Internal);
+            term.info(&format!("using the next unused change address index: {}\n", index)).unwrap();
+            index
+        },
+        (None, HDWalletModel::RandomIndex2Levels) => {
+            term.error("the address index is required, random index wallets have no change addresses\n").unwrap();
+            ::std::process::exit(1);
+        }
+    };
+    let indexes = match index.checked_add(count) {
+        Some(end) if end <= ::cardano::bip::bip44::BIP44_SOFT_UPPER_BOUND || wallet.config.hdwallet_model != HDWalletModel::BIP44 => index..end,
+        _ => {
+            term.error(&format!("cannot create {} addresses from index {}\n", count, index)).unwrap();
+            ::std::process::exit(1);
+        }
+    };
+
+    let mut cache = if no_cache { None } else { Some(wallet.cached_addresses()) };
+    let cached : Option<Vec<(u32, ExtendedAddr)>> = cache.as_ref().and_then(|cache| {
+        indexes.clone().map(|index| {
+            cache.get(account, is_internal, index, hardening).map(|addr| (index, addr.clone()))
+        }).collect()
+    });
+
+    let addrs : Vec<(u32, ExtendedAddr)> = match cached {
+        Some(addrs) => addrs,
+        None => match wallet.config.hdwallet_model {
+            HDWalletModel::BIP44 => {
+                let account = match ::cardano::bip::bip44::Account::new(account) {
+                    Err(err) => {
+                        term.error(&format!("invalid account {}: {}\n", account, err)).unwrap();
+                        let accounts : Vec<String> = used_accounts(&wallet).iter().map(|account| account.to_string()).collect();
+                        if accounts.is_empty() {
+                            term.info("none of the accounts of this wallet received funds yet\n").unwrap();
+                        } else {
+                            term.info(&format!("accounts of this wallet that received funds: {}\n", accounts.join(", "))).unwrap();
+                        }
+                        ::std::process::exit(1);
+                    },
+                    Ok(account) => account
+                };
+                let mut lookup_struct = load_bip44_lookup_structure(&mut term, &wallet);
+                let change = if is_internal { account.internal().unwrap() } else { account.external().unwrap() };
+                indexes.map(|index| {
+                    let addressing = match change.index(index) {
+                        Err(err) => panic!("{:#?}", err),
+                        Ok(addressing) => addressing
+                    };
+                    (index, lookup_struct.get_address_with_hardening(&addressing, hardening))
+                }).collect()
+            },
+            HDWalletModel::RandomIndex2Levels => {
+                let lookup_struct = load_randomindex_lookup_structure(&mut term, &wallet);
+                indexes.map(|index| {
+                    let addressing = ::cardano::wallet::rindex::Addressing(account, index);
+                    (index, lookup_struct.get_address(&addressing))
+                }).collect()
+            }
+        }
+    };
+    if let Some(cache) = cache.as_mut() {
+        if addrs.iter().any(|(index, _)| cache.get(account, is_internal, *index, hardening).is_none()) {
+            for (index, addr) in addrs.iter() {
+                cache.insert(account, is_internal, *index, hardening, addr.clone());
+            }
+            wallet.save_cached_addresses(cache);
+        }
+    }
+
+    let output = match output {
+        None => {
+            if json {
+                writeln!(term, "{}", address_entries_json(account, is_internal, addrs)).unwrap();
+            } else {
+                for (_, addr) in addrs {
+                    writeln!(term, "{}", style!(addr)).unwrap();
+                }
+            }
+            return;
+        },
+        Some(output) => output
+    };
+
+    if output.exists() && !force {
+        let confirmation = ::dialoguer::Confirmation::new(&format!("File `{}' already exists, overwrite it?", output.display()))
+            .use_line_input(true)
+            .clear(false)
+            .default(false)
+            .interact().unwrap();
+        if ! confirmation { ::std::process::exit(0); }
+    }
+
+    let written = addrs.len();
+    let result = fs::File::create(&output).and_then(|mut file| {
+        if json {
+            writeln!(file, "{}", address_entries_json(account, is_internal, addrs))
+        } else {
+            addrs.iter().map(|(_, addr)| writeln!(file, "{}", addr)).collect()
+        }
+    });
+    if let Err(err) = result {
+        term.error(&format!("Cannot write the addresses to `{}': {}\n", output.display(), err)).unwrap();
+        ::std::process::exit(1);
+    }
+
+    term.success(&format!("{} addresses written to `{}'.\n", written, output.display())).unwrap();
+}
+
+/// print the next external address of the given account to give out to
+/// receive funds, then advance to the following one
+///
+/// The address is the first one not used (as found in the wallet LOG)
+/// nor printed by a previous call. The wallet only finds the funds of the
+/// addresses within the gap limit after the last one used, so this fails
+/// once all of them have been printed without receiving funds: sync the
+/// wallet once one of them did.
+pub fn next_address( mut term: Term
+                   , root_dir: PathBuf
+                   , name: WalletName
+                   , account: u32
+                   )
+{
+    use self::lookup::sequentialindex::DEFAULT_GAP_LIMIT;
+    use cardano::wallet::bip44::AddrType;
+
+    // load the wallet
+    let wallet = Wallet::load(root_dir, name);
+    if wallet.config.hdwallet_model != HDWalletModel::BIP44 {
+        term.error("random index wallets have no sequential addresses, give the index to `wallet address' instead\n").unwrap();
+        ::std::process::exit(1);
+    }
+    let bip44_account = match ::cardano::bip::bip44::Account::new(account) {
+        Err(err) => {
+            term.error(&format!("invalid account {}: {}\n", account, err)).unwrap();
+            ::std::process::exit(1);
+        },
+        Ok(account) => account
+    };
+
+    let first_unused = next_unused_index(&wallet, account, AddrType::External);
+    let mut issued = wallet.issued_addresses();
+    let index = ::std::cmp::max(first_unused, issued.next_index(account));
+    if index >= first_unused.saturating_add(DEFAULT_GAP_LIMIT) {
+        term.error(&format!("the {} addresses from index {} were given out without receiving funds yet, the wallet would not find the funds of the next one\n", DEFAULT_GAP_LIMIT, first_unused)).unwrap();
+        term.info("sync the wallet once one of them received funds, or use `wallet address' to print a given address\n").unwrap();
+        ::std::process::exit(1);
+    }
+
+    let mut cache = wallet.cached_addresses();
+    let addr = match cache.get(account, false, index, Hardening::default()).cloned() {
+        Some(addr) => addr,
+        None => {
+            let lookup_struct = load_bip44_lookup_structure(&mut term, &wallet);
+            let addressing = match bip44_account.external().and_then(|change| change.index(index)) {
+                Err(err) => {
+                    term.error(&format!("cannot create the address {} of account {}: {}\n", index, account, err)).unwrap();
+                    ::std::process::exit(1);
+                },
                 Ok(addressing) => addressing
             };
-            lookup_struct.get_address(&addressing)
+            let addr = lookup_struct.get_address(&addressing);
+            cache.insert(account, false, index, Hardening::default(), addr.clone());
+            wallet.save_cached_addresses(&cache);
+            addr
+        }
+    };
+
+    term.info(&format!("address index {} of account {}\n", index, account)).unwrap();
+    writeln!(term, "{}", style!(addr)).unwrap();
+
+    issued.issue(account, index);
+    wallet.save_issued_addresses(&issued);
+}
+
+fn address_entries_json(account: u32, internal: bool, addrs: Vec<(u32, ExtendedAddr)>) -> String {
+    let entries : Vec<AddressEntry> = addrs.into_iter().map(|(index, address)| {
+        AddressEntry { account, internal, index, address }
+    }).collect();
+    ::serde_json::to_string_pretty(&entries).unwrap()
+}
+
+/// tell if the given address belongs to the wallet, printing its
+/// derivation path and the UTxOs of the wallet it holds if it does
+///
+/// The addresses of BIP44 wallets are searched the way `sync` does: up to
+/// the gap limit after the last address found in the wallet LOG. Random
+/// index wallets find their addresses from the derivation path they carry.
+/// The UTxOs are the ones of the wallet LOG, as of the last `sync`.
+pub fn scan_address( mut term: Term
+                   , root_dir: PathBuf
+                   , blockchains_dir: PathBuf
+                   , name: WalletName
+                   , network: Option<String>
+                   , address: ExtendedAddr
+                   )
+{
+    use self::lookup::sequentialindex::DEFAULT_GAP_LIMIT;
+    use cardano::bip::bip44::{BIP44_PURPOSE, BIP44_COIN_TYPE};
+
+    // load the wallet
+    let mut wallet = Wallet::load(root_dir.clone(), name);
+    override_network(&mut term, blockchains_dir.clone(), &mut wallet, network);
+
+    let (found, utxos) = match wallet.config.hdwallet_model {
+        HDWalletModel::BIP44 => {
+            let mut lookup_struct = load_bip44_lookup_structure(&mut term, &wallet);
+            lookup_struct.prepare_next_account().unwrap();
+            let state = create_wallet_state_from_logs(&mut term, &wallet, blockchains_dir, lookup_struct);
+            let found = state.lookup_struct.find(&address).map(|addressing| {
+                vec![BIP44_PURPOSE, BIP44_COIN_TYPE, addressing.account.get_scheme_value(), addressing.change, addressing.index.get_scheme_value()]
+            });
+            (found, state.utxos)
         },
         HDWalletModel::RandomIndex2Levels => {
             let lookup_struct = load_randomindex_lookup_structure(&mut term, &wallet);
-            let addressing = ::cardano::wallet::rindex::Addressing(account, index);
-            lookup_struct.get_address(&addressing)
+            let state = create_wallet_state_from_logs(&mut term, &wallet, blockchains_dir, lookup_struct);
+            let found = state.lookup_struct.find(&address).map(|addressing| vec![addressing.0, addressing.1]);
+            (found, state.utxos)
+        },
+    };
+
+    let path = match found.map(DerivationPath::new) {
+        Some(Ok(path)) => path,
+        Some(Err(err)) => panic!("{:#?}", err),
+        None => {
+            match wallet.config.hdwallet_model {
+                HDWalletModel::BIP44 => {
+                    term.error(&format!("the address is not one of the wallet's addresses within the gap limit ({} addresses after the last one that received funds)\n", DEFAULT_GAP_LIMIT)).unwrap();
+                    term.info("sync the wallet if one of its addresses received funds since the last sync\n").unwrap();
+                },
+                HDWalletModel::RandomIndex2Levels => {
+                    term.error("the address is not one of the wallet's addresses, its derivation path does not match the wallet key\n").unwrap();
+                },
+            }
+            ::std::process::exit(1);
+        }
+    };
+
+    term.success("the address belongs to the wallet\n").unwrap();
+    writeln!(term, "path: {}", style!(path.to_string()).cyan()).unwrap();
+
+    let utxos : Vec<_> = utxos.into_iter().map(|(_, utxo)| utxo).filter(|utxo| utxo.credited_address == address).collect();
+    if utxos.is_empty() {
+        term.info("the address holds no UTxO of the wallet, as of the last sync\n").unwrap();
+        return;
+    }
+    let mut total = Coin::zero();
+    for utxo in utxos {
+        writeln!(term, "{}.{} {}",
+            style!(utxo.transaction_id),
+            style!(utxo.index_in_transaction).yellow(),
+            style!(utxo.credited_value).green()
+        ).unwrap();
+        total = (total + utxo.credited_value).unwrap();
+    }
+    writeln!(term, "total: {}", style!(total).green()).unwrap();
+}
+
+/// derive the wallet's root key along an arbitrary BIP32 derivation path
+/// (e.g. `m/1852'/1815'/0'/0/0`) and print the derived private key, its
+/// public key and the matching address.
+///
+/// The address has no derivation path payload: random index wallets do
+/// not find the funds it receives when syncing.
+pub fn derive( mut term: Term
+             , root_dir: PathBuf
+             , name: WalletName
+             , path: String
+             )
+{
+    let path : DerivationPath = match path.parse() {
+        Ok(path) => path,
+        Err(err) => {
+            term.error(&format!("invalid derivation path `{}': {}\n", path, err)).unwrap();
+            ::std::process::exit(1);
+        }
+    };
+
+    // load the wallet
+    let wallet = Wallet::load(root_dir, name);
+
+    term.info("Enter the wallet password.\n").unwrap();
+    let password = term.password("wallet password: ").unwrap();
+    let root_xprv = match decrypt_primary_key(password.as_bytes(), &wallet.encrypted_key) {
+        Ok(xprv) => xprv,
+        Err(err) => {
+            term.error(&format!("Cannot retrieve the wallet private key: {:?}\n", err)).unwrap();
+            ::std::process::exit(1);
         }
     };
 
-    writeln!(term, "{}", style!(addr));
+    let xprv = path.derive(wallet.config.derivation_scheme, &root_xprv);
+    let xpub = xprv.public();
+    let address = ExtendedAddr::new_simple(xpub);
+
+    writeln!(term, "path:    {}", style!(path.to_string()).cyan()).unwrap();
+    writeln!(term, "xprv:    {}", style!(xprv)).unwrap();
+    writeln!(term, "xpub:    {}", style!(xpub)).unwrap();
+    writeln!(term, "address: {}", style!(address)).unwrap();
 }
@@ -1,5 +1,5 @@
 use std::{path::PathBuf};
-use cardano::{hdwallet::{self, DerivationScheme}};
+use cardano::{hdwallet::{self, DerivationScheme}, util::securemem};
 
 use super::Error;
 use super::Result;
@@ -36,10 +36,23 @@ pub enum HDWalletModel {
     RandomIndex2Levels
 }
 
+/// version of the configuration layout written by this version of the wallet
+///
+/// configurations saved before the `version` field was introduced do not
+/// have it and are considered to be of version `1`.
+pub const CONFIG_VERSION : u32 = 2;
+
+fn legacy_config_version() -> u32 { 1 }
+
 /// this is the wallet configuration and will be saved to the local disk
 ///
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
+    /// version of the layout of the configuration, used to migrate
+    /// configurations saved by older versions of the wallet.
+    #[serde(default = "legacy_config_version")]
+    pub version: u32,
+
     /// optional name of the local blockchain the wallet is attached to
     ///
     /// it is not necessary to have a blockchain attached to perform some operations
@@ -54,15 +67,37 @@ pub struct Config {
     ///
     pub hdwallet_model: HDWalletModel
 }
-impl Default for Config {
-    fn default() -> Self {
+impl Config {
+    pub fn new(derivation_scheme: DerivationScheme, hdwallet_model: HDWalletModel) -> Self {
         Config {
+            version: CONFIG_VERSION,
             attached_blockchain: None,
-            derivation_scheme: DerivationScheme::V2,
-            hdwallet_model: HDWalletModel::BIP44
+            derivation_scheme: derivation_scheme,
+            hdwallet_model: hdwallet_model
+        }
+    }
+
+    /// upgrade a configuration loaded from the disk to the current layout
+    ///
+    /// # Errors
+    ///
+    /// This function fails if the configuration has been saved by a newer
+    /// version of the wallet (a version we do not know how to read).
+    ///
+    pub fn migrate(mut self) -> Result<Self> {
+        match self.version {
+            // version 2 only introduced the `version` field
+            1 => { self.version = CONFIG_VERSION; Ok(self) },
+            CONFIG_VERSION => Ok(self),
+            v => Err(Error::UnsupportedConfigVersion(v))
         }
     }
 }
+impl Default for Config {
+    fn default() -> Self {
+        Config::new(DerivationScheme::V2, HDWalletModel::BIP44)
+    }
+}
 
 /// convenient function to encrypt a HDWallet XPrv with a password
 ///
@@ -80,12 +115,13 @@ pub fn encrypt_primary_key(password: &Password, xprv: &hdwallet::XPrv) -> Vec<u8
 /// * the encrypted value did not represent a HDWallet XPrv
 ///
 pub fn decrypt_primary_key(password: &Password, encrypted_key: &[u8]) -> Result<hdwallet::XPrv> {
-    let xprv_vec = match password_encrypted::decrypt(password, encrypted_key) {
+    let mut xprv_vec = match password_encrypted::decrypt(password, encrypted_key) {
         None        => return Err(Error::CannotRetrievePrivateKeyInvalidPassword),
         Some(bytes) => bytes
     };
 
     if xprv_vec.len() != hdwallet::XPRV_SIZE {
+        securemem::zero(&mut xprv_vec);
         return Err(
             Error::CannotRetrievePrivateKey(
                 hdwallet::Error::InvalidXPrvSize(xprv_vec.len())
@@ -95,6 +131,50 @@ pub fn decrypt_primary_key(password: &Password, encrypted_key: &[u8]) -> Result<
 
     let mut xprv_bytes = [0;hdwallet::XPRV_SIZE];
     xprv_bytes.copy_from_slice(&xprv_vec[..]);
+    securemem::zero(&mut xprv_vec);
+
+    // the key is copied into the `XPrv`, which zeroes it once dropped
+    let xprv = hdwallet::XPrv::from_bytes_verified(xprv_bytes);
+    securemem::zero(&mut xprv_bytes);
+    Ok(xprv?)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_yaml;
+
+    const CONFIG_V1 : &'static str = "---\nattached_blockchain: mainnet\nderivation_scheme: V1\nhdwallet_model: RandomIndex2Levels\n";
+
+    #[test]
+    fn load_config_v1() {
+        let cfg : Config = serde_yaml::from_str(CONFIG_V1).unwrap();
+        assert_eq!(cfg.version, 1);
 
-    Ok(hdwallet::XPrv::from_bytes_verified(xprv_bytes)?)
+        let cfg = cfg.migrate().unwrap();
+        assert_eq!(cfg.version, CONFIG_VERSION);
+        assert_eq!(cfg.attached_blockchain, Some("mainnet".to_owned()));
+        assert_eq!(cfg.derivation_scheme, DerivationScheme::V1);
+        assert_eq!(cfg.hdwallet_model, HDWalletModel::RandomIndex2Levels);
+    }
+
+    #[test]
+    fn config_roundtrip() {
+        let cfg = Config::new(DerivationScheme::V2, HDWalletModel::BIP44);
+        let yaml = serde_yaml::to_string(&cfg).unwrap();
+        let decoded : Config = serde_yaml::from_str(&yaml).unwrap();
+        let decoded = decoded.migrate().unwrap();
+
+        assert_eq!(decoded.version, CONFIG_VERSION);
+        assert_eq!(decoded.attached_blockchain, cfg.attached_blockchain);
+        assert_eq!(decoded.derivation_scheme, cfg.derivation_scheme);
+        assert_eq!(decoded.hdwallet_model, cfg.hdwallet_model);
+    }
+
+    #[test]
+    fn unsupported_config_version() {
+        let mut cfg = Config::default();
+        cfg.version = CONFIG_VERSION + 1;
+        assert!(cfg.migrate().is_err());
+    }
 }
@@ -1,4 +1,4 @@
-use cardano::hdwallet;
+use cardano::{hdwallet, util::hex};
 use storage::utils::lock;
 
 use super::state::log;
@@ -10,11 +10,17 @@ pub enum Error {
     CannotRetrievePrivateKey(hdwallet::Error),
     WalletLogAlreadyLocked(u32),
     WalletLogNotFound,
-    WalletLogError(log::Error)
+    WalletLogError(log::Error),
+    UnsupportedConfigVersion(u32),
+    UnsupportedExportVersion(u32),
+    InvalidExportedKey(hex::Error)
 }
 impl From<hdwallet::Error> for Error {
     fn from(e: hdwallet::Error) -> Self { Error::CannotRetrievePrivateKey(e) }
 }
+impl From<hex::Error> for Error {
+    fn from(e: hex::Error) -> Self { Error::InvalidExportedKey(e) }
+}
 impl From<log::Error> for Error {
     fn from(e: log::Error) -> Self {
         match e {
@@ -0,0 +1,104 @@
+//! wallet keys export format
+//!
+//! The root private key of a wallet is exported encrypted with a
+//! passphrase chosen at export time (PBKDF2 derived key, ChaCha20Poly1305,
+//! see `utils::password_encrypted`). The key is never exported in plaintext.
+
+use cardano::{hdwallet::{self, DerivationScheme}, util::hex};
+
+use super::config::{encrypt_primary_key, decrypt_primary_key, Config, HDWalletModel};
+use super::Error;
+use super::Result;
+use super::super::utils::password_encrypted::{Password};
+
+/// version of the export layout written by this version of the wallet
+pub const EXPORT_VERSION : u32 = 1;
+
+/// the exported keys of a wallet, and what is needed to re-create
+/// the wallet from them
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedKeys {
+    pub version: u32,
+
+    pub derivation_scheme: DerivationScheme,
+
+    pub hdwallet_model: HDWalletModel,
+
+    /// the root private key, encrypted with the export passphrase
+    /// and hexadecimal encoded.
+    pub encrypted_key: String
+}
+impl ExportedKeys {
+    pub fn new(password: &Password, xprv: &hdwallet::XPrv, config: &Config) -> Self {
+        ExportedKeys {
+            version: EXPORT_VERSION,
+            derivation_scheme: config.derivation_scheme,
+            hdwallet_model: config.hdwallet_model,
+            encrypted_key: hex::encode(&encrypt_primary_key(password, xprv))
+        }
+    }
+
+    /// the configuration of the wallet to create from the exported keys
+    pub fn config(&self) -> Config {
+        Config::new(self.derivation_scheme, self.hdwallet_model)
+    }
+
+    /// retrieve the root private key with the export passphrase
+    ///
+    /// # Errors
+    ///
+    /// This function may fail if:
+    ///
+    /// * the keys have been exported by a newer version of the wallet;
+    /// * the encrypted key is not valid hexadecimal;
+    /// * the passphrase is invalid.
+    ///
+    pub fn decrypt(&self, password: &Password) -> Result<hdwallet::XPrv> {
+        if self.version != EXPORT_VERSION {
+            return Err(Error::UnsupportedExportVersion(self.version));
+        }
+        let encrypted_key = hex::decode(&self.encrypted_key)?;
+        decrypt_primary_key(password, &encrypted_key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde_yaml;
+
+    const PASSWORD : &'static [u8] = b"export password";
+
+    fn xprv() -> hdwallet::XPrv {
+        hdwallet::XPrv::normalize_bytes([7;hdwallet::XPRV_SIZE])
+    }
+
+    #[test]
+    fn export_import() {
+        let config = Config::new(DerivationScheme::V1, HDWalletModel::RandomIndex2Levels);
+        let exported = ExportedKeys::new(PASSWORD, &xprv(), &config);
+
+        let yaml = serde_yaml::to_string(&exported).unwrap();
+        assert!(!yaml.contains(&hex::encode(xprv().as_ref())));
+
+        let imported : ExportedKeys = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(imported.decrypt(PASSWORD).unwrap(), xprv());
+        assert_eq!(imported.config().derivation_scheme, DerivationScheme::V1);
+        assert_eq!(imported.config().hdwallet_model, HDWalletModel::RandomIndex2Levels);
+
+        match imported.decrypt(b"wrong password") {
+            Err(Error::CannotRetrievePrivateKeyInvalidPassword) => {},
+            r => panic!("expected an invalid password error, got {:?}", r)
+        }
+    }
+
+    #[test]
+    fn unsupported_export_version() {
+        let mut exported = ExportedKeys::new(PASSWORD, &xprv(), &Config::default());
+        exported.version = EXPORT_VERSION + 1;
+        match exported.decrypt(PASSWORD) {
+            Err(Error::UnsupportedExportVersion(v)) => assert_eq!(v, EXPORT_VERSION + 1),
+            r => panic!("expected an unsupported version error, got {:?}", r)
+        }
+    }
+}
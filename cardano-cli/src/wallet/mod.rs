@@ -1,6 +1,7 @@
 mod config;
 pub mod commands;
 mod error;
+pub mod export;
 mod result;
 pub mod state;
 pub mod utils;
@@ -11,10 +12,12 @@ pub use self::config::{HDWalletModel, Config};
 
 use self::config::{decrypt_primary_key};
 
-use self::state::log::{LogLock, LogWriter};
+use self::state::log::{self, LogLock, LogReader, LogWriter};
+use self::state::balance::{BalanceCache};
+use self::state::addresses::{AddressCache, IssuedAddresses};
 
 use std::{fmt, path::PathBuf, fs, io::{Read, Write}, collections::{BTreeMap}};
-use cardano::{wallet, hdwallet::{XPub, XPUB_SIZE}};
+use cardano::{wallet, hash::Blake2b256, hdwallet::{XPub, XPUB_SIZE}};
 use storage::utils::{tmpfile::{TmpFile}};
 use serde_yaml;
 
@@ -23,6 +26,9 @@ use utils::password_encrypted::{Password};
 static WALLET_CONFIG_FILE : &'static str = "config.yml";
 static WALLET_PRIMARY_KEY : &'static str = "wallet.key";
 static WALLET_PUBLIC_KEY  : &'static str = "wallet.pub";
+static WALLET_BALANCE_CACHE : &'static str = "balance.yml";
+static WALLET_ADDRESS_CACHE : &'static str = "addresses.yml";
+static WALLET_ISSUED_ADDRESSES : &'static str = "issued-addresses.yml";
 
 /// User friendly name associated with a Wallet.
 ///
@@ -134,12 +140,20 @@ impl Wallet {
         };
     }
 
+    /// tell if a wallet of the given name has been created
+    pub fn exists(root_dir: PathBuf, name: &WalletName) -> bool {
+        config::directory(root_dir, &name.as_dirname())
+            .join(WALLET_CONFIG_FILE)
+            .is_file()
+    }
+
     pub fn load(root_dir: PathBuf, name: WalletName) -> Self {
         let dir = config::directory(root_dir.clone(), &name.as_dirname());
 
         let mut file = fs::File::open(&dir.join(WALLET_CONFIG_FILE))
             .unwrap();
-        let cfg = serde_yaml::from_reader(&mut file).unwrap();
+        let cfg : Config = serde_yaml::from_reader(&mut file).unwrap();
+        let cfg = cfg.migrate().unwrap();
 
         let mut file = fs::File::open(&dir.join(WALLET_PRIMARY_KEY))
             .unwrap();
@@ -167,12 +181,138 @@ impl Wallet {
         Ok(writer.release_lock())
     }
 
+    /// tell if the wallet has a log, i.e. if it has already been synced
+    /// with a blockchain
+    pub fn has_log(&self) -> Result<bool> {
+        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        let lock = LogLock::acquire_wallet_log_lock(dir)?;
+
+        match LogReader::open(lock) {
+            Ok(_) => Ok(true),
+            Err(log::Error::LogNotFound) => Ok(false),
+            Err(err) => Err(err.into())
+        }
+    }
+
     pub fn delete_log(&self) -> ::std::io::Result<()> {
+        // the cached balance was computed from the LOG
+        self.delete_cached_balance()?;
+
         let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
         let lock = LogLock::acquire_wallet_log_lock(dir.clone()).unwrap();
         lock.delete_wallet_log_lock(dir)
     }
 
+    /// the last computed balance of the wallet, if any
+    ///
+    /// see `utils::wallet_balance` to bring it up to date with the LOG.
+    pub fn cached_balance(&self) -> Option<BalanceCache> {
+        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        let mut file = fs::File::open(&dir.join(WALLET_BALANCE_CACHE)).ok()?;
+        match serde_yaml::from_reader(&mut file) {
+            Err(err) => {
+                warn!("ignoring invalid balance cache of wallet `{}': {}", self.name, err);
+                None
+            },
+            Ok(cache) => Some(cache)
+        }
+    }
+
+    pub fn save_cached_balance(&self, cache: &BalanceCache) {
+        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        let mut tmpfile = TmpFile::create(dir.clone())
+            .unwrap();
+        serde_yaml::to_writer(&mut tmpfile, cache)
+            .unwrap();
+        tmpfile.render_permanent(&dir.join(WALLET_BALANCE_CACHE))
+            .unwrap();
+    }
+
+    /// identifies the key the addresses of the wallet are derived from:
+    /// its root public key if known, a digest of its encrypted root key
+    /// otherwise (BIP44 wallets do not store their public key)
+    pub fn address_cache_key(&self) -> String {
+        match &self.public_key {
+            Some(xpub) => format!("xpub:{}", xpub),
+            None => format!("encrypted-key:{}", Blake2b256::new(&self.encrypted_key)),
+        }
+    }
+
+    /// the addresses previously derived from the wallet key, an empty
+    /// cache if there are none or if the wallet changed since
+    pub fn cached_addresses(&self) -> AddressCache {
+        let key = self.address_cache_key();
+        let empty = AddressCache::new(key.clone(), self.config.derivation_scheme, self.config.hdwallet_model);
+        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        let mut file = match fs::File::open(&dir.join(WALLET_ADDRESS_CACHE)) {
+            Err(_) => return empty,
+            Ok(file) => file,
+        };
+        match serde_yaml::from_reader::<_, AddressCache>(&mut file) {
+            Err(err) => {
+                warn!("ignoring invalid address cache of wallet `{}': {}", self.name, err);
+                empty
+            },
+            Ok(ref cache) if ! cache.is_valid_for(&key, self.config.derivation_scheme, self.config.hdwallet_model) => {
+                info!("ignoring the address cache of wallet `{}', derived from another key", self.name);
+                empty
+            },
+            Ok(cache) => cache
+        }
+    }
+
+    pub fn save_cached_addresses(&self, cache: &AddressCache) {
+        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        let mut tmpfile = TmpFile::create(dir.clone())
+            .unwrap();
+        serde_yaml::to_writer(&mut tmpfile, cache)
+            .unwrap();
+        tmpfile.render_permanent(&dir.join(WALLET_ADDRESS_CACHE))
+            .unwrap();
+    }
+
+    /// the external addresses printed by `wallet next-address` already,
+    /// whether or not they received funds since. Empty if there are none
+    /// or if the wallet changed since.
+    pub fn issued_addresses(&self) -> IssuedAddresses {
+        let key = self.address_cache_key();
+        let empty = IssuedAddresses::new(key.clone());
+        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        let mut file = match fs::File::open(&dir.join(WALLET_ISSUED_ADDRESSES)) {
+            Err(_) => return empty,
+            Ok(file) => file,
+        };
+        match serde_yaml::from_reader::<_, IssuedAddresses>(&mut file) {
+            Err(err) => {
+                warn!("ignoring invalid issued addresses of wallet `{}': {}", self.name, err);
+                empty
+            },
+            Ok(ref issued) if ! issued.is_valid_for(&key) => {
+                info!("ignoring the issued addresses of wallet `{}', derived from another key", self.name);
+                empty
+            },
+            Ok(issued) => issued
+        }
+    }
+
+    pub fn save_issued_addresses(&self, issued: &IssuedAddresses) {
+        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        let mut tmpfile = TmpFile::create(dir.clone())
+            .unwrap();
+        serde_yaml::to_writer(&mut tmpfile, issued)
+            .unwrap();
+        tmpfile.render_permanent(&dir.join(WALLET_ISSUED_ADDRESSES))
+            .unwrap();
+    }
+
+    pub fn delete_cached_balance(&self) -> ::std::io::Result<()> {
+        let dir = config::directory(self.root_dir.clone(), &self.name.as_dirname());
+        match fs::remove_file(dir.join(WALLET_BALANCE_CACHE)) {
+            Err(ref err) if err.kind() == ::std::io::ErrorKind::NotFound => Ok(()),
+            r => r
+        }
+    }
+
     /// convenient function to reconstruct a BIP44 wallet from the encrypted key and password
     ///
     /// # Error
@@ -251,3 +391,45 @@ impl IntoIterator for Wallets {
     type IntoIter = <BTreeMap<WalletName, Wallet> as IntoIterator>::IntoIter;
     fn into_iter(self) -> Self::IntoIter { self.0.into_iter() }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::hdwallet::DerivationScheme;
+    use std::env;
+
+    fn test_wallet(root_dir: &PathBuf, key: u8) -> Wallet {
+        let name = WalletName::new("issued".to_owned()).unwrap();
+        let config = Config::new(DerivationScheme::V2, HDWalletModel::BIP44);
+        Wallet::new(root_dir.clone(), name, config, vec![key; 32], None)
+    }
+
+    #[test]
+    fn issued_addresses_persist_and_increment() {
+        let root_dir = env::temp_dir().join(format!("cardano-cli-issued-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&root_dir);
+        let wallet = test_wallet(&root_dir, 1);
+        wallet.save();
+
+        assert_eq!(wallet.issued_addresses().next_index(0), 0);
+        for index in 0..3 {
+            let mut issued = wallet.issued_addresses();
+            assert_eq!(issued.next_index(0), index);
+            issued.issue(0, issued.next_index(0));
+            wallet.save_issued_addresses(&issued);
+        }
+        let issued = wallet.issued_addresses();
+        assert_eq!(issued.next_index(0), 3);
+        assert_eq!(issued.next_index(1), 0);
+
+        // not the addresses of another wallet key
+        assert_eq!(test_wallet(&root_dir, 2).issued_addresses().next_index(0), 0);
+
+        // a corrupt file is ignored
+        let file = config::directory(root_dir.clone(), "issued").join(WALLET_ISSUED_ADDRESSES);
+        fs::write(&file, b"{ not yaml").unwrap();
+        assert_eq!(wallet.issued_addresses().next_index(0), 0);
+
+        fs::remove_dir_all(&root_dir).unwrap();
+    }
+}
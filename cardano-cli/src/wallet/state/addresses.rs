@@ -0,0 +1,138 @@
+//! cache of the addresses derived by `wallet address`
+//!
+//! Deriving an address requires the wallet password and, for the BIP44
+//! wallets, to derive the account first. The derived addresses are kept
+//! alongside the wallet with the key they were derived from, so they are
+//! served again as long as the wallet key did not change.
+
+use std::collections::BTreeMap;
+use cardano::{address::ExtendedAddr, hdwallet::DerivationScheme};
+
+use super::lookup::sequentialindex::Hardening;
+use super::super::config::HDWalletModel;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddressCache {
+    /// identifies the wallet key the addresses are derived from, see
+    /// `Wallet::address_cache_key`
+    pub wallet_key: String,
+    pub derivation_scheme: DerivationScheme,
+    pub hdwallet_model: HDWalletModel,
+
+    /// the addresses, by their derivation path below the root key
+    addresses: BTreeMap<String, ExtendedAddr>,
+}
+impl AddressCache {
+    pub fn new(wallet_key: String, derivation_scheme: DerivationScheme, hdwallet_model: HDWalletModel) -> Self {
+        AddressCache { wallet_key, derivation_scheme, hdwallet_model, addresses: BTreeMap::new() }
+    }
+
+    /// tell if the addresses were derived from the given wallet key and
+    /// with the given scheme and model: the cache is invalid otherwise
+    pub fn is_valid_for(&self, wallet_key: &str, derivation_scheme: DerivationScheme, hdwallet_model: HDWalletModel) -> bool {
+        self.wallet_key == wallet_key
+            && self.derivation_scheme == derivation_scheme
+            && self.hdwallet_model == hdwallet_model
+    }
+
+    // the derivation path of the address below the root key, e.g.
+    // `0'/1/5` for the index 5 of the internal chain of the account 0
+    fn path(account: u32, internal: bool, index: u32, hardening: Hardening) -> String {
+        let hardened = |hardened: bool| if hardened { "'" } else { "" };
+        format!("{}'/{}{}/{}{}", account, if internal { 1 } else { 0 }, hardened(hardening.change), index, hardened(hardening.index))
+    }
+
+    pub fn get(&self, account: u32, internal: bool, index: u32, hardening: Hardening) -> Option<&ExtendedAddr> {
+        self.addresses.get(&Self::path(account, internal, index, hardening))
+    }
+
+    pub fn insert(&mut self, account: u32, internal: bool, index: u32, hardening: Hardening, address: ExtendedAddr) {
+        self.addresses.insert(Self::path(account, internal, index, hardening), address);
+    }
+
+    /// number of cached addresses
+    pub fn len(&self) -> usize { self.addresses.len() }
+
+    pub fn is_empty(&self) -> bool { self.addresses.is_empty() }
+}
+
+/// the external addresses handed out by `wallet next-address`, by account
+///
+/// kept with the key the addresses are derived from, like `AddressCache`:
+/// the indices of another wallet key are not meaningful.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IssuedAddresses {
+    /// see `Wallet::address_cache_key`
+    pub wallet_key: String,
+
+    /// the index of the next address to hand out, by account
+    next: BTreeMap<u32, u32>,
+}
+impl IssuedAddresses {
+    pub fn new(wallet_key: String) -> Self {
+        IssuedAddresses { wallet_key, next: BTreeMap::new() }
+    }
+
+    pub fn is_valid_for(&self, wallet_key: &str) -> bool { self.wallet_key == wallet_key }
+
+    /// the index following the last address handed out for the account,
+    /// `0` if none was
+    pub fn next_index(&self, account: u32) -> u32 {
+        self.next.get(&account).cloned().unwrap_or(0)
+    }
+
+    /// record that the address of the given index was handed out
+    pub fn issue(&mut self, account: u32, index: u32) {
+        let next = ::std::cmp::max(self.next_index(account), index + 1);
+        self.next.insert(account, next);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::lookup::sequentialindex::SequentialBip44Lookup;
+    use cardano::{hdwallet, wallet::bip44::{self, Wallet}};
+    use serde_yaml;
+
+    fn lookup() -> SequentialBip44Lookup {
+        let xprv = hdwallet::XPrv::normalize_bytes([5;hdwallet::XPRV_SIZE]);
+        SequentialBip44Lookup::new(Wallet::from_root_key(xprv, DerivationScheme::V2))
+    }
+
+    #[test]
+    fn cache_hits_match_the_derived_addresses() {
+        let lookup = lookup();
+        let hardenings = [Hardening::default(), Hardening { change: true, index: false }, Hardening { change: true, index: true }];
+        let derive = |internal: bool, index: u32, hardening: Hardening| {
+            let addr_type = if internal { bip44::AddrType::Internal } else { bip44::AddrType::External };
+            lookup.get_address_with_hardening(&bip44::Addressing::new(0, addr_type, index).unwrap(), hardening)
+        };
+
+        let mut cache = AddressCache::new("key".to_owned(), DerivationScheme::V2, HDWalletModel::BIP44);
+        for hardening in hardenings.iter() {
+            for index in 0..5 {
+                cache.insert(0, false, index, *hardening, derive(false, index, *hardening));
+                cache.insert(0, true, index, *hardening, derive(true, index, *hardening));
+            }
+        }
+        assert_eq!(cache.len(), 30);
+
+        // the cache survives being saved and loaded
+        let cache : AddressCache = serde_yaml::from_str(&serde_yaml::to_string(&cache).unwrap()).unwrap();
+        for hardening in hardenings.iter() {
+            for index in 0..5 {
+                assert_eq!(cache.get(0, false, index, *hardening), Some(&derive(false, index, *hardening)));
+                assert_eq!(cache.get(0, true, index, *hardening), Some(&derive(true, index, *hardening)));
+            }
+        }
+        assert_eq!(cache.get(1, false, 0, Hardening::default()), None);
+        assert_eq!(cache.get(0, false, 5, Hardening::default()), None);
+        assert_eq!(cache.get(0, false, 0, Hardening { change: false, index: true }), None);
+
+        assert!(cache.is_valid_for("key", DerivationScheme::V2, HDWalletModel::BIP44));
+        assert!(! cache.is_valid_for("other key", DerivationScheme::V2, HDWalletModel::BIP44));
+        assert!(! cache.is_valid_for("key", DerivationScheme::V1, HDWalletModel::BIP44));
+        assert!(! cache.is_valid_for("key", DerivationScheme::V2, HDWalletModel::RandomIndex2Levels));
+    }
+}
@@ -0,0 +1,130 @@
+//! cache of the balance of a wallet
+//!
+//! Computing the balance requires to replay the whole wallet LOG. The
+//! last computed balance is kept alongside the number of LOG entries
+//! it accounts for, so only the entries appended since need to be
+//! replayed.
+
+use cardano::coin::{self, Coin};
+
+use super::{log::Log, ptr::StatePtr};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BalanceCache {
+    /// the balance of the wallet after the first `log_entries` of the LOG
+    pub balance: Coin,
+
+    /// the pointer of the last accounted LOG entry, `None` if the
+    /// wallet has not been synced yet
+    pub ptr: Option<StatePtr>,
+
+    /// number of LOG entries accounted for in the balance
+    pub log_entries: usize,
+}
+impl BalanceCache {
+    /// the balance of a wallet with an empty LOG
+    pub fn new() -> Self {
+        BalanceCache { balance: Coin::zero(), ptr: None, log_entries: 0 }
+    }
+
+    /// account the LOG entries that were appended since the cache was
+    /// computed.
+    ///
+    /// The given iterator is expected to start from the beginning of the
+    /// LOG. This returns `None` if the LOG does not start with the entries
+    /// the cache was computed from (the LOG has been deleted and the wallet
+    /// synced again for example): the cache is invalid and the balance need
+    /// to be computed again from a new `BalanceCache`.
+    pub fn update<A, I>(mut self, logs: I) -> coin::Result<Option<Self>>
+        where I: IntoIterator<Item = Log<A>>
+    {
+        let known_entries = self.log_entries;
+        let mut index = 0;
+        for log in logs {
+            index += 1;
+            if index < known_entries { continue; }
+            if index == known_entries {
+                let matches = match &self.ptr {
+                    None => false,
+                    Some(ptr) => log.ptr().latest_known_hash == ptr.latest_known_hash
+                };
+                if ! matches { return Ok(None) }
+                continue;
+            }
+
+            self.balance = match &log {
+                Log::Checkpoint(_) => self.balance,
+                Log::ReceivedFund(_, utxo) => (self.balance + utxo.credited_value)?,
+                Log::SpentFund(_, utxo) => (self.balance - utxo.credited_value)?,
+            };
+            self.ptr = Some(log.ptr().clone());
+            self.log_entries = index;
+        }
+
+        if index < known_entries { return Ok(None) }
+        Ok(Some(self))
+    }
+}
+impl Default for BalanceCache {
+    fn default() -> Self { BalanceCache::new() }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::utxo::UTxO;
+    use cardano::{address::ExtendedAddr, block::{BlockDate, EpochSlotId, HeaderHash}, hdwallet, tx::TxId};
+
+    fn ptr(slotid: u16, hash: u8) -> StatePtr {
+        StatePtr::new(
+            BlockDate::Normal(EpochSlotId { epoch: 0, slotid }),
+            HeaderHash::from_slice(&[hash;32]).unwrap()
+        )
+    }
+
+    fn utxo(index: u32, value: u64) -> UTxO<()> {
+        UTxO {
+            transaction_id: TxId::new(&[index as u8]),
+            index_in_transaction: index,
+            credited_address: ExtendedAddr::new_simple(hdwallet::XPrv::normalize_bytes([7;hdwallet::XPRV_SIZE]).public()),
+            credited_addressing: (),
+            credited_value: Coin::new(value).unwrap(),
+        }
+    }
+
+    fn logs() -> Vec<Log<()>> {
+        vec![ Log::ReceivedFund(ptr(1, 1), utxo(0, 1000))
+            , Log::ReceivedFund(ptr(2, 2), utxo(1, 500))
+            , Log::Checkpoint(ptr(3, 3))
+            , Log::SpentFund(ptr(4, 4), utxo(0, 1000))
+            ]
+    }
+
+    #[test]
+    fn update_incrementally() {
+        let cache = BalanceCache::new().update(logs().into_iter().take(2)).unwrap().unwrap();
+        assert_eq!(cache.balance, Coin::new(1500).unwrap());
+        assert_eq!(cache.log_entries, 2);
+
+        let cache = cache.update(logs()).unwrap().unwrap();
+        assert_eq!(cache.balance, Coin::new(500).unwrap());
+        assert_eq!(cache.log_entries, 4);
+        assert_eq!(cache.ptr.unwrap().latest_known_hash, ptr(4, 4).latest_known_hash);
+    }
+
+    #[test]
+    fn invalid_after_rollback() {
+        let cache = BalanceCache::new().update(logs()).unwrap().unwrap();
+
+        // the LOG has been deleted
+        assert!(cache.clone().update(Vec::<Log<()>>::new()).unwrap().is_none());
+
+        // the LOG has been re-created with different entries
+        let other = vec![ Log::ReceivedFund(ptr(1, 1), utxo(0, 1000))
+                        , Log::ReceivedFund(ptr(2, 2), utxo(1, 500))
+                        , Log::Checkpoint(ptr(3, 3))
+                        , Log::Checkpoint(ptr(5, 5))
+                        ];
+        assert!(cache.update(other).unwrap().is_none());
+    }
+}
@@ -29,6 +29,17 @@ impl RandomIndexLookup {
     pub fn get_address(&self, addr: &rindex::Addressing) -> ExtendedAddr {
         self.generator.address(addr)
     }
+
+    /// the addressing of the given address, if its derivation path payload
+    /// decrypts with the wallet key and the address derived from it matches
+    pub fn find(&self, address: &ExtendedAddr) -> Option<rindex::Addressing> {
+        match self.generator.try_get_addressing(address) {
+            Ok(Some(addressing)) => {
+                self.generator.compare_address(address, &addressing).ok().map(|()| addressing)
+            },
+            _ => None
+        }
+    }
 }
 impl AddressLookup for RandomIndexLookup {
     type Error = rindex::Error;
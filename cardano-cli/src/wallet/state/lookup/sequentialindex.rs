@@ -7,6 +7,14 @@ use super::super::{utxo::{UTxO}};
 
 pub const DEFAULT_GAP_LIMIT: u32 = 20;
 
+/// the levels of a BIP44 derivation path, below the account, to derive
+/// hardened instead of soft. The account level is always hardened.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Hardening {
+    pub change: bool,
+    pub index: bool,
+}
+
 type Result<T> = bip44::bip44::Result<T>;
 
 pub struct SequentialBip44Lookup {
@@ -52,6 +60,21 @@ impl SequentialBip44Lookup {
         ExtendedAddr::new_simple(*xpub)
     }
 
+    /// same as `get_address` but deriving the given levels hardened
+    ///
+    /// such addresses are not part of the gap limit lookup: the funds
+    /// they receive are not found by the wallet.
+    pub fn get_address_with_hardening(&self, addr: &bip44::Addressing, hardening: Hardening) -> ExtendedAddr {
+        let harden = |hardened: bool, value: u32| {
+            if hardened { value | bip44::bip44::BIP44_SOFT_UPPER_BOUND } else { value }
+        };
+        let scheme = self.wallet.derivation_scheme();
+        let account = self.wallet.account(scheme, addr.account.get_scheme_value());
+        let xprv = account.derive(scheme, harden(hardening.change, addr.change))
+                          .derive(scheme, harden(hardening.index, addr.index.get_scheme_value()));
+        ExtendedAddr::new_simple(xprv.public())
+    }
+
 
     fn mut_generate_from(&mut self, account: &bip44::bip44::Account, change: u32, start: &bip44::Index, nb: u32) -> Result<()> {
         let max = start.incr(nb)?;
@@ -100,6 +123,13 @@ impl SequentialBip44Lookup {
         limits[lidx] = new_threshold;
         Ok(())
     }
+
+    /// the addressing of the given address, if it is one of the expected
+    /// addresses: the ones within the gap limit after the last address
+    /// found (or acknowledged) in each of the prepared accounts
+    pub fn find(&self, address: &ExtendedAddr) -> Option<bip44::Addressing> {
+        self.expected.get(address).cloned()
+    }
 }
 
 impl AddressLookup for SequentialBip44Lookup {
@@ -124,3 +154,42 @@ impl AddressLookup for SequentialBip44Lookup {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::hdwallet::{self, DerivationScheme};
+
+    #[test]
+    fn find_addresses_within_the_gap_limit() {
+        let xprv = hdwallet::XPrv::normalize_bytes([7;hdwallet::XPRV_SIZE]);
+        let mut lookup = SequentialBip44Lookup::new(bip44::Wallet::from_root_key(xprv, DerivationScheme::V2));
+        lookup.prepare_next_account().unwrap();
+
+        let addressing = |index| bip44::Addressing::new(0, bip44::AddrType::External, index).unwrap();
+        let address = |index| lookup.get_address(&addressing(index));
+        let (last_in_gap, first_out_of_gap, further) = (address(DEFAULT_GAP_LIMIT - 1), address(DEFAULT_GAP_LIMIT), address(DEFAULT_GAP_LIMIT + 5));
+
+        assert_eq!(lookup.find(&last_in_gap), Some(addressing(DEFAULT_GAP_LIMIT - 1)));
+        assert_eq!(lookup.find(&first_out_of_gap), None);
+
+        // once an address of the window received funds, the next window is expected too
+        lookup.acknowledge(addressing(3)).unwrap();
+        assert_eq!(lookup.find(&first_out_of_gap), Some(addressing(DEFAULT_GAP_LIMIT)));
+        assert_eq!(lookup.find(&further), Some(addressing(DEFAULT_GAP_LIMIT + 5)));
+    }
+
+    #[test]
+    fn default_hardening_is_the_soft_derivation() {
+        let xprv = hdwallet::XPrv::normalize_bytes([7;hdwallet::XPRV_SIZE]);
+        let lookup = SequentialBip44Lookup::new(bip44::Wallet::from_root_key(xprv, DerivationScheme::V2));
+
+        for &(account, addr_type, index) in [(0, bip44::AddrType::External, 0), (0, bip44::AddrType::Internal, 7), (2, bip44::AddrType::External, 42)].iter() {
+            let addressing = bip44::Addressing::new(account, addr_type, index).unwrap();
+            assert_eq!(lookup.get_address_with_hardening(&addressing, Hardening::default()), lookup.get_address(&addressing));
+            // and hardening any level gives another address
+            let hardened = Hardening { change: false, index: true };
+            assert!(lookup.get_address_with_hardening(&addressing, hardened) != lookup.get_address(&addressing));
+        }
+    }
+}
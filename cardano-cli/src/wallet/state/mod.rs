@@ -7,3 +7,5 @@ pub mod state;
 pub mod ptr;
 pub mod utxo;
 pub mod iter;
+pub mod balance;
+pub mod addresses;
@@ -5,21 +5,22 @@
 //!
 
 use super::{Wallet};
-use super::state::{log, ptr, state, lookup, iter::TransactionIterator, utxo::UTxO, ptr::{StatePtr}};
+use super::state::{log, ptr, state, lookup, iter::TransactionIterator, utxo::UTxO, ptr::{StatePtr}, balance::{BalanceCache}};
 use super::error::{Error};
 use super::config::{HDWalletModel};
 
-use std::{path::PathBuf, io::Write};
-use cardano::{address::ExtendedAddr, block::{BlockDate}, config::ProtocolMagic, tx::{TxInWitness, TxId}};
+use std::{path::PathBuf, io::Write, collections::BTreeSet};
+use cardano::{address::ExtendedAddr, block::{BlockDate, EpochId, HeaderHash}, config::ProtocolMagic, tx::{TxInWitness, TxId}, coin::Coin, bip::bip44::AddrType};
 
 use utils::{term::{Term, style::{Style}}};
 
-use blockchain::{Blockchain};
+use blockchain::{Blockchain, peer};
 
 pub fn update_wallet_state_with_utxos<LS>( term: &mut Term
                                          , wallet: &Wallet
                                          , blockchain: &Blockchain
                                          , state: &mut state::State<LS>
+                                         , persist: bool
                                          )
     where LS: lookup::AddressLookup
 {
@@ -41,7 +42,7 @@ pub fn update_wallet_state_with_utxos<LS>( term: &mut Term
         debug!("transactions in: {}", ptr);
 
         if let Some(addr) = ptr.latest_addr {
-            if last_block_date.get_epochid() != addr.get_epochid() {
+            if persist && last_block_date.get_epochid() != addr.get_epochid() {
 
                 let log_lock = lock_wallet_log(&wallet);
                 let mut writer = log::LogWriter::open(log_lock).unwrap();
@@ -56,9 +57,7 @@ pub fn update_wallet_state_with_utxos<LS>( term: &mut Term
             let logs = state.forward_with_txins(
                 txaux.tx.inputs.iter().map(|txin| (ptr.clone(), txin))
             ).unwrap();
-            let log_lock = lock_wallet_log(&wallet);
-            let mut writer = log::LogWriter::open(log_lock).unwrap();
-            for log in logs { writer.append(&log).unwrap(); }
+            if persist { append_wallet_logs(wallet, logs); }
         }
 
         {
@@ -76,24 +75,200 @@ pub fn update_wallet_state_with_utxos<LS>( term: &mut Term
                     )
                 })
             ).unwrap();
+            if persist { append_wallet_logs(wallet, logs); }
+        }
+    }
+
+    if ! persist {
+        term.warn(&format!("the wallet log was left unchanged, balance on {}: {}\n", blockchain.name, state.total().unwrap())).unwrap();
+    }
+}
+
+fn append_wallet_logs(wallet: &Wallet, logs: Vec<log::Log<lookup::Address>>) {
+    let log_lock = lock_wallet_log(&wallet);
+    let mut writer = log::LogWriter::open(log_lock).unwrap();
+    for log in logs { writer.append(&log).unwrap(); }
+}
+
+/// move the wallet state forward to the epoch boundary `epochs` epochs
+/// before the local blockchain's tip, so the sync does not go through
+/// the older blocks.
+///
+/// If this epoch boundary is not in the local blockchain (e.g. nothing
+/// was downloaded yet), the epoch `epochs` epochs before the tip of the
+/// native peers is looked up on them with `GetBlockHeader` instead: the
+/// blockchain then has to be pulled up to it before the wallet is synced.
+///
+/// The funds received before this epoch are not known to the wallet: the
+/// balance is incomplete. This does nothing if the wallet is already
+/// synced further than this epoch.
+pub fn skip_to_recent_epochs<LS>( term: &mut Term
+                                , blockchain: &Blockchain
+                                , state: &mut state::State<LS>
+                                , epochs: u32
+                                )
+    where LS: lookup::AddressLookup
+{
+    let epoch = recent_epoch(blockchain.load_tip().0.date.get_epochid(), epochs);
+    let (epoch, hash) = match blockchain.epoch_boundary(epoch) {
+        Some(hash) => (epoch, hash),
+        None => match peers_epoch_boundary(term, blockchain, epochs) {
+            Some(boundary) => boundary,
+            None => {
+                term.error(&format!("epoch {} is not in the local blockchain nor on the peers\n", epoch)).unwrap();
+                ::std::process::exit(1);
+            },
+        },
+    };
+    let boundary_date = BlockDate::Genesis(epoch);
 
-            let log_lock = lock_wallet_log(&wallet);
-            let mut writer = log::LogWriter::open(log_lock).unwrap();
-            for log in logs { writer.append(&log).unwrap(); }
+    if let Some(ref date) = state.ptr().latest_addr {
+        if date >= &boundary_date {
+            term.info(&format!("wallet already synced to {}, ignoring the number of epochs\n", date)).unwrap();
+            return;
         }
     }
+
+    if ::storage::block_read(&blockchain.storage, hash.bytes()).is_none() {
+        term.error(&format!("the start of epoch {} ({}) is not in the local blockchain yet, pull the blockchain first\n", epoch, hash)).unwrap();
+        ::std::process::exit(1);
+    }
+
+    term.warn(&format!("syncing from epoch {} only, the funds received before are not accounted\n", epoch)).unwrap();
+    state.ptr = StatePtr::new(boundary_date, hash);
 }
 
-pub fn display_wallet_state_utxos<LS>( term: &mut Term
-                                     , state: state::State<LS>
-                                     )
+// the epoch `epochs` epochs before the tip of the first native peer
+// answering, and the hash of its epoch boundary block, walking through
+// the headers of the peer from the local tip
+fn peers_epoch_boundary(term: &mut Term, blockchain: &Blockchain, epochs: u32) -> Option<(EpochId, HeaderHash)> {
+    let (tip, is_genesis) = blockchain.load_tip();
+    let checkpoint = if is_genesis { blockchain.config.genesis_prev.clone() } else { tip.hash };
+    for np in blockchain.peers() {
+        if ! np.is_native() { continue; }
+
+        let mut peer = match peer::Peer::prepare(blockchain, np.name().to_owned()).connect(term) {
+            Ok(peer) => peer,
+            Err(()) => continue,
+        };
+        let epoch = recent_epoch(peer.query_tip().date.get_epochid(), epochs);
+        term.info(&format!("looking for the start of epoch {} on peer: {}\n", epoch, np.name())).unwrap();
+        match peer.get_epoch_boundary(&checkpoint, epoch) {
+            Some(Ok(hash)) => return Some((epoch, hash)),
+            Some(Err(err)) => term.warn(&format!("Unable to find the start of epoch {} on peer {}: {:?}\n", epoch, np.name(), err)).unwrap(),
+            None => {},
+        }
+    }
+    None
+}
+
+/// the epoch `epochs` epochs before the epoch of the tip: 0 is the
+/// epoch of the tip itself, the first epoch if there are not that many
+fn recent_epoch(tip_epoch: EpochId, epochs: u32) -> EpochId {
+    tip_epoch.saturating_sub(epochs as u64)
+}
+
+/// replay the blocks of the blockchain up to the wallet's last synced
+/// block into a fresh UTxO set, and compare the wallet's UTxOs with the
+/// outputs of this set credited to the wallet.
+///
+/// The replay starts from the UTxO set saved when the oldest epochs of
+/// the blockchain were pruned, if they were (see `Storage::utxo_after`).
+///
+/// Prints the discrepancies and returns how many were found.
+pub fn audit_wallet_state_utxos<LS>( term: &mut Term
+                                   , blockchain: &Blockchain
+                                   , state: state::State<LS>
+                                   ) -> usize
     where LS: lookup::AddressLookup
 {
-    for (_, utxo) in state.utxos {
-        writeln!(term, "{}.{} {}",
+    let to = match state.ptr().latest_addr {
+        None => {
+            term.info("the wallet has not been synced yet, nothing to audit\n").unwrap();
+            return 0;
+        },
+        Some(date) => {
+            term.info(&format!("replaying the blockchain up to {}\n", date)).unwrap();
+            state.ptr().latest_known_hash.clone()
+        }
+    };
+
+    let utxos = match blockchain.storage.utxo_after(&to) {
+        Err(::storage::Error::BlockNotStored(hash)) => {
+            term.error(&format!("the wallet is synced up to block {}, which is not in the blockchain anymore (pruned?)\n", hash)).unwrap();
+            ::std::process::exit(1);
+        },
+        Err(err) => {
+            term.error(&format!("cannot replay the blockchain: {:?}\n", err)).unwrap();
+            ::std::process::exit(1);
+        },
+        Ok(utxos) => utxos
+    };
+
+    let state::State { mut lookup_struct, utxos: mut wallet_utxos, .. } = state;
+    let mut discrepancies = 0;
+    for (txin, txout) in utxos {
+        let utxo = UTxO {
+            transaction_id: txin.id,
+            index_in_transaction: txin.index,
+            credited_address: txout.address.clone(),
+            credited_addressing: txout.address,
+            credited_value: txout.value
+        };
+        let utxo = match lookup_struct.lookup(utxo).unwrap() {
+            None => continue,
+            Some(utxo) => utxo
+        };
+        match wallet_utxos.remove(&txin) {
+            None => {
+                discrepancies += 1;
+                term.warn(&format!("unspent but not in the wallet: {}\n", utxo)).unwrap();
+            },
+            Some(known) => {
+                if known.credited_value != utxo.credited_value || known.credited_address != utxo.credited_address {
+                    discrepancies += 1;
+                    term.warn(&format!("differs in the wallet: {} (wallet: {})\n", utxo, known)).unwrap();
+                }
+            }
+        }
+    }
+    for (_, utxo) in wallet_utxos {
+        discrepancies += 1;
+        term.warn(&format!("in the wallet but spent or unknown: {}\n", utxo)).unwrap();
+    }
+    discrepancies
+}
+
+/// a UTxO of the wallet, as output in JSON by `display_wallet_utxos`
+#[derive(Serialize)]
+struct UTxOEntry {
+    transaction_id: TxId,
+    index_in_transaction: u32,
+    value: Coin,
+    address: ExtendedAddr,
+}
+
+pub fn display_wallet_utxos( term: &mut Term
+                           , utxos: Vec<UTxO<lookup::Address>>
+                           , json: bool
+                           )
+{
+    if json {
+        let entries : Vec<UTxOEntry> = utxos.into_iter().map(|utxo| UTxOEntry {
+            transaction_id: utxo.transaction_id,
+            index_in_transaction: utxo.index_in_transaction,
+            value: utxo.credited_value,
+            address: utxo.credited_address,
+        }).collect();
+        writeln!(term, "{}", ::serde_json::to_string_pretty(&entries).unwrap()).unwrap();
+        return;
+    }
+    for utxo in utxos {
+        writeln!(term, "{}.{} {} {}",
             style!(utxo.transaction_id),
             style!(utxo.index_in_transaction).yellow(),
-            style!(utxo.credited_value).green()
+            style!(utxo.credited_value).green(),
+            style!(utxo.credited_address)
         ).unwrap()
     }
 }
@@ -196,7 +371,7 @@ pub fn dump_utxo<L>(term: &mut Term, ptr: StatePtr, utxo: UTxO<L>, debit: bool)
 }
 
 
-pub fn create_wallet_state_from_logs<LS>(term: &mut Term, wallet: &Wallet, root_dir: PathBuf, lookup_structure: LS) -> state::State<LS>
+pub fn create_wallet_state_from_logs<LS>(term: &mut Term, wallet: &Wallet, blockchains_dir: PathBuf, lookup_structure: LS) -> state::State<LS>
     where LS: lookup::AddressLookup
 {
     let log_lock = lock_wallet_log(wallet);
@@ -216,7 +391,7 @@ pub fn create_wallet_state_from_logs<LS>(term: &mut Term, wallet: &Wallet, root_
         Err(lookup_structure) => {
             // create empty state
             // 1. get the wallet's blockchain
-            let blockchain = load_attached_blockchain(term, root_dir, wallet.config.attached_blockchain.clone());
+            let blockchain = load_attached_blockchain(term, blockchains_dir, wallet.config.attached_blockchain.clone());
 
             // 2. prepare the wallet state
             let initial_ptr = ptr::StatePtr::new_before_genesis(blockchain.config.genesis.clone());
@@ -225,6 +400,95 @@ pub fn create_wallet_state_from_logs<LS>(term: &mut Term, wallet: &Wallet, root_
     }
 }
 
+/// compute the balance of the wallet, replaying only the LOG entries
+/// appended since the cached balance (unless `refresh` is set or the
+/// cache does not match the LOG anymore), and update the cache.
+pub fn wallet_balance(wallet: &Wallet, refresh: bool) -> BalanceCache {
+    let read_logs = || {
+        let log_lock = lock_wallet_log(wallet);
+        let reader : log::LogIterator<lookup::Address> = log::LogReader::open(log_lock).unwrap() // BAD
+            .into_iter();
+        reader.map(|r| {
+            match r {
+                Err(err) => panic!("{:?}", err),
+                Ok(v) => v
+            }
+        })
+    };
+
+    let cache = if refresh { None } else { wallet.cached_balance() };
+    let cache = match cache.map(|cache| cache.update(read_logs()).unwrap()) {
+        Some(Some(cache)) => cache,
+        Some(None) => {
+            info!("wallet `{}' LOG changed since the balance was cached, recomputing", wallet.name);
+            BalanceCache::new().update(read_logs()).unwrap().unwrap()
+        },
+        None => BalanceCache::new().update(read_logs()).unwrap().unwrap()
+    };
+
+    wallet.save_cached_balance(&cache);
+    cache
+}
+
+/// the index following the highest index of the addresses of the given
+/// type (internal, i.e. change, or external) of the given account that
+/// received funds, as found in the wallet LOG. `0` if none did (or if
+/// the wallet has not been synced).
+pub fn next_unused_index(wallet: &Wallet, account: u32, addr_type: AddrType) -> u32 {
+    let log_lock = lock_wallet_log(wallet);
+    let reader = match log::LogReader::open(log_lock) {
+        Err(err) => {
+            debug!("cannot read the wallet LOG, assuming no {:?} address was used: {:?}", addr_type, err);
+            return 0;
+        },
+        Ok(reader) => reader
+    };
+
+    let mut next = 0;
+    for log in reader.into_iter::<lookup::Address>() {
+        let utxo = match log {
+            Err(err) => panic!("{:?}", err),
+            Ok(log::Log::Checkpoint(_)) => continue,
+            Ok(log::Log::ReceivedFund(_, utxo)) => utxo,
+            Ok(log::Log::SpentFund(_, utxo)) => utxo,
+        };
+        if let lookup::Address::Bip44(addressing) = utxo.credited_addressing {
+            if addressing.account.get_account_number() == account
+                && addressing.address_type() == addr_type
+            {
+                next = ::std::cmp::max(next, addressing.index.get_scheme_value() + 1);
+            }
+        }
+    }
+    next
+}
+
+/// the BIP44 accounts that received funds, as found in the wallet LOG
+pub fn used_accounts(wallet: &Wallet) -> BTreeSet<u32> {
+    let mut accounts = BTreeSet::new();
+    let log_lock = lock_wallet_log(wallet);
+    let reader = match log::LogReader::open(log_lock) {
+        Err(err) => {
+            debug!("cannot read the wallet LOG, assuming no account was used: {:?}", err);
+            return accounts;
+        },
+        Ok(reader) => reader
+    };
+
+    for log in reader.into_iter::<lookup::Address>() {
+        match log {
+            Err(err) => panic!("{:?}", err),
+            Ok(log::Log::ReceivedFund(_, utxo)) => {
+                if let lookup::Address::Bip44(addressing) = utxo.credited_addressing {
+                    accounts.insert(addressing.account.get_account_number());
+                }
+            },
+            Ok(_) => {}
+        }
+    }
+    accounts
+}
+
 pub fn load_bip44_lookup_structure(term: &mut Term, wallet: &Wallet) -> lookup::sequentialindex::SequentialBip44Lookup {
     // TODO: to prevent from the need of the password, we can ask the user to create accounts ahead.
     //       if we store the wallet's account public keys in the config file we may not need for the
@@ -288,14 +552,14 @@ pub fn lock_wallet_log(wallet: &Wallet) -> log::LogLock {
     }
 }
 
-pub fn load_attached_blockchain(term: &mut Term, root_dir: PathBuf, name: Option<String>) -> Blockchain {
+pub fn load_attached_blockchain(term: &mut Term, blockchains_dir: PathBuf, name: Option<String>) -> Blockchain {
     match name {
         None => {
             term.error("Wallet is not attached to any blockchain\n").unwrap();
             ::std::process::exit(1);
         },
         Some(blockchain) => {
-            Blockchain::load(root_dir, blockchain)
+            Blockchain::load(blockchains_dir, blockchain)
         }
     }
 }
@@ -329,3 +593,77 @@ The process will panic with an error message, this is because something
 unexpected happened. Please report the error message with the panic
 error message to: https://github.com/input-output-hk/rust-cardano/issues
 ";
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use super::super::state::lookup::accum::Accum;
+    use cardano::{block::RawBlock, hdwallet, tx::{TxIn, TxOut}};
+    use exe_common::config::net;
+    use storage::utxo::{self as chain_utxo, UtxoSet};
+    use std::{env, fs};
+    use utils::term;
+
+    /// the epoch boundary block of the epoch 1: magic, previous, body
+    /// proof, consensus (epoch, difficulty), attributes; no slot leaders;
+    /// extra
+    fn boundary_block_of_epoch_1() -> RawBlock {
+        let mut bytes = vec![0x82, 0x00, 0x83, 0x85, 0x1a, 0x2d, 0x96, 0x4a, 0x09, 0x58, 0x20];
+        bytes.extend_from_slice(&[0; 32]);
+        bytes.extend_from_slice(&[0x58, 0x20]);
+        bytes.extend_from_slice(&[0; 32]);
+        bytes.extend_from_slice(&[0x82, 0x01, 0x81, 0x00, 0xa0, 0x9f, 0xff, 0x81, 0xa0]);
+        RawBlock::from_dat(bytes)
+    }
+
+    #[test]
+    fn audit_wallet_state_utxos_of_a_pruned_blockchain() {
+        let dir = env::temp_dir().join(format!("cardano-cli-audit-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        // the blocks of the epoch 0 were pruned, leaving the UTxO set at
+        // the start of the epoch 1 and the blocks from there
+        let blockchain = Blockchain::new(dir.clone(), "testnet".to_owned(), net::Config::testnet());
+        let block = boundary_block_of_epoch_1();
+        let hash = block.to_header().unwrap().compute_hash();
+        ::storage::blob::write(&blockchain.storage, hash.bytes(), block.as_ref()).unwrap();
+        ::storage::tag::write_hash(&blockchain.storage, &::storage::tag::HEAD, &hash);
+        let xpub = hdwallet::XPrv::normalize_bytes([7;hdwallet::XPRV_SIZE]).public();
+        let txin = TxIn::new(TxId::new(b"funds of the epoch 0"), 0);
+        let txout = TxOut::new(ExtendedAddr::new_simple(xpub), Coin::new(10).unwrap());
+        let mut snapshot = UtxoSet::new();
+        snapshot.insert(txin.clone(), txout.clone());
+        chain_utxo::write_snapshot(&blockchain.storage_config, 1, &snapshot).unwrap();
+        let blockchain = Blockchain::load(dir.clone(), "testnet".to_owned());
+        assert_eq!(blockchain.storage.first_epoch(), 1);
+
+        let mut term = Term::new(term::Config::default());
+        let wallet_state = |utxos: &[(TxIn, TxOut)]| {
+            let mut state = state::State::new(StatePtr::new(BlockDate::Genesis(1), hash.clone()), Accum::default());
+            for (txin, txout) in utxos {
+                state.utxos.insert(txin.clone(), UTxO {
+                    transaction_id: txin.id,
+                    index_in_transaction: txin.index,
+                    credited_address: txout.address.clone(),
+                    credited_addressing: txout.address.clone().into(),
+                    credited_value: txout.value,
+                });
+            }
+            state
+        };
+        assert_eq!(audit_wallet_state_utxos(&mut term, &blockchain, wallet_state(&[(txin, txout)])), 0);
+        assert_eq!(audit_wallet_state_utxos(&mut term, &blockchain, wallet_state(&[])), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn recent_epoch_counts_back_from_the_tip() {
+        assert_eq!(recent_epoch(10, 0), 10);
+        assert_eq!(recent_epoch(10, 1), 9);
+        assert_eq!(recent_epoch(10, 3), 7);
+        // not that many epochs: start from the first one
+        assert_eq!(recent_epoch(2, 3), 0);
+        assert_eq!(recent_epoch(0, 1), 0);
+    }
+}
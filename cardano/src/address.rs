@@ -377,16 +377,66 @@ impl ExtendedAddr {
         let mut raw = RawCbor::from(buf);
         cbor_event::de::Deserialize::deserialize(&mut raw)
     }
+
+    /// the kind of the address, inferred from its structure
+    pub fn kind(&self) -> AddressKind {
+        match self.addr_type {
+            AddrType::ATRedeem => AddressKind::Redeem,
+            AddrType::ATScript => AddressKind::Script,
+            AddrType::ATPubKey => match self.attributes.derivation_path {
+                Some(_) => AddressKind::RandomIndex,
+                None    => AddressKind::Bip44,
+            },
+        }
+    }
+}
+
+/// the kinds of addresses, as told apart by their structure
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+pub enum AddressKind {
+    /// public key address of a random index wallet: the derivation path
+    /// of the key is encrypted in the attributes (`DdzFF...` addresses)
+    RandomIndex,
+    /// public key address without derivation path, as used by the BIP44
+    /// wallets (`Ae2...` addresses)
+    Bip44,
+    /// address of the redemption keys of the genesis
+    Redeem,
+    /// address locked by a script
+    Script,
+}
+impl fmt::Display for AddressKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddressKind::RandomIndex => write!(f, "random index"),
+            AddressKind::Bip44 => write!(f, "BIP44"),
+            AddressKind::Redeem => write!(f, "redeem"),
+            AddressKind::Script => write!(f, "script"),
+        }
+    }
 }
+
 #[derive(Debug)]
 pub enum ParseExtendedAddrError {
     EncodingError(cbor_event::Error),
     Base58Error(base58::Error)
 }
+impl fmt::Display for ParseExtendedAddrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseExtendedAddrError::EncodingError(err) => {
+                write!(f, "unrecognized address format: {:?}", err)
+            },
+            ParseExtendedAddrError::Base58Error(err) => {
+                write!(f, "address is not valid base58: {:?}", err)
+            },
+        }
+    }
+}
 impl ::std::str::FromStr for ExtendedAddr {
     type Err = ParseExtendedAddrError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let bytes = base58::decode(s)
+        let bytes = base58::decode_lenient(s)
             .map_err(ParseExtendedAddrError::Base58Error)?;
 
         Self::from_bytes(&bytes)
@@ -513,6 +563,7 @@ impl cbor_event::se::Serialize for SpendingData {
 mod tests {
     use address::*;
     use hdwallet;
+    use redeem;
     use util::base58;
 
     #[test]
@@ -636,6 +687,57 @@ mod tests {
         assert_eq!(r.attributes.stake_distribution, StakeDistribution::BootstrapEraDistr);
     }
 
+    #[test]
+    fn address_kind_random_index() {
+        let addr : ExtendedAddr = "DdzFFzCqrhsyhumccfGyEj3WZzztSPr92ntRWB6UVVwzcMTpwoafVQ5vD9mdZ5Xind8ycugbmA8esxmo7NycjQFGSbDeKrxabTz8MVzf".parse().unwrap();
+        assert_eq!(addr.kind(), AddressKind::RandomIndex);
+    }
+
+    #[test]
+    fn address_kind_bip44() {
+        let addr : ExtendedAddr = "Ae2tdPwUPEZFRbyhz3cpfC2CumGzNkFBN2L42rcUc2yjQpEkxDbkPodpMAi".parse().unwrap();
+        assert_eq!(addr.kind(), AddressKind::Bip44);
+
+        let seed = hdwallet::Seed::from_bytes([0;hdwallet::SEED_SIZE]);
+        let pk = hdwallet::XPrv::generate_from_seed(&seed).public();
+        assert_eq!(ExtendedAddr::new_simple(pk).kind(), AddressKind::Bip44);
+    }
+
+    #[test]
+    fn address_kind_redeem() {
+        let pk = redeem::PrivateKey::generate(&[0;32]).public();
+        let addr = ExtendedAddr::new(AddrType::ATRedeem, SpendingData::RedeemASD(pk), Attributes::new_bootstrap_era(None));
+        let addr : ExtendedAddr = format!("{}", addr).parse().unwrap();
+        assert_eq!(addr.kind(), AddressKind::Redeem);
+    }
+
+    #[test]
+    fn address_kind_script() {
+        let addr = ExtendedAddr {
+            addr: Addr::from_bytes([0;28]),
+            attributes: Attributes::new_bootstrap_era(None),
+            addr_type: AddrType::ATScript,
+        };
+        let addr : ExtendedAddr = format!("{}", addr).parse().unwrap();
+        assert_eq!(addr.kind(), AddressKind::Script);
+    }
+
+    #[test]
+    fn address_kind_unrecognized() {
+        // valid base58, but not the encoding of an address
+        let err = base58::encode(&[0x82, 0x00, 0x01]).parse::<ExtendedAddr>().unwrap_err();
+        match err {
+            ParseExtendedAddrError::EncodingError(_) => {},
+            err => panic!("unexpected error {:?}", err),
+        }
+        assert!(format!("{}", err).starts_with("unrecognized address format"));
+
+        match "0OIl".parse::<ExtendedAddr>().unwrap_err() {
+            ParseExtendedAddrError::Base58Error(_) => {},
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
     #[test]
     fn decode_address_no_derivation_path() {
         let bytes     = vec![0x82, 0xd8, 0x18, 0x58, 0x21, 0x83, 0x58, 0x1c, 0x10, 0x2a, 0x74, 0xca, 0x44, 0x05, 0xb8, 0xc1, 0x8d, 0x20, 0x84, 0x1e, 0x8c, 0x66, 0x4f, 0xe1, 0xde, 0x7d, 0x66, 0x07, 0x48, 0x08, 0x70, 0x4f, 0x91, 0x79, 0xe0, 0xfa, 0xa0, 0x00, 0x1a, 0xad, 0xf7, 0x10, 0x68];
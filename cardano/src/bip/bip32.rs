@@ -0,0 +1,190 @@
+//! BIP32 derivation paths
+//!
+//! parse and print derivation paths such as `m/1852'/1815'/0'/0/0`,
+//! to derive keys at arbitrary levels of an HDWallet.
+//!
+//! # Example
+//!
+//! ```
+//! # extern crate cardano;
+//! use cardano::bip::bip32::{DerivationPath, HARDENED_OFFSET};
+//!
+//! let path : DerivationPath = "m/44'/1815'/0'/0/1".parse().unwrap();
+//!
+//! assert_eq!(path.as_ref(), &[44 | HARDENED_OFFSET, 1815 | HARDENED_OFFSET, HARDENED_OFFSET, 0, 1]);
+//! assert_eq!(path.to_string(), "m/44'/1815'/0'/0/1");
+//! ```
+
+use hdwallet::{XPrv, DerivationIndex, DerivationScheme};
+use std::{fmt, result, str::FromStr};
+
+/// derivation indexes from this offset are hard derivations
+pub const HARDENED_OFFSET : DerivationIndex = 0x80000000;
+
+/// the depth of a key is encoded on one byte in BIP32, a path cannot
+/// have more levels than that.
+pub const MAX_DERIVATION_DEPTH : usize = 255;
+
+/// Error relating to the parsing of a `DerivationPath`
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Error {
+    /// the path does not start with the `m` of the root key
+    MissingRoot,
+
+    /// the level at the given position (starting at 1) is empty
+    EmptyLevel(usize),
+
+    /// the level at the given position (starting at 1) is not a decimal
+    /// index, optionally followed by a single hardening marker (`'`, `h`
+    /// or `H`)
+    InvalidLevel(usize, String),
+
+    /// the index of the level at the given position (starting at 1) does
+    /// not fit below `HARDENED_OFFSET`
+    IndexOutOfBound(usize, String),
+
+    /// the path has more levels than `MAX_DERIVATION_DEPTH`
+    TooDeep(usize),
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &Error::MissingRoot => write!(f, "derivation path should start with `m'"),
+            &Error::EmptyLevel(level) => write!(f, "level {} of the derivation path is empty", level),
+            &Error::InvalidLevel(level, ref given) => write!(f, "level {} of the derivation path is invalid, expecting an index optionally followed by `'' but received `{}'", level, given),
+            &Error::IndexOutOfBound(level, ref given) => write!(f, "index of level {} of the derivation path is out of bound, should be lower than {} but received `{}'", level, HARDENED_OFFSET, given),
+            &Error::TooDeep(given) => write!(f, "derivation path too deep, expecting at most {} levels but received {}", MAX_DERIVATION_DEPTH, given),
+        }
+    }
+}
+
+pub type Result<T> = result::Result<T, Error>;
+
+/// a derivation path from the root key, each level being hardened if its
+/// index is from `HARDENED_OFFSET`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DerivationPath(Vec<DerivationIndex>);
+impl DerivationPath {
+    /// create a derivation path from the given indexes
+    pub fn new(indexes: Vec<DerivationIndex>) -> Result<Self> {
+        if indexes.len() > MAX_DERIVATION_DEPTH {
+            return Err(Error::TooDeep(indexes.len()));
+        }
+        Ok(DerivationPath(indexes))
+    }
+
+    /// derive the given root key along the path
+    pub fn derive(&self, scheme: DerivationScheme, root: &XPrv) -> XPrv {
+        self.0.iter().fold(root.clone(), |xprv, index| xprv.derive(scheme, *index))
+    }
+}
+impl AsRef<[DerivationIndex]> for DerivationPath {
+    fn as_ref(&self) -> &[DerivationIndex] { self.0.as_ref() }
+}
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for index in self.0.iter() {
+            if *index >= HARDENED_OFFSET {
+                write!(f, "/{}'", index - HARDENED_OFFSET)?;
+            } else {
+                write!(f, "/{}", index)?;
+            }
+        }
+        Ok(())
+    }
+}
+impl FromStr for DerivationPath {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self> {
+        let mut levels = s.split('/');
+        if levels.next() != Some("m") { return Err(Error::MissingRoot); }
+
+        let mut indexes = Vec::new();
+        for (i, level) in levels.enumerate() {
+            let position = i + 1;
+            if position > MAX_DERIVATION_DEPTH {
+                return Err(Error::TooDeep(s.split('/').count() - 1));
+            }
+            if level.is_empty() { return Err(Error::EmptyLevel(position)); }
+
+            let (digits, hardened) = match level.char_indices().last() {
+                Some((last, '\'')) | Some((last, 'h')) | Some((last, 'H')) => (&level[..last], true),
+                _ => (level, false),
+            };
+            if digits.is_empty() || ! digits.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(Error::InvalidLevel(position, level.to_string()));
+            }
+            let index = match digits.parse::<DerivationIndex>() {
+                Ok(index) if index < HARDENED_OFFSET => index,
+                _ => return Err(Error::IndexOutOfBound(position, level.to_string())),
+            };
+            indexes.push(if hardened { index | HARDENED_OFFSET } else { index });
+        }
+        Ok(DerivationPath(indexes))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hdwallet::XPRV_SIZE;
+
+    #[test]
+    fn parse_and_print() {
+        let path : DerivationPath = "m/1852'/1815'/0'/0/0".parse().unwrap();
+        assert_eq!(path.as_ref(), &[1852 | HARDENED_OFFSET, 1815 | HARDENED_OFFSET, HARDENED_OFFSET, 0, 0]);
+        assert_eq!(path.to_string(), "m/1852'/1815'/0'/0/0");
+
+        let path : DerivationPath = "m/44h/1815H/2147483647".parse().unwrap();
+        assert_eq!(path.to_string(), "m/44'/1815'/2147483647");
+
+        let root : DerivationPath = "m".parse().unwrap();
+        assert!(root.as_ref().is_empty());
+    }
+
+    #[test]
+    fn reject_invalid_syntax() {
+        assert_eq!("44'/0".parse::<DerivationPath>(), Err(Error::MissingRoot));
+        assert_eq!("".parse::<DerivationPath>(), Err(Error::MissingRoot));
+        assert_eq!("m/".parse::<DerivationPath>(), Err(Error::EmptyLevel(1)));
+        assert_eq!("m/0//1".parse::<DerivationPath>(), Err(Error::EmptyLevel(2)));
+        assert_eq!("m/0/'".parse::<DerivationPath>(), Err(Error::InvalidLevel(2, "'".to_string())));
+        assert_eq!("m/0''".parse::<DerivationPath>(), Err(Error::InvalidLevel(1, "0''".to_string())));
+        assert_eq!("m/-1".parse::<DerivationPath>(), Err(Error::InvalidLevel(1, "-1".to_string())));
+        assert_eq!("m/+1".parse::<DerivationPath>(), Err(Error::InvalidLevel(1, "+1".to_string())));
+        assert_eq!("m/1x".parse::<DerivationPath>(), Err(Error::InvalidLevel(1, "1x".to_string())));
+    }
+
+    #[test]
+    fn reject_out_of_bound_index() {
+        assert_eq!("m/2147483648".parse::<DerivationPath>(), Err(Error::IndexOutOfBound(1, "2147483648".to_string())));
+        assert_eq!("m/0/2147483648'".parse::<DerivationPath>(), Err(Error::IndexOutOfBound(2, "2147483648'".to_string())));
+        assert_eq!("m/99999999999".parse::<DerivationPath>(), Err(Error::IndexOutOfBound(1, "99999999999".to_string())));
+    }
+
+    #[test]
+    fn reject_too_deep() {
+        let max = format!("m{}", "/0".repeat(MAX_DERIVATION_DEPTH));
+        assert_eq!(max.parse::<DerivationPath>().unwrap().as_ref().len(), MAX_DERIVATION_DEPTH);
+
+        let deeper = format!("m{}", "/0".repeat(MAX_DERIVATION_DEPTH + 1));
+        assert_eq!(deeper.parse::<DerivationPath>(), Err(Error::TooDeep(MAX_DERIVATION_DEPTH + 1)));
+        assert_eq!(DerivationPath::new(vec![0; MAX_DERIVATION_DEPTH + 1]), Err(Error::TooDeep(MAX_DERIVATION_DEPTH + 1)));
+    }
+
+    #[test]
+    fn derive_level_by_level() {
+        let root = XPrv::normalize_bytes([3;XPRV_SIZE]);
+        let scheme = DerivationScheme::V2;
+        let path : DerivationPath = "m/1852'/1815'/0'/0/0".parse().unwrap();
+
+        let expected = root.derive(scheme, 1852 | HARDENED_OFFSET)
+                           .derive(scheme, 1815 | HARDENED_OFFSET)
+                           .derive(scheme, HARDENED_OFFSET)
+                           .derive(scheme, 0)
+                           .derive(scheme, 0);
+        assert_eq!(path.derive(scheme, &root).as_ref(), expected.as_ref());
+        assert_eq!("m".parse::<DerivationPath>().unwrap().derive(scheme, &root).as_ref(), root.as_ref());
+    }
+}
@@ -4,5 +4,6 @@
 //! library for managing wallet, private keys and addresses.
 //!
 
+pub mod bip32;
 pub mod bip39;
 pub mod bip44;
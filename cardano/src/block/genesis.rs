@@ -37,13 +37,17 @@ impl cbor_event::se::Serialize for Body {
 impl cbor_event::de::Deserialize for Body {
     fn deserialize<'a>(raw: &mut RawCbor<'a>) -> cbor_event::Result<Self> {
         let len = raw.array()?;
-        assert_eq!(len, cbor_event::Len::Indefinite);
+        if len != cbor_event::Len::Indefinite {
+            return Err(cbor_event::Error::CustomError(format!("Invalid genesis::Body: expected an indefinite array, got {:?}", len)));
+        }
         let mut slot_leaders = Vec::new();
         while {
             let t = raw.cbor_type()?;
             if t == cbor_event::Type::Special {
                 let special = raw.special()?;
-                assert_eq!(special, cbor_event::Special::Break);
+                if special != cbor_event::Special::Break {
+                    return Err(cbor_event::Error::CustomError(format!("Invalid genesis::Body: expected a break, got {:?}", special)));
+                }
                 false
             } else {
                 slot_leaders.push(cbor_event::de::Deserialize::deserialize(raw)?);
@@ -92,13 +92,17 @@ impl cbor_event::se::Serialize for TxPayload {
 impl cbor_event::de::Deserialize for TxPayload {
     fn deserialize<'a>(raw: &mut RawCbor<'a>) -> cbor_event::Result<Self> {
         let num_inputs = raw.array()?;
-        assert_eq!(num_inputs, cbor_event::Len::Indefinite);
+        if num_inputs != cbor_event::Len::Indefinite {
+            return Err(cbor_event::Error::CustomError(format!("Invalid TxPayload: expected an indefinite array, got {:?}", num_inputs)));
+        }
         let mut l = Vec::new();
         while {
             let t = raw.cbor_type()?;
             if t == cbor_event::Type::Special {
                 let special = raw.special()?;
-                assert_eq!(special, cbor_event::Special::Break);
+                if special != cbor_event::Special::Break {
+                    return Err(cbor_event::Error::CustomError(format!("Invalid TxPayload: expected a break, got {:?}", special)));
+                }
                 false
             } else {
                 l.push(cbor_event::de::Deserialize::deserialize(raw)?);
@@ -114,13 +118,34 @@ impl cbor_event::de::Deserialize for TxPayload {
 pub struct Body {
     pub tx: TxPayload,
     pub ssc: SscPayload,
-    pub delegation: cbor_event::Value, // TODO: decode into DlgPayload
+    pub delegation: cbor_event::Value, // see `Body::certificates`
     pub update: update::UpdatePayload,
 }
 impl Body {
     pub fn new(tx: TxPayload, ssc: SscPayload, delegation: cbor_event::Value, update: update::UpdatePayload) -> Self {
         Body { tx, ssc, delegation, update }
     }
+
+    /// decode the certificates of the delegation payload
+    ///
+    /// the payload is kept undecoded in the body so the block encodes
+    /// back to the same bytes.
+    pub fn certificates(&self) -> cbor_event::Result<Vec<Certificate>> {
+        let bytes = cbor!(&self.delegation)?;
+        let psks : Vec<ProxySecretKey> = RawCbor::from(&bytes).deserialize()?;
+        Ok(psks.into_iter().map(Certificate::HeavyDelegation).collect())
+    }
+}
+
+/// a certificate of the delegation payload of a block
+///
+/// the only certificates of this chain are the heavyweight delegations:
+/// there is no stake key registration, the stake is delegated along with
+/// the right to issue blocks.
+#[derive(Debug, Clone)]
+pub enum Certificate {
+    /// the issuer delegates to the delegate, from the epoch `omega`
+    HeavyDelegation(ProxySecretKey),
 }
 impl fmt::Display for Body {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
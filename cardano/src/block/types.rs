@@ -2,6 +2,7 @@ use std::{fmt};
 use hash;
 use hash::{HASH_SIZE, Blake2b256};
 use cbor_event::{self, de::RawCbor};
+use config::{ProtocolMagic};
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
 pub struct Version {
@@ -23,7 +24,7 @@ impl fmt::Display for Version {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Serialize, Deserialize)]
 pub struct HeaderHash(Blake2b256);
 impl AsRef<[u8]> for HeaderHash { fn as_ref(&self) -> &[u8] { self.0.as_ref() } }
 impl fmt::Display for HeaderHash {
@@ -41,7 +42,30 @@ impl HeaderHash {
         Blake2b256::from_hex(hex).map(|h| HeaderHash(h))
     }
     pub fn new(bytes: &[u8]) -> Self { HeaderHash(Blake2b256::new(bytes))  }
+
+    /// the hash of the genesis block of the mainnet, i.e. the epoch
+    /// boundary block of its first epoch
+    pub fn mainnet_genesis() -> Self {
+        HeaderHash::from_hex(MAINNET_GENESIS).unwrap()
+    }
+
+    /// the hash of the genesis block of the test network with the given
+    /// protocol magic, `None` if the network is not a known one
+    pub fn testnet_genesis(protocol_magic: ProtocolMagic) -> Option<Self> {
+        TESTNET_GENESIS.iter()
+            .find(|(magic, _)| *magic == *protocol_magic)
+            .map(|(_, hash)| HeaderHash::from_hex(hash).unwrap())
+    }
 }
+
+const MAINNET_GENESIS : &'static str = "89d9b5a5b8ddc8d7e5a6795e9774d97faf1efea59b2caf7eaf9f8c5b32059df4";
+
+/// the protocol magic of the known test networks (the testnet and the
+/// staging network) and the hash of their genesis block
+const TESTNET_GENESIS : [(u32, &'static str); 2] =
+    [ (1097911063, "81a965de1412623ccd1cb3664f4d61a6cb4b9d53b44d779ed918e87bf3493f02")
+    , (633343913, "b365f1be6863b453f12b93e1810909b10c79a95ee44bf53414888513fe172c90")
+    ];
 impl From<[u8;HASH_SIZE]> for HeaderHash {
     fn from(bytes: [u8;HASH_SIZE]) -> Self { HeaderHash(Blake2b256::from(bytes)) }
 }
@@ -52,6 +76,10 @@ impl BlockVersion {
     pub fn new(major: u16, minor: u16, revision: u8) -> Self {
         BlockVersion(major, minor, revision)
     }
+
+    pub fn major(&self) -> u16 { self.0 }
+    pub fn minor(&self) -> u16 { self.1 }
+    pub fn revision(&self) -> u8 { self.2 }
 }
 impl fmt::Debug for BlockVersion {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -136,21 +164,64 @@ impl fmt::Display for ChainDifficulty {
         write!(f, "{}", self.0)
     }
 }
+impl From<ChainDifficulty> for u64 {
+    fn from(difficulty: ChainDifficulty) -> u64 { difficulty.0 }
+}
 
 pub type EpochId = u64; // == EpochIndex
 pub type SlotId = u16; // == LocalSlotIndex
 
+/// number of slots in an epoch of the mainnet and of the testnets: ten
+/// times their security parameter `k` (2160)
+pub const SLOTS_PER_EPOCH : u64 = 21600;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub struct EpochSlotId {
     pub epoch: EpochId,
     pub slotid: SlotId,
 }
 impl EpochSlotId {
+    /// the next slot of the same epoch, see `add_slots` to move across
+    /// epochs
     pub fn next(&self) -> Self {
         EpochSlotId { epoch: self.epoch, slotid: self.slotid + 1 }
     }
-    pub fn slot_number(&self) -> usize {
-        (self.epoch as usize) * 21600 + (self.slotid as usize)
+
+    /// the absolute slot number, counted from the first slot of the epoch 0
+    pub fn slot_number(&self, slots_per_epoch: u64) -> u64 {
+        self.epoch * slots_per_epoch + u64::from(self.slotid)
+    }
+
+    /// the epoch and slot of the given absolute slot number
+    ///
+    /// # panics
+    ///
+    /// if `slots_per_epoch` is 0 or above the number of slots a `SlotId`
+    /// can index
+    pub fn from_slot_number(slot_number: u64, slots_per_epoch: u64) -> Self {
+        assert!(slots_per_epoch > 0 && slots_per_epoch <= u64::from(SlotId::max_value()) + 1);
+        EpochSlotId {
+            epoch: slot_number / slots_per_epoch,
+            slotid: (slot_number % slots_per_epoch) as SlotId,
+        }
+    }
+
+    /// the slot `slots` slots later, in a following epoch if need be
+    pub fn add_slots(&self, slots: u64, slots_per_epoch: u64) -> Self {
+        Self::from_slot_number(self.slot_number(slots_per_epoch) + slots, slots_per_epoch)
+    }
+
+    /// the slot `slots` slots earlier, in a previous epoch if need be.
+    /// `None` if it would be before the first slot of the epoch 0.
+    pub fn sub_slots(&self, slots: u64, slots_per_epoch: u64) -> Option<Self> {
+        self.slot_number(slots_per_epoch).checked_sub(slots)
+            .map(|slot_number| Self::from_slot_number(slot_number, slots_per_epoch))
+    }
+
+    /// tell if this is the last slot of its epoch, the next block being
+    /// the epoch boundary block of the next epoch
+    pub fn is_last_of_epoch(&self, slots_per_epoch: u64) -> bool {
+        u64::from(self.slotid) + 1 == slots_per_epoch
     }
 }
 impl fmt::Display for EpochSlotId {
@@ -159,10 +230,12 @@ impl fmt::Display for EpochSlotId {
     }
 }
 
+/// the number of slots between the two, with `SLOTS_PER_EPOCH` slots per
+/// epoch
 impl ::std::ops::Sub<EpochSlotId> for EpochSlotId {
     type Output = usize;
     fn sub(self, rhs: Self) -> Self::Output {
-        self.slot_number() - rhs.slot_number()
+        (self.slot_number(SLOTS_PER_EPOCH) - rhs.slot_number(SLOTS_PER_EPOCH)) as usize
     }
 }
 
@@ -412,3 +485,82 @@ impl cbor_event::de::Deserialize for SystemTag {
         Ok(SystemTag::new(raw.deserialize()?)?)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::collections::{BTreeMap, HashMap};
+
+    #[test]
+    fn slot_arithmetic_at_epoch_edges() {
+        let slot = |epoch, slotid| EpochSlotId { epoch, slotid };
+
+        assert_eq!(EpochSlotId::from_slot_number(0, SLOTS_PER_EPOCH), slot(0, 0));
+        assert_eq!(EpochSlotId::from_slot_number(21599, SLOTS_PER_EPOCH), slot(0, 21599));
+        assert_eq!(EpochSlotId::from_slot_number(21600, SLOTS_PER_EPOCH), slot(1, 0));
+        assert_eq!(slot(1, 0).slot_number(SLOTS_PER_EPOCH), 21600);
+        assert_eq!(slot(3, 7).slot_number(10), 37);
+        assert_eq!(EpochSlotId::from_slot_number(37, 10), slot(3, 7));
+
+        assert!(slot(0, 21599).is_last_of_epoch(SLOTS_PER_EPOCH));
+        assert!(! slot(1, 0).is_last_of_epoch(SLOTS_PER_EPOCH));
+        assert_eq!(slot(0, 21599).add_slots(1, SLOTS_PER_EPOCH), slot(1, 0));
+        assert_eq!(slot(0, 21599).add_slots(0, SLOTS_PER_EPOCH), slot(0, 21599));
+        assert_eq!(slot(2, 5).add_slots(2 * 21600, SLOTS_PER_EPOCH), slot(4, 5));
+        assert_eq!(slot(1, 0).sub_slots(1, SLOTS_PER_EPOCH), Some(slot(0, 21599)));
+        assert_eq!(slot(1, 0).sub_slots(21600, SLOTS_PER_EPOCH), Some(slot(0, 0)));
+        assert_eq!(slot(1, 0).sub_slots(21601, SLOTS_PER_EPOCH), None);
+        assert_eq!(slot(0, 0).sub_slots(0, SLOTS_PER_EPOCH), Some(slot(0, 0)));
+
+        // the largest epoch a `SlotId` can index
+        let max = u64::from(SlotId::max_value()) + 1;
+        assert_eq!(slot(0, SlotId::max_value()).add_slots(1, max), slot(1, 0));
+        assert_eq!(slot(4, 1) - slot(3, 21599), 2);
+    }
+
+    #[test]
+    #[should_panic]
+    fn slots_per_epoch_above_slot_id_range() {
+        EpochSlotId::from_slot_number(0, u64::from(SlotId::max_value()) + 2);
+    }
+
+    #[test]
+    fn genesis_hashes() {
+        assert_eq!(HeaderHash::mainnet_genesis().to_string(), "89d9b5a5b8ddc8d7e5a6795e9774d97faf1efea59b2caf7eaf9f8c5b32059df4");
+        assert_eq!(HeaderHash::testnet_genesis(ProtocolMagic::from(1097911063)).unwrap().to_string(), "81a965de1412623ccd1cb3664f4d61a6cb4b9d53b44d779ed918e87bf3493f02");
+        assert_eq!(HeaderHash::testnet_genesis(ProtocolMagic::from(633343913)).unwrap().to_string(), "b365f1be6863b453f12b93e1810909b10c79a95ee44bf53414888513fe172c90");
+        assert_eq!(HeaderHash::testnet_genesis(ProtocolMagic::default()), None);
+    }
+
+    fn header_hashes() -> Vec<HeaderHash> {
+        (0u8..8).map(|i| HeaderHash::new(&[i])).collect()
+    }
+
+    #[test]
+    fn header_hash_as_hashmap_key() {
+        let hashes = header_hashes();
+        let mut map = HashMap::new();
+        for (i, hh) in hashes.iter().enumerate() {
+            map.insert(hh.clone(), i);
+        }
+        // inserting an equal key must replace the existing entry
+        map.insert(hashes[0].clone(), 42);
+
+        assert_eq!(map.len(), hashes.len());
+        assert_eq!(map.get(&hashes[0]), Some(&42));
+        for (i, hh) in hashes.iter().enumerate().skip(1) {
+            assert_eq!(map.get(hh), Some(&i));
+        }
+    }
+
+    #[test]
+    fn header_hash_as_btreemap_key() {
+        let hashes = header_hashes();
+        let map : BTreeMap<HeaderHash, usize> = hashes.iter().cloned().enumerate().map(|(i, hh)| (hh, i)).collect();
+
+        assert_eq!(map.len(), hashes.len());
+        let mut sorted = hashes.clone();
+        sorted.sort();
+        assert_eq!(map.keys().cloned().collect::<Vec<_>>(), sorted);
+    }
+}
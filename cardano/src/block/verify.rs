@@ -10,7 +10,7 @@ use cbor_event::{self, se};
 use std::collections::{BTreeSet, HashSet};
 use merkle;
 use tags;
-use hdwallet::{Signature};
+use hdwallet::{Signature, XPub};
 
 #[derive(Debug)]
 pub enum Error {
@@ -26,6 +26,7 @@ pub enum Error {
     NoTxWitnesses,
     RedeemOutput,
     SelfSignedPSK,
+    UnsupportedLightDelegation,
     WrongBlockHash,
     WrongDelegationProof,
     WrongExtraDataProof,
@@ -207,8 +208,12 @@ impl Verify for normal::Block {
         // check consensus
         // FIXME: check slotid?
         match &hdr.consensus.block_signature {
-            BlockSignature::Signature(_) => panic!("not implemented"),
-            BlockSignature::ProxyLight(_) => panic!("not implemented"),
+            BlockSignature::Signature(_) => {
+                if !hdr.verify_signature(&hdr.consensus.leader_key) {
+                    return Err(Error::BadBlockSig);
+                }
+            },
+            BlockSignature::ProxyLight(_) => return Err(Error::UnsupportedLightDelegation),
             BlockSignature::ProxyHeavy(proxy_sig) => {
 
                 // check against self-signed PSKs
@@ -217,15 +222,7 @@ impl Verify for normal::Block {
                 }
 
                 // verify the signature
-                let to_sign = MainToSign {
-                    previous_header: &hdr.previous_header,
-                    body_proof: &hdr.body_proof,
-                    slot: &hdr.consensus.slot_id,
-                    chain_difficulty: &hdr.consensus.chain_difficulty,
-                    extra_data: &hdr.extra_data,
-                };
-
-                if !verify_proxy_sig(protocol_magic, tags::SigningTag::MainBlockHeavy, proxy_sig, &to_sign) {
+                if !hdr.verify_signature(&hdr.consensus.leader_key) {
                     return Err(Error::BadBlockSig);
                 }
             }
@@ -235,6 +232,39 @@ impl Verify for normal::Block {
     }
 }
 
+impl normal::BlockHeader {
+    /// verify the header has been signed by the given slot leader's key
+    ///
+    /// With heavyweight delegation, the slot leader is the issuer of the
+    /// proxy secret key and the header is signed by the delegate.
+    /// Lightweight delegation signatures are not supported yet, and
+    /// are always considered invalid.
+    pub fn verify_signature(&self, leader_key: &XPub) -> bool {
+        if &self.consensus.leader_key != leader_key {
+            return false;
+        }
+
+        let to_sign = MainToSign {
+            previous_header: &self.previous_header,
+            body_proof: &self.body_proof,
+            slot: &self.consensus.slot_id,
+            chain_difficulty: &self.consensus.chain_difficulty,
+            extra_data: &self.extra_data,
+        };
+
+        match &self.consensus.block_signature {
+            BlockSignature::Signature(sig) => {
+                verify_sig(self.protocol_magic, tags::SigningTag::MainBlock, leader_key, sig, &to_sign)
+            },
+            BlockSignature::ProxyLight(_) => false,
+            BlockSignature::ProxyHeavy(proxy_sig) => {
+                &proxy_sig.psk.issuer_pk == leader_key
+                    && verify_proxy_sig(self.protocol_magic, tags::SigningTag::MainBlockHeavy, proxy_sig, &to_sign)
+            }
+        }
+    }
+}
+
 impl Verify for update::UpdatePayload {
     fn verify(&self, protocol_magic: ProtocolMagic) -> Result<(), Error> {
         if let Some(proposal) = &self.proposal {
@@ -274,6 +304,25 @@ impl<'a> cbor_event::se::Serialize for MainToSign<'a> {
     }
 }
 
+fn verify_sig<T, S>(
+    protocol_magic: ProtocolMagic,
+    tag: tags::SigningTag,
+    key: &XPub,
+    sig: &Signature<S>,
+    data: &T)
+    -> bool
+    where T: se::Serialize
+{
+    let mut buf = vec![];
+
+    se::Serializer::new(&mut buf)
+        .serialize(&(tag as u8)).unwrap()
+        .serialize(&protocol_magic).unwrap()
+        .serialize(data).unwrap();
+
+    key.verify(&buf, sig)
+}
+
 pub fn verify_proxy_sig<T>(
     protocol_magic: ProtocolMagic,
     tag: tags::SigningTag,
@@ -483,6 +532,28 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_verify_header_signature() {
+        let blk = RawBlock(BLOCK1.to_vec()).decode().unwrap();
+        let mut hdr = match blk {
+            Block::MainBlock(mblk) => mblk.header,
+            _ => panic!("expected a main block"),
+        };
+        let leader_key = hdr.consensus.leader_key.clone();
+        assert!(hdr.verify_signature(&leader_key));
+
+        // not the slot leader
+        if let normal::BlockSignature::ProxyHeavy(proxy_sig) = &hdr.consensus.block_signature {
+            assert!(!hdr.verify_signature(&proxy_sig.psk.delegate_pk));
+        } else {
+            panic!("expected a heavyweight delegation signature")
+        }
+
+        // the header has been tampered with
+        hdr.previous_header = HeaderHash::from_hex(&"ae443ffffe52cc29de83312d2819b3955fc306ce65ae6aa5b26f1d3c76e91841").unwrap();
+        assert!(!hdr.verify_signature(&leader_key));
+    }
+
     #[test]
     fn test_verify() {
         let hash = HeaderHash::from_hex(&HEADER_HASH1).unwrap();
@@ -591,6 +662,28 @@ mod tests {
             expect_error(&verify_block(pm, &hash, &blk), Error::BadBlockSig);
         }
 
+        // signed by the slot leader itself, but not with its key
+        {
+            let mut blk = blk.clone();
+            if let Block::MainBlock(mblk) = &mut blk {
+                let sig = match &mblk.header.consensus.block_signature {
+                    normal::BlockSignature::ProxyHeavy(proxy_sig) => proxy_sig.sig.clone().coerce(),
+                    _ => panic!("expected a heavyweight delegation signature"),
+                };
+                mblk.header.consensus.block_signature = normal::BlockSignature::Signature(sig);
+            }
+            expect_error(&verify_block(pm, &hash, &blk), Error::BadBlockSig);
+        }
+
+        // lightweight delegation is not supported
+        {
+            let mut blk = blk.clone();
+            if let Block::MainBlock(mblk) = &mut blk {
+                mblk.header.consensus.block_signature = normal::BlockSignature::ProxyLight(vec![]);
+            }
+            expect_error(&verify_block(pm, &hash, &blk), Error::UnsupportedLightDelegation);
+        }
+
         // invalidate a VSS certificate
         {
             let mut blk = blk3.clone();
@@ -680,7 +773,7 @@ mod tests {
             let mut rblk = BLOCK1.to_vec();
             rblk.push(123);
             let rblk = RawBlock(rblk);
-            expect_error(&rblk.decode(), cbor_event::Error::TrailingData);
+            expect_error(&rblk.decode().map_err(|err| err.error), cbor_event::Error::TrailingData);
         }
 
         // TODO: SelfSignedPSK, WrongGenesisProof
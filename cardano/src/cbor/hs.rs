@@ -18,7 +18,9 @@ pub mod util {
     }
     pub fn raw_with_crc32<'a, 'b>(raw: &'b mut RawCbor<'a>) -> cbor_event::Result<Bytes<'a>> {
         let len = raw.array()?;
-        assert!(len == Len::Len(2));
+        if len != Len::Len(2) {
+            return Err(cbor_event::Error::WrongLen(2, len, "crc32 protected bytes"));
+        }
 
         let tag = raw.tag()?;
         if tag != 24 {
@@ -125,6 +125,31 @@ impl LinearFee {
         let coin = Coin::new(fee.to_integral())?;
         Ok(Fee(coin))
     }
+
+    /// estimate the fee of a transaction with the given number of inputs
+    /// and outputs, before building it.
+    ///
+    /// The size of the transaction is an upper bound: every input is
+    /// expected to come with its witness and every output to pay to an
+    /// address with a derivation path attribute. `extra_bytes` is added to
+    /// the estimated size (e.g. for transaction attributes).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cardano::fee::{LinearFee};
+    ///
+    /// let fee = LinearFee::default().fee_for(1, 2, 0);
+    /// assert!(fee > 155381);
+    /// ```
+    pub fn fee_for(&self, inputs: usize, outputs: usize, extra_bytes: usize) -> u64 {
+        let sz = CBOR_TXAUX_OVERHEAD
+               + inputs * (TX_IN_CBOR_SIZE + TX_IN_WITNESS_CBOR_SIZE)
+               + outputs * TX_OUT_CBOR_SIZE
+               + extra_bytes;
+        let fee = self.constant + self.coefficient * Milli::integral(sz as u64);
+        fee.to_integral()
+    }
 }
 
 /// Calculation of fees for a specific chosen algorithm
@@ -152,6 +177,8 @@ impl Default for LinearFee {
 }
 
 const TX_IN_WITNESS_CBOR_SIZE: usize = 140;
+const TX_IN_CBOR_SIZE: usize = 46;
+const TX_OUT_CBOR_SIZE: usize = 86;
 const CBOR_TXAUX_OVERHEAD: usize = 51;
 impl SelectionAlgorithm for LinearFee {
     fn compute<'a, 'b, I, O, Addressing>( &self
@@ -164,56 +191,198 @@ impl SelectionAlgorithm for LinearFee {
         where I : 'a + Iterator<Item = &'a Input<Addressing>> + ExactSizeIterator
             , O : 'b + Iterator<Item = &'b TxOut> + Clone
             , Addressing: 'a
+    {
+        self.compute_with(&policy, inputs, outputs, output_policy)
+    }
+}
+impl LinearFee {
+    /// same as `SelectionAlgorithm::compute` but the inputs are selected
+    /// by the given `CoinSelector`
+    pub fn compute_with<'a, 'b, S, I, O, Addressing>( &self
+                                                    , selector: &S
+                                                    , inputs: I
+                                                    , outputs: O
+                                                    , output_policy: &OutputPolicy
+                                                    )
+            -> Result<(Fee, Vec<&'a Input<Addressing>>, Coin)>
+        where S : CoinSelector
+            , I : 'a + Iterator<Item = &'a Input<Addressing>> + ExactSizeIterator
+            , O : 'b + Iterator<Item = &'b TxOut> + Clone
+            , Addressing: 'a
     {
         if inputs.len() == 0 { return Err(Error::NoInputs); }
 
         let output_value = output_sum(outputs.clone())?;
-        let mut fee = self.estimate(0)?;
-        let mut input_value = Coin::zero();
-        let mut selected_inputs = Vec::new();
-
-        // create the Tx on the fly
-        let mut txins = Vec::new();
-        let     txouts : Vec<TxOut> = outputs.cloned().collect();
-
-        // for now we only support this selection algorithm
-        // we need to remove this assert when we extend to more
-        // granulated selection policy
-        assert!(policy == SelectionPolicy::FirstMatchFirst);
-
-        for input in inputs {
-            input_value = (input_value + input.value())?;
-            selected_inputs.push(input);
-            txins.push(input.ptr.clone());
-
-            // calculate fee from the Tx serialised + estimated size for signing
-            let mut tx = Tx::new_with(txins.clone(), txouts.clone());
-            let txbytes = cbor!(&tx)?;
-
-            let estimated_fee = (self.estimate(txbytes.len() + CBOR_TXAUX_OVERHEAD + (TX_IN_WITNESS_CBOR_SIZE * selected_inputs.len())))?;
-
-            // add the change in the estimated fee
-            if let Ok(change_value) = output_value - input_value - estimated_fee.to_coin() {
-                if change_value > Coin::zero() {
-                    match output_policy {
-                        OutputPolicy::One(change_addr) => tx.add_output(TxOut::new(change_addr.clone(), change_value)),
-                    }
+        let txouts : Vec<TxOut> = outputs.cloned().collect();
+        let fee = |selected: &[&'a Input<Addressing>]| {
+            self.selection_fee(selected, &txouts, output_value, output_policy)
+        };
+
+        let selected_inputs = selector.select(inputs.collect(), output_value, &fee)?;
+
+        let input_value = input_sum(&selected_inputs)?;
+        let fee = fee(&selected_inputs)?;
+        if Ok(input_value) < (output_value + fee.to_coin()) {
+            return Err(Error::NotEnoughInput);
+        }
+
+        Ok((fee, selected_inputs, (input_value - output_value - fee.to_coin())?))
+    }
+
+    /// the fee of the transaction spending the given inputs, the remaining
+    /// value being sent to the change address
+    fn selection_fee<Addressing>( &self
+                                , selected_inputs: &[&Input<Addressing>]
+                                , txouts: &[TxOut]
+                                , output_value: Coin
+                                , output_policy: &OutputPolicy
+                                ) -> Result<Fee>
+    {
+        let input_value = input_sum(selected_inputs)?;
+        let txins = selected_inputs.iter().map(|input| input.ptr.clone()).collect();
+
+        // calculate fee from the Tx serialised + estimated size for signing
+        let mut tx = Tx::new_with(txins, txouts.to_vec());
+        let txbytes = cbor!(&tx)?;
+
+        let estimated_fee = (self.estimate(txbytes.len() + CBOR_TXAUX_OVERHEAD + (TX_IN_WITNESS_CBOR_SIZE * selected_inputs.len())))?;
+
+        // add the change in the estimated fee
+        if let Ok(change_value) = input_value - output_value - estimated_fee.to_coin() {
+            if change_value > Coin::zero() {
+                match output_policy {
+                    OutputPolicy::One(change_addr) => tx.add_output(TxOut::new(change_addr.clone(), change_value)),
                 }
-            };
+            }
+        };
 
-            let txbytes = cbor!(&tx)?;
-            let corrected_fee = self.estimate(txbytes.len() + CBOR_TXAUX_OVERHEAD + (TX_IN_WITNESS_CBOR_SIZE * selected_inputs.len()));
+        let txbytes = cbor!(&tx)?;
+        self.estimate(txbytes.len() + CBOR_TXAUX_OVERHEAD + (TX_IN_WITNESS_CBOR_SIZE * selected_inputs.len()))
+    }
+}
 
-            fee = corrected_fee?;
+fn input_sum<Addressing>(inputs: &[&Input<Addressing>]) -> coin::Result<Coin> {
+    coin::sum_coins(inputs.iter().map(|input| input.value()))
+}
 
-            if Ok(input_value) >= (output_value + fee.to_coin()) { break; }
+/// strategy choosing the inputs to spend in a transaction
+///
+/// Implement it to use a custom strategy with `LinearFee::compute_with`.
+pub trait CoinSelector {
+    /// select, among the `available` inputs, the inputs to spend for
+    /// outputs of a total of `output_value`.
+    ///
+    /// `fee` returns the fee to pay when spending the given inputs. The
+    /// selected inputs must cover the outputs and the fee, or the selection
+    /// fails with `Error::NotEnoughInput`.
+    fn select<'a, Addressing, F>(&self, available: Vec<&'a Input<Addressing>>, output_value: Coin, fee: &F)
+            -> Result<Vec<&'a Input<Addressing>>>
+        where F: Fn(&[&'a Input<Addressing>]) -> Result<Fee>
+    ;
+}
+
+/// value the inputs provide once the fee is paid
+fn net_value<'a, Addressing, F>(inputs: &[&'a Input<Addressing>], fee: &F) -> Result<u64>
+    where F: Fn(&[&'a Input<Addressing>]) -> Result<Fee>
+{
+    let input_value = u64::from(input_sum(inputs)?);
+    let fee = u64::from(fee(inputs)?.to_coin());
+    Ok(input_value.saturating_sub(fee))
+}
+
+/// select the inputs in the given order until they cover the outputs and
+/// the fee, returns the selected inputs and the ones left.
+fn select_in_order<'a, Addressing, F>(available: Vec<&'a Input<Addressing>>, output_value: Coin, fee: &F)
+        -> Result<(Vec<&'a Input<Addressing>>, Vec<&'a Input<Addressing>>)>
+    where F: Fn(&[&'a Input<Addressing>]) -> Result<Fee>
+{
+    let mut selected = Vec::new();
+    let mut available = available.into_iter();
+    while let Some(input) = available.next() {
+        selected.push(input);
+        if net_value(&selected, fee)? >= u64::from(output_value) {
+            return Ok((selected, available.collect()));
         }
+    }
+    Err(Error::NotEnoughInput)
+}
 
-        if Ok(input_value) < (output_value + fee.to_coin()) {
-            return Err(Error::NotEnoughInput);
+/// select the first inputs that matches, no optimisation
+#[derive(Debug, Clone, Copy)]
+pub struct FirstMatchFirst;
+impl CoinSelector for FirstMatchFirst {
+    fn select<'a, Addressing, F>(&self, available: Vec<&'a Input<Addressing>>, output_value: Coin, fee: &F)
+            -> Result<Vec<&'a Input<Addressing>>>
+        where F: Fn(&[&'a Input<Addressing>]) -> Result<Fee>
+    {
+        Ok(select_in_order(available, output_value, fee)?.0)
+    }
+}
+
+/// select the largest inputs first, spending as few inputs as possible
+#[derive(Debug, Clone, Copy)]
+pub struct LargestFirst;
+impl CoinSelector for LargestFirst {
+    fn select<'a, Addressing, F>(&self, mut available: Vec<&'a Input<Addressing>>, output_value: Coin, fee: &F)
+            -> Result<Vec<&'a Input<Addressing>>>
+        where F: Fn(&[&'a Input<Addressing>]) -> Result<Fee>
+    {
+        available.sort_by(|a, b| b.value().cmp(&a.value()));
+        Ok(select_in_order(available, output_value, fee)?.0)
+    }
+}
+
+/// select random inputs until the outputs are covered, then keep adding
+/// random inputs as long as it brings the change closer to the value of
+/// the outputs (without exceeding twice this value).
+///
+/// This makes the change outputs look like payments and leaves the
+/// UTxO set with inputs of sizes similar to the payments being made.
+/// The random choices are derived from the given seed.
+#[derive(Debug, Clone, Copy)]
+pub struct RandomImprove(pub u64);
+impl CoinSelector for RandomImprove {
+    fn select<'a, Addressing, F>(&self, mut available: Vec<&'a Input<Addressing>>, output_value: Coin, fee: &F)
+            -> Result<Vec<&'a Input<Addressing>>>
+        where F: Fn(&[&'a Input<Addressing>]) -> Result<Fee>
+    {
+        let mut rng = XorShift::new(self.0);
+        for i in (1..available.len()).rev() {
+            let j = (rng.next() % (i as u64 + 1)) as usize;
+            available.swap(i, j);
         }
 
-        Ok((fee, selected_inputs, (input_value - output_value - fee.to_coin())?))
+        let (mut selected, remaining) = select_in_order(available, output_value, fee)?;
+
+        // the ideal change is the value of the outputs
+        let target = u64::from(output_value).saturating_mul(2);
+        let upper_bound = u64::from(output_value).saturating_mul(3);
+        let distance = |value: u64| if value > target { value - target } else { target - value };
+
+        let mut current = net_value(&selected, fee)?;
+        for input in remaining {
+            selected.push(input);
+            let value = net_value(&selected, fee)?;
+            if value <= upper_bound && distance(value) < distance(current) {
+                current = value;
+            } else {
+                selected.pop();
+            }
+        }
+        Ok(selected)
+    }
+}
+
+// xorshift64*: enough to shuffle the inputs, without depending on
+// a random number generator crate
+struct XorShift(u64);
+impl XorShift {
+    fn new(seed: u64) -> Self { XorShift(if seed == 0 { 0x9e3779b97f4a7c15 } else { seed }) }
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
     }
 }
 
@@ -222,11 +391,27 @@ impl SelectionAlgorithm for LinearFee {
 #[derive(Serialize, Deserialize, PartialEq, Eq, Debug, Clone, Copy)]
 pub enum SelectionPolicy {
     /// select the first inputs that matches, no optimisation
-    FirstMatchFirst
+    FirstMatchFirst,
+    /// select the largest inputs first (see `LargestFirst`)
+    LargestFirst,
+    /// select random inputs, using the given seed (see `RandomImprove`)
+    RandomImprove(u64),
 }
 impl Default for SelectionPolicy {
     fn default() -> Self { SelectionPolicy::FirstMatchFirst }
 }
+impl CoinSelector for SelectionPolicy {
+    fn select<'a, Addressing, F>(&self, available: Vec<&'a Input<Addressing>>, output_value: Coin, fee: &F)
+            -> Result<Vec<&'a Input<Addressing>>>
+        where F: Fn(&[&'a Input<Addressing>]) -> Result<Fee>
+    {
+        match self {
+            SelectionPolicy::FirstMatchFirst => FirstMatchFirst.select(available, output_value, fee),
+            SelectionPolicy::LargestFirst => LargestFirst.select(available, output_value, fee),
+            SelectionPolicy::RandomImprove(seed) => RandomImprove(*seed).select(available, output_value, fee),
+        }
+    }
+}
 
 
 #[cfg(test)]
@@ -264,4 +449,111 @@ mod test {
         test_milli_mul_eq(         241, 900001_900);
         test_milli_mul_eq(         241,        400);
     }
+
+    #[test]
+    fn fee_for_mainnet_parameters() {
+        let alg = LinearFee::new(Milli::integral(155381), Milli::new(43,946));
+        // 155381 + 43.946 * 51
+        assert_eq!(alg.fee_for(0, 0, 0), 157623);
+        // 155381 + 43.946 * (51 + 186 + 86)
+        assert_eq!(alg.fee_for(1, 1, 0), 169576);
+        // 155381 + 43.946 * (51 + 2 * 186 + 3 * 86 + 100)
+        assert_eq!(alg.fee_for(2, 3, 100), 189703);
+    }
+
+    #[test]
+    fn fee_for_is_an_upper_bound() {
+        use address::{ExtendedAddr, AddrType, SpendingData, Attributes};
+        use hdpayload::{HDKey, Path};
+        use hdwallet::{XPrv, XPRV_SIZE};
+        use tx::{TxIn, TxId, TxWitness};
+        use config::ProtocolMagic;
+
+        let xprv = XPrv::normalize_bytes([3;XPRV_SIZE]);
+        let hdap = HDKey::new(&xprv.public()).encrypt_path(&Path::new(vec![0x80000000, 0x80000000]));
+        let addr = ExtendedAddr::new(AddrType::ATPubKey, SpendingData::PubKeyASD(xprv.public()), Attributes::new_bootstrap_era(Some(hdap)));
+        let txid = TxId::from_slice(&[0;32]).unwrap();
+
+        let tx = Tx::new_with(
+            vec![TxIn::new(txid.clone(), 0xffffffff)],
+            vec![TxOut::new(addr, Coin::new(45_000_000_000_000_000).unwrap())]
+        );
+        let witness = TxWitness::from(vec![TxInWitness::new(ProtocolMagic::default(), &xprv, &tx.id())]);
+        let txaux = TxAux::new(tx, witness);
+
+        let alg = LinearFee::default();
+        let fee = alg.calculate_for_txaux(&txaux).unwrap();
+        assert!(alg.fee_for(1, 1, 0) >= u64::from(fee.to_coin()));
+    }
+
+    fn selection_address(seed: u8) -> ::address::ExtendedAddr {
+        use address::{ExtendedAddr, AddrType, SpendingData, Attributes};
+        use hdwallet::{XPrv, XPRV_SIZE};
+
+        let xprv = XPrv::normalize_bytes([seed;XPRV_SIZE]);
+        ExtendedAddr::new(AddrType::ATPubKey, SpendingData::PubKeyASD(xprv.public()), Attributes::new_bootstrap_era(None))
+    }
+
+    fn selection_utxos(values: &[u64]) -> Vec<Input<()>> {
+        use tx::{TxIn, TxId};
+
+        values.iter().enumerate().map(|(index, value)| {
+            Input::new(
+                TxIn::new(TxId::new(&[index as u8]), index as u32),
+                TxOut::new(selection_address(1), Coin::new(*value).unwrap()),
+                ()
+            )
+        }).collect()
+    }
+
+    fn select<S: CoinSelector>(selector: &S, utxos: &[Input<()>], payment: u64) -> Result<(Fee, Vec<u64>, Coin)> {
+        let outputs = vec![TxOut::new(selection_address(2), Coin::new(payment).unwrap())];
+        let output_policy = OutputPolicy::One(selection_address(3));
+        let (fee, selected, change) = LinearFee::default().compute_with(selector, utxos.iter(), outputs.iter(), &output_policy)?;
+        Ok((fee, selected.iter().map(|input| u64::from(input.value())).collect(), change))
+    }
+
+    #[test]
+    fn selection_strategies_on_fixed_utxos() {
+        let utxos = selection_utxos(&[1_000_000, 5_000_000, 2_000_000, 10_000_000, 3_000_000]);
+
+        let (first_fee, first, first_change) = select(&FirstMatchFirst, &utxos, 4_000_000).unwrap();
+        assert_eq!(first, vec![1_000_000, 5_000_000]);
+        assert_eq!(u64::from(first_change), 6_000_000 - 4_000_000 - u64::from(first_fee.to_coin()));
+
+        // a single input: a smaller transaction, so a smaller fee
+        let (largest_fee, largest, largest_change) = select(&LargestFirst, &utxos, 4_000_000).unwrap();
+        assert_eq!(largest, vec![10_000_000]);
+        assert!(largest_fee < first_fee);
+        assert_eq!(u64::from(largest_change), 10_000_000 - 4_000_000 - u64::from(largest_fee.to_coin()));
+
+        // the policy dispatches to the same strategies
+        assert_eq!(select(&SelectionPolicy::LargestFirst, &utxos, 4_000_000).unwrap().1, largest);
+        assert_eq!(select(&SelectionPolicy::FirstMatchFirst, &utxos, 4_000_000).unwrap().1, first);
+
+        for selector in &[SelectionPolicy::FirstMatchFirst, SelectionPolicy::LargestFirst, SelectionPolicy::RandomImprove(42)] {
+            match select(selector, &utxos, 21_000_000) {
+                Err(Error::NotEnoughInput) => {},
+                r => panic!("expected not enough input with {:?}, got {:?}", selector, r),
+            }
+        }
+    }
+
+    #[test]
+    fn random_improve_change_close_to_payment() {
+        let utxos = selection_utxos(&[1_000_000; 20]);
+
+        // the fewest inputs covering the payment and the fee
+        let (_, first, first_change) = select(&FirstMatchFirst, &utxos, 3_000_000).unwrap();
+        assert_eq!(first.len(), 4);
+
+        let (fee, random, change) = select(&RandomImprove(42), &utxos, 3_000_000).unwrap();
+        assert!(random.len() > first.len());
+        assert!(change > first_change);
+        assert!(change >= Coin::new(2_000_000).unwrap() && change <= Coin::new(4_000_000).unwrap());
+        assert_eq!(u64::from(change), random.len() as u64 * 1_000_000 - 3_000_000 - u64::from(fee.to_coin()));
+
+        // the same seed makes the same choices
+        assert_eq!(select(&RandomImprove(42), &utxos, 3_000_000).unwrap().1, random);
+    }
 }
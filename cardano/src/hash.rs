@@ -34,7 +34,7 @@ pub type Result<T> = result::Result<T, Error>;
 pub const HASH_SIZE : usize = 32;
 
 /// Blake2b 256 bits
-#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
 pub struct Blake2b256([u8;HASH_SIZE]);
 impl AsRef<[u8]> for Blake2b256 {
     fn as_ref(&self) -> &[u8] { self.0.as_ref() }
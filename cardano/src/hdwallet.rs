@@ -1086,6 +1086,16 @@ mod tests {
 
         assert_eq!(xpub1_ref, xpub1);
     }
+
+    #[test]
+    fn xprv_is_zeroed_on_drop() {
+        // keep the memory of the key around once it is dropped, this is
+        // as close as it gets to look at what is left behind
+        let mut xprv = ::std::mem::ManuallyDrop::new(XPrv::from_bytes_verified(D1).unwrap());
+        assert!(xprv.as_ref().iter().any(|b| *b != 0));
+        unsafe { ::std::ptr::drop_in_place(&mut *xprv) };
+        assert!(xprv.as_ref().iter().all(|b| *b == 0));
+    }
 }
 
 #[cfg(test)]
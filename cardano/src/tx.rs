@@ -275,6 +275,8 @@ impl Tx {
     pub fn new_with(ins: Vec<TxIn>, outs: Vec<TxOut>) -> Self {
         Tx { inputs: ins, outputs: outs }
     }
+    /// the identifier of the transaction: the hash of its CBOR encoding,
+    /// as referenced by the `TxIn`s spending its outputs
     pub fn id(&self) -> TxId {
         let buf = cbor!(self).expect("encode Tx");
         TxId::new(&buf)
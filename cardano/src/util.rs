@@ -1,19 +1,19 @@
 pub mod securemem {
 
+    use std::{ptr, sync::atomic};
+
     /// zero the given slice.
     ///
-    /// We assume the compiler won't optimise out the call to this function
+    /// The bytes are written with volatile writes, which the compiler does
+    /// not optimise out even if the slice is never read again (e.g. when
+    /// called from `Drop`), nor reorders after the following code.
     pub fn zero(to_zero: &mut [u8]) {
-
-        // the unsafety of this call is bounded to the existence of the pointer
-        // and the accuracy of the length of the array.
-        //
-        // since to_zero existence is bound to live at least as long as the call
-        // of this function and that we use the length (in bytes) of the given
-        // slice, this call is safe.
-        unsafe {
-            ::std::ptr::write_bytes(to_zero.as_mut_ptr(), 0, to_zero.len())
+        for byte in to_zero.iter_mut() {
+            // the pointer comes from a mutable reference: it is valid
+            // and aligned for the write, so this call is safe.
+            unsafe { ptr::write_volatile(byte, 0) }
         }
+        atomic::compiler_fence(atomic::Ordering::SeqCst);
     }
 }
 
@@ -164,13 +164,20 @@ pub mod base58 {
     pub enum Error {
         /// error when a given character is not part of the supported
         /// base58 `ALPHABET`. Contains the index of the faulty byte.
-        UnknownSymbol(usize)
+        UnknownSymbol(usize),
+        /// error returned by `decode_lenient` when a given character is not
+        /// part of the supported base58 `ALPHABET`. Contains the index of
+        /// the faulty character in the input and the character itself.
+        InvalidCharacter { position: usize, character: char }
     }
     impl ::std::fmt::Display for Error {
         fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
             match self {
                 &Error::UnknownSymbol(idx) => {
                     write!(f, "Unknown symbol at byte index {}", idx)
+                },
+                &Error::InvalidCharacter { position, character } => {
+                    write!(f, "Invalid character `{}' at position {}", character.escape_default(), position)
                 }
             }
         }
@@ -230,6 +237,36 @@ pub mod base58 {
         base_decode(ALPHABET, input)
     }
 
+    /// decode from base58 the given user input
+    ///
+    /// the surrounding whitespaces are ignored. Note that base58 is case
+    /// sensitive, the case of the characters is kept as is.
+    ///
+    /// # Errors
+    ///
+    /// returns `Error::InvalidCharacter` with the position of the first
+    /// character (from the beginning of the untrimmed input) that is not
+    /// part of the `ALPHABET`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use cardano::util::base58;
+    ///
+    /// let encoded = " TcgsE5dzphUWfjcb9i5\n";
+    /// let decoded = b"Hello World...";
+    ///
+    /// assert_eq!(decoded, base58::decode_lenient(encoded).unwrap().as_slice());
+    /// ```
+    pub fn decode_lenient(input: &str) -> Result<Vec<u8>> {
+        let offset = input.chars().take_while(|c| c.is_whitespace()).count();
+        let trimmed = input.trim();
+        if let Some((position, character)) = trimmed.chars().enumerate().find(|&(_, c)| ! ALPHABET.contains(c)) {
+            return Err(Error::InvalidCharacter { position: offset + position, character: character });
+        }
+        decode(trimmed)
+    }
+
     #[cfg(test)]
     mod tests {
         fn encode(input: &[u8], expected: &str) {
@@ -288,6 +325,22 @@ pub mod base58 {
             encode(b"abcdefghijklmnopqrstuvwxyz", "3yxU3u1igY8WkgtjK92fbJQCd4BZiiT1v25f");
             decode(b"abcdefghijklmnopqrstuvwxyz", "3yxU3u1igY8WkgtjK92fbJQCd4BZiiT1v25f");
         }
+
+        #[test]
+        fn decode_lenient_trims_whitespaces() {
+            let decoded = super::decode_lenient("\t TcgsE5dzphUWfjcb9i5 \n").unwrap();
+            assert_eq!(decoded.as_slice(), b"Hello World...");
+        }
+
+        #[test]
+        fn decode_lenient_invalid_character() {
+            assert_eq!( super::decode_lenient("  TcgsE5dz0hUWfjcb9i5")
+                      , Err(super::Error::InvalidCharacter { position: 10, character: '0' })
+                      );
+            assert_eq!( super::decode_lenient("Tcgs E5dz")
+                      , Err(super::Error::InvalidCharacter { position: 4, character: ' ' })
+                      );
+        }
     }
 
 
@@ -241,42 +241,22 @@ impl Deref for Account<XPub> {
 impl scheme::Account for Account<XPub> {
     type Addressing = (bip44::AddrType, u32);
 
-    fn generate_addresses<'a, I>(&'a self, addresses: I) -> Vec<ExtendedAddr>
-        where I: Iterator<Item = &'a Self::Addressing>
-    {
-        let (hint_low, hint_max) = addresses.size_hint();
-        let mut vec = Vec::with_capacity(hint_max.unwrap_or(hint_low));
-
-        for addressing in addresses {
-            let key = self.cached_root_key
-                          .change(self.derivation_scheme, addressing.0).expect("cannot fail")
-                          .index(self.derivation_scheme, addressing.1).expect("cannot fail");
-            let addr = ExtendedAddr::new_simple(key.0);
-            vec.push(addr);
-        }
-
-        vec
+    fn generate_address(&self, addressing: &Self::Addressing) -> ExtendedAddr {
+        let key = self.cached_root_key
+                      .change(self.derivation_scheme, addressing.0).expect("cannot fail")
+                      .index(self.derivation_scheme, addressing.1).expect("cannot fail");
+        ExtendedAddr::new_simple(key.0)
     }
 }
 impl scheme::Account for Account<XPrv> {
     type Addressing = (bip44::AddrType, u32);
 
-    fn generate_addresses<'a, I>(&'a self, addresses: I) -> Vec<ExtendedAddr>
-        where I: Iterator<Item = &'a Self::Addressing>
-    {
-        let (hint_low, hint_max) = addresses.size_hint();
-        let mut vec = Vec::with_capacity(hint_max.unwrap_or(hint_low));
-
-        for addressing in addresses {
-            let key = self.cached_root_key
-                          .change(self.derivation_scheme, addressing.0)
-                          .index(self.derivation_scheme, addressing.1)
-                          .public();
-            let addr = ExtendedAddr::new_simple(key.0);
-            vec.push(addr);
-        }
-
-        vec
+    fn generate_address(&self, addressing: &Self::Addressing) -> ExtendedAddr {
+        let key = self.cached_root_key
+                      .change(self.derivation_scheme, addressing.0)
+                      .index(self.derivation_scheme, addressing.1)
+                      .public();
+        ExtendedAddr::new_simple(key.0)
     }
 }
 
@@ -85,11 +85,7 @@ impl Wallet {
                 // i.e. it is possible to a mean player to reuse existing
                 // payload in their own addresses to make recipient believe
                 // they have received funds. This check prevents that to happen.
-                let addresses = scheme::Account::generate_addresses(account, [addressing].iter());
-
-                debug_assert!(addresses.len() == 1, "we expect to generate only one address here...");
-
-                if address == &addresses[0] {
+                if address == &scheme::Account::generate_address(account, &addressing) {
                     return Some(addressing);
                 }
             }
@@ -211,9 +207,14 @@ impl scheme::Wallet for Wallet {
 impl scheme::Account for RootKey {
     type Addressing = Addressing;
 
+    fn generate_address(&self, addressing: &Self::Addressing) -> ExtendedAddr {
+        self.address_generator().address(addressing)
+    }
+
     fn generate_addresses<'a, I>(&'a self, addresses: I) -> Vec<ExtendedAddr>
         where I: Iterator<Item = &'a Self::Addressing>
     {
+        // share the same generator (and its cached keys) for all the addresses
         self.address_generator().iter_with(addresses).collect()
     }
 }
@@ -93,6 +93,13 @@ pub trait Account {
     /// from a wallet point of view.
     type Addressing;
 
+    /// generate the address associated to the given addressing
+    fn generate_address(&self, addressing: &Self::Addressing) -> ExtendedAddr;
+
+    /// generate the addresses associated to each of the given addressings
     fn generate_addresses<'a, I>(&'a self, addresses: I) -> Vec<ExtendedAddr>
-        where I: Iterator<Item = &'a Self::Addressing>;
+        where I: Iterator<Item = &'a Self::Addressing>
+    {
+        addresses.map(|addressing| self.generate_address(addressing)).collect()
+    }
 }
@@ -73,7 +73,9 @@ impl<T: Deserialize> Deserialize for Vec<T> {
                     let t = raw.cbor_type()?;
                     if t == Type::Special {
                         let special = raw.special()?;
-                        assert_eq!(special, Special::Break);
+                        if special != Special::Break {
+                            return Err(Error::CustomError(format!("expected a break, got {:?}", special)));
+                        }
                         false
                     } else {
                         vec.push(Deserialize::deserialize(raw)?);
@@ -100,7 +102,9 @@ impl<K: Deserialize+Ord, V: Deserialize> Deserialize for BTreeMap<K,V> {
                     let t = raw.cbor_type()?;
                     if t == Type::Special {
                         let special = raw.special()?;
-                        assert_eq!(special, Special::Break);
+                        if special != Special::Break {
+                            return Err(Error::CustomError(format!("expected a break, got {:?}", special)));
+                        }
                         false
                     } else {
                         let k = Deserialize::deserialize(raw)?;
@@ -253,6 +257,14 @@ impl<'a> RawCbor<'a> {
         }
     }
     #[inline]
+    fn slice(&self, start: usize, end: usize) -> Result<&'a [u8]> {
+        if self.0.len() < end {
+            Err(Error::NotEnough(self.len(), end))
+        } else {
+            Ok(&self.0[start..end])
+        }
+    }
+    #[inline]
     fn u8(&self, index: usize) -> Result<u64> {
         let b = self.get(index)?;
         Ok(b as u64)
@@ -461,7 +473,7 @@ impl<'a> RawCbor<'a> {
             Len::Len(len) => {
                 let start = 1 + len_sz;
                 let end   = start + len as usize;
-                let bytes = Bytes::from(&self.0[start..end as usize]);
+                let bytes = Bytes::from(self.slice(start, end)?);
                 self.advance(end)?;
                 Ok(bytes)
             }
@@ -492,7 +504,7 @@ impl<'a> RawCbor<'a> {
             Len::Len(len) => {
                 let start = 1 + len_sz;
                 let end   = start + len as usize;
-                let bytes = &self.0[start..end as usize];
+                let bytes = self.slice(start, end)?;
                 let text = String::from_utf8(Vec::from(bytes))?;
                 self.advance(end)?;
                 Ok(text)
@@ -684,6 +696,16 @@ mod test {
         let bytes = raw.bytes().unwrap();
         assert!(bytes.is_empty());
     }
+    #[test]
+    fn bytes_not_enough() {
+        let vec = vec![0x52, 0x73, 0x6F, 0x6D, 0x65];
+        let mut raw = RawCbor::from(&vec);
+
+        match raw.bytes() {
+            Err(Error::NotEnough(5, 19)) => {},
+            r => panic!("expected NotEnough, got {:?}", r),
+        }
+    }
 
     #[test]
     fn text() {
@@ -136,7 +136,9 @@ impl Deserialize for Value {
                             let t = raw.cbor_type()?;
                             if t == Type::Special {
                                 let special = raw.special()?;
-                                assert_eq!(special, Special::Break);
+                                if special != Special::Break {
+                                    return Err(Error::CustomError(format!("expected a break, got {:?}", special)));
+                                }
                                 false
                             } else {
                                 vec.push(Deserialize::deserialize(raw)?);
@@ -162,7 +164,9 @@ impl Deserialize for Value {
                             let t = raw.cbor_type()?;
                             if t == Type::Special {
                                 let special = raw.special()?;
-                                assert_eq!(special, Special::Break);
+                                if special != Special::Break {
+                                    return Err(Error::CustomError(format!("expected a break, got {:?}", special)));
+                                }
                                 false
                             } else {
                                 let k = Deserialize::deserialize(raw)?;
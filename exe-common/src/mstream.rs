@@ -5,6 +5,7 @@ use std::io;
 use std::fmt;
 
 use network::{Result, Error};
+use protocol::ntt::ReadTimeout;
 
 pub struct MetricStart {
     bytes_start: u64,
@@ -107,3 +108,10 @@ impl Write for MStream {
     }
     fn flush(&mut self) -> io::Result<()> { self.stream.flush() }
 }
+
+impl ReadTimeout for MStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> { self.stream.read_timeout() }
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+}
@@ -1,7 +1,8 @@
-use std::{io};
+use std::{io, net::SocketAddr};
 use protocol::{self, ntt};
 use hyper;
 use cbor_event;
+use cardano::block::{HeaderHash, BlockDate, BlockDecodeError};
 
 #[derive(Debug)]
 pub enum Error {
@@ -9,9 +10,31 @@ pub enum Error {
     NttError(ntt::Error),
     ProtocolError(protocol::Error),
     CborError(cbor_event::Error),
+    BlockDecodeError(BlockDecodeError),
     HyperError(hyper::Error),
     ConnectionTimedOut,
+    /// the host name did not resolve to any address
+    AddressNotResolved(String),
     HttpError(String, hyper::StatusCode),
+    /// the first block received is not the configured genesis block
+    /// (expected hash, received hash)
+    GenesisMismatch(HeaderHash, HeaderHash),
+    /// a block received does not link to the previous one (block hash,
+    /// its parent hash, expected parent hash)
+    InvalidChain(HeaderHash, HeaderHash, HeaderHash),
+    /// the date of a block received does not follow the date of its
+    /// parent, e.g. a missing epoch boundary (block hash, parent date,
+    /// block date)
+    InvalidBlockDate(HeaderHash, BlockDate, BlockDate),
+    /// the command failed on every connection it has been tried on
+    /// (the address of the peer and the error, in the order tried)
+    AllPeersFailed(Vec<(SocketAddr, Error)>),
+    /// there is no peer to try the command on: no peer has been added
+    /// and there is no connection in the pool
+    NoPeers,
+    /// the peer answered with another number of headers or blocks than
+    /// asked for (expected, received)
+    UnexpectedResponseLength(usize, usize),
 }
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self { Error::IoError(e) }
@@ -28,3 +51,6 @@ impl From<ntt::Error> for Error {
 impl From<cbor_event::Error> for Error {
     fn from(e: cbor_event::Error) -> Self { Error::CborError(e) }
 }
+impl From<BlockDecodeError> for Error {
+    fn from(e: BlockDecodeError) -> Self { Error::BlockDecodeError(e) }
+}
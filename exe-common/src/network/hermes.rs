@@ -1,4 +1,4 @@
-use cardano::{block::{block, Block, BlockHeader, BlockDate, RawBlock, HeaderHash}, tx::{TxAux}};
+use cardano::{block::{block, Block, BlockHeader, BlockDate, RawBlock, HeaderHash, SLOTS_PER_EPOCH}, tx::{TxAux}};
 use cardano::hash::HASH_SIZE;
 use storage;
 use std::io::Write;
@@ -113,7 +113,7 @@ impl Api for HermesEndPoint {
 
             // FIXME: hack
             if let BlockDate::Normal(d) = from.date {
-                if d.slotid == 21599 && !inclusive {
+                if d.is_last_of_epoch(SLOTS_PER_EPOCH) && !inclusive {
                     from = BlockRef {
                         hash: HeaderHash::from_bytes([0;HASH_SIZE]), // FIXME: use None?
                         parent: from.hash.clone(),
@@ -2,8 +2,8 @@ use protocol;
 use mstream::{MStream, MetricStart, MetricStats};
 use cardano::{config::{ProtocolMagic}};
 use rand;
-use std::{net::{SocketAddr, ToSocketAddrs}, ops::{Deref, DerefMut}};
-use cardano::{block::{Block, BlockHeader, RawBlock, HeaderHash}, tx::{TxAux}};
+use std::{io, net::{SocketAddr, ToSocketAddrs}, ops::{Deref, DerefMut}, cmp::Reverse, collections::{HashMap, HashSet}, time::{Duration, Instant}};
+use cardano::{block::{Block, BlockHeader, EpochId, RawBlock, HeaderHash}, tx::{TxAux}};
 use protocol::command::*;
 
 use network::{Error, Result};
@@ -24,23 +24,30 @@ pub struct PeerPool {
 }
 impl PeerPool {
     pub fn new(name: String, address: String, protocol_magic: ProtocolMagic) -> Result<Self> {
-        let mut connections = Vec::new();
-        for sockaddr in address.to_socket_addrs()? {
-            match Connection::new(sockaddr, protocol_magic) {
-                Ok(connection) => {
-                    connections.push(connection);
-                    break
-                },
-                Err(Error::ConnectionTimedOut) => {
-                    warn!("connection peer `{}' address {} timed out, ignoring for now.", name, sockaddr)
-                },
-                Err(err) => {
-                    error!("connection peer `{}' address {} failed: {:?}", name, sockaddr, err);
-                    return Err(err)
-                },
+        let connection = match Connection::connect(&address, protocol_magic) {
+            Ok(connection) => connection,
+            Err(err) => {
+                error!("connection peer `{}' address {} failed: {:?}", name, address, err);
+                return Err(err)
             }
+        };
+        Ok(PeerPool { name, address, connections: vec![connection] })
+    }
+
+    /// see `OpenPeer::get_block_by_height`
+    pub fn get_block_by_height(&mut self, checkpoint: &HeaderHash, height: u64) -> Result<RawBlock> {
+        match self.connections.get_mut(0) {
+            None => panic!("We expect at lease one connection on any native peer"),
+            Some(conn) => conn.get_block_by_height(checkpoint, height)
+        }
+    }
+
+    /// see `OpenPeer::get_epoch_boundary`
+    pub fn get_epoch_boundary(&mut self, checkpoint: &HeaderHash, epoch: EpochId) -> Result<HeaderHash> {
+        match self.connections.get_mut(0) {
+            None => panic!("We expect at lease one connection on any native peer"),
+            Some(conn) => conn.get_epoch_boundary(checkpoint, epoch)
         }
-        Ok(PeerPool { name, address, connections })
     }
 }
 
@@ -93,12 +100,243 @@ impl Api for PeerPool {
     }
 }
 
+/// default number of connections `ConnectionPool::execute_command`
+/// tries a command on before giving up
+pub const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+/// priority of the peers that have not been added with
+/// `ConnectionPool::add_peer`
+pub const DEFAULT_PRIORITY: u32 = 0;
+
+/// default duration for which a peer a command failed on is tried only
+/// after the others by `ConnectionPool::execute_command`
+pub const DEFAULT_DOWN_DURATION: Duration = Duration::from_secs(30);
+
+/// cache of handshaked connections, keyed by the address of the peer
+///
+/// the connections are reused by the subsequent commands on the same
+/// address and closed once they have not been used for the given TTL.
+pub struct ConnectionPool {
+    protocol_magic: ProtocolMagic,
+    ttl: Duration,
+    max_attempts: usize,
+    down_duration: Duration,
+    connections: HashMap<SocketAddr, (Connection, Instant)>,
+    // the priorities of the peers added with `add_peer`
+    peers: HashMap<SocketAddr, u32>,
+    // the peers a command recently failed on, until when they are
+    // deprioritized
+    down_until: HashMap<SocketAddr, Instant>,
+}
+impl ConnectionPool {
+    pub fn new(protocol_magic: ProtocolMagic, ttl: Duration) -> Self {
+        ConnectionPool {
+            protocol_magic: protocol_magic,
+            ttl: ttl,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            down_duration: DEFAULT_DOWN_DURATION,
+            connections: HashMap::new(),
+            peers: HashMap::new(),
+            down_until: HashMap::new(),
+        }
+    }
+
+    /// add a peer `execute_command` tries the commands on, even when
+    /// there is no connection to it in the pool yet. The peers with the
+    /// highest priority are tried first, the peers not added here have
+    /// the `DEFAULT_PRIORITY`.
+    ///
+    /// Adding a peer again changes its priority.
+    pub fn add_peer(&mut self, addr: SocketAddr, priority: u32) {
+        self.peers.insert(addr, priority);
+    }
+
+    /// stop trying the commands on the given peer unless there is a
+    /// connection to it in the pool
+    pub fn remove_peer(&mut self, addr: &SocketAddr) {
+        self.peers.remove(addr);
+    }
+
+    /// set for how long a peer a command failed on is tried only after
+    /// the peers in good health, whatever its priority
+    pub fn set_down_duration(&mut self, down_duration: Duration) {
+        self.down_duration = down_duration;
+    }
+
+    pub fn get_down_duration(&self) -> Duration { self.down_duration }
+
+    /// set the maximum number of connections `execute_command` tries a
+    /// command on (at least one)
+    pub fn set_max_attempts(&mut self, max_attempts: usize) {
+        self.max_attempts = ::std::cmp::max(1, max_attempts);
+    }
+
+    pub fn get_max_attempts(&self) -> usize { self.max_attempts }
+
+    /// number of connections currently cached
+    pub fn len(&self) -> usize { self.connections.len() }
+
+    pub fn is_empty(&self) -> bool { self.connections.is_empty() }
+
+    /// close the connections that have not been used for longer than the TTL
+    pub fn evict_idle(&mut self) {
+        let ttl = self.ttl;
+        self.connections.retain(|addr, (_, last_used)| {
+            let keep = last_used.elapsed() < ttl;
+            if ! keep { debug!("closing idle connection to {}", addr) }
+            keep
+        });
+    }
+
+    /// run `f` with the connection to the given address, establishing
+    /// the connection if it is not in the pool already.
+    ///
+    /// If the cached connection has been closed by the peer in the meantime,
+    /// the connection is re-established and `f` is called again. On any
+    /// other error the connection is dropped from the pool.
+    pub fn with_connection<F, T>(&mut self, addr: SocketAddr, mut f: F) -> Result<T>
+        where F: FnMut(&mut Connection) -> Result<T>
+    {
+        self.evict_idle();
+
+        let (mut connection, cached) = match self.connections.remove(&addr) {
+            Some((connection, _)) => (connection, true),
+            None => (Connection::new(addr, self.protocol_magic)?, false)
+        };
+
+        let result = match f(&mut connection) {
+            Err(ref err) if cached && is_connection_closed(err) => {
+                info!("connection to {} closed by the peer, reconnecting", addr);
+                connection = Connection::new(addr, self.protocol_magic)?;
+                f(&mut connection)
+            },
+            result => result
+        };
+
+        if result.is_ok() {
+            self.connections.insert(addr, (connection, Instant::now()));
+        }
+        result
+    }
+
+    /// the peers `execute_command` tries, in order: the peers a command
+    /// failed on in the last `get_down_duration` come last, then the
+    /// peers with the highest priority come first and, for the same
+    /// priority, the most recently used connections.
+    fn candidates(&self, now: Instant) -> Vec<SocketAddr> {
+        let addrs : HashSet<SocketAddr> = self.peers.keys().chain(self.connections.keys()).cloned().collect();
+        let mut addrs : Vec<SocketAddr> = addrs.into_iter().collect();
+        addrs.sort_by_key(|addr| {
+            let down = self.down_until.get(addr).map_or(false, |until| *until > now);
+            let priority = self.peers.get(addr).cloned().unwrap_or(DEFAULT_PRIORITY);
+            let last_used = self.connections.get(addr).map(|(_, last_used)| *last_used);
+            (down, Reverse(priority), Reverse(last_used), *addr)
+        });
+        addrs
+    }
+
+    /// execute the command on one of the peers of the pool, see
+    /// `add_peer` for the order they are tried in.
+    ///
+    /// If the command fails because of the peer (see `is_recoverable`),
+    /// the failing connection is dropped from the pool, the peer is
+    /// deprioritized for `get_down_duration` and the command is tried on
+    /// the next peer, up to `get_max_attempts` peers. If none succeeds,
+    /// the errors of every attempt are returned in `Error::AllPeersFailed`.
+    ///
+    /// `Error::NoPeers` is returned, without trying anything, if there is
+    /// neither a peer nor a connection in the pool.
+    pub fn execute_command<C>(&mut self, cmd: &C) -> Result<C::Output>
+        where C: Command<MStream>
+    {
+        self.evict_idle();
+        let now = Instant::now();
+        self.down_until.retain(|_, until| *until > now);
+
+        let candidates = self.candidates(now);
+        if candidates.is_empty() { return Err(Error::NoPeers) }
+
+        let mut errors = Vec::new();
+        for addr in candidates.into_iter().take(self.max_attempts) {
+            match self.with_connection(addr, |connection| Ok(cmd.execute(&mut (connection.1).0)?)) {
+                Ok(output) => {
+                    self.down_until.remove(&addr);
+                    return Ok(output)
+                },
+                Err(err) => {
+                    if ! is_recoverable(&err) { return Err(err) }
+                    warn!("command failed on {}: {:?}, trying the next peer", addr, err);
+                    self.down_until.insert(addr, Instant::now() + self.down_duration);
+                    errors.push((addr, err));
+                }
+            }
+        }
+        Err(Error::AllPeersFailed(errors))
+    }
+}
+
+/// tell if the command failing with this error may succeed on another peer
+///
+/// a transaction rejected by a peer would be rejected by the others too.
+fn is_recoverable(err: &Error) -> bool {
+    match err {
+        Error::ProtocolError(protocol::Error::TransactionRejected) => false,
+        Error::IoError(_)
+            | Error::NttError(_)
+            | Error::ProtocolError(_)
+            | Error::CborError(_)
+            | Error::ConnectionTimedOut => true,
+        _ => false
+    }
+}
+
+/// tell if the error means that the peer closed the connection
+fn is_connection_closed(err: &Error) -> bool {
+    let ioerr = match err {
+        Error::IoError(e) => e,
+        Error::NttError(protocol::ntt::Error::IOError(e)) => e,
+        Error::ProtocolError(protocol::Error::IOError(e)) => e,
+        Error::ProtocolError(protocol::Error::NttError(protocol::ntt::Error::IOError(e))) => e,
+        _ => return false
+    };
+    match ioerr.kind() {
+        io::ErrorKind::UnexpectedEof
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::BrokenPipe
+            | io::ErrorKind::NotConnected => true,
+        _ => false
+    }
+}
+
 pub struct Connection(pub SocketAddr, pub OpenPeer);
 impl Connection {
     pub fn new(sockaddr: SocketAddr, protocol_magic: ProtocolMagic) -> Result<Self> {
         let network = OpenPeer::new(protocol_magic, &sockaddr)?;
         Ok(Connection (sockaddr, network))
     }
+
+    /// resolve the given address (a host name or an IPv4 or IPv6 address,
+    /// and the port) and connect to the first of the resolved addresses
+    /// that accepts the handshake.
+    ///
+    /// If none does, the error of the last attempt is returned.
+    pub fn connect(address: &str, protocol_magic: ProtocolMagic) -> Result<Self> {
+        let mut last_error = None;
+        for sockaddr in address.to_socket_addrs()? {
+            match Connection::new(sockaddr, protocol_magic) {
+                Ok(connection) => {
+                    info!("connected to {} ({})", address, sockaddr);
+                    return Ok(connection);
+                },
+                Err(err) => {
+                    warn!("connection to {} ({}) failed: {:?}, trying the next address", address, sockaddr, err);
+                    last_error = Some(err);
+                },
+            }
+        }
+        Err(last_error.unwrap_or_else(|| Error::AddressNotResolved(address.to_owned())))
+    }
 }
 impl Deref for Connection {
     type Target = OpenPeer;
@@ -108,6 +346,9 @@ impl DerefMut for Connection {
     fn deref_mut(&mut self) -> &mut Self::Target { & mut self.1 }
 }
 
+/// how long to wait for the node id of the peer after its handshake
+const HANDSHAKE_NODEID_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub struct OpenPeer(pub protocol::Connection<MStream>);
 
 impl OpenPeer {
@@ -118,9 +359,7 @@ impl OpenPeer {
 
         let stream = MStream::init(host)?;
 
-        let conn = protocol::ntt::Connection::handshake(drg_seed, stream)?;
-        let mut conne = protocol::Connection::new(conn);
-        conne.handshake(&hs)?;
+        let mut conne = protocol::Connection::establish_with_timeout(drg_seed, stream, &hs, HANDSHAKE_NODEID_TIMEOUT)?;
 
         // FIXME: make it configurable whether we want to subscribe to
         // receive tip updates.
@@ -129,6 +368,19 @@ impl OpenPeer {
         Ok(OpenPeer(conne))
     }
 
+    /// download the block at the given height, walking through the headers
+    /// from the checkpoint, see `protocol::command::GetBlockByHeight`
+    pub fn get_block_by_height(&mut self, checkpoint: &HeaderHash, height: u64) -> Result<RawBlock> {
+        Ok(GetBlockByHeight::new(checkpoint, height).execute(&mut self.0)?)
+    }
+
+    /// find the hash of the epoch boundary block of the given epoch, walking
+    /// through the headers from the checkpoint, see
+    /// `protocol::command::GetEpochBoundary`
+    pub fn get_epoch_boundary(&mut self, checkpoint: &HeaderHash, epoch: EpochId) -> Result<HeaderHash> {
+        Ok(GetEpochBoundary::new(checkpoint, epoch).execute(&mut self.0)?)
+    }
+
     pub fn read_start(&self) -> MetricStart {
         MetricStart::new(self.0.get_backend().get_read_sz())
     }
@@ -137,19 +389,23 @@ impl OpenPeer {
         start.diff(self.0.get_backend().get_read_sz())
     }
 }
+// the item of the answer to a command asking for a single header or block
+fn only_one<T>(mut items: Vec<T>) -> Result<T> {
+    if items.len() != 1 {
+        return Err(Error::UnexpectedResponseLength(1, items.len()));
+    }
+    Ok(items.remove(0))
+}
+
 impl Api for OpenPeer {
     fn get_tip(&mut self) -> Result<BlockHeader> {
 
         if let Some(prev_tip) = self.0.get_latest_tip() { return Ok(prev_tip) }
 
-        let block_headers_raw = GetBlockHeader::tip().execute(&mut self.0).expect("to get one header at least");
+        let block_headers_raw = GetBlockHeader::tip().execute(&mut self.0)?;
 
         let block_headers = block_headers_raw.decode()?;
-
-        if block_headers.len() != 1 {
-            panic!("get head header return more than 1 header")
-        }
-        Ok(block_headers[0].clone())
+        only_one(block_headers)
     }
 
     fn wait_for_new_tip(&mut self, prev_tip: &HeaderHash) -> Result<BlockHeader> {
@@ -165,10 +421,8 @@ impl Api for OpenPeer {
     }
 
     fn get_block(&mut self, hash: &HeaderHash) -> Result<RawBlock> {
-        let b = GetBlock::only(&hash).execute(&mut self.0)
-            .expect("to get one block at least");
-
-        Ok(RawBlock::from_dat(b[0].as_ref().to_vec()))
+        let blocks = GetBlock::only(&hash).execute(&mut self.0)?;
+        only_one(blocks)
     }
 
     fn get_blocks<F>( &mut self
@@ -179,89 +433,143 @@ impl Api for OpenPeer {
                     ) -> Result<()>
         where F: FnMut(&HeaderHash, &Block, &RawBlock) -> ()
     {
+        if inclusive {
+            if from.date > to.date { return Ok(()) }
+            info!("  ### get blocks [{}..{}]", from.hash, to.hash);
+        } else {
+            if from.date >= to.date { return Ok(()) }
+            info!("  ### get blocks ({}..{}]", from.hash, to.hash);
+        }
+
         let mut inclusive = inclusive;
         let mut from = from.clone();
+        let mut error = None;
+
+        let metrics = self.read_start();
+        PipelinedGetBlocks::new(&from.hash, inclusive, &to.hash, &to.parent)
+            .execute(&mut self.0, |blocks_raw| {
+                info!("  got {} blocks", blocks_raw.len());
+                if error.is_some() { return }
+
+                for block_raw in blocks_raw.iter() {
+                    let block = match block_raw.decode() {
+                        Ok(block) => block,
+                        Err(err) => { error = Some(err); return },
+                    };
+                    let hdr = block.get_header();
+                    let date = hdr.get_blockdate();
+                    let blockhash = hdr.compute_hash();
+
+                    if !inclusive && hdr.get_previous_header() != from.hash {
+                        panic!("previous header doesn't match: hash {} date {} got {} expected {}",
+                               blockhash, date, hdr.get_previous_header(), from.hash)
+                    }
+
+                    got_block(&blockhash, &block, &block_raw);
+
+                    from = BlockRef {
+                        hash: blockhash,
+                        parent: hdr.get_previous_header(),
+                        date: date
+                    };
+                    inclusive = false;
+                }
+            })?;
+        info!("  got the blocks  ( {} )", self.read_elapsed(&metrics));
 
-        loop {
-            // FIXME: Work around a GetBlockHeader bug: it fails on
-            // the interval (x.parent, x].
-            if (inclusive && from.hash == to.hash) || (!inclusive && from.hash == to.parent) {
-                let block_raw = self.get_block(&to.hash)?;
-                got_block(&to.hash, &block_raw.decode()?, &block_raw);
-                return Ok(());
-            }
-
-            if inclusive {
-                if from.date > to.date { break }
-                info!("  ### get headers [{}..{}]", from.hash, to.hash);
-            } else {
-                if from.date >= to.date { break }
-                info!("  ### get headers ({}..{}]", from.hash, to.hash);
-            }
-            let metrics = self.read_start();
-            let block_headers_raw = GetBlockHeader::range(
-                &vec![from.hash.clone()], to.hash.clone())
-                .execute(&mut self.0).expect("to get one header at least");
-            let hdr_metrics = self.read_elapsed(&metrics);
-            let block_headers = block_headers_raw.decode()?;
-            info!("  got {} headers  ( {} )", block_headers.len(), hdr_metrics);
-
-            assert!(!block_headers.is_empty());
-
-            let start = 0;
-            let end = block_headers.len() - 1;
-
-            info!("  start {} {} <- {}", block_headers[start].compute_hash(), block_headers[start].get_blockdate(), block_headers[start].get_previous_header());
-            info!("  end   {} {} <- {}", block_headers[end].compute_hash(), block_headers[end].get_blockdate(), block_headers[end].get_previous_header());
-
-            // The server will return the oldest ~2000 blocks starting at
-            // 'from'. However, they're in reverse order. Thus the last
-            // element of 'block_headers' should have 'from' as its
-            // parent.
-            assert!(block_headers[end].get_previous_header() == from.hash);
-
-            let start_hash = if inclusive { block_headers[end].get_previous_header() } else { block_headers[end].compute_hash() };
-            let end_hash = block_headers[start].compute_hash();
-
-            info!("  get blocks [{}..{}]", start_hash, end_hash);
+        match error {
+            None => Ok(()),
+            Some(err) => Err(err.into()),
+        }
+    }
 
-            let metrics = self.read_start();
-            let blocks_raw = GetBlock::from(&start_hash, &end_hash)
-                .execute(&mut self.0)
-                .expect("to get one block at least");
-            let blocks_metrics = self.read_elapsed(&metrics);
-            info!("  got {} blocks  ( {} )", blocks_raw.len(), blocks_metrics);
+    fn send_transaction( &mut self, txaux: TxAux) -> Result<bool> {
+        Ok(SendTx::new(txaux).execute(&mut self.0).map(|_| true)?)
+    }
+}
 
-            assert!(!blocks_raw.is_empty());
+#[cfg(test)]
+mod test {
+    use super::*;
 
-            for block_raw in blocks_raw.iter() {
-                let block = block_raw.decode()?;
-                let hdr = block.get_header();
-                let date = hdr.get_blockdate();
-                let blockhash = hdr.compute_hash();
+    fn addr(port: u16) -> SocketAddr { SocketAddr::from(([127, 0, 0, 1], port)) }
 
-                //info!("  got block {} {} prev {}", blockhash, date, hdr.get_previous_header());
+    // distinct local addresses nothing listens on: connecting to them
+    // is refused right away
+    fn refusing_addrs(count: usize) -> Vec<SocketAddr> {
+        let listeners : Vec<_> = (0..count).map(|_| ::std::net::TcpListener::bind(addr(0)).unwrap()).collect();
+        listeners.iter().map(|listener| listener.local_addr().unwrap()).collect()
+    }
 
-                if !inclusive && hdr.get_previous_header() != from.hash {
-                    panic!("previous header doesn't match: hash {} date {} got {} expected {}",
-                           blockhash, date, hdr.get_previous_header(), from.hash)
-                }
+    #[test]
+    fn execute_command_without_peers() {
+        let mut pool = ConnectionPool::new(ProtocolMagic::default(), Duration::from_secs(60));
+        match pool.execute_command(&GetBlockHeader::tip()) {
+            Err(Error::NoPeers) => {},
+            r => panic!("expected NoPeers, got {:?}", r.map(|_| ())),
+        }
+    }
 
-                got_block(&hdr.compute_hash(), &block, &block_raw);
+    #[test]
+    fn execute_command_tries_the_next_peers() {
+        let mut pool = ConnectionPool::new(ProtocolMagic::default(), Duration::from_secs(60));
+        let peers = refusing_addrs(3);
+        pool.add_peer(peers[0], 30);
+        pool.add_peer(peers[1], 20);
+        pool.add_peer(peers[2], 10);
+        pool.set_max_attempts(2);
+
+        // the peers are tried by priority, up to the maximum attempts
+        let tried : Vec<SocketAddr> = match pool.execute_command(&GetBlockHeader::tip()) {
+            Err(Error::AllPeersFailed(errors)) => errors.into_iter().map(|(addr, _)| addr).collect(),
+            r => panic!("expected AllPeersFailed, got {:?}", r.map(|_| ())),
+        };
+        assert_eq!(tried, vec![peers[0], peers[1]]);
+        assert!(pool.is_empty());
+
+        // and the ones that failed come after the others next time
+        let tried : Vec<SocketAddr> = match pool.execute_command(&GetBlockHeader::tip()) {
+            Err(Error::AllPeersFailed(errors)) => errors.into_iter().map(|(addr, _)| addr).collect(),
+            r => panic!("expected AllPeersFailed, got {:?}", r.map(|_| ())),
+        };
+        assert_eq!(tried, vec![peers[2], peers[0]]);
+    }
 
-                from = BlockRef {
-                    hash: blockhash,
-                    parent: hdr.get_previous_header(),
-                    date: date
-                };
-                inclusive = false;
-            }
+    #[test]
+    fn only_one_of_the_response() {
+        assert_eq!(only_one(vec![7]).unwrap(), 7);
+        match only_one::<u8>(vec![]) {
+            Err(Error::UnexpectedResponseLength(1, 0)) => {},
+            r => panic!("expected UnexpectedResponseLength, got {:?}", r),
+        }
+        match only_one(vec![1, 2]) {
+            Err(Error::UnexpectedResponseLength(1, 2)) => {},
+            r => panic!("expected UnexpectedResponseLength, got {:?}", r),
         }
-
-        Ok(())
     }
 
-    fn send_transaction( &mut self, txaux: TxAux) -> Result<bool> {
-        Ok(SendTx::new(txaux).execute(&mut self.0).map(|_| true)?)
+    #[test]
+    fn candidates_by_priority_and_health() {
+        let mut pool = ConnectionPool::new(ProtocolMagic::default(), Duration::from_secs(60));
+        assert!(pool.candidates(Instant::now()).is_empty());
+
+        pool.add_peer(addr(1), DEFAULT_PRIORITY);
+        pool.add_peer(addr(2), 10);
+        pool.add_peer(addr(3), 5);
+        pool.add_peer(addr(4), 10);
+        let now = Instant::now();
+        assert_eq!(pool.candidates(now), vec![addr(2), addr(4), addr(3), addr(1)]);
+
+        // a high priority peer that failed is tried after the others
+        pool.down_until.insert(addr(2), now + pool.get_down_duration());
+        assert_eq!(pool.candidates(now), vec![addr(4), addr(3), addr(1), addr(2)]);
+
+        // and by its priority again once it is not down anymore
+        let later = now + pool.get_down_duration() + Duration::from_secs(1);
+        assert_eq!(pool.candidates(later), vec![addr(2), addr(4), addr(3), addr(1)]);
+
+        pool.add_peer(addr(1), 20);
+        pool.remove_peer(&addr(4));
+        assert_eq!(pool.candidates(later), vec![addr(1), addr(2), addr(3)]);
     }
 }
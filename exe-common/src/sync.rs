@@ -1,8 +1,7 @@
 use config::net;
-use network::{Peer, api::Api, api::BlockRef, Result};
+use network::{Peer, api::Api, api::BlockRef, Result, Error as NetworkError};
 use storage::{self, tag, Error, block_read};
 use cardano::block::{BlockDate, EpochId, HeaderHash, BlockHeader};
-use cardano::util::{hex};
 use std::time::{SystemTime, Duration};
 use std::mem;
 
@@ -10,6 +9,66 @@ fn duration_print(d: Duration) -> String {
     format!("{}.{:03} seconds", d.as_secs(), d.subsec_millis())
 }
 
+/// the next block to accept from the network: the genesis block when
+/// syncing from scratch, a child of the last block received otherwise.
+///
+/// This prevents a peer from feeding us a chain that does not link back
+/// to the configured genesis block.
+#[derive(Debug, Clone)]
+pub enum NextBlock {
+    Is(HeaderHash),
+    ChildOf(HeaderHash),
+}
+impl NextBlock {
+    /// the block to accept first when fetching from the given tip, `inclusive`
+    /// as given to `Api::get_blocks`
+    pub fn new(from: &BlockRef, inclusive: bool) -> Self {
+        if inclusive {
+            NextBlock::Is(from.hash.clone())
+        } else {
+            NextBlock::ChildOf(from.hash.clone())
+        }
+    }
+
+    /// check the block of the given hash and parent hash is the one
+    /// expected, then expect a child of this block next
+    pub fn accept(&mut self, hash: &HeaderHash, parent: &HeaderHash) -> Result<()> {
+        self.check(hash, parent)?;
+        *self = NextBlock::ChildOf(hash.clone());
+        Ok(())
+    }
+
+    fn check(&self, hash: &HeaderHash, parent: &HeaderHash) -> Result<()> {
+        match self {
+            NextBlock::Is(expected) if expected != hash => {
+                Err(NetworkError::GenesisMismatch(expected.clone(), hash.clone()))
+            },
+            NextBlock::ChildOf(expected) if expected != parent => {
+                Err(NetworkError::InvalidChain(hash.clone(), parent.clone(), expected.clone()))
+            },
+            _ => Ok(())
+        }
+    }
+}
+
+/// check the date of a block follows the date of its parent: the genesis
+/// block of an epoch follows a block of the previous epoch, and the main
+/// blocks of an epoch follow its genesis block, slot after slot.
+fn check_block_date(hash: &HeaderHash, parent_date: &BlockDate, date: &BlockDate) -> Result<()> {
+    let valid = match (parent_date, date) {
+        (_, BlockDate::Genesis(epoch)) => parent_date.get_epochid() + 1 == *epoch,
+        (BlockDate::Genesis(epoch), BlockDate::Normal(slot)) => *epoch == slot.epoch,
+        (BlockDate::Normal(parent), BlockDate::Normal(slot)) => {
+            parent.epoch == slot.epoch && parent.slotid < slot.slotid
+        },
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(NetworkError::InvalidBlockDate(hash.clone(), parent_date.clone(), date.clone()))
+    }
+}
+
 struct EpochWriterState {
     epoch_id: EpochId,
     writer: storage::containers::packfile::Writer,
@@ -121,8 +180,31 @@ fn net_sync_to<A: Api>(
         maybe_create_epoch(storage, first_unstable_epoch - 1, &cur_hash);
     }
 
+    let mut next_block = NextBlock::new(&our_tip.0, our_tip.1);
+    let mut chain_error = None;
+
+    // the date of the parent of the next block, unknown for the genesis
+    // block of the chain
+    let mut parent_date = if our_tip.1 { None } else { Some(our_tip.0.date.clone()) };
+
     net.get_blocks(&our_tip.0, our_tip.1, &tip, &mut |block_hash, block, block_raw| {
+        // ignore the blocks following an invalid one
+        if chain_error.is_some() { return }
+        if let Err(err) = next_block.accept(block_hash, &block.get_header().get_previous_header()) {
+            error!("rejecting the chain sent by the peer: {:?}", err);
+            chain_error = Some(err);
+            return
+        }
+
         let date = block.get_header().get_blockdate();
+        if let Some(parent_date) = &parent_date {
+            if let Err(err) = check_block_date(block_hash, parent_date, &date) {
+                error!("rejecting the chain sent by the peer: {:?}", err);
+                chain_error = Some(err);
+                return
+            }
+        }
+        parent_date = Some(date.clone());
 
         // Flush the previous epoch (if any).
         if date.is_genesis() {
@@ -173,7 +255,10 @@ fn net_sync_to<A: Api>(
                             &storage::types::header_to_blockhash(&block_hash));
     }
 
-    Ok(())
+    match chain_error {
+        None => Ok(()),
+        Some(err) => Err(err),
+    }
 }
 
 /// Synchronize the local blockchain stored in `storage` with the
@@ -283,7 +368,7 @@ fn finish_epoch(storage: &storage::Storage, epoch_writer_state: EpochWriterState
 
     storage::epoch::epoch_create(&storage.config, &packhash, epoch_id);
 
-    info!("=> pack {} written for epoch {} in {}", hex::encode(&packhash[..]),
+    info!("=> pack {} written for epoch {} in {}", packhash,
           epoch_id, duration_print(epoch_time_elapsed));
 
     for hash in &epoch_writer_state.blobs_to_delete {
@@ -306,3 +391,208 @@ pub fn get_peer(blockchain: &str, cfg: &net::Config, native: bool) -> Peer {
 
     panic!("no peer to connect to")
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{block::{Block, EpochSlotId, RawBlock}, config::ProtocolMagic, hash::Blake2b256, tx::TxAux};
+    use cbor_event::{self, se::Serializer};
+    use config::net::Peers;
+    use std::{env, fs};
+
+    /// a main block of the mainnet, used as a template for the main blocks
+    /// of the test chains
+    const MAIN_BLOCK : &'static [u8] = &[130, 1, 131, 133, 26, 45, 150, 74, 9, 88, 32, 62, 112, 94, 154, 162, 127, 229, 78, 44, 102, 42, 10, 90, 168, 12, 54, 11, 212, 124, 226, 75, 185, 66, 157, 250, 79, 223, 23, 12, 45, 237, 129, 132, 131, 3, 88, 32, 10, 86, 22, 140, 149, 198, 120, 31, 227, 126, 104, 83, 155, 108, 239, 136, 206, 225, 180, 114, 225, 210, 154, 123, 227, 237, 73, 121, 41, 194, 156, 61, 88, 32, 79, 163, 255, 228, 159, 194, 53, 158, 174, 181, 226, 78, 112, 192, 122, 233, 82, 0, 12, 57, 201, 15, 166, 113, 149, 40, 182, 171, 39, 208, 57, 63, 130, 3, 88, 32, 211, 106, 38, 25, 166, 114, 73, 70, 4, 225, 27, 180, 71, 203, 207, 82, 49, 233, 242, 186, 37, 194, 22, 145, 119, 237, 201, 65, 189, 80, 173, 108, 88, 32, 175, 192, 218, 100, 24, 59, 242, 102, 79, 61, 78, 236, 114, 56, 213, 36, 186, 96, 127, 174, 234, 178, 79, 193, 0, 235, 134, 29, 186, 105, 151, 27, 88, 32, 78, 102, 40, 12, 217, 77, 89, 16, 114, 52, 155, 236, 10, 48, 144, 165, 58, 169, 69, 86, 46, 251, 109, 8, 213, 110, 83, 101, 75, 14, 64, 152, 132, 130, 1, 25, 55, 178, 88, 64, 27, 201, 122, 47, 224, 44, 41, 120, 128, 206, 142, 207, 217, 151, 254, 76, 30, 192, 158, 225, 15, 238, 238, 159, 104, 103, 96, 22, 107, 5, 40, 29, 98, 131, 70, 143, 253, 147, 190, 203, 12, 149, 108, 205, 221, 100, 45, 249, 177, 36, 76, 145, 89, 17, 24, 95, 164, 147, 85, 246, 242, 43, 250, 185, 129, 25, 139, 254, 130, 2, 130, 132, 0, 88, 64, 27, 201, 122, 47, 224, 44, 41, 120, 128, 206, 142, 207, 217, 151, 254, 76, 30, 192, 158, 225, 15, 238, 238, 159, 104, 103, 96, 22, 107, 5, 40, 29, 98, 131, 70, 143, 253, 147, 190, 203, 12, 149, 108, 205, 221, 100, 45, 249, 177, 36, 76, 145, 89, 17, 24, 95, 164, 147, 85, 246, 242, 43, 250, 185, 88, 64, 97, 38, 26, 149, 183, 97, 62, 230, 191, 32, 103, 218, 215, 123, 112, 52, 151, 41, 176, 197, 13, 87, 188, 28, 243, 13, 224, 219, 74, 30, 115, 168, 133, 208, 5, 74, 247, 194, 63, 198, 195, 121, 25, 219, 164, 28, 96, 42, 87, 226, 208, 249, 50, 154, 121, 84, 184, 103, 51, 141, 111, 178, 201, 69, 88, 64, 224, 62, 98, 240, 131, 223, 85, 118, 54, 14, 96, 163, 46, 34, 187, 176, 123, 60, 141, 244, 252, 171, 128, 121, 241, 214, 246, 26, 243, 149, 77, 36, 43, 168, 160, 101, 22, 195, 149, 147, 159, 36, 9, 111, 61, 241, 78, 16, 58, 125, 156, 43, 128, 166, 138, 147, 99, 207, 31, 39, 199, 164, 227, 7, 88, 64, 42, 100, 242, 153, 199, 254, 84, 67, 51, 137, 202, 116, 199, 207, 142, 44, 53, 255, 70, 58, 54, 18, 240, 140, 181, 106, 206, 181, 158, 252, 117, 219, 71, 72, 173, 124, 18, 247, 65, 137, 253, 229, 115, 105, 145, 72, 224, 252, 249, 120, 242, 145, 208, 193, 222, 166, 247, 245, 217, 138, 12, 177, 27, 5, 132, 131, 0, 0, 0, 130, 106, 99, 97, 114, 100, 97, 110, 111, 45, 115, 108, 1, 160, 88, 32, 75, 169, 42, 163, 32, 198, 10, 204, 154, 215, 185, 166, 79, 46, 218, 85, 196, 210, 236, 40, 230, 4, 250, 241, 134, 112, 139, 79, 12, 78, 142, 223, 132, 159, 130, 131, 159, 130, 0, 216, 24, 88, 36, 130, 88, 32, 196, 201, 143, 96, 200, 75, 77, 220, 200, 197, 238, 183, 77, 246, 208, 230, 58, 170, 131, 97, 127, 141, 150, 72, 27, 66, 38, 76, 115, 159, 62, 152, 1, 255, 159, 130, 130, 216, 24, 88, 66, 131, 88, 28, 109, 41, 37, 255, 14, 12, 164, 98, 33, 206, 227, 159, 180, 245, 102, 218, 174, 143, 145, 218, 231, 243, 166, 197, 27, 62, 176, 105, 161, 1, 88, 30, 88, 28, 156, 233, 149, 81, 19, 219, 223, 184, 26, 202, 202, 89, 7, 131, 173, 125, 28, 221, 19, 150, 254, 144, 90, 50, 43, 6, 46, 42, 0, 26, 132, 103, 17, 249, 27, 0, 0, 0, 2, 115, 156, 125, 31, 130, 130, 216, 24, 88, 66, 131, 88, 28, 198, 65, 169, 229, 147, 191, 175, 29, 108, 155, 53, 49, 126, 7, 55, 98, 103, 184, 234, 16, 227, 110, 150, 26, 14, 82, 238, 70, 161, 1, 88, 30, 88, 28, 202, 62, 85, 60, 156, 99, 197, 85, 63, 78, 15, 67, 192, 251, 32, 17, 249, 167, 65, 253, 190, 50, 79, 220, 219, 107, 108, 118, 0, 26, 85, 144, 176, 113, 27, 0, 0, 0, 7, 115, 89, 64, 0, 255, 160, 129, 130, 0, 216, 24, 88, 133, 130, 88, 64, 52, 33, 34, 217, 196, 36, 81, 143, 53, 26, 6, 104, 73, 172, 143, 127, 82, 47, 14, 92, 238, 235, 183, 157, 91, 219, 210, 229, 195, 239, 106, 129, 194, 10, 146, 48, 16, 248, 89, 121, 19, 60, 81, 167, 56, 39, 239, 167, 204, 54, 186, 230, 48, 7, 199, 49, 166, 61, 229, 28, 205, 153, 88, 151, 88, 64, 185, 100, 27, 141, 91, 107, 2, 249, 90, 103, 122, 45, 68, 15, 249, 66, 194, 175, 190, 156, 30, 207, 74, 146, 17, 80, 210, 145, 249, 144, 1, 199, 112, 93, 142, 235, 71, 241, 179, 88, 21, 156, 169, 97, 55, 68, 226, 174, 162, 166, 164, 195, 143, 123, 193, 189, 172, 32, 135, 145, 102, 251, 150, 13, 130, 131, 159, 130, 0, 216, 24, 88, 36, 130, 88, 32, 254, 249, 136, 177, 233, 204, 49, 255, 41, 187, 1, 103, 73, 165, 67, 240, 118, 89, 173, 97, 230, 119, 102, 61, 159, 29, 117, 241, 94, 249, 108, 155, 0, 255, 159, 130, 130, 216, 24, 88, 66, 131, 88, 28, 253, 232, 220, 241, 35, 230, 18, 203, 65, 245, 5, 98, 140, 94, 242, 66, 119, 141, 108, 102, 86, 53, 183, 246, 7, 162, 109, 54, 161, 1, 88, 30, 88, 28, 202, 62, 85, 60, 156, 99, 197, 54, 189, 86, 50, 67, 221, 70, 75, 55, 45, 223, 197, 30, 135, 48, 245, 33, 52, 83, 215, 212, 0, 26, 24, 39, 231, 206, 27, 0, 0, 68, 42, 61, 49, 72, 182, 130, 130, 216, 24, 88, 66, 131, 88, 28, 164, 97, 148, 87, 168, 130, 95, 44, 96, 48, 61, 203, 225, 14, 55, 237, 114, 162, 20, 215, 22, 208, 80, 228, 196, 56, 148, 92, 161, 1, 88, 30, 88, 28, 202, 62, 85, 60, 156, 99, 197, 127, 196, 34, 34, 67, 116, 107, 58, 95, 49, 200, 247, 77, 85, 7, 56, 21, 66, 246, 127, 127, 0, 26, 196, 69, 157, 80, 26, 0, 149, 137, 64, 255, 160, 129, 130, 0, 216, 24, 88, 133, 130, 88, 64, 155, 184, 74, 86, 173, 97, 208, 223, 214, 4, 126, 202, 70, 59, 110, 105, 26, 139, 232, 220, 6, 77, 0, 78, 92, 155, 121, 117, 33, 85, 182, 121, 10, 167, 156, 202, 239, 176, 76, 171, 95, 99, 108, 212, 143, 127, 147, 149, 146, 109, 86, 95, 231, 127, 215, 36, 197, 237, 231, 220, 62, 35, 150, 220, 88, 64, 35, 117, 37, 48, 190, 106, 102, 239, 185, 196, 100, 118, 185, 43, 127, 201, 118, 155, 180, 45, 51, 210, 22, 138, 191, 235, 42, 194, 88, 249, 50, 63, 179, 81, 60, 152, 42, 13, 78, 131, 156, 226, 150, 18, 165, 110, 168, 172, 166, 55, 169, 13, 135, 99, 93, 217, 37, 254, 29, 110, 149, 228, 107, 2, 130, 131, 159, 130, 0, 216, 24, 88, 36, 130, 88, 32, 199, 231, 1, 92, 250, 75, 68, 18, 224, 185, 52, 234, 204, 157, 167, 1, 160, 181, 154, 237, 242, 130, 41, 43, 77, 47, 164, 45, 158, 112, 122, 97, 0, 255, 159, 130, 130, 216, 24, 88, 66, 131, 88, 28, 163, 218, 5, 111, 245, 194, 8, 14, 101, 50, 34, 31, 29, 115, 41, 218, 45, 53, 104, 161, 65, 111, 93, 157, 220, 88, 50, 119, 161, 1, 88, 30, 88, 28, 212, 214, 100, 87, 247, 230, 137, 18, 233, 14, 67, 83, 249, 72, 243, 110, 203, 204, 34, 103, 73, 150, 185, 178, 143, 128, 107, 78, 0, 26, 181, 179, 13, 99, 27, 0, 0, 0, 75, 49, 142, 246, 128, 130, 130, 216, 24, 88, 66, 131, 88, 28, 6, 251, 79, 181, 192, 149, 80, 229, 54, 76, 214, 94, 36, 111, 110, 21, 71, 201, 75, 12, 182, 244, 84, 255, 253, 170, 124, 24, 161, 1, 88, 30, 88, 28, 202, 62, 85, 60, 156, 99, 197, 120, 165, 214, 82, 67, 73, 247, 123, 106, 164, 183, 94, 5, 188, 198, 45, 79, 156, 4, 67, 62, 0, 26, 125, 51, 214, 184, 27, 0, 0, 1, 27, 15, 159, 21, 146, 255, 160, 129, 130, 0, 216, 24, 88, 133, 130, 88, 64, 38, 89, 182, 201, 162, 103, 59, 81, 234, 18, 97, 102, 246, 232, 45, 127, 221, 63, 182, 36, 193, 177, 115, 84, 201, 172, 245, 43, 114, 161, 80, 197, 102, 139, 116, 190, 240, 163, 235, 16, 61, 190, 118, 12, 43, 129, 109, 238, 119, 3, 78, 105, 197, 20, 30, 186, 112, 158, 24, 1, 27, 208, 240, 201, 88, 64, 50, 40, 38, 231, 87, 89, 38, 206, 149, 84, 138, 12, 206, 233, 146, 156, 60, 39, 6, 111, 20, 177, 185, 25, 145, 135, 65, 46, 153, 206, 183, 141, 72, 223, 211, 154, 88, 187, 246, 84, 170, 54, 124, 84, 116, 144, 130, 40, 237, 254, 121, 108, 212, 242, 177, 213, 162, 150, 34, 1, 145, 220, 229, 1, 255, 130, 3, 217, 1, 2, 128, 159, 255, 130, 128, 159, 255, 129, 160];
+
+    fn genesis_block(epoch: EpochId, previous: &HeaderHash) -> RawBlock {
+        let bytes = Serializer::new_vec()
+            .write_array(cbor_event::Len::Len(2)).unwrap()
+            .write_unsigned_integer(0).unwrap()
+            .write_array(cbor_event::Len::Len(3)).unwrap()
+            // header: magic, previous, body proof, consensus, attributes
+            .write_array(cbor_event::Len::Len(5)).unwrap()
+            .serialize(&ProtocolMagic::default()).unwrap()
+            .serialize(previous).unwrap()
+            .serialize(&Blake2b256::new(&[])).unwrap()
+            .write_array(cbor_event::Len::Len(2)).unwrap()
+            .write_unsigned_integer(epoch).unwrap()
+            .write_array(cbor_event::Len::Len(1)).unwrap()
+            .write_unsigned_integer(0).unwrap()
+            .write_map(cbor_event::Len::Len(0)).unwrap()
+            // body: no slot leaders
+            .write_array(cbor_event::Len::Indefinite).unwrap()
+            .write_special(cbor_event::Special::Break).unwrap()
+            // extra
+            .write_array(cbor_event::Len::Len(1)).unwrap()
+            .write_map(cbor_event::Len::Len(0)).unwrap()
+            .finalize();
+        RawBlock::from_dat(bytes)
+    }
+
+    fn main_block(epoch: EpochId, slotid: u16, previous: &HeaderHash) -> RawBlock {
+        let mut block = match RawBlock::from_dat(MAIN_BLOCK.to_vec()).decode().unwrap() {
+            Block::MainBlock(block) => block,
+            Block::GenesisBlock(_) => unreachable!(),
+        };
+        block.header.previous_header = previous.clone();
+        block.header.consensus.slot_id = EpochSlotId { epoch, slotid };
+        RawBlock::from_dat(Serializer::new_vec().serialize(&Block::MainBlock(block)).unwrap().finalize())
+    }
+
+    /// a peer serving the given chain, from its genesis block
+    struct MockPeer(Vec<RawBlock>);
+    impl Api for MockPeer {
+        fn get_tip(&mut self) -> Result<BlockHeader> {
+            Ok(self.0.last().unwrap().decode()?.get_header())
+        }
+
+        fn wait_for_new_tip(&mut self, _prev_tip: &HeaderHash) -> Result<BlockHeader> {
+            panic!("not used by net_sync")
+        }
+
+        fn get_block(&mut self, hash: &HeaderHash) -> Result<RawBlock> {
+            let block = self.0.iter()
+                .find(|raw| raw.decode().unwrap().get_header().compute_hash() == *hash)
+                .unwrap();
+            Ok(RawBlock::from_dat(block.as_ref().to_vec()))
+        }
+
+        fn get_blocks<F>( &mut self
+                        , from: &BlockRef
+                        , inclusive: bool
+                        , to: &BlockRef
+                        , got_block: &mut F
+                        ) -> Result<()>
+            where F: FnMut(&HeaderHash, &Block, &RawBlock) -> ()
+        {
+            let mut started = false;
+            for raw in self.0.iter() {
+                let block = raw.decode()?;
+                let hash = block.get_header().compute_hash();
+                if hash == from.hash {
+                    started = true;
+                    if ! inclusive { continue }
+                }
+                if started { got_block(&hash, &block, raw) }
+                if hash == to.hash { break }
+            }
+            Ok(())
+        }
+
+        fn send_transaction(&mut self, _txaux: TxAux) -> Result<bool> {
+            panic!("not used by net_sync")
+        }
+    }
+
+    fn hash(raw: &RawBlock) -> HeaderHash {
+        raw.decode().unwrap().get_header().compute_hash()
+    }
+
+    #[test]
+    fn sync_across_epoch_boundary() {
+        let genesis_prev = HeaderHash::new(b"genesis-1");
+        let mut chain = vec![genesis_block(0, &genesis_prev)];
+        for &(epoch, slotid) in [(0, 0), (0, 1)].iter() {
+            let previous = hash(chain.last().unwrap());
+            chain.push(main_block(epoch, slotid, &previous));
+        }
+        let previous = hash(chain.last().unwrap());
+        chain.push(genesis_block(1, &previous));
+        for &(epoch, slotid) in [(1, 0), (1, 1)].iter() {
+            let previous = hash(chain.last().unwrap());
+            chain.push(main_block(epoch, slotid, &previous));
+        }
+        let hashes : Vec<HeaderHash> = chain.iter().map(hash).collect();
+
+        let net_cfg = net::Config {
+            genesis: hashes[0].clone(),
+            genesis_prev: genesis_prev,
+            // the epoch 0 is stable as soon as the epoch 1 has a main block
+            epoch_stability_depth: 0,
+            protocol_magic: ProtocolMagic::default(),
+            epoch_start: 0,
+            peers: Peers::new(),
+            linear_fee: Default::default(),
+        };
+
+        let dir = env::temp_dir().join(format!("exe-common-sync-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let storage_cfg = storage::config::StorageConfig::new(&dir);
+        let storage = storage::Storage::init(&storage_cfg).unwrap();
+
+        net_sync(&mut MockPeer(chain), &net_cfg, &storage, true).unwrap();
+
+        // open the storage again to see the packs written
+        let storage = storage::Storage::init(&storage_cfg).unwrap();
+
+        // the epoch 0 is packed, with its genesis block, the blocks of
+        // the epoch 1 are stored on their own
+        assert!(epoch_exists(&storage, 0));
+        assert!(! epoch_exists(&storage, 1));
+        assert_eq!(tag::read_hash(&storage, &tag::HEAD), Some(hashes[5].clone()));
+        for hash in hashes.iter() {
+            let block = block_read(&storage, hash.bytes()).unwrap().decode().unwrap();
+            assert_eq!(block.get_header().compute_hash(), *hash);
+        }
+        let genesis = block_read(&storage, hashes[3].bytes()).unwrap().decode().unwrap();
+        assert!(genesis.get_header().get_blockdate().is_genesis());
+        assert_eq!(genesis.get_header().get_previous_header(), hashes[2]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn check_epoch_boundaries() {
+        let hash = HeaderHash::new(b"block");
+        let genesis = |epoch| BlockDate::Genesis(epoch);
+        let main = |epoch, slotid| BlockDate::Normal(EpochSlotId { epoch, slotid });
+
+        assert!(check_block_date(&hash, &genesis(0), &main(0, 0)).is_ok());
+        assert!(check_block_date(&hash, &main(0, 0), &main(0, 1)).is_ok());
+        assert!(check_block_date(&hash, &main(0, 21599), &genesis(1)).is_ok());
+
+        // the genesis block of the epoch 1 is missing
+        match check_block_date(&hash, &main(0, 21599), &main(1, 0)) {
+            Err(NetworkError::InvalidBlockDate(_, parent, date)) => {
+                assert_eq!(parent, main(0, 21599));
+                assert_eq!(date, main(1, 0));
+            },
+            r => panic!("expected an invalid block date, got {:?}", r),
+        }
+        // an epoch is skipped
+        assert!(check_block_date(&hash, &main(0, 21599), &genesis(2)).is_err());
+        // the main blocks go back in time
+        assert!(check_block_date(&hash, &main(0, 2), &main(0, 1)).is_err());
+        assert!(check_block_date(&hash, &genesis(1), &main(0, 3)).is_err());
+    }
+
+    #[test]
+    fn reject_forged_genesis() {
+        let genesis = HeaderHash::new(b"genesis");
+        let forged = HeaderHash::new(b"forged genesis");
+        let parent = HeaderHash::new(b"genesis-1");
+
+        assert!(NextBlock::Is(genesis.clone()).check(&genesis, &parent).is_ok());
+        match NextBlock::Is(genesis.clone()).check(&forged, &parent) {
+            Err(NetworkError::GenesisMismatch(expected, got)) => {
+                assert_eq!(expected, genesis);
+                assert_eq!(got, forged);
+            },
+            r => panic!("expected a genesis mismatch, got {:?}", r),
+        }
+
+        // a block building on the forged genesis does not link to ours
+        let block = HeaderHash::new(b"block");
+        let mut next_block = NextBlock::Is(genesis.clone());
+        next_block.accept(&genesis, &parent).unwrap();
+        match next_block.clone().accept(&block, &forged) {
+            Err(NetworkError::InvalidChain(hash, parent, expected)) => {
+                assert_eq!(hash, block);
+                assert_eq!(parent, forged);
+                assert_eq!(expected, genesis);
+            },
+            r => panic!("expected an invalid chain, got {:?}", r),
+        }
+        assert!(next_block.accept(&block, &genesis).is_ok());
+    }
+}
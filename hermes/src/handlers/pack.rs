@@ -50,8 +50,7 @@ impl iron::Handler for Handler {
             Some(t) => t
         };
 
-        let mut packhash = [0;storage::types::HASH_SIZE];
-        packhash[..].clone_from_slice(packhash_vec.as_slice());
+        let packhash = storage::types::PackHash::from_slice(&packhash_vec).unwrap();
         let path = net.storage.config.get_pack_filepath(&packhash);
 
         Ok(Response::with((status::Ok, path)))
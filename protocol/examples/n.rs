@@ -9,7 +9,7 @@ use protocol::packet::{Handshake};
 
 use protocol::command::{Command};
 
-use protocol::{command, ntt, Connection};
+use protocol::{command, Connection};
 use cardano::{config::ProtocolMagic, hdwallet,
               wallet::scheme::{Wallet, Account},
               wallet::bip44,
@@ -42,9 +42,7 @@ fn main() {
     stream.set_nodelay(true).unwrap();
 
     info!("############## sending handshake to {}", HOST);
-    let conn = ntt::Connection::handshake(drg_seed, stream).unwrap();
-    let mut connection = Connection::new(conn);
-    connection.handshake(&hs).unwrap();
+    let mut connection = Connection::establish(drg_seed, stream, &hs).unwrap();
 
     if false {
         let mbh = command::GetBlockHeader::tip().execute(&mut connection)
@@ -71,12 +69,9 @@ fn main() {
 
         // 2. create a valid transaction
         let input_index = 2;
-        let input_addr = account.generate_addresses(
-            [(bip44::AddrType::External, input_index)].iter()).pop().unwrap();
-        let output_addr = account.generate_addresses(
-            [(bip44::AddrType::External, input_index + 1)].iter()).pop().unwrap();
-        let change_addr = account.generate_addresses(
-            [(bip44::AddrType::Internal, 1)].iter()).pop().unwrap();
+        let input_addr = account.generate_address(&(bip44::AddrType::External, input_index));
+        let output_addr = account.generate_address(&(bip44::AddrType::External, input_index + 1));
+        let change_addr = account.generate_address(&(bip44::AddrType::Internal, 1));
 
         let txin = tx::TxIn::new(tx::TxId::from_slice(&hex::decode("e276efdd613403ed096471c361b78f53b942de3904fbb142e838069e4374a793").unwrap()).unwrap(), 0);
         let addressing = bip44::Addressing::new(account_number, bip44::AddrType::External, input_index).unwrap();
@@ -1,12 +1,26 @@
+#![cfg_attr(feature = "with-bench", feature(test))]
+
 extern crate cardano;
 #[macro_use]
 extern crate log;
 #[macro_use]
 extern crate cbor_event;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde;
+#[cfg(test)]
+extern crate serde_json;
+
+#[cfg(test)]
+#[cfg(feature = "with-bench")]
+extern crate test;
 
 pub mod ntt;
 pub mod packet;
 
 mod protocol;
 
+#[cfg(test)]
+mod mock;
+
 pub use protocol::*;
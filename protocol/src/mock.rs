@@ -0,0 +1,55 @@
+//! in-memory transport to drive the connection state machines in tests
+
+use std::io::{self, Read, Write, Cursor};
+use std::time::Duration;
+
+use ntt::ReadTimeout;
+
+/// a `Read+Write` stream reading from a pre-recorded buffer and
+/// recording everything written to it, in `output`, and the buffer of
+/// each write, in `writes`.
+///
+/// once the buffer is exhausted, reads time out if a read timeout is set
+/// and return end of file otherwise.
+pub struct MockStream {
+    input: Cursor<Vec<u8>>,
+    pub output: Vec<u8>,
+    pub writes: Vec<Vec<u8>>,
+    read_timeout: Option<Duration>,
+    closed: bool,
+}
+impl MockStream {
+    pub fn new(input: Vec<u8>) -> Self {
+        MockStream { input: Cursor::new(input), output: Vec::new(), writes: Vec::new(), read_timeout: None, closed: false }
+    }
+
+    /// simulate the peer closing the transport: reads return end of
+    /// file and writes fail with a broken pipe from now on
+    pub fn close(&mut self) {
+        self.closed = true;
+    }
+}
+impl Read for MockStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.closed { return Ok(0) }
+        if self.read_timeout.is_some() && self.input.position() == self.input.get_ref().len() as u64 {
+            return Err(io::ErrorKind::WouldBlock.into())
+        }
+        self.input.read(buf)
+    }
+}
+impl ReadTimeout for MockStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> { Ok(self.read_timeout) }
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        self.read_timeout = timeout;
+        Ok(())
+    }
+}
+impl Write for MockStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.closed { return Err(io::ErrorKind::BrokenPipe.into()) }
+        self.writes.push(buf.to_vec());
+        self.output.write(buf)
+    }
+    fn flush(&mut self) -> io::Result<()> { Ok(()) }
+}
@@ -1,11 +1,29 @@
 use std::io::{Write, Read};
-use std::{iter, io, result};
+use std::{io, result, thread};
+use std::net::TcpStream;
+use std::time::Duration;
 use cardano::util::hex;
 
 pub type LightweightConnectionId = u32;
 
 pub const LIGHT_ID_MIN : u32 = 1024;
 
+/// default upper bound on the length of a single data frame we accept
+/// to allocate and read from the wire.
+pub const DEFAULT_MAX_PAYLOAD_SIZE : u32 = 16 * 1024 * 1024;
+
+/// default upper bound on the length of a single data frame we send,
+/// see `Connection::set_max_send_frame_size`.
+pub const DEFAULT_MAX_SEND_FRAME_SIZE : u32 = 2 * 1024 * 1024;
+
+/// default number of bytes of a data frame read from the transport at
+/// once, see `Connection::set_read_chunk_size`.
+pub const DEFAULT_READ_CHUNK_SIZE : usize = 64 * 1024;
+
+/// default number of bytes of frames buffered before they are written
+/// to the transport, see `Connection::set_write_buffer_size`.
+pub const DEFAULT_WRITE_BUFFER_SIZE : usize = 64 * 1024;
+
 pub struct EndPoint(Vec<u8>);
 impl AsRef<[u8]> for EndPoint {
     fn as_ref(&self) -> &[u8] { &self.0 }
@@ -24,7 +42,11 @@ pub enum Error {
     InvalidRequest,
     CrossedRequest,
     UnknownErrorCode(u32),
-    CommandFailed // TODO add command error in this sum type
+    /// the peer announced a data frame larger than the configured maximum
+    PayloadTooLarge(u32),
+    /// the peer sent a control frame with the given header, which is not
+    /// one of the `protocol::ControlHeader`
+    UnknownControl(u32),
 }
 impl From<io::Error> for Error {
     fn from(e: io::Error) -> Self { Error::IOError(e) }
@@ -32,10 +54,60 @@ impl From<io::Error> for Error {
 
 type Result<T> = result::Result<T, Error>;
 
+/// a transport whose reads can be bounded in time, see `Connection::try_recv`
+pub trait ReadTimeout {
+    fn read_timeout(&self) -> io::Result<Option<Duration>>;
+
+    /// reads blocking longer than the given duration fail with
+    /// `io::ErrorKind::WouldBlock` or `io::ErrorKind::TimedOut`
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()>;
+}
+impl ReadTimeout for TcpStream {
+    fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        TcpStream::read_timeout(self)
+    }
+    fn set_read_timeout(&mut self, timeout: Option<Duration>) -> io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+}
+
+/// counters of the traffic of a `Connection` since it was opened
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// control and data frames sent
+    pub frames_sent: u64,
+    /// control and data frames received
+    pub frames_received: u64,
+    /// bytes written to the transport, handshake included
+    pub bytes_sent: u64,
+    /// bytes read from the transport, handshake included
+    pub bytes_received: u64,
+    /// control frames received with an unknown header
+    pub unknown_controls_received: u64,
+}
+impl Stats {
+    /// the traffic since the `earlier` counters were taken
+    pub fn since(&self, earlier: &Stats) -> Stats {
+        Stats {
+            frames_sent: self.frames_sent - earlier.frames_sent,
+            frames_received: self.frames_received - earlier.frames_received,
+            bytes_sent: self.bytes_sent - earlier.bytes_sent,
+            bytes_received: self.bytes_received - earlier.bytes_received,
+            unknown_controls_received: self.unknown_controls_received - earlier.unknown_controls_received,
+        }
+    }
+}
+
 pub struct Connection<W: Sized> {
     stream: W,
     drg: u64,
     debug: bool,
+    max_payload_size: u32,
+    max_send_frame_size: u32,
+    read_chunk_size: usize,
+    write_buffer: Vec<u8>,
+    write_buffer_size: usize,
+    stats: Stats,
 }
 
 impl<W: Sized+Write+Read> Connection<W> {
@@ -44,13 +116,90 @@ impl<W: Sized+Write+Read> Connection<W> {
         &self.stream
     }
 
+    pub fn get_backend_mut(&mut self) -> &mut W {
+        &mut self.stream
+    }
+
     pub fn set_debug(&mut self) {
         self.debug = true
     }
 
+    /// set the maximum length of a data frame we accept to receive.
+    ///
+    /// lengths are announced by the peer, so any frame above this limit
+    /// is rejected before allocating the buffer to read it.
+    pub fn set_max_payload_size(&mut self, max_payload_size: u32) {
+        self.max_payload_size = max_payload_size
+    }
+
+    pub fn get_max_payload_size(&self) -> u32 {
+        self.max_payload_size
+    }
+
+    /// set the maximum length of a data frame we send.
+    ///
+    /// this is independent of `set_max_payload_size`, which only bounds
+    /// what the peer sends us: larger payloads are split in frames of at
+    /// most this length so they stay below what the peer accepts.
+    pub fn set_max_send_frame_size(&mut self, max_send_frame_size: u32) {
+        self.max_send_frame_size = ::std::cmp::max(1, max_send_frame_size)
+    }
+
+    pub fn get_max_send_frame_size(&self) -> u32 {
+        self.max_send_frame_size
+    }
+
+    /// set the number of bytes of a data frame read at once.
+    ///
+    /// the buffer of a frame grows one chunk at a time as its data
+    /// arrives, so a large frame does not allocate its whole announced
+    /// length upfront. The read is still blocking: the calling thread is
+    /// busy until the whole frame is received, it only yields its time
+    /// slice to the scheduler between the chunks.
+    pub fn set_read_chunk_size(&mut self, read_chunk_size: usize) {
+        self.read_chunk_size = ::std::cmp::max(1, read_chunk_size)
+    }
+
+    pub fn get_read_chunk_size(&self) -> usize {
+        self.read_chunk_size
+    }
+
+    /// set the number of bytes of frames kept in memory before being
+    /// written to the transport.
+    ///
+    /// The frames sent are buffered so the header and the data of a
+    /// frame, or the frames of a command, go out in a single write. The
+    /// buffer is written once it holds at least this many bytes, on
+    /// `flush` and before reading from the transport.
+    pub fn set_write_buffer_size(&mut self, write_buffer_size: usize) {
+        self.write_buffer_size = ::std::cmp::max(1, write_buffer_size)
+    }
+
+    pub fn get_write_buffer_size(&self) -> usize {
+        self.write_buffer_size
+    }
+
+    pub fn get_stats(&self) -> Stats {
+        self.stats
+    }
+
+    fn new(drg_seed: u64, stream: W) -> Self {
+        Connection {
+            stream: stream,
+            drg: drg_seed,
+            debug: false,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+            max_send_frame_size: DEFAULT_MAX_SEND_FRAME_SIZE,
+            read_chunk_size: DEFAULT_READ_CHUNK_SIZE,
+            write_buffer: Vec::new(),
+            write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+            stats: Stats::default(),
+        }
+    }
+
     pub fn handshake(drg_seed: u64, stream: W) -> Result<Self> {
         trace!("sending initial handshake");
-        let mut conn = Connection { stream: stream, drg: drg_seed, debug: false };
+        let mut conn = Connection::new(drg_seed, stream);
         let mut buf = vec![];
         protocol::handshake(&mut buf);
         conn.emit("handshake", &buf)?;
@@ -63,9 +212,40 @@ impl<W: Sized+Write+Read> Connection<W> {
         }
     }
 
+    /// accept the handshake of a peer connecting to us, the server side
+    /// of `handshake`.
+    pub fn accept(drg_seed: u64, stream: W) -> Result<Self> {
+        trace!("waiting for initial handshake");
+        let mut conn = Connection::new(drg_seed, stream);
+        let version = conn.recv_u32()?;
+        let handshake_length = conn.recv_u32()?;
+        let _handshake = conn.recv_len(handshake_length)?;
+        let _their_endpoint_id = conn.recv_u32()?;
+        let endpoint_length = conn.recv_u32()?;
+        let _their_endpoint = conn.recv_len(endpoint_length)?;
+
+        let mut buf = vec![];
+        let supported = protocol::handshake_response(version, &mut buf);
+        conn.emit("handshake response", &buf)?;
+        conn.flush()?;
+        if supported {
+            info!("HANDSHAKE OK");
+            Ok(conn)
+        } else {
+            Err(Error::UnsupportedVersion)
+        }
+    }
+
+    /// allocate the nonce of a new node id.
+    ///
+    /// Nonces are allocated in increasing order starting from the
+    /// `drg_seed` given to `handshake` or `accept`, wrapping around after
+    /// `Nonce::max_value()`. A nonce is only returned again once all the
+    /// others have been, the caller is responsible for checking it is not
+    /// still in use by then.
     pub fn get_nonce(&mut self) -> protocol::Nonce {
         let v = self.drg;
-        self.drg += 1;
+        self.drg = self.drg.wrapping_add(1);
         v
     }
 
@@ -73,6 +253,7 @@ impl<W: Sized+Write+Read> Connection<W> {
         assert!(cid >= LIGHT_ID_MIN);
         let mut buf = vec![];
         protocol::create_conn(cid, &mut buf);
+        self.stats.frames_sent += 1;
         self.emit("create-connection", &buf)
     }
 
@@ -80,9 +261,20 @@ impl<W: Sized+Write+Read> Connection<W> {
         assert!(cid >= LIGHT_ID_MIN);
         let mut buf = vec![];
         protocol::delete_conn(cid, &mut buf);
+        self.stats.frames_sent += 1;
         self.emit("close-connection", &buf)
     }
 
+    /// reply to a `ProbeSocket` of the peer, which is waiting for it to
+    /// tell the socket is still alive: the reply is written right away
+    pub fn ack_probe(&mut self) -> Result<()> {
+        let mut buf = vec![];
+        protocol::probe_socket_ack(&mut buf);
+        self.stats.frames_sent += 1;
+        self.emit("probe-socket-ack", &buf)?;
+        self.flush()
+    }
+
     pub fn send_endpoint(&mut self, endpoint: &EndPoint) -> Result<()> {
         let mut buf = vec![];
         protocol::append_with_length(endpoint.as_ref(), &mut buf);
@@ -92,21 +284,56 @@ impl<W: Sized+Write+Read> Connection<W> {
     pub fn light_send_data(&mut self, lwc: LightweightConnectionId, dat: &[u8]) -> Result<()> {
         let mut buf = vec![];
         protocol::append_lightweight_data(lwc, dat.len() as u32, &mut buf);
-        self.emit("send lightcon data header", &buf)?;
+        self.stats.frames_sent += 1;
+        self.buffer("send lightcon data header", &buf);
         self.emit("send lightcon data",  &dat)
     }
 
+    /// drop the frames buffered and not written yet, e.g. once the
+    /// transport they were meant for failed
+    pub fn discard_write_buffer(&mut self) {
+        self.write_buffer.clear();
+    }
+
+    /// write the buffered frames to the transport and flush it
+    pub fn flush(&mut self) -> Result<()> {
+        self.write_buffered()?;
+        self.stream.flush()?;
+        Ok(())
+    }
+
     // emit utility
     fn emit(&mut self, step: &str, dat: &[u8]) -> Result<()> {
+        self.buffer(step, dat);
+        if self.write_buffer.len() >= self.write_buffer_size {
+            self.write_buffered()?;
+        }
+        Ok(())
+    }
+
+    // append to the frames to write, without writing them even if the
+    // buffer is full: used to keep the parts of a frame together
+    fn buffer(&mut self, step: &str, dat: &[u8]) {
         trace!("{}, bytes({}): {:?}", step, dat.len(), hex::encode(dat));
-        self.stream.write_all(dat)?;
+        self.write_buffer.extend_from_slice(dat);
+    }
+
+    // write the buffered frames, if any, in a single write. This is done
+    // before each read as the peer may be waiting for them to answer.
+    fn write_buffered(&mut self) -> Result<()> {
+        if self.write_buffer.is_empty() { return Ok(()) }
+        let buf = ::std::mem::replace(&mut self.write_buffer, Vec::new());
+        self.stream.write_all(&buf)?;
+        self.stats.bytes_sent += buf.len() as u64;
         Ok(())
     }
 
     // TODO some kind of error
     fn recv_u32(&mut self) -> Result<u32> {
+        self.write_buffered()?;
         let mut buf = [0u8; 4];
         self.stream.read_exact(&mut buf)?;
+        self.stats.bytes_received += buf.len() as u64;
         let v = ((buf[0] as u32) << 24) |
                 ((buf[1] as u32) << 16) |
                 ((buf[2] as u32) << 8) |
@@ -116,13 +343,59 @@ impl<W: Sized+Write+Read> Connection<W> {
 
     pub fn recv(&mut self) -> Result<protocol::Command>  {
         let hdr = self.recv_u32()?;
+        self.recv_command(hdr)
+    }
+
+    /// same as `recv`, but returns `None` if no frame started to arrive
+    /// within the given duration.
+    ///
+    /// Only the wait for the first byte of the frame is bounded: once
+    /// it arrived, the rest of the frame header is read with the
+    /// transport's own timeout, so a frame is never left half read.
+    pub fn try_recv(&mut self, timeout: Duration) -> Result<Option<protocol::Command>>
+        where W: ReadTimeout
+    {
+        self.write_buffered()?;
+        let previous = self.stream.read_timeout()?;
+        self.stream.set_read_timeout(Some(timeout))?;
+        let mut first = [0u8; 1];
+        let read = self.stream.read(&mut first);
+        self.stream.set_read_timeout(previous)?;
+        match read {
+            Err(ref err) if err.kind() == io::ErrorKind::WouldBlock || err.kind() == io::ErrorKind::TimedOut => {
+                return Ok(None)
+            },
+            Err(err) => return Err(err.into()),
+            Ok(0) => return Err(io::Error::from(io::ErrorKind::UnexpectedEof).into()),
+            Ok(_) => {},
+        }
+        let mut rest = [0u8; 3];
+        self.stream.read_exact(&mut rest)?;
+        self.stats.bytes_received += 4;
+        let hdr = ((first[0] as u32) << 24) |
+                  ((rest[0] as u32) << 16) |
+                  ((rest[1] as u32) << 8) |
+                  (rest[2] as u32);
+        self.recv_command(hdr).map(Some)
+    }
+
+    // read the rest of the frame header starting with `hdr`
+    fn recv_command(&mut self, hdr: u32) -> Result<protocol::Command> {
+        self.stats.frames_received += 1;
         if hdr < LIGHT_ID_MIN {
             match protocol::ControlHeader::from_u32(hdr) {
-                Some(c)  => {
+                Some(c) if c.has_argument() => {
                     let r = self.recv_u32()?;
                     Ok(protocol::Command::Control(c, r))
                 },
-                None => Err(Error::CommandFailed)
+                Some(c) => Ok(protocol::Command::Control(c, 0)),
+                None => {
+                    // the length of what follows is unknown, the
+                    // transport cannot be read any further
+                    error!("received control frame with unknown header {:#x}", hdr);
+                    self.stats.unknown_controls_received += 1;
+                    Err(Error::UnknownControl(hdr))
+                },
             }
         } else {
             let len = self.recv_u32()?;
@@ -142,14 +415,26 @@ impl<W: Sized+Write+Read> Connection<W> {
         let lwc = self.recv_u32()?;
         trace!("received data: {}", lwc);
         let len = self.recv_u32()?;
-        let mut buf : Vec<u8> = iter::repeat(0).take(len as usize).collect();
-        self.stream.read_exact(&mut buf[..])?;
+        let buf = self.recv_len(len)?;
         Ok((lwc,buf))
     }
 
     pub fn recv_len(&mut self, len: u32) -> Result<Vec<u8>> {
-        let mut buf : Vec<u8> = iter::repeat(0).take(len as usize).collect();
-        self.stream.read_exact(&mut buf[..])?;
+        if len > self.max_payload_size {
+            error!("received data frame of {} bytes, maximum is {}", len, self.max_payload_size);
+            return Err(Error::PayloadTooLarge(len));
+        }
+        self.write_buffered()?;
+        let len = len as usize;
+        let mut buf : Vec<u8> = Vec::with_capacity(::std::cmp::min(len, self.read_chunk_size));
+        while buf.len() < len {
+            if buf.len() > 0 { thread::yield_now() }
+            let start = buf.len();
+            let end = ::std::cmp::min(len, start + self.read_chunk_size);
+            buf.resize(end, 0);
+            self.stream.read_exact(&mut buf[start..end])?;
+            self.stats.bytes_received += (end - start) as u64;
+        }
         trace!("received({}): {:?}", buf.len(), hex::encode(&buf));
         Ok(buf)
     }
@@ -161,13 +446,21 @@ pub mod protocol {
 
     const PROTOCOL_VERSION : u32 = 0x00000000;
 
-    #[derive(Debug)]
+    #[derive(Debug, PartialEq, Eq, Clone, Copy)]
     pub enum ControlHeader {
+        /// the sender created the light connection given in argument
         CreateNewConnection = 0,
+        /// the sender closed the light connection given in argument
         CloseConnection = 1,
+        /// the sender closes the socket, unless it did not receive yet
+        /// the light connections created after the one given in argument
         CloseSocket = 2,
+        /// the sender closes its end point, and so the socket
         CloseEndPoint = 3,
+        /// the sender checks the socket is alive, expecting a
+        /// `ProbeSocketAck` in reply
         ProbeSocket = 4,
+        /// the reply to a `ProbeSocket`
         ProbeSocketAck = 5,
     }
 
@@ -183,13 +476,47 @@ pub mod protocol {
                 _ => None,
             }
         }
+
+        /// tell if the header is followed by an argument (a light
+        /// connection id), the others are alone in their frame
+        pub fn has_argument(&self) -> bool {
+            match self {
+                ControlHeader::CreateNewConnection
+                    | ControlHeader::CloseConnection
+                    | ControlHeader::CloseSocket => true,
+                ControlHeader::CloseEndPoint
+                    | ControlHeader::ProbeSocket
+                    | ControlHeader::ProbeSocketAck => false,
+            }
+        }
     }
 
     #[derive(Debug)]
     pub enum Command {
+        /// a control frame and its argument, `0` for the controls
+        /// without one (see `ControlHeader::has_argument`)
         Control(ControlHeader, super::LightweightConnectionId),
         Data(super::LightweightConnectionId, u32),
     }
+    impl Command {
+        /// the light connection and the length of the data to read, if
+        /// this is a data frame
+        pub fn as_data(&self) -> Option<(super::LightweightConnectionId, u32)> {
+            match self {
+                Command::Data(cid, len) => Some((*cid, *len)),
+                Command::Control(_, _) => None,
+            }
+        }
+
+        /// the control and the light connection it applies to, if this
+        /// is a control frame
+        pub fn as_control(&self) -> Option<(ControlHeader, super::LightweightConnectionId)> {
+            match self {
+                Command::Control(ch, cid) => Some((*ch, *cid)),
+                Command::Data(_, _) => None,
+            }
+        }
+    }
 
     pub type Nonce = u64;
 
@@ -199,8 +526,11 @@ pub mod protocol {
         Ack,
     }
 
-    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
-    pub struct NodeId([u8;9]);
+    /// length of a node id: its header followed by its nonce
+    pub const NODEID_SIZE : usize = 9;
+
+    #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+    pub struct NodeId([u8;NODEID_SIZE]);
     impl fmt::Display for NodeId {
         fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
             write!(f, "{}", hex::encode(self.as_ref()))
@@ -234,9 +564,9 @@ pub mod protocol {
 
     impl NodeId {
         pub fn from_slice(slice: &[u8]) -> Option<Self> {
+            if slice.len() != NODEID_SIZE { return None }
             if slice[0] != NODEID_SYN && slice[0] != NODEID_ACK { return None }
-            if slice.len() != 9 { return None }
-            let mut buf = [0u8;9];
+            let mut buf = [0u8;NODEID_SIZE];
             buf.clone_from_slice(slice);
             Some(NodeId(buf))
         }
@@ -290,6 +620,18 @@ pub mod protocol {
         //append_u32(0, buf); // ignored but should be handshake length
     }
 
+    /// the answer to the peer's `handshake`, returns if the version of
+    /// the peer is supported
+    pub fn handshake_response(version: u32, buf: &mut Vec<u8>) -> bool {
+        if version == PROTOCOL_VERSION {
+            append_u32(0x00000000, buf);
+            true
+        } else {
+            append_u32(0xffffffff, buf);
+            false
+        }
+    }
+
     /// encode an int32
     /*
     fn append_i32(v: i32, buf: &mut Vec<u8>) {
@@ -323,8 +665,152 @@ pub mod protocol {
         append_u32(cid, buf);
     }
 
+    pub fn probe_socket_ack(buf: &mut Vec<u8>) {
+        append_u32(ControlHeader::ProbeSocketAck as u32, buf);
+    }
+
     pub fn append_with_length(dat: &[u8], buf: &mut Vec<u8>) {
         append_u32(dat.len() as u32, buf);
         buf.extend_from_slice(dat);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::MockStream;
+
+    fn connection(input: Vec<u8>) -> Connection<MockStream> {
+        Connection {
+            stream: MockStream::new(input),
+            drg: 0,
+            debug: false,
+            max_payload_size: DEFAULT_MAX_PAYLOAD_SIZE,
+            max_send_frame_size: DEFAULT_MAX_SEND_FRAME_SIZE,
+            read_chunk_size: DEFAULT_READ_CHUNK_SIZE,
+            write_buffer: Vec::new(),
+            write_buffer_size: DEFAULT_WRITE_BUFFER_SIZE,
+            stats: Stats::default(),
+        }
+    }
+
+    #[test]
+    fn frames_written_in_batches() {
+        let mut conn = connection(vec![0, 0, 0x04, 0x00, 0, 0, 0, 1, 7]);
+        conn.create_light(LIGHT_ID_MIN).unwrap();
+        conn.light_send_data(LIGHT_ID_MIN, &[1, 2, 3]).unwrap();
+        assert!(conn.get_backend().output.is_empty());
+        assert_eq!(conn.get_stats().bytes_sent, 0);
+
+        // the frames are written at once before waiting for the reply
+        let (lwc, bytes) = conn.recv_data().unwrap();
+        assert_eq!((lwc, bytes), (LIGHT_ID_MIN, vec![7]));
+        let mut expected = vec![];
+        protocol::create_conn(LIGHT_ID_MIN, &mut expected);
+        protocol::append_lightweight_data(LIGHT_ID_MIN, 3, &mut expected);
+        expected.extend(&[1, 2, 3]);
+        assert_eq!(conn.get_backend().writes, vec![expected.clone()]);
+        assert_eq!(conn.get_stats().bytes_sent, expected.len() as u64);
+
+        conn.close_light(LIGHT_ID_MIN).unwrap();
+        conn.flush().unwrap();
+        let mut close = vec![];
+        protocol::delete_conn(LIGHT_ID_MIN, &mut close);
+        assert_eq!(conn.get_backend().writes, vec![expected, close]);
+
+        // a full buffer is written without waiting for a flush
+        conn.set_write_buffer_size(16);
+        conn.light_send_data(LIGHT_ID_MIN, &[0; 7]).unwrap();
+        assert_eq!(conn.get_backend().writes.len(), 2);
+        conn.light_send_data(LIGHT_ID_MIN, &[0; 1]).unwrap();
+        assert_eq!(conn.get_backend().writes.len(), 3);
+        assert_eq!(conn.get_backend().writes[2].len(), 24);
+    }
+
+    #[test]
+    fn get_nonce_wraps_around() {
+        let mut conn = connection(vec![]);
+        conn.drg = protocol::Nonce::max_value() - 1;
+        assert_eq!(conn.get_nonce(), protocol::Nonce::max_value() - 1);
+        assert_eq!(conn.get_nonce(), protocol::Nonce::max_value());
+        assert_eq!(conn.get_nonce(), 0);
+        assert_eq!(conn.get_nonce(), 1);
+    }
+
+    #[test]
+    fn recv_data_within_limit() {
+        let mut conn = connection(vec![0, 0, 0x04, 0x00, 0, 0, 0, 3, 1, 2, 3]);
+        let (lwc, bytes) = conn.recv_data().unwrap();
+        assert_eq!(lwc, LIGHT_ID_MIN);
+        assert_eq!(bytes, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn recv_data_oversized_length() {
+        // announce a 4GiB frame without sending any of it
+        let mut conn = connection(vec![0, 0, 0x04, 0x00, 0xff, 0xff, 0xff, 0xff]);
+        match conn.recv_data() {
+            Err(Error::PayloadTooLarge(0xffffffff)) => {},
+            r => panic!("expected PayloadTooLarge, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn recv_len_custom_limit() {
+        let mut conn = connection(vec![1, 2, 3, 4]);
+        conn.set_max_payload_size(3);
+        match conn.recv_len(4) {
+            Err(Error::PayloadTooLarge(4)) => {},
+            r => panic!("expected PayloadTooLarge, got {:?}", r),
+        }
+        assert_eq!(conn.recv_len(3).unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn recv_len_in_chunks() {
+        let len = 3 * 1024 * 1024 + 17;
+        let frame : Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        for chunk_size in &[1000, DEFAULT_READ_CHUNK_SIZE, len, 2 * len] {
+            let mut conn = connection(frame.clone());
+            conn.set_read_chunk_size(*chunk_size);
+            assert!(conn.recv_len(len as u32).unwrap() == frame);
+            assert_eq!(conn.get_stats().bytes_received, len as u64);
+        }
+    }
+
+    #[test]
+    fn command_accessors() {
+        let mut conn = connection(vec![0, 0, 0, 0, 0, 0, 0x04, 0x00, 0, 0, 0x04, 0x01, 0, 0, 0, 3]);
+        let control = conn.recv().unwrap();
+        assert_eq!(control.as_control(), Some((protocol::ControlHeader::CreateNewConnection, LIGHT_ID_MIN)));
+        assert_eq!(control.as_data(), None);
+
+        let data = conn.recv().unwrap();
+        assert_eq!(data.as_data(), Some((LIGHT_ID_MIN + 1, 3)));
+        assert_eq!(data.as_control(), None);
+    }
+
+    #[test]
+    fn control_frames_with_and_without_argument() {
+        use self::protocol::ControlHeader;
+        let mut conn = connection(vec![0, 0, 0, 2, 0, 0, 0x04, 0x01, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0, 5, 0, 0, 0, 9, 0, 0, 0, 0]);
+        assert_eq!(conn.recv().unwrap().as_control(), Some((ControlHeader::CloseSocket, LIGHT_ID_MIN + 1)));
+        assert_eq!(conn.recv().unwrap().as_control(), Some((ControlHeader::CloseEndPoint, 0)));
+        assert_eq!(conn.recv().unwrap().as_control(), Some((ControlHeader::ProbeSocket, 0)));
+        assert_eq!(conn.recv().unwrap().as_control(), Some((ControlHeader::ProbeSocketAck, 0)));
+        assert_eq!(conn.get_stats().unknown_controls_received, 0);
+
+        match conn.recv() {
+            Err(Error::UnknownControl(9)) => {},
+            r => panic!("expected UnknownControl, got {:?}", r),
+        }
+        assert_eq!(conn.get_stats().unknown_controls_received, 1);
+    }
+
+    #[test]
+    fn ack_probe_is_written_right_away() {
+        let mut conn = connection(vec![]);
+        conn.ack_probe().unwrap();
+        assert_eq!(conn.get_backend().writes, vec![vec![0, 0, 0, 5]]);
+    }
+}
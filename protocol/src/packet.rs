@@ -2,17 +2,22 @@ use std::collections::{BTreeMap};
 use std::{fmt};
 use cardano::config::{ProtocolMagic};
 use cardano::block;
+#[cfg(feature = "commands")]
 use cardano::block::{HeaderHash};
+#[cfg(feature = "commands")]
 use cardano::tx;
 
 use cbor_event::{self, se, de::{self, RawCbor}};
 
-type MessageCode = u32;
+pub type MessageCode = u32;
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone)]
 pub struct HandlerSpec(u16);
 impl HandlerSpec {
     pub fn new(c: u16) -> Self { HandlerSpec(c) }
+
+    /// the message code of the reply to the message
+    pub fn code(&self) -> u16 { self.0 }
 }
 impl fmt::Display for HandlerSpec {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -88,6 +93,16 @@ impl HandlerSpecs {
         bm.insert(0x53,  HandlerSpec::new(0x00));
         HandlerSpecs(bm)
     }
+
+    /// how the given message is handled, `None` if it is not
+    pub fn get(&self, code: MessageCode) -> Option<&HandlerSpec> {
+        self.0.get(&code)
+    }
+
+    /// the handled messages, by message code
+    pub fn iter<'a>(&'a self) -> ::std::collections::btree_map::Iter<'a, MessageCode, HandlerSpec> {
+        self.0.iter()
+    }
 }
 impl se::Serialize for HandlerSpecs {
     fn serialize<W>(&self, serializer: se::Serializer<W>) -> cbor_event::Result<se::Serializer<W>>
@@ -170,22 +185,60 @@ impl cbor_event::de::Deserialize for Handshake {
 
 pub fn send_handshake(hs: &Handshake) -> Vec<u8> { cbor!(hs).unwrap() }
 
-pub type Message = (u8, Vec<u8>);
+/// a message: its identifier and its CBOR encoded content
+pub type Message = (MessageId, Vec<u8>);
+
+/// the identifiers of the messages, the byte prefixing their content
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
+pub enum MessageId {
+    GetHeaders,
+    Headers,
+    GetBlocks,
+    Subscribe,
+    AnnounceTx, // == InvOrData key TxMsgContents
+}
+impl MessageId {
+    /// the message of the given identifier byte, if known
+    pub fn from_u8(byte: u8) -> Option<Self> {
+        match byte {
+            0x04 => Some(MessageId::GetHeaders),
+            0x05 => Some(MessageId::Headers),
+            0x06 => Some(MessageId::GetBlocks),
+            0x0d => Some(MessageId::Subscribe),
+            0x25 => Some(MessageId::AnnounceTx),
+            _ => None,
+        }
+    }
+
+    pub fn as_u8(self) -> u8 {
+        match self {
+            MessageId::GetHeaders => 0x04,
+            MessageId::Headers => 0x05,
+            MessageId::GetBlocks => 0x06,
+            MessageId::Subscribe => 0x0d,
+            MessageId::AnnounceTx => 0x25,
+        }
+    }
 
-pub enum MsgType {
-    MsgGetHeaders = 0x4,
-    MsgHeaders = 0x5,
-    MsgGetBlocks = 0x6,
-    MsgSubscribe = 0xd,
-    MsgAnnounceTx = 0x25, // == InvOrData key TxMsgContents
+    /// the code of the message in the handlers of a `Handshake`
+    pub fn code(self) -> MessageCode { self.as_u8() as MessageCode }
+}
+impl From<MessageId> for u8 {
+    fn from(id: MessageId) -> u8 { id.as_u8() }
+}
+impl fmt::Display for MessageId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?} (0x{:02x})", self, self.as_u8())
+    }
 }
 
 pub fn send_msg_subscribe(keep_alive: bool) -> Message {
     let value = if keep_alive { 43 } else { 42 };
     let dat = se::Serializer::new_vec().write_unsigned_integer(value).unwrap().finalize();
-    (MsgType::MsgSubscribe as u8, dat)
+    (MessageId::Subscribe, dat)
 }
 
+#[cfg(feature = "commands")]
 pub fn send_msg_getheaders(froms: &[block::HeaderHash], to: &Option<block::HeaderHash>) -> Message {
     let serializer = se::Serializer::new_vec().write_array(cbor_event::Len::Len(2)).unwrap();
     let serializer = se::serialize_indefinite_array(froms.iter(), serializer).unwrap();
@@ -197,30 +250,34 @@ pub fn send_msg_getheaders(froms: &[block::HeaderHash], to: &Option<block::Heade
         }
     };
     let dat = serializer.finalize();
-    (MsgType::MsgGetHeaders as u8, dat)
+    (MessageId::GetHeaders, dat)
 }
 
+#[cfg(feature = "commands")]
 pub fn send_msg_getblocks(from: &HeaderHash, to: &HeaderHash) -> Message {
     let dat = se::Serializer::new_vec().write_array(cbor_event::Len::Len(2)).unwrap()
         .serialize(from).unwrap()
         .serialize(to).unwrap()
         .finalize();
-    (MsgType::MsgGetBlocks as u8, dat)
+    (MessageId::GetBlocks, dat)
 }
 
+#[cfg(feature = "commands")]
 pub fn send_msg_announcetx(txid: &tx::TxId) -> Message {
     let dat = se::Serializer::new_vec().write_array(cbor_event::Len::Len(2)).unwrap()
         .serialize(&0u8).unwrap() // == Left constructor of InvOrData (i.e. InvMsg)
         .serialize(txid).unwrap()
         .finalize();
-    (MsgType::MsgAnnounceTx as u8, dat)
+    (MessageId::AnnounceTx, dat)
 }
 
+#[cfg(feature = "commands")]
 #[derive(Debug)]
 pub enum BlockHeaderResponse {
     Ok(Vec<block::BlockHeader>),
     Err(String)
 }
+#[cfg(feature = "commands")]
 impl fmt::Display for BlockHeaderResponse {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -236,6 +293,7 @@ impl fmt::Display for BlockHeaderResponse {
         write!(f, "")
     }
 }
+#[cfg(feature = "commands")]
 impl de::Deserialize for BlockHeaderResponse {
     fn deserialize<'a>(raw: &mut RawCbor<'a>) -> cbor_event::Result<Self> {
         raw.tuple(2, "BlockHeaderResponse")?;
@@ -254,10 +312,12 @@ impl de::Deserialize for BlockHeaderResponse {
     }
 }
 
+#[cfg(feature = "commands")]
 #[derive(Debug)]
 pub enum BlockResponse {
     Ok(block::Block)
 }
+#[cfg(feature = "commands")]
 impl de::Deserialize for BlockResponse {
     fn deserialize<'a>(raw: &mut RawCbor<'a>) -> cbor_event::Result<Self> {
         raw.tuple(2, "BlockResponse")?;
@@ -274,11 +334,12 @@ impl de::Deserialize for BlockResponse {
 }
 
 #[cfg(test)]
-mod tests {
+pub mod tests {
     use super::*;
     use cbor_event::{de::{RawCbor}};
 
-    const GET_BLOCK_HEADER_BYTES : &'static [u8] = &[
+    #[cfg(feature = "commands")]
+    pub const GET_BLOCK_HEADER_BYTES : &'static [u8] = &[
           0x82, 0x00, 0x9f, 0x82, 0x01, 0x85, 0x1a, 0x2d
         , 0x96, 0x4a, 0x09, 0x58, 0x20, 0x9d, 0x63, 0xd4, 0x66, 0x7d, 0x43, 0x26, 0x09, 0x8b, 0x1a, 0xb9
         , 0xa9, 0x61, 0xef, 0x30, 0x35, 0xbc, 0xe2, 0x49, 0x99, 0x07, 0xa0, 0x31, 0x24, 0x95, 0x5f, 0xbd
@@ -321,6 +382,7 @@ mod tests {
         , 0x4f, 0x0c, 0x4e, 0x8e, 0xdf, 0xff
     ];
 
+    #[cfg(feature = "commands")]
     #[test]
     fn parse_get_block_headers_response() {
         let b = RawCbor::from(GET_BLOCK_HEADER_BYTES).deserialize().unwrap();
@@ -330,7 +392,8 @@ mod tests {
         }
     }
 
-    const HANDSHAKE_BYTES : &'static [u8] = &[
+    /// the default handshake, as sent by the mainnet nodes
+    pub const HANDSHAKE_BYTES : &'static [u8] = &[
         0x84, 0x1a, 0x2d, 0x96, 0x4a, 0x09, 0x83, 0x00
       , 0x01, 0x00, 0xb3, 0x04, 0x82, 0x00, 0xd8, 0x18, 0x41, 0x05, 0x05, 0x82, 0x00, 0xd8, 0x18, 0x41
       , 0x04, 0x06, 0x82, 0x00, 0xd8, 0x18, 0x41, 0x07, 0x18, 0x22, 0x82, 0x00, 0xd8, 0x18, 0x42, 0x18
@@ -367,6 +430,24 @@ mod tests {
         assert_eq!(hs, hs_);
     }
 
+    #[test]
+    fn message_id_bytes() {
+        let ids = [ MessageId::GetHeaders, MessageId::Headers, MessageId::GetBlocks
+                  , MessageId::Subscribe, MessageId::AnnounceTx
+                  ];
+        for id in ids.iter() {
+            assert_eq!(MessageId::from_u8(id.as_u8()), Some(*id));
+        }
+        assert_eq!(MessageId::from_u8(0x00), None);
+
+        // the messages we send are among the out handlers of the mainnet nodes
+        let hs : Handshake = RawCbor::from(HANDSHAKE_BYTES).deserialize().unwrap();
+        assert!(hs.out_handlers.get(send_msg_subscribe(false).0.code()).is_some());
+        assert!(hs.out_handlers.get(MessageId::GetHeaders.code()).is_some());
+        assert!(hs.out_handlers.get(MessageId::GetBlocks.code()).is_some());
+        assert!(hs.out_handlers.get(MessageId::AnnounceTx.code()).is_some());
+    }
+
     #[test]
     fn handshake_encoding() {
         let hs = Handshake::default();
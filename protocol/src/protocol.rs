@@ -1,6 +1,7 @@
 use std::collections::BTreeMap;
 use std::io::{Read, Write};
-use std::{io, fmt, result};
+use std::{io, fmt, result, str, num};
+use std::time::{Duration, Instant};
 
 use packet;
 use packet::{Handshake, Message};
@@ -8,7 +9,7 @@ use ntt;
 
 use cardano;
 
-use cbor_event::{self, se, de::{RawCbor}, Deserialize};
+use cbor_event::{self, se, de::{RawCbor}};
 
 #[derive(Debug)]
 pub enum Error {
@@ -16,12 +17,52 @@ pub enum Error {
     IOError(io::Error),
     ByteEncodingError(cbor_event::Error),
     ServerCreatedLightIdTwice(LightId),
-    UnsupportedControl(ntt::protocol::ControlHeader),
+    /// the peer closes the socket, nothing more can be exchanged on it
+    SocketClosedByPeer,
+    /// the peer closed its end point, and so the socket
+    EndPointClosedByPeer,
     NodeIdNotFound(ntt::protocol::NodeId),
     ClientIdNotFoundFromNodeId(ntt::protocol::NodeId, LightId),
     UnexpectedResponse(),
     ServerError(String),
     TransactionRejected,
+    ReceiveBufferFull(usize),
+    InvalidHeaderSignature(cardano::block::HeaderHash),
+    /// the peer did not return any header when asked for its tip
+    NoTip,
+    /// too many frames have been processed without receiving anything
+    /// for the given light connection
+    LightConnectionStarved(LightId, usize),
+    /// the command running on the given light connection did not
+    /// complete in time
+    CommandTimedOut(LightId),
+    /// the server acknowledged a node id matching more than one of
+    /// our light connections
+    AmbiguousNodeId(ntt::protocol::NodeId, Vec<LightId>),
+    /// the peer is on another network: our protocol magic, then the peer's
+    NetworkMismatch(cardano::config::ProtocolMagic, cardano::config::ProtocolMagic),
+    /// the nonces wrapped around and the next one is still used by the
+    /// given light connection
+    NonceInUse(ntt::protocol::Nonce, LightId),
+    /// the peer did not send the node id of the given light connection
+    /// after its handshake
+    MissingNodeId(LightId),
+    /// the requested block height is above the tip of the peer: the
+    /// requested height, then the height of the tip
+    HeightAboveTip(u64, u64),
+    /// no block of the given height follows the checkpoint given to
+    /// `GetBlockByHeight`
+    HeightNotFound(u64),
+    /// the requested epoch is after the epoch of the tip of the peer: the
+    /// requested epoch, then the epoch of the tip
+    EpochAboveTip(cardano::block::EpochId, cardano::block::EpochId),
+    /// no epoch boundary block of the given epoch follows the checkpoint
+    /// given to `GetEpochBoundary`
+    EpochBoundaryNotFound(cardano::block::EpochId),
+    /// the oldest of the headers returned for a range does not follow the
+    /// start of the range: the start of the range, then the parent of the
+    /// oldest header
+    UnlinkedHeaders(cardano::block::HeaderHash, cardano::block::HeaderHash),
 }
 impl From<cbor_event::Error> for Error {
     fn from(e: cbor_event::Error) -> Self { Error::ByteEncodingError(e) }
@@ -36,7 +77,7 @@ impl From<ntt::Error> for Error {
 pub type Result<T> = result::Result<T, Error>;
 
 /// Light ID create by the server or by the client
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone)]
 pub struct LightId(pub u32);
 impl LightId {
     /// create a `LightId` from the given number
@@ -62,6 +103,34 @@ impl fmt::Display for LightId {
         write!(f, "{}", self.0)
     }
 }
+impl str::FromStr for LightId {
+    type Err = ParseLightIdError;
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let id = s.parse().map_err(ParseLightIdError::InvalidNumber)?;
+        if id < ntt::LIGHT_ID_MIN {
+            return Err(ParseLightIdError::ReservedId(id));
+        }
+        Ok(LightId(id))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum ParseLightIdError {
+    InvalidNumber(num::ParseIntError),
+    ReservedId(u32),
+}
+impl fmt::Display for ParseLightIdError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseLightIdError::InvalidNumber(err) => {
+                write!(f, "invalid light id: {}", err)
+            },
+            ParseLightIdError::ReservedId(id) => {
+                write!(f, "light id {} is reserved (expected at least {})", id, ntt::LIGHT_ID_MIN)
+            }
+        }
+    }
+}
 
 /// A client light connection will hold pending message to send or
 /// awaiting to be read data
@@ -71,6 +140,7 @@ pub struct LightConnection {
     node_id: Option<ntt::protocol::NodeId>,
     received: Vec<Vec<u8>>,
     eos: bool,
+    last_activity: Instant,
 }
 impl LightConnection {
     pub fn new(id: LightId) -> Self {
@@ -79,6 +149,7 @@ impl LightConnection {
             node_id: None,
             received: Vec::new(),
             eos: false,
+            last_activity: Instant::now(),
         }
     }
 
@@ -88,6 +159,7 @@ impl LightConnection {
             node_id: Some(ntt::protocol::NodeId::make_syn(nonce)),
             received: Vec::new(),
             eos: false,
+            last_activity: Instant::now(),
         }
     }
 
@@ -97,6 +169,7 @@ impl LightConnection {
             node_id: Some(node),
             received: Vec::new(),
             eos: false,
+            last_activity: Instant::now(),
         }
     }
 
@@ -107,10 +180,21 @@ impl LightConnection {
         self.received.len() > 0
     }
 
+    /// number of bytes received and not consumed yet
+    pub fn received_len(&self) -> usize {
+        self.received.iter().map(|v| v.len()).sum()
+    }
+
     pub fn is_eos(&self) -> bool {
         self.eos
     }
 
+    /// when data was last sent or received on this light connection (or
+    /// when it was created if none was)
+    pub fn last_activity(&self) -> Instant {
+        self.last_activity
+    }
+
     /// consume the eventual data to read
     ///
     /// to call only if you are ready to process the data
@@ -122,11 +206,74 @@ impl LightConnection {
     fn add_to_receive(&mut self, bytes: &[u8]) {
         let mut v = Vec::new();
         v.extend_from_slice(bytes);
-        self.received.push(v)
+        self.received.push(v);
+        self.last_activity = Instant::now();
+    }
+
+    pub fn snapshot(&self) -> LightConnectionSnapshot {
+        LightConnectionSnapshot {
+            id: self.id.0,
+            node_id: self.node_id.map(|node_id| node_id.to_string()),
+            pending_messages: self.received.len(),
+            pending_bytes: self.received_len(),
+            eos: self.eos,
+        }
     }
 }
 
-pub struct Connection<T> {
+/// state of a `LightConnection`, without the received data
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct LightConnectionSnapshot {
+    pub id: u32,
+    /// hexadecimal encoding of the node id
+    pub node_id: Option<String>,
+    /// number of received messages not consumed yet
+    pub pending_messages: usize,
+    /// number of received bytes not consumed yet
+    pub pending_bytes: usize,
+    pub eos: bool,
+}
+
+/// state of the light connections of a `Connection`, see
+/// `Connection::snapshot`
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct ConnectionSnapshot {
+    pub server_cons: Vec<LightConnectionSnapshot>,
+    pub client_cons: Vec<LightConnectionSnapshot>,
+    /// the server node ids (hexadecimal) and the client light
+    /// connection they reply to
+    pub map_to_client: Vec<(String, u32)>,
+}
+
+/// counters of a `Connection`, see `Connection::stats`
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Stats {
+    /// frames and bytes exchanged on the transport
+    pub transport: ntt::Stats,
+    /// light connections opened by the peer and not closed yet
+    pub server_light_connections: usize,
+    /// light connections opened by us and not closed yet
+    pub client_light_connections: usize,
+}
+
+/// lifecycle events of a `Connection`, see `Connection::on_event`
+#[derive(Debug)]
+pub enum ConnectionEvent<'a> {
+    /// a light connection has been created, by the peer or by us
+    LightConnectionCreated { id: LightId, by_peer: bool },
+    /// the light-protocol handshake with the peer succeeded
+    Established,
+    /// a light connection has been closed, by the peer or by us
+    LightConnectionClosed { id: LightId, by_peer: bool },
+    /// the given number of bytes has been received on the given light
+    /// connection of the peer
+    DataReceived { id: LightId, len: usize },
+    /// the handshake, the transport or the processing of a frame from the
+    /// peer failed
+    ProtocolError(&'a Error),
+}
+
+pub struct Connection<T: Write+Read> {
     ntt: ntt::Connection<T>,
     // this is a line of active connections open by the server/client
     // that have not been closed yet. Note that light connections are
@@ -143,24 +290,175 @@ pub struct Connection<T> {
 
     next_light_id: LightId,
 
+    #[cfg(feature = "commands")]
     latest_tip: Option<cardano::block::BlockHeader>,
+
+    received_high_water_mark: usize,
+
+    max_wait_frames: usize,
+
+    // time after which waiting on a light connection fails, set for
+    // the duration of a command (see `Command::execute_with_timeout`)
+    deadline: Option<Instant>,
+    // read the next frame header waiting at most the given duration, set
+    // with the `deadline` so the reads of the transport are bounded by it
+    recv_within: Option<RecvWithin<T>>,
+
+    // the handshake the peer sent, once the light-protocol handshake
+    // has been performed
+    peer_handshake: Option<Handshake>,
+
+    // called on the lifecycle events, see `on_event`
+    on_event: Option<Box<dyn FnMut(ConnectionEvent) + Send>>,
+
+    // an error leaving the connection unusable has been reported, see
+    // `is_healthy`
+    failed: bool,
 }
 
 const INITIAL_LIGHT_ID : u32 = ntt::LIGHT_ID_MIN;
 
+type RecvWithin<T> = fn(&mut ntt::Connection<T>, Duration) -> result::Result<Option<ntt::protocol::Command>, ntt::Error>;
+
+fn recv_within<T: Read + Write + ntt::ReadTimeout>(ntt: &mut ntt::Connection<T>, timeout: Duration) -> result::Result<Option<ntt::protocol::Command>, ntt::Error> {
+    ntt.try_recv(timeout)
+}
+
+/// default number of received, not yet consumed, bytes above which
+/// `process_message` stops reading from the transport.
+pub const DEFAULT_RECEIVED_HIGH_WATER_MARK : usize = 64 * 1024 * 1024;
+
+/// default number of frames `wait_msg` processes for the other light
+/// connections before giving up on the one it is waiting on.
+pub const DEFAULT_MAX_WAIT_FRAMES : usize = 4096;
+
+// tell if the connection cannot be used anymore after the given error:
+// the frames for unknown light connections are discarded and a full
+// receive buffer can still be drained, the other errors leave the
+// transport or the state of the light connections unreliable
+fn is_fatal(err: &Error) -> bool {
+    match err {
+        Error::NodeIdNotFound(_)
+            | Error::ClientIdNotFoundFromNodeId(_, _)
+            | Error::ReceiveBufferFull(_) => false,
+        _ => true,
+    }
+}
+
+// the handshake decodes the same whatever the network, check the peer
+// is on ours before going any further
+fn check_protocol_magic(ours: &Handshake, theirs: &Handshake) -> Result<()> {
+    if ours.protocol_magic != theirs.protocol_magic {
+        error!("peer is on network {}, expected network {}", theirs.protocol_magic, ours.protocol_magic);
+        return Err(Error::NetworkMismatch(ours.protocol_magic, theirs.protocol_magic));
+    }
+    Ok(())
+}
+
+// decode the node id sent by the peer, failing with an `io::Error`
+// describing the malformed node id rather than mistaking it for data
+fn decode_nodeid(bytes: &[u8]) -> Result<ntt::protocol::NodeId> {
+    match ntt::protocol::NodeId::from_slice(bytes) {
+        Some(nodeid) => Ok(nodeid),
+        None => {
+            error!("malformed node id of {} bytes received from the peer", bytes.len());
+            Err(Error::IOError(io::Error::new(io::ErrorKind::InvalidData,
+                format!("malformed node id: received {} bytes, expected {} bytes starting with `S' or `A'",
+                        bytes.len(), ntt::protocol::NODEID_SIZE))))
+        }
+    }
+}
+
 impl<T: Write+Read> Connection<T> {
 
     pub fn get_backend(&self) -> &T {
         self.ntt.get_backend()
     }
 
+    pub fn get_backend_mut(&mut self) -> &mut T {
+        self.ntt.get_backend_mut()
+    }
+
+    /// capture the state of the light connections, e.g. to attach it
+    /// to a bug report about a stuck connection
+    ///
+    /// only the ids, node ids and amount of pending data are captured,
+    /// not the data itself.
+    pub fn snapshot(&self) -> ConnectionSnapshot {
+        ConnectionSnapshot {
+            server_cons: self.server_cons.values().map(LightConnection::snapshot).collect(),
+            client_cons: self.client_cons.values().map(LightConnection::snapshot).collect(),
+            map_to_client: self.map_to_client.iter().map(|(node_id, id)| (node_id.to_string(), id.0)).collect(),
+        }
+    }
+
+    /// the handshake the peer sent, `None` until `handshake` (or
+    /// `accept`) succeeded
+    pub fn peer_handshake(&self) -> Option<&Handshake> {
+        self.peer_handshake.as_ref()
+    }
+
+    /// the messages the peer handles when we send them, with the
+    /// replies it sends back
+    pub fn peer_in_capabilities(&self) -> Option<&packet::HandlerSpecs> {
+        self.peer_handshake.as_ref().map(|hs| &hs.in_handlers)
+    }
+
+    /// the messages the peer may send us, with the replies it expects
+    pub fn peer_out_capabilities(&self) -> Option<&packet::HandlerSpecs> {
+        self.peer_handshake.as_ref().map(|hs| &hs.out_handlers)
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            transport: self.ntt.get_stats(),
+            server_light_connections: self.server_cons.len(),
+            client_light_connections: self.client_cons.len(),
+        }
+    }
+
+    /// the `stats` in the Prometheus text exposition format, for a relay
+    /// to expose the stats of its connections to a scraper.
+    pub fn metrics_prometheus(&self) -> String {
+        let stats = self.stats();
+        let mut metrics = String::new();
+        {
+            let mut metric = |name: &str, kind: &str, help: &str, samples: &[(&str, u64)]| {
+                metrics.push_str(&format!("# HELP {} {}\n", name, help));
+                metrics.push_str(&format!("# TYPE {} {}\n", name, kind));
+                for (labels, value) in samples {
+                    metrics.push_str(&format!("{}{} {}\n", name, labels, value));
+                }
+            };
+            metric("cardano_protocol_frames_total", "counter", "Frames exchanged with the peer.",
+                   &[("{direction=\"sent\"}", stats.transport.frames_sent),
+                     ("{direction=\"received\"}", stats.transport.frames_received)]);
+            metric("cardano_protocol_bytes_total", "counter", "Bytes exchanged with the peer.",
+                   &[("{direction=\"sent\"}", stats.transport.bytes_sent),
+                     ("{direction=\"received\"}", stats.transport.bytes_received)]);
+            metric("cardano_protocol_light_connections", "gauge", "Light connections not closed yet.",
+                   &[("{opened_by=\"server\"}", stats.server_light_connections as u64),
+                     ("{opened_by=\"client\"}", stats.client_light_connections as u64)]);
+        }
+        metrics
+    }
+
+    #[cfg(any(feature = "commands", test))]
     fn get_free_light_id(&mut self) -> LightId {
         let id = self.next_light_id;
         self.next_light_id = id.next();
         id
     }
 
-    pub fn new(ntt: ntt::Connection<T>) -> Self {
+    /// create a new `Connection` on top of the given `ntt::Connection`
+    ///
+    /// `max_payload_size` bounds the length of the data frames we accept
+    /// from the peer (see `ntt::DEFAULT_MAX_PAYLOAD_SIZE`), and they are
+    /// read `read_chunk_size` bytes at a time (see
+    /// `ntt::DEFAULT_READ_CHUNK_SIZE`).
+    pub fn new(mut ntt: ntt::Connection<T>, max_payload_size: u32, read_chunk_size: usize) -> Self {
+        ntt.set_max_payload_size(max_payload_size);
+        ntt.set_read_chunk_size(read_chunk_size);
         Connection {
             ntt: ntt,
             server_cons: BTreeMap::new(),
@@ -168,12 +466,168 @@ impl<T: Write+Read> Connection<T> {
             map_to_client: BTreeMap::new(),
             //server_dones: BTreeMap::new(),
             next_light_id: LightId::new(INITIAL_LIGHT_ID + 1),
+            #[cfg(feature = "commands")]
             latest_tip: None,
+            received_high_water_mark: DEFAULT_RECEIVED_HIGH_WATER_MARK,
+            max_wait_frames: DEFAULT_MAX_WAIT_FRAMES,
+            deadline: None,
+            recv_within: None,
+            peer_handshake: None,
+            on_event: None,
+            failed: false,
+        }
+    }
+
+    /// register the callback called on the lifecycle events of this
+    /// connection, e.g. to log them or to collect metrics. It replaces
+    /// the previously registered one.
+    ///
+    /// To observe the handshake, create the connection with `new` and
+    /// register the callback before calling `handshake`.
+    pub fn on_event(&mut self, callback: Box<dyn FnMut(ConnectionEvent) + Send>) {
+        self.on_event = Some(callback);
+    }
+
+    #[inline]
+    fn emit(&mut self, event: ConnectionEvent) {
+        if let Some(callback) = self.on_event.as_mut() {
+            callback(event)
         }
     }
 
+    // report the error of the given result as a `ProtocolError` event
+    fn report<R>(&mut self, result: Result<R>) -> Result<R> {
+        if let Err(ref err) = result {
+            if is_fatal(err) { self.failed = true }
+            self.emit(ConnectionEvent::ProtocolError(err));
+        }
+        result
+    }
+
+    // report the error of the given transport operation
+    fn report_transport<R>(&mut self, result: result::Result<R, ntt::Error>) -> Result<R> {
+        self.report(result.map_err(Error::from))
+    }
+
+    /// tell if the connection can still be used: the light-protocol
+    /// handshake succeeded and no transport or protocol error has been
+    /// reported since (see `ConnectionEvent::ProtocolError`).
+    ///
+    /// This does not send anything to the peer, use `command::Ping` to
+    /// check the peer is still responding.
+    pub fn is_healthy(&self) -> bool {
+        self.peer_handshake.is_some() && ! self.failed
+    }
+
+    /// establish a connection over the given transport: perform the ntt
+    /// handshake and then the light-protocol handshake, returning
+    /// a connection ready to execute commands.
+    ///
+    /// `drg_seed` is the seed of the nonces generator of the ntt connection.
+    pub fn establish(drg_seed: u64, transport: T, hs: &packet::Handshake) -> Result<Self> {
+        let ntt = ntt::Connection::handshake(drg_seed, transport)?;
+        let mut connection = Connection::new(ntt, ntt::DEFAULT_MAX_PAYLOAD_SIZE, ntt::DEFAULT_READ_CHUNK_SIZE);
+        connection.handshake(hs)?;
+        Ok(connection)
+    }
+
+    /// same as `establish`, but fails with `Error::MissingNodeId` if the
+    /// peer does not send its node id within the given duration after
+    /// its handshake, instead of blocking.
+    pub fn establish_with_timeout(drg_seed: u64, transport: T, hs: &packet::Handshake, timeout: Duration) -> Result<Self>
+        where T: ntt::ReadTimeout
+    {
+        let ntt = ntt::Connection::handshake(drg_seed, transport)?;
+        let mut connection = Connection::new(ntt, ntt::DEFAULT_MAX_PAYLOAD_SIZE, ntt::DEFAULT_READ_CHUNK_SIZE);
+        connection.handshake_with_timeout(hs, timeout)?;
+        Ok(connection)
+    }
+
+    /// set the number of frames `wait_msg` and `wait_msg_eos` may process
+    /// without receiving anything for the light connection they wait on,
+    /// before failing with `Error::LightConnectionStarved`.
+    pub fn set_max_wait_frames(&mut self, max_wait_frames: usize) {
+        self.max_wait_frames = max_wait_frames;
+    }
+
+    pub fn get_max_wait_frames(&self) -> usize {
+        self.max_wait_frames
+    }
+
+    /// set the number of received bytes, buffered in the light connections
+    /// and not yet consumed, above which `process_message` stops reading
+    /// from the transport (see `DEFAULT_RECEIVED_HIGH_WATER_MARK`).
+    ///
+    /// The pending frames are left in the transport until the consumer
+    /// drains the buffered data (with `wait_msg` or `wait_msg_eos`) and
+    /// the peer is then naturally slowed down by the transport itself.
+    pub fn set_received_high_water_mark(&mut self, high_water_mark: usize) {
+        self.received_high_water_mark = high_water_mark;
+    }
+
+    pub fn get_received_high_water_mark(&self) -> usize {
+        self.received_high_water_mark
+    }
+
+    /// number of received bytes not yet consumed, in all the light connections
+    pub fn received_len(&self) -> usize {
+        self.server_cons.values().chain(self.client_cons.values())
+            .map(|con| con.received_len())
+            .sum()
+    }
+
     pub fn handshake(&mut self, hs: &packet::Handshake) -> Result<()> {
-        use ntt::protocol::{ControlHeader, Command};
+        let result = self.try_handshake(hs);
+        self.report(result)
+    }
+
+    fn try_handshake(&mut self, hs: &packet::Handshake) -> Result<()> {
+        let (siv, server_handshake, server_nodeid) = self.handshake_start(hs)?;
+        let server_nodeid = match server_nodeid {
+            Some(nodeid) => nodeid,
+            None => loop {
+                let frame = self.ntt.recv()?;
+                if let Some(nodeid) = self.recv_nodeid_on(siv, frame)? { break nodeid }
+            }
+        };
+        self.handshake_finish(siv, server_handshake, server_nodeid)
+    }
+
+    /// same as `handshake`, but fails with `Error::MissingNodeId` if the
+    /// peer does not send its node id within the given duration.
+    pub fn handshake_with_timeout(&mut self, hs: &packet::Handshake, timeout: Duration) -> Result<()>
+        where T: ntt::ReadTimeout
+    {
+        let result = self.try_handshake_with_timeout(hs, timeout);
+        self.report(result)
+    }
+
+    fn try_handshake_with_timeout(&mut self, hs: &packet::Handshake, timeout: Duration) -> Result<()>
+        where T: ntt::ReadTimeout
+    {
+        let (siv, server_handshake, server_nodeid) = self.handshake_start(hs)?;
+        let server_nodeid = match server_nodeid {
+            Some(nodeid) => nodeid,
+            None => loop {
+                match self.ntt.try_recv(timeout)? {
+                    None => {
+                        warn!("no node id received from the peer after its handshake");
+                        return Err(Error::MissingNodeId(siv))
+                    },
+                    Some(frame) => {
+                        if let Some(nodeid) = self.recv_nodeid_on(siv, frame)? { break nodeid }
+                    },
+                }
+            }
+        };
+        self.handshake_finish(siv, server_handshake, server_nodeid)
+    }
+
+    // send our handshake and receive the peer's, returning the light
+    // connection of the peer, its handshake and its node id if it was
+    // sent in the same frame as the handshake
+    fn handshake_start(&mut self, hs: &packet::Handshake) -> Result<(LightId, Handshake, Option<ntt::protocol::NodeId>)> {
+        use ntt::protocol::{ControlHeader};
         let lcid = LightId::new(INITIAL_LIGHT_ID);
         let lc = LightConnection::new_with_nodeid(lcid, self.ntt.get_nonce());
 
@@ -185,60 +639,271 @@ impl<T: Write+Read> Connection<T> {
         debug!("my node = {}", lc.node_id.unwrap());
 
         self.client_cons.insert(lcid, lc);
+        self.emit(ConnectionEvent::LightConnectionCreated { id: lcid, by_peer: false });
 
         // FIXME: should use process_message() here.
 
         /* wait answer from server, which should be a new light connection creation,
          * followed by the handshake data and then the node id
          */
-        let siv = match self.ntt.recv()? {
-            Command::Control(ControlHeader::CreateNewConnection, cid) => { LightId::new(cid) },
-            _ => { unimplemented!() }
+        let siv = match self.ntt.recv()?.as_control() {
+            Some((ControlHeader::CreateNewConnection, cid)) => LightId::new(cid),
+            _ => return Err(Error::UnexpectedResponse())
         };
+        self.emit(ConnectionEvent::LightConnectionCreated { id: siv, by_peer: true });
 
-        fn data_recv_on<T: Read+Write>(con: &mut Connection<T>, expected_id: LightId) -> Result<Vec<u8>> {
-            match con.ntt.recv()? {
-                ntt::protocol::Command::Data(cid, len) => {
-                    if cid == expected_id.0 {
-                        let bytes = con.ntt.recv_len(len)?;
-                        Ok(bytes)
-                    } else {
-                        unimplemented!()
-                    }
-                }
-                _ => { unimplemented!() }
-            }
+        info!("creating initial light connection {}", lcid);
+        let server_bytes_hs = self.recv_data_on(siv)?;
+        let mut raw = RawCbor::from(&server_bytes_hs);
+        let server_handshake : Handshake = raw.deserialize()?;
+        check_protocol_magic(hs, &server_handshake)?;
+
+        // some peers send their node id right after the handshake, in
+        // the same frame
+        let server_nodeid = if raw.is_empty() {
+            None
+        } else {
+            Some(decode_nodeid(raw.as_ref())?)
         };
 
-        info!("creating initial light connection {}", lcid);
-        let server_bytes_hs = data_recv_on(self, siv)?;
-        let _server_handshake : Handshake = RawCbor::from(&server_bytes_hs).deserialize()?;
+        Ok((siv, server_handshake, server_nodeid))
+    }
 
-        let server_bytes_nodeid = data_recv_on(self, siv)?;
-        let server_nodeid = match ntt::protocol::NodeId::from_slice(&server_bytes_nodeid[..]) {
-            None   => unimplemented!(),
-            Some(nodeid) => nodeid,
-        };
+    // receive the data of the given frame, expecting the node id of the
+    // given light connection. An empty data frame is ignored, as in
+    // `route_frame`, and gives `None`.
+    fn recv_nodeid_on(&mut self, expected_id: LightId, frame: ntt::protocol::Command) -> Result<Option<ntt::protocol::NodeId>> {
+        match frame.as_data() {
+            Some((cid, len)) if cid == expected_id.0 => {
+                let bytes = self.ntt.recv_len(len)?;
+                if bytes.is_empty() {
+                    trace!("ignoring empty data frame on light connection {}", expected_id);
+                    return Ok(None)
+                }
+                Ok(Some(decode_nodeid(&bytes[..])?))
+            },
+            _ => Err(Error::MissingNodeId(expected_id))
+        }
+    }
 
+    fn handshake_finish(&mut self, siv: LightId, server_handshake: Handshake, server_nodeid: ntt::protocol::NodeId) -> Result<()> {
         // TODO compare server_nodeid and client_id
 
         self.server_cons.insert(siv, LightConnection::new_expecting_nodeid(siv, server_nodeid));
+        self.peer_handshake = Some(server_handshake);
+        self.emit(ConnectionEvent::Established);
+
+        Ok(())
+    }
+
+    /// accept a connection from a peer over the given transport, the
+    /// server side of `establish`: answer the ntt handshake and the
+    /// light-protocol handshake of the peer.
+    ///
+    /// The light connection opened by the peer is kept in the same maps
+    /// as in the client mode, the data the peer sends on the connections
+    /// it opens are buffered there.
+    pub fn accept(drg_seed: u64, transport: T, hs: &packet::Handshake) -> Result<Self> {
+        let ntt = ntt::Connection::accept(drg_seed, transport)?;
+        let mut connection = Connection::new(ntt, ntt::DEFAULT_MAX_PAYLOAD_SIZE, ntt::DEFAULT_READ_CHUNK_SIZE);
+        connection.accept_handshake(hs)?;
+        Ok(connection)
+    }
+
+    // the mirror of `handshake`
+    fn accept_handshake(&mut self, hs: &packet::Handshake) -> Result<()> {
+        use ntt::protocol::{ControlHeader};
+
+        /* wait for the client to create its light connection, then for the
+         * handshake data and then the node id associated to it
+         */
+        let cid = match self.ntt.recv()?.as_control() {
+            Some((ControlHeader::CreateNewConnection, cid)) => LightId::new(cid),
+            _ => return Err(Error::UnexpectedResponse())
+        };
+
+        let client_bytes_hs = self.recv_data_on(cid)?;
+        let client_handshake : Handshake = RawCbor::from(&client_bytes_hs).deserialize()?;
+        debug!("client handshake: {}", client_handshake);
+        check_protocol_magic(hs, &client_handshake)?;
+
+        let client_bytes_nodeid = self.recv_data_on(cid)?;
+        let client_nodeid = decode_nodeid(&client_bytes_nodeid[..])?;
+        if ! client_nodeid.is_syn() { return Err(Error::UnexpectedResponse()) }
+        debug!("client node = {}", client_nodeid);
+
+        self.server_cons.insert(cid, LightConnection::new_expecting_nodeid(cid, client_nodeid));
+
+        /* create the reciprocal connection, send our handshake and echo
+         * the node id of the client
+         */
+        let lcid = LightId::new(INITIAL_LIGHT_ID);
+        let ack = client_nodeid.syn_to_ack();
+        self.ntt.create_light(lcid.0)?;
+        self.send_bytes(lcid, &packet::send_handshake(hs))?;
+        self.send_nodeid(lcid, &ack)?;
+
+        info!("accepted light connection {} from node {}", cid, client_nodeid);
+        self.client_cons.insert(lcid, LightConnection::new_expecting_nodeid(lcid, ack));
+        self.peer_handshake = Some(client_handshake);
 
         Ok(())
     }
 
+    // receive the next non-empty data frame, expecting it on the given
+    // light connection; empty data frames are ignored, as in `route_frame`
+    fn recv_data_on(&mut self, expected_id: LightId) -> Result<Vec<u8>> {
+        loop {
+            match self.ntt.recv()?.as_data() {
+                Some((cid, len)) if cid == expected_id.0 => {
+                    let bytes = self.ntt.recv_len(len)?;
+                    if bytes.is_empty() {
+                        trace!("ignoring empty data frame on light connection {}", expected_id);
+                        continue
+                    }
+                    return Ok(bytes)
+                },
+                _ => return Err(Error::UnexpectedResponse())
+            }
+        }
+    }
+
+    /// open a light connection and send its node id.
+    ///
+    /// Fails with `Error::NonceInUse` rather than reusing the node id of
+    /// another of our light connections once the nonces wrapped around.
     pub fn new_light_connection(&mut self, id: LightId) -> Result<()> {
+        let nonce = self.ntt.get_nonce();
+        let node_id = ntt::protocol::NodeId::make_syn(nonce);
+        if let Some(con) = self.client_cons.values().find(|con| con.node_id == Some(node_id)) {
+            return Err(Error::NonceInUse(nonce, con.id));
+        }
         self.ntt.create_light(id.0)?;
 
-        let lc = LightConnection::new_with_nodeid(id, self.ntt.get_nonce());
+        let lc = LightConnection::new_with_nodeid(id, nonce);
         self.send_nodeid(id, &lc.node_id.unwrap())?;
         self.client_cons.insert(id, lc);
+        self.emit(ConnectionEvent::LightConnectionCreated { id, by_peer: false });
+        Ok(())
+    }
+
+    /// close our light connections on which nothing was sent or received
+    /// for longer than `idle`, returning their ids
+    ///
+    /// The light connections opened by the peer are left to the peer.
+    pub fn reap_idle(&mut self, idle: Duration) -> Result<Vec<LightId>> {
+        self.reap_idle_at(Instant::now(), idle)
+    }
+
+    fn reap_idle_at(&mut self, now: Instant, idle: Duration) -> Result<Vec<LightId>> {
+        let ids : Vec<LightId> = self.client_cons.values()
+            .filter(|con| now.duration_since(con.last_activity) > idle)
+            .map(|con| con.id)
+            .collect();
+        for id in ids.iter() {
+            debug!("closing light connection {}, idle for more than {:?}", id, idle);
+            self.close_light_connection(*id)?;
+        }
+        Ok(ids)
+    }
+
+    /// close the given light connection, if not closed already
+    ///
+    /// The light connection is forgotten even if the close could not be
+    /// sent to the peer, the transport error is returned and the
+    /// connection reported unhealthy (see `is_healthy`).
+    pub fn close_light_connection(&mut self, id: LightId) -> Result<()> {
+        // already closed by `cancel_light_connection`
+        if self.client_cons.remove(&id).is_some() {
+            let closed = self.ntt.close_light(id.0);
+            self.report_transport(closed)?;
+            self.flush()?;
+            self.emit(ConnectionEvent::LightConnectionClosed { id, by_peer: false });
+        }
         Ok(())
     }
 
-    pub fn close_light_connection(&mut self, id: LightId) {
+    /// stop reading the replies on the given light connection before
+    /// the end: close it and discard the frames the peer sends for it
+    /// until the peer closes its side too.
+    ///
+    /// The `Connection` can then be used for other commands, the
+    /// remaining replies will not be mistaken for theirs. As with
+    /// `close_light_connection`, the light connection is forgotten if the
+    /// close could not be sent to the peer, the transport error is
+    /// returned and the connection reported unhealthy.
+    pub fn cancel_light_connection(&mut self, id: LightId) -> Result<()> {
+        if !self.client_cons.contains_key(&id) { return Ok(()) }
+        debug!("cancelling light connection {}", id);
+        let closed = self.ntt.close_light(id.0);
+        let sent = self.report_transport(closed).and_then(|()| self.flush());
+        if sent.is_err() {
+            self.client_cons.remove(&id);
+            return sent;
+        }
+        loop {
+            self.wait_readable(id)?;
+            let con = self.client_cons.get_mut(&id).unwrap();
+            con.received.clear();
+            if con.eos { break }
+        }
         self.client_cons.remove(&id);
-        self.ntt.close_light(id.0).unwrap();
+        self.emit(ConnectionEvent::LightConnectionClosed { id, by_peer: false });
+        Ok(())
+    }
+
+    /// close all the light connections we opened on this `Connection`
+    ///
+    /// This is also done when the `Connection` is dropped, but errors
+    /// can only be logged there. Prefer calling `shutdown` explicitly
+    /// to know if the peer has been notified.
+    pub fn shutdown(&mut self) -> Result<()> {
+        let ids : Vec<LightId> = self.client_cons.keys().cloned().collect();
+        for id in ids {
+            self.client_cons.remove(&id);
+            debug!("closing light connection {}", id);
+            self.ntt.close_light(id.0)?;
+            self.emit(ConnectionEvent::LightConnectionClosed { id, by_peer: false });
+        }
+        self.map_to_client.clear();
+        self.flush()
+    }
+
+    /// forget the state of the previous session, to be called once the
+    /// transport is reconnected (see `get_backend_mut`) and before the
+    /// light-protocol handshake is performed again on it.
+    ///
+    /// The light connections of both sides are dropped without notifying
+    /// the peer of the previous session, the light ids are allocated from
+    /// the start again, and the peer's handshake, its latest tip and the
+    /// errors reported are forgotten: `is_healthy` is false until the new
+    /// handshake succeeds. The frames not written yet are dropped.
+    pub fn reset_session(&mut self) {
+        self.ntt.discard_write_buffer();
+        self.client_cons.clear();
+        self.server_cons.clear();
+        self.map_to_client.clear();
+        self.next_light_id = LightId::new(INITIAL_LIGHT_ID + 1);
+        self.deadline = None;
+        self.recv_within = None;
+        self.peer_handshake = None;
+        self.failed = false;
+        #[cfg(feature = "commands")]
+        {
+            self.latest_tip = None;
+        }
+    }
+
+    /// write the frames buffered so far to the transport.
+    ///
+    /// The frames of an operation (e.g. the creation of a light
+    /// connection and its node id, or the header and the data of each
+    /// frame of a message) are buffered and written at once when the
+    /// operation completes, so this is only needed after sending frames
+    /// directly on the `ntt::Connection`.
+    pub fn flush(&mut self) -> Result<()> {
+        let flushed = self.ntt.flush();
+        self.report_transport(flushed)
     }
 
     pub fn has_bytes_to_read_or_finish(&self, id: LightId) -> bool {
@@ -248,10 +913,89 @@ impl<T: Write+Read> Connection<T> {
         }
     }
 
-    pub fn wait_msg(&mut self, id: LightId) -> Result<Vec<u8>> {
-        while !self.has_bytes_to_read_or_finish(id) {
-            self.process_message()?;
+    /// tell if the server acknowledged the given client light connection
+    pub fn is_acknowledged(&self, id: LightId) -> bool {
+        self.map_to_client.values().any(|client_id| *client_id == id)
+    }
+
+    /// wait for the server to acknowledge the given client light connection
+    pub fn wait_light_ack(&mut self, id: LightId) -> Result<()> {
+        self.wait_until(id, |con| con.is_acknowledged(id))
+    }
+
+    /// wait for the server to acknowledge the node id returned by
+    /// `send_bytes_ack`
+    ///
+    /// This returns once the server acknowledged it, or once the light
+    /// connection has been closed. It fails with `Error::NodeIdNotFound`
+    /// if none of our light connections has been opened with this node id.
+    pub fn wait_ack(&mut self, node_id: &ntt::protocol::NodeId) -> Result<()> {
+        let id = match self.client_cons.iter().find(|(_, con)| con.node_id == Some(*node_id)) {
+            None => return Err(Error::NodeIdNotFound(*node_id)),
+            Some((id, _)) => *id,
+        };
+        self.wait_until(id, |con| match con.client_cons.get(&id) {
+            None => true,
+            Some(lc) => lc.eos || con.is_acknowledged(id),
+        })
+    }
+
+    // process frames until there is something to read on the given light
+    // connection, or the connection has been closed
+    fn wait_readable(&mut self, id: LightId) -> Result<()> {
+        self.wait_until(id, |con| con.has_bytes_to_read_or_finish(id))
+    }
+
+    // process frames until `ready` holds, waiting on behalf of the given
+    // light connection
+    fn wait_until<F>(&mut self, id: LightId, ready: F) -> Result<()>
+        where F: Fn(&Self) -> bool
+    {
+        let mut frames = 0;
+        while !ready(self) {
+            let remaining = match self.deadline {
+                None => None,
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        warn!("light connection {} timed out", id);
+                        return Err(Error::CommandTimedOut(id));
+                    }
+                    Some(deadline - now)
+                },
+            };
+            if frames >= self.max_wait_frames {
+                warn!("no data received for light connection {} after {} frames", id, frames);
+                return Err(Error::LightConnectionStarved(id, frames));
+            }
+            match (remaining, self.recv_within) {
+                (Some(remaining), Some(recv)) => {
+                    if ! self.process_message_within(recv, remaining)? {
+                        warn!("light connection {} timed out waiting for the peer", id);
+                        return Err(Error::CommandTimedOut(id));
+                    }
+                },
+                _ => self.process_message()?,
+            }
+            frames += 1;
         }
+        Ok(())
+    }
+
+    /// wait for a message on the given light connection
+    ///
+    /// The transport is only read when there is no message already
+    /// buffered for this connection, so this always allows to drain the
+    /// buffer of the given connection. However, if the received high water
+    /// mark has been reached because of the data buffered on the *other*
+    /// connections, this returns `Error::ReceiveBufferFull` instead of
+    /// blocking forever.
+    ///
+    /// This fails with `Error::LightConnectionStarved` if more than the
+    /// maximum number of wait frames (see `set_max_wait_frames`) have been
+    /// processed without receiving anything for this connection.
+    pub fn wait_msg(&mut self, id: LightId) -> Result<Vec<u8>> {
+        self.wait_readable(id)?;
 
         match self.client_cons.get_mut(&id) {
             None => panic!("oops"),
@@ -267,31 +1011,58 @@ impl<T: Write+Read> Connection<T> {
     // same as wait_msg, except returns a vector of result
     pub fn wait_msg_eos(&mut self, id: LightId) -> Result<Vec<Vec<u8>>> {
         let mut r = Vec::new();
-        loop {
-            while !self.has_bytes_to_read_or_finish(id) {
-                self.process_message()?;
-            }
+        while let Some(msg) = self.wait_msg_or_eos(id)? {
+            r.push(msg)
+        }
+        Ok(r)
+    }
 
-            match self.client_cons.get_mut(&id) {
-                None => panic!("oops"),
-                Some(ref mut con) => {
-                    match con.pop_received() {
-                        None => { if con.eos { return Ok(r) } else { panic!("oops 2") } },
-                        Some(yy) => r.push(yy),
-                    }
-                },
-            }
+    /// wait for the next message on the given light connection, returns
+    /// `None` once the server closed the connection and all its messages
+    /// have been consumed.
+    pub fn wait_msg_or_eos(&mut self, id: LightId) -> Result<Option<Vec<u8>>> {
+        self.wait_readable(id)?;
+
+        match self.client_cons.get_mut(&id) {
+            None => panic!("oops"),
+            Some(ref mut con) => {
+                match con.pop_received() {
+                    None => { if con.eos { Ok(None) } else { panic!("oops 2") } },
+                    Some(yy) => Ok(Some(yy)),
+                }
+            },
         }
     }
 
+    /// send the given bytes on the light connection
+    ///
+    /// payloads larger than the maximum send frame size of the ntt
+    /// connection (see `ntt::Connection::set_max_send_frame_size`) are
+    /// split in multiple data frames on the same light connection, the
+    /// peer reassembles them.
+    ///
+    /// the frames are not sent atomically: if sending one of them fails,
+    /// the frames before it are left sent (or buffered) and the peer has
+    /// received a truncated payload. The light connection should not be
+    /// used any further for this exchange.
     pub fn send_bytes(&mut self, id: LightId, bytes: &[u8]) -> Result<()> {
-        self.ntt.light_send_data(id.0, bytes)?;
-        Ok(())
+        let max = self.ntt.get_max_send_frame_size() as usize;
+        let sent = if bytes.len() <= max {
+            self.ntt.light_send_data(id.0, bytes)
+        } else {
+            let ntt = &mut self.ntt;
+            bytes.chunks(max).map(|chunk| ntt.light_send_data(id.0, chunk)).collect()
+        };
+        self.report_transport(sent)?;
+        if let Some(con) = self.client_cons.get_mut(&id) {
+            con.last_activity = Instant::now();
+        }
+        self.flush()
     }
 
     pub fn send_message(&mut self, id: LightId, msg: &Message) -> Result<()> {
         let mut v = vec![];
-        v.extend(&se::Serializer::new_vec().serialize(&msg.0)?.finalize());
+        v.extend(&se::Serializer::new_vec().serialize(&msg.0.as_u8())?.finalize());
         v.extend(&msg.1[..]);
         self.send_bytes(id, &v[..])?;
         Ok(())
@@ -299,19 +1070,19 @@ impl<T: Write+Read> Connection<T> {
 
     pub fn send_nodeid(&mut self, id: LightId, nodeid: &ntt::protocol::NodeId) -> Result<()> {
         trace!("send NodeID {} associated to light id {}", nodeid, id);
-        self.ntt.light_send_data(id.0, nodeid.as_ref())?;
-        Ok(())
+        let sent = self.ntt.light_send_data(id.0, nodeid.as_ref());
+        self.report_transport(sent)?;
+        self.flush()
     }
 
     // TODO return some kind of opaque token
     pub fn send_bytes_ack(&mut self, id: LightId, bytes: &[u8]) -> Result<ntt::protocol::NodeId> {
-        match self.client_cons.get(&id) {
+        let node_id = match self.client_cons.get(&id) {
             None => panic!("send bytes ack ERROR. connection doesn't exist"),
-            Some(con) => {
-                self.ntt.light_send_data(id.0, bytes)?;
-                Ok(con.node_id.unwrap())
-            }
-        }
+            Some(con) => con.node_id.unwrap()
+        };
+        self.send_bytes(id, bytes)?;
+        Ok(node_id)
     }
 
     // Process one message from the connection. This is one of two type:
@@ -319,14 +1090,98 @@ impl<T: Write+Read> Connection<T> {
     //
     // control message control light stream creation and closing
     // whereas data message are associated to a light connection
+    //
+    // nothing is read from the transport if the received high water mark
+    // is reached, `Error::ReceiveBufferFull` is returned instead.
     pub fn process_message(&mut self) -> Result<()> {
+        self.check_received_high_water_mark()?;
+        let frame = self.ntt.recv();
+        let frame = self.report_transport(frame)?;
+        self.route_frame(frame)
+    }
+
+    // same as `process_message`, but returns false if no frame started
+    // to arrive within the given duration
+    fn process_message_within(&mut self, recv: RecvWithin<T>, timeout: Duration) -> Result<bool> {
+        self.check_received_high_water_mark()?;
+        let frame = recv(&mut self.ntt, timeout);
+        match self.report_transport(frame)? {
+            None => Ok(false),
+            Some(frame) => self.route_frame(frame).map(|()| true),
+        }
+    }
+
+    fn check_received_high_water_mark(&self) -> Result<()> {
+        let received = self.received_len();
+        if received >= self.received_high_water_mark {
+            debug!("received buffers full ({} bytes), not reading from the transport", received);
+            return Err(Error::ReceiveBufferFull(received));
+        }
+        Ok(())
+    }
+
+    /// read the header of the next frame, waiting at most the given
+    /// duration for it to start arriving. Returns `None` on timeout.
+    ///
+    /// Nothing is done with the frame: the light connections are left
+    /// untouched and, for a data frame, its payload is still to be read
+    /// from the transport. The caller must pass the frame to `route_frame`
+    /// before reading anything else from this `Connection`.
+    pub fn try_recv_frame(&mut self, timeout: Duration) -> Result<Option<ntt::protocol::Command>>
+        where T: ntt::ReadTimeout
+    {
+        let frame = self.ntt.try_recv(timeout);
+        self.report_transport(frame)
+    }
+
+    /// process the frames sent by the peer until none arrives within
+    /// the `idle` duration, e.g. to get rid of the replies to a failed
+    /// command before reusing the `Connection`.
+    ///
+    /// The data for light connections that do not exist anymore is
+    /// discarded. Returns the number of frames processed.
+    pub fn drain_until_idle(&mut self, idle: Duration) -> Result<usize>
+        where T: ntt::ReadTimeout
+    {
+        let mut frames = 0;
+        while let Some(frame) = self.try_recv_frame(idle)? {
+            frames += 1;
+            match self.route_frame(frame) {
+                Ok(()) => {},
+                Err(Error::NodeIdNotFound(node_id)) => {
+                    debug!("discarding data for unknown node id {}", node_id);
+                },
+                Err(Error::ClientIdNotFoundFromNodeId(node_id, id)) => {
+                    debug!("discarding data for closed light connection {} (node id {})", id, node_id);
+                },
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(frames)
+    }
+
+    /// process a frame header received with `try_recv_frame`, the same
+    /// way `process_message` does with the frames it receives.
+    ///
+    /// A data frame without payload is ignored: it is neither a message
+    /// nor the node id of a new light connection, and does not mark the
+    /// end of a message (the messages are delimited by their encoding).
+    pub fn route_frame(&mut self, frame: ntt::protocol::Command) -> Result<()> {
+        let result = self.try_route_frame(frame);
+        self.report(result)
+    }
+
+    fn try_route_frame(&mut self, frame: ntt::protocol::Command) -> Result<()> {
         use ntt::protocol::{ControlHeader, Command};
-        let x = self.ntt.recv();
-        match x? {
+        match frame {
             Command::Control(ControlHeader::CloseConnection, cid) => {
                 let id = LightId::new(cid);
                 debug!("received close of light connection {}", id);
-                match &self.server_cons.remove(&id) {
+                let closed = self.server_cons.remove(&id);
+                if closed.is_some() {
+                    self.emit(ConnectionEvent::LightConnectionClosed { id, by_peer: true });
+                }
+                match &closed {
                     Some(LightConnection { node_id: None, .. }) => {
                         Ok(())
                     },
@@ -339,6 +1194,11 @@ impl<T: Write+Read> Connection<T> {
                         Ok(())
                     },
                     Some(LightConnection { node_id: Some(node_id), .. }) => {
+                        // the replies of the peer are buffered on our light
+                        // connection as they arrive, not on the peer's: they
+                        // outlive its close and are drained with `wait_msg`
+                        // (or `wait_msg_or_eos`, until the end) before our
+                        // light connection is closed in turn
                         match self.map_to_client.remove(&node_id) {
                             Some(lightid) => {
                                 match self.client_cons.get_mut(&lightid) {
@@ -350,11 +1210,6 @@ impl<T: Write+Read> Connection<T> {
                             },
                             None          => {},
                         }
-                        /*
-                        if let Some(_) = v.received {
-                            self.server_dones.insert(id, v);
-                        }
-                        */
                         Ok(())
                     },
                     None    => {
@@ -372,16 +1227,44 @@ impl<T: Write+Read> Connection<T> {
                     Err(Error::ServerCreatedLightIdTwice(id))
                 } else {
                     self.server_cons.insert(id, LightConnection::new(id));
+                    self.emit(ConnectionEvent::LightConnectionCreated { id, by_peer: true });
                     Ok(())
                 }
             },
-            Command::Control(ch, cid) => {
-                error!("LightId({}) Unsupported control `{:?}`", cid, ch);
-                Err(Error::UnsupportedControl(ch))
+            Command::Control(ControlHeader::CloseSocket, last_id) => {
+                // the peer only closes the socket if it received all the
+                // light connections we created, it keeps it otherwise
+                let last_created = self.next_light_id.0 - 1;
+                if last_id < last_created {
+                    debug!("ignoring close of the socket, the peer received light connections up to {} of {}", last_id, last_created);
+                    Ok(())
+                } else {
+                    info!("the peer closes the socket");
+                    Err(Error::SocketClosedByPeer)
+                }
+            },
+            Command::Control(ControlHeader::CloseEndPoint, _) => {
+                info!("the peer closed its end point");
+                Err(Error::EndPointClosedByPeer)
+            },
+            Command::Control(ControlHeader::ProbeSocket, _) => {
+                debug!("acknowledging the probe of the socket");
+                self.ntt.ack_probe()?;
+                Ok(())
+            },
+            Command::Control(ControlHeader::ProbeSocketAck, _) => {
+                // we never probe the socket, nothing is waiting for it
+                debug!("ignoring unexpected acknowledgement of a socket probe");
+                Ok(())
             },
             Command::Data(server_id, len) => {
-                let bytes = self.ntt.recv_len(len).unwrap();
+                let bytes = self.ntt.recv_len(len)?;
                 let id = LightId::new(server_id);
+                if bytes.is_empty() {
+                    trace!("ignoring empty data frame on light connection {}", id);
+                    return Ok(())
+                }
+                self.emit(ConnectionEvent::DataReceived { id, len: bytes.len() });
                 match self.server_cons.get_mut(&id) {
                     // connection is established to a client side yet
                     // append the data to the receiving buffer
@@ -413,7 +1296,10 @@ impl<T: Write+Read> Connection<T> {
                     // add a established connection and setup the routing to the client
                     Some(scon@LightConnection { node_id: None, .. }) => {
                         let nodeid = match ntt::protocol::NodeId::from_slice(&bytes[..]) {
-                            None         => panic!("ERROR: expecting nodeid but received data"),
+                            None         => {
+                                error!("expecting a node id on light connection {} but received data", id);
+                                return Err(Error::UnexpectedResponse());
+                            },
                             Some(nodeid) => nodeid,
                         };
 
@@ -432,16 +1318,25 @@ impl<T: Write+Read> Connection<T> {
                             debug!("sending ack {} on {}", ack, ack_conn_id);
                             //self.send_nodeid(ack_conn_id, ack)?; // FIXME: mutable borrow of self
                             self.ntt.light_send_data(ack_conn_id.0, ack.as_ref())?;
-                            self.ntt.close_light(ack_conn_id.0).unwrap();
+                            self.ntt.close_light(ack_conn_id.0)?;
+                            self.ntt.flush()?;
                         } else {
                             // This is an ACK, so it should correspond
                             // to a SYN sent by us.
-                            match self.client_cons.iter().find(|&(_,v)| v.node_id.unwrap().match_ack(&nodeid)) {
-                                None        => {
+                            let matching : Vec<LightId> = self.client_cons.iter()
+                                .filter(|&(_,v)| v.node_id.unwrap().match_ack(&nodeid))
+                                .map(|(k,_)| *k)
+                                .collect();
+                            match matching.len() {
+                                0 => {
                                     info!("server sent unexpected ACK {}", nodeid);
                                 },
-                                Some((z,_)) => {
-                                    self.map_to_client.insert(nodeid, *z);
+                                1 => {
+                                    self.map_to_client.insert(nodeid, matching[0]);
+                                },
+                                _ => {
+                                    error!("ACK {} matches light connections {:?}", nodeid, matching);
+                                    return Err(Error::AmbiguousNodeId(nodeid, matching));
                                 }
                             }
                         }
@@ -469,17 +1364,28 @@ impl<T: Write+Read> Connection<T> {
 
     // Process a message received from a peer via the subscription
     // mechanism.
+    #[cfg(feature = "commands")]
     pub fn process_async_message(&mut self, msg_type: u8, msg: &[u8]) {
-        if msg_type == packet::MsgType::MsgHeaders as u8 {
-            self.process_async_headers(msg).unwrap(); // FIXME
-        } else {
-            warn!("Received unknown message type {:?} from peer", msg_type);
+        match packet::MessageId::from_u8(msg_type) {
+            Some(packet::MessageId::Headers) => {
+                self.process_async_headers(msg).unwrap(); // FIXME
+            },
+            _ => {
+                warn!("Received unknown message type {:?} from peer", msg_type);
+            }
         }
     }
 
+    // without the block decoding, the messages of the subscription are dropped
+    #[cfg(not(feature = "commands"))]
+    pub fn process_async_message(&mut self, msg_type: u8, _msg: &[u8]) {
+        debug!("ignoring message type {:?} from peer", msg_type);
+    }
+
     // Process a 'Headers' message.
+    #[cfg(feature = "commands")]
     pub fn process_async_headers(&mut self, msg: &[u8]) -> Result<()> {
-        let mut headers = cardano::block::BlockHeaders::deserialize(&mut RawCbor::from(msg))?;
+        let mut headers : cardano::block::BlockHeaders = RawCbor::from(msg).deserialize()?;
 
         info!("received {} asynchronous headers", headers.len());
 
@@ -490,15 +1396,28 @@ impl<T: Write+Read> Connection<T> {
         Ok(())
     }
 
+    #[cfg(feature = "commands")]
     pub fn get_latest_tip(&self) -> Option<cardano::block::BlockHeader> {
         self.latest_tip.clone()
     }
 }
 
+impl<T: Write+Read> Drop for Connection<T> {
+    fn drop(&mut self) {
+        if let Err(err) = self.shutdown() {
+            warn!("error while closing light connections on drop: {:?}", err);
+        }
+    }
+}
+
+#[cfg(feature = "commands")]
 pub mod command {
+    use std::{fmt, cell::RefCell, collections::VecDeque};
     use std::io::{Read, Write};
+    use std::time::{Duration, Instant};
     use super::{LightId, Connection, Result, Error};
-    use cardano::{self, tx};
+    use ntt;
+    use cardano::{self, tx, block::{BlockDate, EpochId, EpochSlotId, SlotId}};
     use packet;
     use cbor_event::{de::RawCbor, se, self};
 
@@ -515,34 +1434,133 @@ pub mod command {
             Ok(id)
         }
         fn execute(&self, connection: &mut Connection<W>) -> Result<Self::Output> {
-            let id = Command::initial(self, connection)?;
+            run(self, connection, None)
+        }
 
-            Command::command(self, connection, id)?;
-            let ret = Command::result(self, connection, id)?;
+        /// same as `execute`, but fails with `Error::CommandTimedOut` if
+        /// the command has not completed, response and acknowledgment
+        /// included, after the given duration. The light connection of
+        /// the command is then closed.
+        ///
+        /// Waiting for the next frame of the peer is bounded by the time
+        /// left: the transport's read timeout is set to it for the time
+        /// of the read, so this also returns if the peer hangs. Once a
+        /// frame started to arrive, the rest of it is read with the
+        /// transport's own timeout, so a frame is never left half read.
+        fn execute_with_timeout(&self, connection: &mut Connection<W>, timeout: Option<Duration>) -> Result<Self::Output>
+            where W: ntt::ReadTimeout
+        {
+            Command::execute_detailed_with_timeout(self, connection, timeout).map(|(output, _, _)| output)
+        }
 
-            Command::terminate(self, connection, id)?;
+        /// same as `execute`, but also returns the light connection the
+        /// command ran on and the traffic it caused on the transport, e.g.
+        /// to correlate the logs of concurrent commands
+        fn execute_detailed(&self, connection: &mut Connection<W>) -> Result<(Self::Output, LightId, ntt::Stats)> {
+            run_detailed(self, connection, None)
+        }
 
-            Ok(ret)
+        /// same as `execute_with_timeout`, with the details of
+        /// `execute_detailed`
+        fn execute_detailed_with_timeout(&self, connection: &mut Connection<W>, timeout: Option<Duration>) -> Result<(Self::Output, LightId, ntt::Stats)>
+            where W: ntt::ReadTimeout
+        {
+            let deadline = timeout.map(|timeout| (Instant::now() + timeout, super::recv_within as super::RecvWithin<W>));
+            run_detailed(self, connection, deadline)
         }
         fn terminate(&self, connection: &mut Connection<W>, id: LightId) -> Result<()> {
-            connection.close_light_connection(id);
-            Ok(())
+            connection.close_light_connection(id)
         }
     }
 
+    fn run<W, C>(command: &C, connection: &mut Connection<W>, deadline: Option<(Instant, super::RecvWithin<W>)>) -> Result<C::Output>
+        where W: Read+Write, C: Command<W> + ?Sized
+    {
+        run_detailed(command, connection, deadline).map(|(output, _, _)| output)
+    }
+
+    // execute the command, failing with `Error::CommandTimedOut` once the
+    // deadline is passed, if any
+    fn run_detailed<W, C>(command: &C, connection: &mut Connection<W>, deadline: Option<(Instant, super::RecvWithin<W>)>) -> Result<(C::Output, LightId, ntt::Stats)>
+        where W: Read+Write, C: Command<W> + ?Sized
+    {
+        let before = connection.stats().transport;
+        let id = command.initial(connection)?;
+
+        connection.deadline = deadline.map(|(deadline, _)| deadline);
+        connection.recv_within = deadline.map(|(_, recv)| recv);
+        let ret = command.command(connection, id)
+            .and_then(|()| command.result(connection, id));
+        connection.deadline = None;
+        connection.recv_within = None;
+
+        let ret = match ret {
+            Err(Error::CommandTimedOut(id)) => {
+                command.terminate(connection, id)?;
+                return Err(Error::CommandTimedOut(id))
+            },
+            ret => ret?
+        };
+
+        command.terminate(connection, id)?;
+
+        Ok((ret, id, connection.stats().transport.since(&before)))
+    }
+
     #[derive(Debug)]
     pub struct GetBlockHeader {
         from: Vec<cardano::block::HeaderHash>,
-        to: Option<cardano::block::HeaderHash>
+        to: Option<cardano::block::HeaderHash>,
+        verify_signatures: bool
     }
     impl GetBlockHeader {
-        pub fn tip() -> Self { GetBlockHeader { from: vec![], to: None } }
+        pub fn tip() -> Self { GetBlockHeader { from: vec![], to: None, verify_signatures: false } }
         pub fn range(from: &[cardano::block::HeaderHash], to: cardano::block::HeaderHash) -> Self {
             let mut vec = Vec::new();
             for f in from.iter() {
                 vec.push(f.clone());
             }
-            GetBlockHeader { from: vec, to: Some(to) }
+            GetBlockHeader { from: vec, to: Some(to), verify_signatures: false }
+        }
+
+        /// the request for the headers after `from` up to `to` included,
+        /// `to_parent` being the parent of `to`.
+        ///
+        /// `None` if there is nothing to request: no header if `from` is
+        /// `to`, or only `to` itself if `from` is its parent, as the peer
+        /// fails on the interval (x.parent, x].
+        pub fn after( from: &cardano::block::HeaderHash
+                    , to: &cardano::block::HeaderHash
+                    , to_parent: &cardano::block::HeaderHash
+                    ) -> Option<Self>
+        {
+            if from == to || from == to_parent { return None }
+            Some(GetBlockHeader::range(&[from.clone()], to.clone()))
+        }
+
+        /// the headers after `from` up to the header `to` included, newest
+        /// first, requested from the peer if needed (see `after`)
+        ///
+        /// There are none if `from` is not on the chain of the peer.
+        pub fn headers_after<W: Read+Write>( connection: &mut Connection<W>
+                                           , from: &cardano::block::HeaderHash
+                                           , to: &cardano::block::BlockHeader
+                                           ) -> Result<Vec<cardano::block::BlockHeader>>
+        {
+            let to_hash = to.compute_hash();
+            match GetBlockHeader::after(from, &to_hash, &to.get_previous_header()) {
+                None if *from == to_hash => Ok(vec![]),
+                None => Ok(vec![to.clone()]),
+                Some(cmd) => Ok(cmd.execute(connection)?.decode()?),
+            }
+        }
+
+        /// check the signature of every main block header received against
+        /// the slot leader's key declared in the header, the command fails
+        /// with `Error::InvalidHeaderSignature` if one of them is invalid.
+        pub fn verify_signatures(mut self) -> Self {
+            self.verify_signatures = true;
+            self
         }
     }
 
@@ -554,13 +1572,36 @@ pub mod command {
         }
         fn result(&self, connection: &mut Connection<W>, id: LightId) -> Result<Self::Output> {
             // require the initial header
-            let dat = connection.wait_msg(id)?;
+            let dat = wait_cbor_value(connection, id)?;
             match decode_sum_type(&dat) {
                 None => Err(Error::UnexpectedResponse()),
                 Some((0, dat)) => {
                     let mut v = Vec::new();
                     v.extend_from_slice(dat);
-                    Ok(cardano::block::RawBlockHeaderMultiple::from_dat(v))
+                    let headers = cardano::block::RawBlockHeaderMultiple::from_dat(v);
+                    if self.verify_signatures || self.to.is_none() {
+                        let decoded = headers.decode()?;
+                        // an empty range is a valid answer (e.g. we are
+                        // already at the tip), but there is always a tip.
+                        if self.to.is_none() && decoded.is_empty() {
+                            return Err(Error::NoTip);
+                        }
+                        if self.verify_signatures {
+                            for header in decoded.iter() {
+                                match header {
+                                    cardano::block::BlockHeader::MainBlockHeader(mbh) => {
+                                        if !mbh.verify_signature(&mbh.consensus.leader_key) {
+                                            return Err(Error::InvalidHeaderSignature(header.compute_hash()));
+                                        }
+                                    },
+                                    // epoch boundary blocks are not signed, they are
+                                    // returned as is (e.g. when syncing across an epoch)
+                                    cardano::block::BlockHeader::GenesisBlockHeader(_) => {},
+                                }
+                            }
+                        }
+                    }
+                    Ok(headers)
                 },
                 Some((1, dat)) => Err(Error::ServerError(RawCbor::from(dat).text()?)),
                 Some((_n, _dat)) => Err(Error::UnexpectedResponse())
@@ -568,14 +1609,69 @@ pub mod command {
         }
     }
 
+    /// check the peer is alive: open a light connection and measure the
+    /// time the server takes to acknowledge it.
+    ///
+    /// Use `execute_with_timeout` to bound the time to wait for an
+    /// unresponsive peer.
     #[derive(Debug)]
-    pub struct GetBlock {
+    pub struct Ping;
+
+    impl<W> Command<W> for Ping where W: Read+Write {
+        type Output = Duration;
+        fn command(&self, _connection: &mut Connection<W>, _id: LightId) -> Result<()> {
+            // opening the light connection sent our node id already,
+            // this is all the server needs to reply
+            Ok(())
+        }
+        fn result(&self, connection: &mut Connection<W>, id: LightId) -> Result<Self::Output> {
+            let start = Instant::now();
+            connection.wait_light_ack(id)?;
+            Ok(start.elapsed())
+        }
+    }
+
+    pub struct GetBlock<'a> {
         from: cardano::block::HeaderHash,
-        to:   cardano::block::HeaderHash
+        to:   cardano::block::HeaderHash,
+        progress: Option<RefCell<Box<dyn FnMut(usize) + 'a>>>,
+        until: Option<RefCell<Box<dyn FnMut(&[cardano::block::RawBlock]) -> bool + 'a>>>
     }
-    impl GetBlock {
+    impl<'a> GetBlock<'a> {
         pub fn only(hh: &cardano::block::HeaderHash) -> Self { GetBlock::from(&hh.clone(), &hh.clone()) }
-        pub fn from(from: &cardano::block::HeaderHash, to: &cardano::block::HeaderHash) -> Self { GetBlock { from: from.clone(), to: to.clone() } }
+        pub fn from(from: &cardano::block::HeaderHash, to: &cardano::block::HeaderHash) -> Self { GetBlock { from: from.clone(), to: to.clone(), progress: None, until: None } }
+
+        /// call `progress` with the number of blocks received so far,
+        /// every time a block is received.
+        pub fn with_progress<F>(mut self, progress: F) -> Self
+            where F: FnMut(usize) + 'a
+        {
+            self.progress = Some(RefCell::new(Box::new(progress)));
+            self
+        }
+
+        /// call `until` with the blocks received so far, every time a block
+        /// is received, and cancel the download as soon as it returns `true`.
+        ///
+        /// The blocks still in flight are discarded (see
+        /// `Connection::cancel_light_connection`), leaving the connection
+        /// usable for the next commands.
+        pub fn until<F>(mut self, until: F) -> Self
+            where F: FnMut(&[cardano::block::RawBlock]) -> bool + 'a
+        {
+            self.until = Some(RefCell::new(Box::new(until)));
+            self
+        }
+    }
+    impl<'a> fmt::Debug for GetBlock<'a> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.debug_struct("GetBlock")
+                .field("from", &self.from)
+                .field("to", &self.to)
+                .field("progress", &self.progress.is_some())
+                .field("until", &self.until.is_some())
+                .finish()
+        }
     }
 
     fn strip_msg_response(msg: &[u8]) -> Result<cardano::block::RawBlock> {
@@ -594,7 +1690,7 @@ pub mod command {
         }
     }
 
-    impl<W> Command<W> for GetBlock where W: Read+Write {
+    impl<'a, W> Command<W> for GetBlock<'a> where W: Read+Write {
         type Output = Vec<cardano::block::RawBlock>;
         fn command(&self, connection: &mut Connection<W>, id: LightId) -> Result<()> {
             // require the initial header
@@ -603,16 +1699,537 @@ pub mod command {
         }
 
         fn result(&self, connection: &mut Connection<W>, id: LightId) -> Result<Self::Output> {
-            let msg_response = connection.wait_msg_eos(id)?;
             let mut msgs = Vec::new();
-            for response in msg_response.iter() {
+            while let Some(response) = connection.wait_msg_or_eos(id)? {
                 let msg = strip_msg_response(&response[..])?;
-                msgs.push(msg)
+                msgs.push(msg);
+                if let Some(ref progress) = self.progress {
+                    (&mut *progress.borrow_mut())(msgs.len());
+                }
+                if let Some(ref until) = self.until {
+                    if (&mut *until.borrow_mut())(&msgs) {
+                        connection.cancel_light_connection(id)?;
+                        break;
+                    }
+                }
             }
             Ok(msgs)
         }
     }
 
+    /// download the blocks of a range of slots, both bounds included
+    ///
+    /// The peer only serves blocks by header hash, so the hashes of the
+    /// first and last blocks of the range are resolved with `GetBlockHeader`
+    /// beforehand. This costs extra round trips:
+    ///
+    /// * one to get the tip of the peer;
+    /// * one for every batch of headers (the peer returns about 2000 headers
+    ///   at a time) between the checkpoint and the end of the range;
+    /// * one final `GetBlock` to download the blocks.
+    ///
+    /// The checkpoint is the hash of a block strictly before the range (e.g.
+    /// the genesis hash): the closer it is to the range, the fewer headers
+    /// are downloaded.
+    ///
+    /// Slots without a block are skipped: the blocks from the first block at
+    /// or after the start of the range to the last block at or before its end
+    /// are returned. A range starting at slot 0 includes the epoch boundary
+    /// block of its first epoch. A range without any block, or past the tip
+    /// of the peer, returns no block.
+    ///
+    /// This is made of several commands, each on its own light connection,
+    /// so it does not implement `Command`.
+    #[derive(Debug)]
+    pub struct GetBlocksBySlot {
+        checkpoint: cardano::block::HeaderHash,
+        from_epoch: EpochId,
+        from_slot: SlotId,
+        to_epoch: EpochId,
+        to_slot: SlotId,
+    }
+    impl GetBlocksBySlot {
+        pub fn new(checkpoint: &cardano::block::HeaderHash, from_epoch: EpochId, from_slot: SlotId, to_epoch: EpochId, to_slot: SlotId) -> Self {
+            GetBlocksBySlot { checkpoint: checkpoint.clone(), from_epoch, from_slot, to_epoch, to_slot }
+        }
+
+        fn range(&self) -> SlotRange {
+            let from = if self.from_slot == 0 {
+                BlockDate::Genesis(self.from_epoch)
+            } else {
+                BlockDate::Normal(EpochSlotId { epoch: self.from_epoch, slotid: self.from_slot })
+            };
+            let to = BlockDate::Normal(EpochSlotId { epoch: self.to_epoch, slotid: self.to_slot });
+            SlotRange::new(from, to)
+        }
+
+        pub fn execute<W: Read+Write>(&self, connection: &mut Connection<W>) -> Result<Vec<cardano::block::RawBlock>> {
+            let mut range = self.range();
+            if range.complete { return Ok(Vec::new()) }
+
+            // never empty, `GetBlockHeader::tip` fails with `Error::NoTip` otherwise
+            let tip = GetBlockHeader::tip().execute(connection)?.decode()?.remove(0);
+            let tip_hash = tip.compute_hash();
+
+            let mut cursor = self.checkpoint.clone();
+            while !range.complete && cursor != tip_hash {
+                let headers = GetBlockHeader::headers_after(connection, &cursor, &tip)?;
+                if headers.is_empty() { break }
+
+                // the headers are returned newest first
+                for header in headers.iter().rev() {
+                    range.push(header.get_blockdate(), header.compute_hash());
+                }
+                cursor = headers[0].compute_hash();
+            }
+
+            match (range.first, range.last) {
+                (Some(first), Some(last)) => GetBlock::from(&first, &last).execute(connection),
+                _ => Ok(Vec::new()),
+            }
+        }
+    }
+
+    /// download the block at the given height, i.e. the block whose chain
+    /// difficulty is the height
+    ///
+    /// The epoch boundary blocks have the difficulty of the main block
+    /// before them, so a height designates a main block, but for the
+    /// height 0 which is the epoch boundary block of the first epoch.
+    ///
+    /// The peer only serves blocks by header hash, so the hash is resolved
+    /// by going through the headers from the checkpoint, in batches of about
+    /// 2000 headers (see `GetBlocksBySlot`). The checkpoint must be a block
+    /// strictly below the height (e.g. the genesis hash): the closer it
+    /// is, the fewer headers are downloaded.
+    ///
+    /// Fails with `Error::HeightAboveTip` if the chain of the peer is not
+    /// that high yet, and with `Error::HeightNotFound` if the checkpoint is
+    /// already above the height.
+    ///
+    /// This is made of several commands, each on its own light connection,
+    /// so it does not implement `Command`.
+    #[derive(Debug)]
+    pub struct GetBlockByHeight {
+        checkpoint: cardano::block::HeaderHash,
+        height: u64,
+    }
+    impl GetBlockByHeight {
+        pub fn new(checkpoint: &cardano::block::HeaderHash, height: u64) -> Self {
+            GetBlockByHeight { checkpoint: checkpoint.clone(), height }
+        }
+
+        /// the hash of the block at the height, without downloading it
+        pub fn resolve<W: Read+Write>(&self, connection: &mut Connection<W>) -> Result<cardano::block::HeaderHash> {
+            // never empty, `GetBlockHeader::tip` fails with `Error::NoTip` otherwise
+            let tip = GetBlockHeader::tip().execute(connection)?.decode()?.remove(0);
+            let tip_height = u64::from(tip.difficulty());
+            if self.height > tip_height {
+                return Err(Error::HeightAboveTip(self.height, tip_height))
+            }
+
+            let found = walk_headers_after(connection, &self.checkpoint, &tip, |header| {
+                let height = u64::from(header.difficulty());
+                if height == self.height { return Some(Ok(header.compute_hash())) }
+                if height > self.height { return Some(Err(Error::HeightNotFound(self.height))) }
+                None
+            })?;
+            found.ok_or(Error::HeightNotFound(self.height))
+        }
+
+        pub fn execute<W: Read+Write>(&self, connection: &mut Connection<W>) -> Result<cardano::block::RawBlock> {
+            let hash = self.resolve(connection)?;
+            match GetBlock::only(&hash).execute(connection)?.into_iter().next() {
+                None => Err(Error::UnexpectedResponse()),
+                Some(block) => Ok(block),
+            }
+        }
+    }
+
+    // go through the headers of the peer after the checkpoint up to the
+    // tip, oldest first, until `visit` returns a result. `None` if it
+    // never does.
+    fn walk_headers_after<W, F, T>( connection: &mut Connection<W>
+                                  , checkpoint: &cardano::block::HeaderHash
+                                  , tip: &cardano::block::BlockHeader
+                                  , mut visit: F
+                                  ) -> Result<Option<T>>
+        where W: Read+Write
+            , F: FnMut(&cardano::block::BlockHeader) -> Option<Result<T>>
+    {
+        let tip_hash = tip.compute_hash();
+
+        let mut cursor = checkpoint.clone();
+        while cursor != tip_hash {
+            let headers = GetBlockHeader::headers_after(connection, &cursor, tip)?;
+            if headers.is_empty() { break }
+
+            // the headers are returned newest first
+            for header in headers.iter().rev() {
+                if let Some(result) = visit(header) { return result.map(Some) }
+            }
+            cursor = headers[0].compute_hash();
+        }
+        Ok(None)
+    }
+
+    /// find the hash of the epoch boundary block of the given epoch on the
+    /// chain of the peer
+    ///
+    /// As for `GetBlockByHeight`, the hash is resolved by going through the
+    /// headers from the checkpoint, which must be a block before the epoch
+    /// boundary block (e.g. the genesis hash): the closer it is, the fewer
+    /// headers are downloaded.
+    ///
+    /// Fails with `Error::EpochAboveTip` if the chain of the peer has not
+    /// reached the epoch yet, and with `Error::EpochBoundaryNotFound` if
+    /// the checkpoint is already past the epoch boundary block.
+    ///
+    /// This is made of several commands, each on its own light connection,
+    /// so it does not implement `Command`.
+    #[derive(Debug)]
+    pub struct GetEpochBoundary {
+        checkpoint: cardano::block::HeaderHash,
+        epoch: cardano::block::EpochId,
+    }
+    impl GetEpochBoundary {
+        pub fn new(checkpoint: &cardano::block::HeaderHash, epoch: cardano::block::EpochId) -> Self {
+            GetEpochBoundary { checkpoint: checkpoint.clone(), epoch }
+        }
+
+        pub fn execute<W: Read+Write>(&self, connection: &mut Connection<W>) -> Result<cardano::block::HeaderHash> {
+            // never empty, `GetBlockHeader::tip` fails with `Error::NoTip` otherwise
+            let tip = GetBlockHeader::tip().execute(connection)?.decode()?.remove(0);
+            let tip_epoch = tip.get_blockdate().get_epochid();
+            if self.epoch > tip_epoch {
+                return Err(Error::EpochAboveTip(self.epoch, tip_epoch))
+            }
+
+            let found = walk_headers_after(connection, &self.checkpoint, &tip, |header| {
+                match header.get_blockdate() {
+                    cardano::block::BlockDate::Genesis(epoch) if epoch == self.epoch => Some(Ok(header.compute_hash())),
+                    date => if date.get_epochid() >= self.epoch { Some(Err(Error::EpochBoundaryNotFound(self.epoch))) } else { None },
+                }
+            })?;
+            found.ok_or(Error::EpochBoundaryNotFound(self.epoch))
+        }
+    }
+
+    /// default number of `GetBlock` requests `PipelinedGetBlocks` keeps in
+    /// flight
+    pub const DEFAULT_PIPELINE_DEPTH : usize = 2;
+
+    /// download the blocks from `from` up to `to`, the next batch of
+    /// headers being requested while the blocks of the previous batches
+    /// are still downloading.
+    ///
+    /// Each request runs on its own light connection of the same
+    /// `Connection`, the frames of the others being kept in their buffers
+    /// until they are waited for. The blocks are given to the callback in
+    /// chain order, one batch per `GetBlock` request.
+    ///
+    /// The responses of all the requests in flight may have to be kept in
+    /// memory, so the depth should stay small: the connection fails with
+    /// `Error::ReceiveBufferFull` otherwise.
+    ///
+    /// This is made of several commands, each on its own light connection,
+    /// so it does not implement `Command`.
+    #[derive(Debug)]
+    pub struct PipelinedGetBlocks {
+        from: cardano::block::HeaderHash,
+        inclusive: bool,
+        to: cardano::block::HeaderHash,
+        to_parent: cardano::block::HeaderHash,
+        depth: usize,
+    }
+    impl PipelinedGetBlocks {
+        /// `to_parent` is the parent of the block `to`, the block `from` is
+        /// only downloaded if `inclusive`
+        pub fn new( from: &cardano::block::HeaderHash
+                  , inclusive: bool
+                  , to: &cardano::block::HeaderHash
+                  , to_parent: &cardano::block::HeaderHash
+                  ) -> Self
+        {
+            PipelinedGetBlocks {
+                from: from.clone(),
+                inclusive,
+                to: to.clone(),
+                to_parent: to_parent.clone(),
+                depth: DEFAULT_PIPELINE_DEPTH,
+            }
+        }
+
+        /// set the number of `GetBlock` requests to keep in flight (at
+        /// least 1), see `DEFAULT_PIPELINE_DEPTH`
+        pub fn depth(mut self, depth: usize) -> Self {
+            self.depth = ::std::cmp::max(1, depth);
+            self
+        }
+
+        pub fn execute<W, F>(&self, connection: &mut Connection<W>, mut got_blocks: F) -> Result<()>
+            where W: Read+Write
+                , F: FnMut(Vec<cardano::block::RawBlock>)
+        {
+            let mut blocks = VecDeque::new();
+            let mut headers = None;
+            // the blocks of the last headers received, requested after the
+            // next headers so these are not stuck behind the blocks
+            let mut next_blocks = None;
+            // where to request the next headers from, and whether the
+            // block itself is to be downloaded
+            let mut next_headers = Some((self.from.clone(), self.inclusive));
+
+            loop {
+                if headers.is_none() {
+                    if let Some((from, inclusive)) = next_headers.take() {
+                        match GetBlockHeader::after(&from, &self.to, &self.to_parent) {
+                            None if from == self.to => {
+                                if inclusive { next_blocks = Some(GetBlock::from(&self.to, &self.to)) }
+                            },
+                            None => {
+                                let start = if inclusive { &from } else { &self.to };
+                                next_blocks = Some(GetBlock::from(start, &self.to));
+                            },
+                            Some(cmd) => {
+                                let id = start_command(&cmd, connection)?;
+                                headers = Some((id, cmd, from, inclusive));
+                            },
+                        }
+                    }
+                }
+                if let Some(cmd) = next_blocks.take() {
+                    let id = start_command(&cmd, connection)?;
+                    blocks.push_back((id, cmd));
+                }
+
+                // receive the oldest blocks once enough are in flight, or
+                // when there are no more headers to wait for
+                if blocks.len() >= self.depth || (headers.is_none() && !blocks.is_empty()) {
+                    let (id, cmd) = blocks.pop_front().unwrap();
+                    got_blocks(finish_command(&cmd, connection, id)?);
+                    continue;
+                }
+
+                let (id, cmd, from, inclusive) = match headers.take() {
+                    None => return Ok(()),
+                    Some(headers) => headers,
+                };
+                let received = finish_command(&cmd, connection, id)?.decode()?;
+                // empty when already at the tip of the peer
+                if received.is_empty() { continue }
+
+                // the headers are returned newest first, the oldest having
+                // `from` as parent
+                let parent = received[received.len() - 1].get_previous_header();
+                if parent != from {
+                    warn!("headers following {} start after {}", from, parent);
+                    return Err(Error::UnlinkedHeaders(from, parent));
+                }
+                let last = received[0].compute_hash();
+                let first = if inclusive { from } else { received[received.len() - 1].compute_hash() };
+                next_blocks = Some(GetBlock::from(&first, &last));
+                next_headers = Some((last, false));
+            }
+        }
+    }
+
+    /// a subscription to the new tips of the peer that survives the loss
+    /// of its connection.
+    ///
+    /// The new headers are returned in chain order by `next`, and the
+    /// consumer acknowledges those it is done with by calling `ack`. When
+    /// the connection fails, a new one is opened with the `connect`
+    /// function and the headers following the last acknowledged one are
+    /// fetched again, skipping those `next` already returned.
+    ///
+    /// The hashes of the headers returned and not acknowledged yet are
+    /// kept to detect the overlap, so the consumer should acknowledge them
+    /// regularly.
+    pub struct ResumableSubscription<T: Read+Write, F> {
+        connect: F,
+        connection: Option<Connection<T>>,
+        acked: Option<cardano::block::HeaderHash>,
+        // the headers returned since the last acknowledged one, in chain order
+        delivered: Vec<cardano::block::HeaderHash>,
+        // the newest header queued in `pending` or already returned
+        last: Option<cardano::block::HeaderHash>,
+        pending: VecDeque<cardano::block::BlockHeader>,
+    }
+    impl<T, F> ResumableSubscription<T, F>
+        where T: Read+Write
+            , F: FnMut() -> Result<Connection<T>>
+    {
+        /// `connect` opens a connection to the peer, the light-protocol
+        /// handshake performed. The subscription starts after the
+        /// `checkpoint`, or at the tip of the peer if there is none.
+        pub fn new(connect: F, checkpoint: Option<&cardano::block::HeaderHash>) -> Self {
+            ResumableSubscription {
+                connect,
+                connection: None,
+                acked: checkpoint.cloned(),
+                delivered: Vec::new(),
+                last: checkpoint.cloned(),
+                pending: VecDeque::new(),
+            }
+        }
+
+        /// the header last acknowledged with `ack`, or the checkpoint
+        pub fn acked(&self) -> Option<&cardano::block::HeaderHash> {
+            self.acked.as_ref()
+        }
+
+        /// acknowledge the given header, and the ones returned before it:
+        /// the subscription resumes after it on reconnection
+        pub fn ack(&mut self, hash: &cardano::block::HeaderHash) {
+            if let Some(position) = self.delivered.iter().position(|h| h == hash) {
+                self.delivered.drain(..position + 1);
+            }
+            self.acked = Some(hash.clone());
+        }
+
+        /// wait for the next header of the chain of the peer.
+        ///
+        /// If the connection fails, this reconnects once: the errors of the
+        /// reconnection are returned, calling `next` again retries. The
+        /// other errors are returned as is.
+        pub fn next(&mut self) -> Result<cardano::block::BlockHeader> {
+            let mut reconnected = false;
+            loop {
+                if let Some(header) = self.pending.pop_front() {
+                    self.delivered.push(header.compute_hash());
+                    return Ok(header)
+                }
+
+                if self.connection.is_none() {
+                    reconnected = true;
+                    self.resume()?;
+                    continue;
+                }
+
+                if let Err(err) = self.poll() {
+                    let healthy = self.connection.as_ref().map_or(false, |c| c.is_healthy());
+                    if healthy { return Err(err) }
+                    self.connection = None;
+                    if reconnected { return Err(err) }
+                    warn!("subscription connection failed: {:?}, reconnecting", err);
+                }
+            }
+        }
+
+        // connect and queue the headers following the last acknowledged one
+        fn resume(&mut self) -> Result<()> {
+            let mut connection = (self.connect)()?;
+            connection.subscribe()?;
+            // never empty, `GetBlockHeader::tip` fails with `Error::NoTip` otherwise
+            let tip = GetBlockHeader::tip().execute(&mut connection)?.decode()?.remove(0);
+            self.connection = Some(connection);
+
+            let from = self.acked.clone();
+            let result = self.catch_up(from, tip);
+            if result.is_err() { self.connection = None }
+            result
+        }
+
+        // queue the headers up to the latest tip the peer sent, waiting for
+        // the next frame if there is no new tip
+        fn poll(&mut self) -> Result<()> {
+            let tip = self.connection.as_ref().and_then(|c| c.get_latest_tip());
+            match tip {
+                Some(ref tip) if Some(tip.compute_hash()) != self.last => {
+                    let from = self.last.clone();
+                    self.catch_up(from, tip.clone())
+                },
+                _ => self.connection.as_mut().unwrap().process_message(),
+            }
+        }
+
+        // queue the headers after `from` up to `tip`, or as many as the
+        // peer returns, skipping the ones already returned
+        fn catch_up(&mut self, from: Option<cardano::block::HeaderHash>, tip: cardano::block::BlockHeader) -> Result<()> {
+            let headers = match from {
+                Some(ref from) => {
+                    let connection = self.connection.as_mut().unwrap();
+                    let mut headers = GetBlockHeader::headers_after(connection, from, &tip)?;
+                    // the headers are returned newest first, and there are
+                    // none if `from` is not on the chain of the peer
+                    headers.reverse();
+                    if headers.is_empty() && *from != tip.compute_hash() { vec![tip] } else { headers }
+                },
+                None => vec![tip],
+            };
+
+            for header in headers {
+                let hash = header.compute_hash();
+                let known = self.acked.as_ref() == Some(&hash)
+                    || self.delivered.contains(&hash)
+                    || self.pending.iter().any(|h| h.compute_hash() == hash);
+                if ! known { self.pending.push_back(header) }
+                self.last = Some(hash);
+            }
+            Ok(())
+        }
+    }
+
+    // open the light connection of the command and send the request
+    fn start_command<W: Read+Write, C: Command<W>>(cmd: &C, connection: &mut Connection<W>) -> Result<LightId> {
+        let id = cmd.initial(connection)?;
+        cmd.command(connection, id)?;
+        Ok(id)
+    }
+
+    // wait for the response of the command and close its light connection
+    fn finish_command<W: Read+Write, C: Command<W>>(cmd: &C, connection: &mut Connection<W>, id: LightId) -> Result<C::Output> {
+        let ret = cmd.result(connection, id)?;
+        cmd.terminate(connection, id)?;
+        Ok(ret)
+    }
+
+    /// the hashes of the first and last blocks within the dates `from` and
+    /// `to`, found while going through the headers in chain order.
+    struct SlotRange {
+        from: BlockDate,
+        to: BlockDate,
+        first: Option<cardano::block::HeaderHash>,
+        last: Option<cardano::block::HeaderHash>,
+        /// a header past the end of the range was found
+        complete: bool,
+    }
+    impl SlotRange {
+        fn new(from: BlockDate, to: BlockDate) -> Self {
+            SlotRange { complete: from > to, from, to, first: None, last: None }
+        }
+
+        fn push(&mut self, date: BlockDate, hash: cardano::block::HeaderHash) {
+            if self.complete || date < self.from { return }
+            if date > self.to {
+                self.complete = true;
+                return
+            }
+            if self.first.is_none() { self.first = Some(hash.clone()) }
+            self.last = Some(hash);
+        }
+    }
+
+    /// wait for a complete CBOR value on the given light connection
+    ///
+    /// a large response may be split over multiple data frames, so we keep
+    /// appending the frames until the buffer can be decoded entirely.
+    fn wait_cbor_value<W: Read+Write>(connection: &mut Connection<W>, id: LightId) -> Result<Vec<u8>> {
+        let mut dat = connection.wait_msg(id)?;
+        loop {
+            match RawCbor::from(&dat).deserialize::<cbor_event::Value>() {
+                Ok(_) => return Ok(dat),
+                Err(cbor_event::Error::NotEnough(got, expected)) => {
+                    trace!("incomplete CBOR value ({} bytes, expected {}), waiting for more data", got, expected);
+                },
+                Err(err) => return Err(Error::from(err)),
+            }
+            let more = connection.wait_msg(id)?;
+            dat.extend_from_slice(&more);
+        }
+    }
+
     // FIXME: use cardano::decode_sum_type().
     fn decode_sum_type(input: &[u8]) -> Option<(u8, &[u8])> {
         if input.len() > 2 && input[0] == 0x82 && input[1] < 23 {
@@ -683,3 +2300,1422 @@ pub mod command {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use mock::MockStream;
+
+    /// create a `Connection` whose ntt handshake succeeded and
+    /// which will then read the given bytes from the peer
+    pub fn connection(input: &[u8]) -> Connection<MockStream> {
+        let mut bytes = vec![0, 0, 0, 0]; // ntt handshake OK
+        bytes.extend_from_slice(input);
+        let ntt = ntt::Connection::handshake(0, MockStream::new(bytes)).unwrap();
+        Connection::new(ntt, ntt::DEFAULT_MAX_PAYLOAD_SIZE, ntt::DEFAULT_READ_CHUNK_SIZE)
+    }
+
+    /// same as `connection`, but reads time out instead of reaching the
+    /// end of the input
+    fn connection_with_timeout(input: &[u8]) -> Connection<MockStream> {
+        use std::time::Duration;
+        use ntt::ReadTimeout;
+
+        let mut bytes = vec![0, 0, 0, 0]; // ntt handshake OK
+        bytes.extend_from_slice(input);
+        let mut stream = MockStream::new(bytes);
+        stream.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let ntt = ntt::Connection::handshake(0, stream).unwrap();
+        Connection::new(ntt, ntt::DEFAULT_MAX_PAYLOAD_SIZE, ntt::DEFAULT_READ_CHUNK_SIZE)
+    }
+
+    fn close_frame(id: LightId) -> Vec<u8> {
+        let mut buf = vec![];
+        ntt::protocol::delete_conn(id.0, &mut buf);
+        buf
+    }
+
+    pub fn create_frame(id: LightId) -> Vec<u8> {
+        let mut buf = vec![];
+        ntt::protocol::create_conn(id.0, &mut buf);
+        buf
+    }
+
+    pub fn data_frame(id: LightId, dat: &[u8]) -> Vec<u8> {
+        let mut buf = vec![];
+        ntt::protocol::append_lightweight_data(id.0, dat.len() as u32, &mut buf);
+        buf.extend_from_slice(dat);
+        buf
+    }
+
+    /// frames opening a server light connection acknowledging
+    /// the client connection created with the given nonce
+    pub fn ack_frames(server_id: LightId, nonce: ntt::protocol::Nonce) -> Vec<u8> {
+        let mut buf = create_frame(server_id);
+        buf.extend(data_frame(server_id, ntt::protocol::NodeId::make_ack(nonce).as_ref()));
+        buf
+    }
+
+    #[test]
+    fn establish_performs_both_handshakes() {
+        let hs = packet::Handshake::default();
+        let server_id = LightId::new(0x500);
+
+        let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+        input.extend(create_frame(server_id));
+        input.extend(data_frame(server_id, &packet::send_handshake(&hs)));
+        input.extend(data_frame(server_id, ntt::protocol::NodeId::make_ack(0).as_ref()));
+
+        let conn = Connection::establish(0, MockStream::new(input), &hs).unwrap();
+        assert!(conn.client_cons.contains_key(&LightId::new(INITIAL_LIGHT_ID)));
+        assert!(conn.server_cons.contains_key(&server_id));
+    }
+
+    #[test]
+    fn establish_with_nodeid_in_handshake_frame() {
+        let hs = packet::Handshake::default();
+        let server_id = LightId::new(0x500);
+
+        let mut data = packet::send_handshake(&hs);
+        data.extend_from_slice(ntt::protocol::NodeId::make_ack(0).as_ref());
+        let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+        input.extend(create_frame(server_id));
+        input.extend(data_frame(server_id, &data));
+
+        let conn = Connection::establish(0, MockStream::new(input), &hs).unwrap();
+        assert_eq!(conn.server_cons[&server_id].node_id, Some(ntt::protocol::NodeId::make_ack(0)));
+    }
+
+    #[test]
+    fn establish_with_truncated_nodeid() {
+        let hs = packet::Handshake::default();
+        let server_id = LightId::new(0x500);
+        let nodeid = ntt::protocol::NodeId::make_ack(0);
+
+        let handshake = |nodeid_frame: Vec<u8>| {
+            let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+            input.extend(create_frame(server_id));
+            input.extend(data_frame(server_id, &packet::send_handshake(&hs)));
+            input.extend(nodeid_frame);
+            Connection::establish(0, MockStream::new(input), &hs)
+        };
+        let expect_malformed = |result: Result<Connection<MockStream>>, len: usize| match result {
+            Err(Error::IOError(err)) => {
+                assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+                assert!(err.to_string().contains(&format!("received {} bytes", len)), "{}", err);
+            },
+            Err(err) => panic!("expected a malformed node id error, got {:?}", err),
+            Ok(_) => panic!("expected a malformed node id error"),
+        };
+
+        expect_malformed(handshake(data_frame(server_id, &nodeid.as_ref()[..5])), 5);
+
+        // truncated node id in the same frame as the handshake
+        let mut data = packet::send_handshake(&hs);
+        data.extend_from_slice(&nodeid.as_ref()[..3]);
+        let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+        input.extend(create_frame(server_id));
+        input.extend(data_frame(server_id, &data));
+        expect_malformed(Connection::establish(0, MockStream::new(input), &hs), 3);
+    }
+
+    #[test]
+    fn establish_ignores_empty_data_frames() {
+        use std::time::Duration;
+        use ntt::ReadTimeout;
+
+        let hs = packet::Handshake::default();
+        let server_id = LightId::new(0x500);
+
+        let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+        input.extend(create_frame(server_id));
+        input.extend(data_frame(server_id, &[]));
+        input.extend(data_frame(server_id, &packet::send_handshake(&hs)));
+        input.extend(data_frame(server_id, &[]));
+        input.extend(data_frame(server_id, &[]));
+        input.extend(data_frame(server_id, ntt::protocol::NodeId::make_ack(0).as_ref()));
+
+        let conn = Connection::establish(0, MockStream::new(input.clone()), &hs).unwrap();
+        assert_eq!(conn.server_cons[&server_id].node_id, Some(ntt::protocol::NodeId::make_ack(0)));
+
+        let mut stream = MockStream::new(input);
+        stream.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        let conn = Connection::establish_with_timeout(0, stream, &hs, Duration::from_millis(10)).unwrap();
+        assert_eq!(conn.server_cons[&server_id].node_id, Some(ntt::protocol::NodeId::make_ack(0)));
+    }
+
+    #[test]
+    fn establish_without_nodeid() {
+        use std::time::Duration;
+        use ntt::ReadTimeout;
+
+        let hs = packet::Handshake::default();
+        let server_id = LightId::new(0x500);
+
+        let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+        input.extend(create_frame(server_id));
+        input.extend(data_frame(server_id, &packet::send_handshake(&hs)));
+
+        // the peer sends nothing after its handshake
+        let mut stream = MockStream::new(input.clone());
+        stream.set_read_timeout(Some(Duration::from_secs(1))).unwrap();
+        match Connection::establish_with_timeout(0, stream, &hs, Duration::from_millis(10)) {
+            Err(Error::MissingNodeId(id)) => assert_eq!(id, server_id),
+            Err(err) => panic!("expected a missing node id error, got {:?}", err),
+            Ok(_) => panic!("expected a missing node id error"),
+        }
+
+        // the peer goes on with another frame
+        input.extend(close_frame(server_id));
+        match Connection::establish(0, MockStream::new(input), &hs) {
+            Err(Error::MissingNodeId(id)) => assert_eq!(id, server_id),
+            Err(err) => panic!("expected a missing node id error, got {:?}", err),
+            Ok(_) => panic!("expected a missing node id error"),
+        }
+    }
+
+    #[test]
+    fn empty_data_frames_are_ignored() {
+        let server_id = LightId::new(0x500);
+        let async_id = LightId::new(0x501);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &[]));
+        input.extend(data_frame(server_id, &[1, 2]));
+        // before the node id of a light connection of the peer
+        input.extend(create_frame(async_id));
+        input.extend(data_frame(async_id, &[]));
+        input.extend(data_frame(async_id, ntt::protocol::NodeId::make_syn(7).as_ref()));
+
+        let mut conn = connection(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+        conn.wait_light_ack(id).unwrap();
+
+        conn.process_message().unwrap();
+        assert!(! conn.has_bytes_to_read_or_finish(id));
+        assert_eq!(conn.received_len(), 0);
+        assert_eq!(conn.wait_msg(id).unwrap(), vec![1, 2]);
+
+        for _ in 0..3 { conn.process_message().unwrap(); }
+        assert_eq!(conn.server_cons.get(&async_id).unwrap().node_id, Some(ntt::protocol::NodeId::make_syn(7)));
+    }
+
+    #[test]
+    fn replies_are_read_after_the_peer_closed() {
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &[1, 2]));
+        input.extend(data_frame(server_id, &[3]));
+        input.extend(close_frame(server_id));
+
+        let mut conn = connection(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+        for _ in 0..5 { conn.process_message().unwrap(); }
+        assert!(conn.server_cons.is_empty());
+        assert!(! conn.is_acknowledged(id));
+        assert!(conn.client_cons.get(&id).unwrap().is_eos());
+
+        // the input is exhausted: the replies are read from the buffer
+        assert_eq!(conn.wait_msg(id).unwrap(), vec![1, 2]);
+        assert_eq!(conn.wait_msg_or_eos(id).unwrap(), Some(vec![3]));
+        assert_eq!(conn.wait_msg_or_eos(id).unwrap(), None);
+
+        conn.close_light_connection(id).unwrap();
+        assert!(conn.client_cons.is_empty());
+        assert_eq!(conn.received_len(), 0);
+    }
+
+    #[test]
+    fn probe_socket_is_acknowledged() {
+        let mut conn = connection(&[0, 0, 0, 4, 0, 0, 0, 5]);
+        let written = conn.get_backend().output.len();
+        conn.process_message().unwrap();
+        assert_eq!(&conn.get_backend().output[written..], &[0, 0, 0, 5]);
+
+        // an acknowledgement is not replied to
+        conn.process_message().unwrap();
+        assert_eq!(conn.get_backend().output.len(), written + 4);
+        assert!(! conn.failed);
+    }
+
+    #[test]
+    fn close_socket_once_the_peer_received_our_light_connections() {
+        let mut conn = connection(&[0, 0, 0, 2, 0, 0, 0x04, 0x00, 0, 0, 0, 2, 0, 0, 0x04, 0x01]);
+        let id = conn.get_free_light_id();
+        assert_eq!(id, LightId::new(0x401));
+
+        // the peer did not receive our last light connection yet
+        conn.process_message().unwrap();
+        assert!(! conn.failed);
+
+        match conn.process_message() {
+            Err(Error::SocketClosedByPeer) => {},
+            r => panic!("expected SocketClosedByPeer, got {:?}", r),
+        }
+        assert!(conn.failed);
+    }
+
+    #[test]
+    fn close_end_point_closes_the_connection() {
+        let mut conn = connection(&[0, 0, 0, 3]);
+        match conn.process_message() {
+            Err(Error::EndPointClosedByPeer) => {},
+            r => panic!("expected EndPointClosedByPeer, got {:?}", r),
+        }
+        assert!(conn.failed);
+    }
+
+    #[test]
+    fn reset_session_starts_from_a_clean_slate() {
+        let hs = packet::Handshake::default();
+        let server_id = LightId::new(0x500);
+        let async_id = LightId::new(0x501);
+
+        let mut input = create_frame(server_id);
+        input.extend(data_frame(server_id, &packet::send_handshake(&hs)));
+        input.extend(data_frame(server_id, ntt::protocol::NodeId::make_ack(0).as_ref()));
+        input.extend(create_frame(async_id));
+        let mut conn = connection(&input);
+        conn.handshake(&hs).unwrap();
+        conn.process_message().unwrap();
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+        assert!(conn.is_healthy());
+        assert_eq!(conn.stats().server_light_connections, 2);
+        assert_eq!(conn.stats().client_light_connections, 2);
+
+        conn.reset_session();
+        assert!(! conn.is_healthy());
+        assert!(conn.peer_handshake().is_none());
+        assert_eq!(conn.stats().server_light_connections, 0);
+        assert_eq!(conn.stats().client_light_connections, 0);
+        assert!(conn.map_to_client.is_empty());
+        assert_eq!(conn.next_light_id, LightId::new(0x401));
+        assert_eq!(conn.get_free_light_id(), LightId::new(0x401));
+
+        // nothing is closed on the transport of the previous session
+        let written = conn.get_backend().output.len();
+        conn.shutdown().unwrap();
+        assert_eq!(conn.get_backend().output.len(), written);
+    }
+
+    #[test]
+    fn transport_error_makes_the_connection_unhealthy() {
+        let hs = packet::Handshake::default();
+        let server_id = LightId::new(0x500);
+        let other_id = LightId::new(0x501);
+
+        let mut input = create_frame(server_id);
+        input.extend(data_frame(server_id, &packet::send_handshake(&hs)));
+        input.extend(data_frame(server_id, ntt::protocol::NodeId::make_ack(0).as_ref()));
+        // a response for a node id we never sent
+        input.extend(create_frame(other_id));
+        input.extend(data_frame(other_id, ntt::protocol::NodeId::make_ack(42).as_ref()));
+        input.extend(data_frame(other_id, b"hello"));
+        let mut conn = connection(&input);
+        assert!(! conn.is_healthy());
+
+        conn.handshake(&hs).unwrap();
+        assert!(conn.is_healthy());
+
+        // discarding the data of the peer does not affect the connection
+        conn.process_message().unwrap();
+        conn.process_message().unwrap();
+        match conn.process_message() {
+            Err(Error::NodeIdNotFound(_)) => {},
+            r => panic!("expected NodeIdNotFound, got {:?}", r),
+        }
+        assert!(conn.is_healthy());
+
+        // the end of the input
+        match conn.process_message() {
+            Err(Error::NttError(ntt::Error::IOError(_))) => {},
+            r => panic!("expected a transport error, got {:?}", r),
+        }
+        assert!(! conn.is_healthy());
+    }
+
+    #[test]
+    fn events_of_the_connection_lifecycle() {
+        use std::sync::{Arc, Mutex};
+
+        let hs = packet::Handshake::default();
+        let server_id = LightId::new(0x500);
+        let async_id = LightId::new(0x501);
+        let client_id = LightId::new(INITIAL_LIGHT_ID);
+
+        let mut input = create_frame(server_id);
+        input.extend(data_frame(server_id, &packet::send_handshake(&hs)));
+        input.extend(data_frame(server_id, ntt::protocol::NodeId::make_ack(0).as_ref()));
+        input.extend(create_frame(async_id));
+        input.extend(data_frame(async_id, ntt::protocol::NodeId::make_syn(7).as_ref()));
+        input.extend(close_frame(async_id));
+        // the peer creates its handshake light connection again
+        input.extend(create_frame(server_id));
+        let mut conn = connection(&input);
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        conn.on_event(Box::new(move |event| recorded.lock().unwrap().push(format!("{:?}", event))));
+
+        conn.handshake(&hs).unwrap();
+        for _ in 0..3 { conn.process_message().unwrap(); }
+        conn.close_light_connection(client_id).unwrap();
+        assert!(conn.process_message().is_err());
+
+        let expected = vec![
+            format!("{:?}", ConnectionEvent::LightConnectionCreated { id: client_id, by_peer: false }),
+            format!("{:?}", ConnectionEvent::LightConnectionCreated { id: server_id, by_peer: true }),
+            format!("{:?}", ConnectionEvent::Established),
+            format!("{:?}", ConnectionEvent::LightConnectionCreated { id: async_id, by_peer: true }),
+            format!("{:?}", ConnectionEvent::DataReceived { id: async_id, len: 9 }),
+            format!("{:?}", ConnectionEvent::LightConnectionClosed { id: async_id, by_peer: true }),
+            format!("{:?}", ConnectionEvent::LightConnectionClosed { id: client_id, by_peer: false }),
+            format!("{:?}", ConnectionEvent::ProtocolError(&Error::ServerCreatedLightIdTwice(server_id))),
+        ];
+        assert_eq!(*events.lock().unwrap(), expected);
+    }
+
+    #[test]
+    fn connection_is_send() {
+        fn assert_send<T: Send>() {}
+        assert_send::<Connection<::std::net::TcpStream>>();
+    }
+
+    #[test]
+    fn peer_capabilities_of_mainnet_handshake() {
+        let hs = packet::Handshake::default();
+        let server_id = LightId::new(0x500);
+
+        let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+        input.extend(create_frame(server_id));
+        input.extend(data_frame(server_id, packet::tests::HANDSHAKE_BYTES));
+        input.extend(data_frame(server_id, ntt::protocol::NodeId::make_ack(0).as_ref()));
+
+        let conn = Connection::establish(0, MockStream::new(input), &hs).unwrap();
+        let ins = conn.peer_in_capabilities().unwrap();
+        assert_eq!(ins.iter().count(), 19);
+        assert_eq!(ins.get(0x04), Some(&packet::HandlerSpec::new(0x05)));
+        assert_eq!(ins.get(0x53).map(packet::HandlerSpec::code), Some(0x00));
+        assert_eq!(ins.get(0x0d), None);
+
+        let outs = conn.peer_out_capabilities().unwrap();
+        assert_eq!(outs.iter().count(), 13);
+        assert_eq!(outs.get(0x0d), Some(&packet::HandlerSpec::new(0x00)));
+        assert_eq!(outs.get(0x2b), Some(&packet::HandlerSpec::new(0x5d)));
+        assert_eq!(outs.get(0x22), None);
+    }
+
+    #[test]
+    fn new_light_connection_on_closed_transport() {
+        let mut conn = connection(&[]);
+        conn.get_backend_mut().close();
+
+        let id = conn.get_free_light_id();
+        match conn.new_light_connection(id) {
+            Err(Error::NttError(ntt::Error::IOError(ref err))) if err.kind() == io::ErrorKind::BrokenPipe => {},
+            Err(err) => panic!("expected a broken pipe error, got {:?}", err),
+            Ok(()) => panic!("expected a broken pipe error"),
+        }
+        assert!(! conn.client_cons.contains_key(&id));
+    }
+
+    #[test]
+    fn close_light_connection_on_closed_transport() {
+        let mut conn = connection(&[]);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+        conn.get_backend_mut().close();
+
+        match conn.close_light_connection(id) {
+            Err(Error::NttError(ntt::Error::IOError(ref err))) if err.kind() == io::ErrorKind::BrokenPipe => {},
+            r => panic!("expected a broken pipe error, got {:?}", r),
+        }
+        assert!(! conn.client_cons.contains_key(&id));
+        assert!(! conn.is_healthy());
+    }
+
+    #[test]
+    fn cancel_light_connection_on_closed_transport() {
+        let mut conn = connection(&[]);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+        conn.get_backend_mut().close();
+
+        match conn.cancel_light_connection(id) {
+            Err(Error::NttError(ntt::Error::IOError(ref err))) if err.kind() == io::ErrorKind::BrokenPipe => {},
+            r => panic!("expected a broken pipe error, got {:?}", r),
+        }
+        assert!(! conn.client_cons.contains_key(&id));
+        assert!(! conn.is_healthy());
+    }
+
+    fn establish_with_server_magic(ours: u32, theirs: u32) -> Result<Connection<MockStream>> {
+        let mut hs = packet::Handshake::default();
+        hs.protocol_magic = cardano::config::ProtocolMagic::from(ours);
+        let mut server_hs = packet::Handshake::default();
+        server_hs.protocol_magic = cardano::config::ProtocolMagic::from(theirs);
+        let server_id = LightId::new(0x500);
+
+        let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+        input.extend(create_frame(server_id));
+        input.extend(data_frame(server_id, &packet::send_handshake(&server_hs)));
+        input.extend(data_frame(server_id, ntt::protocol::NodeId::make_ack(0).as_ref()));
+
+        Connection::establish(0, MockStream::new(input), &hs)
+    }
+
+    #[test]
+    fn establish_unexpected_response() {
+        let server_id = LightId::new(0x500);
+
+        // the server sends data before creating its light connection
+        let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+        input.extend(data_frame(server_id, &[1, 2, 3]));
+
+        match Connection::establish(0, MockStream::new(input), &packet::Handshake::default()) {
+            Err(Error::UnexpectedResponse()) => {},
+            Err(err) => panic!("expected an unexpected response error, got {:?}", err),
+            Ok(_) => panic!("expected an unexpected response error"),
+        }
+    }
+
+    const MAINNET_MAGIC : u32 = 764824073;
+    const TESTNET_MAGIC : u32 = 1097911063;
+
+    #[test]
+    fn establish_mainnet_with_testnet_peer() {
+        match establish_with_server_magic(MAINNET_MAGIC, TESTNET_MAGIC) {
+            Err(Error::NetworkMismatch(ours, theirs)) => {
+                assert_eq!(*ours, MAINNET_MAGIC);
+                assert_eq!(*theirs, TESTNET_MAGIC);
+            },
+            Err(err) => panic!("expected a network mismatch, got {:?}", err),
+            Ok(_) => panic!("expected a network mismatch"),
+        }
+    }
+
+    #[test]
+    fn establish_testnet_with_mainnet_peer() {
+        match establish_with_server_magic(TESTNET_MAGIC, MAINNET_MAGIC) {
+            Err(Error::NetworkMismatch(ours, theirs)) => {
+                assert_eq!(*ours, TESTNET_MAGIC);
+                assert_eq!(*theirs, MAINNET_MAGIC);
+            },
+            Err(err) => panic!("expected a network mismatch, got {:?}", err),
+            Ok(_) => panic!("expected a network mismatch"),
+        }
+        assert!(establish_with_server_magic(TESTNET_MAGIC, TESTNET_MAGIC).is_ok());
+    }
+
+    #[test]
+    fn snapshot_without_received_data() {
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &[0xde, 0xad, 0xbe, 0xef]));
+        let mut conn = connection(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+        conn.process_message().unwrap();
+        conn.process_message().unwrap();
+        conn.process_message().unwrap();
+
+        let snapshot = conn.snapshot();
+        assert_eq!(snapshot.client_cons, vec![LightConnectionSnapshot {
+            id: id.0,
+            node_id: Some("530000000000000000".to_string()),
+            pending_messages: 1,
+            pending_bytes: 4,
+            eos: false,
+        }]);
+        assert_eq!(snapshot.server_cons.len(), 1);
+        assert_eq!(snapshot.map_to_client, vec![("410000000000000000".to_string(), id.0)]);
+
+        let json = ::serde_json::to_string(&snapshot).unwrap();
+        assert!(!json.contains("deadbeef") && !json.contains("222,173,190,239"));
+        assert_eq!(::serde_json::from_str::<ConnectionSnapshot>(&json).unwrap(), snapshot);
+    }
+
+    #[test]
+    fn try_recv_frame_then_route() {
+        use std::time::Duration;
+        use ntt::ReadTimeout;
+
+        let server_id = LightId::new(0x500);
+        let mut conn = connection_with_timeout(&ack_frames(server_id, 0));
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        let timeout = Duration::from_millis(10);
+        let frame = conn.try_recv_frame(timeout).unwrap().unwrap();
+        assert_eq!(frame.as_control(), Some((ntt::protocol::ControlHeader::CreateNewConnection, server_id.0)));
+        assert!(conn.server_cons.is_empty());
+        conn.route_frame(frame).unwrap();
+        assert!(conn.server_cons.contains_key(&server_id));
+
+        let frame = conn.try_recv_frame(timeout).unwrap().unwrap();
+        assert_eq!(frame.as_data(), Some((server_id.0, 9)));
+        conn.route_frame(frame).unwrap();
+        assert!(conn.is_acknowledged(id));
+
+        assert!(conn.try_recv_frame(timeout).unwrap().is_none());
+        // the timeout of the transport is restored
+        assert_eq!(conn.get_backend().read_timeout().unwrap(), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn drain_until_idle_discards_dead_connections_data() {
+        use std::time::Duration;
+
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &[1, 2, 3]));
+        input.extend(close_frame(server_id));
+        let mut conn = connection_with_timeout(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+        conn.wait_light_ack(id).unwrap();
+        conn.client_cons.remove(&id);
+
+        assert_eq!(conn.drain_until_idle(Duration::from_millis(10)).unwrap(), 2);
+        assert!(conn.server_cons.is_empty());
+        assert_eq!(conn.received_len(), 0);
+    }
+
+    #[test]
+    fn nonce_wraparound_does_not_reuse_pending_node_id() {
+        let max = ntt::protocol::Nonce::max_value();
+        let server_id = LightId::new(0x500);
+        let mut input = vec![0, 0, 0, 0]; // ntt handshake OK
+        input.extend(ack_frames(server_id, max));
+        let ntt = ntt::Connection::handshake(max, MockStream::new(input)).unwrap();
+        let mut conn = Connection::new(ntt, ntt::DEFAULT_MAX_PAYLOAD_SIZE, ntt::DEFAULT_READ_CHUNK_SIZE);
+
+        // a light connection from before the wraparound, still pending ack
+        let old_id = LightId::new(0x600);
+        conn.client_cons.insert(old_id, LightConnection::new_with_nodeid(old_id, 0));
+
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+        conn.wait_light_ack(id).unwrap();
+
+        let other = LightId::new(id.0 + 1);
+        match conn.new_light_connection(other) {
+            Err(Error::NonceInUse(0, cid)) => assert_eq!(cid, old_id),
+            r => panic!("expected NonceInUse, got {:?}", r),
+        }
+        assert!(!conn.client_cons.contains_key(&other));
+
+        // the next nonce is free
+        conn.new_light_connection(other).unwrap();
+        assert_eq!(conn.client_cons[&other].node_id, Some(ntt::protocol::NodeId::make_syn(1)));
+    }
+
+    #[test]
+    fn reap_idle_light_connections() {
+        use std::time::Duration;
+
+        let mut conn = connection(&[]);
+        let idle = conn.get_free_light_id();
+        let active = conn.get_free_light_id();
+        conn.new_light_connection(idle).unwrap();
+        conn.new_light_connection(active).unwrap();
+        assert!(conn.reap_idle(Duration::from_secs(60)).unwrap().is_empty());
+
+        // there has been some activity on `active` 90s after the start
+        let start = Instant::now();
+        conn.client_cons.get_mut(&active).unwrap().last_activity = start + Duration::from_secs(90);
+
+        let before = conn.get_backend().output.len();
+        let reaped = conn.reap_idle_at(start + Duration::from_secs(120), Duration::from_secs(60)).unwrap();
+        assert_eq!(reaped, vec![idle]);
+        assert!(!conn.client_cons.contains_key(&idle));
+        assert!(conn.client_cons.contains_key(&active));
+        assert_eq!(&conn.get_backend().output[before..], &close_frame(idle)[..]);
+
+        let reaped = conn.reap_idle_at(start + Duration::from_secs(180), Duration::from_secs(60)).unwrap();
+        assert_eq!(reaped, vec![active]);
+    }
+
+    #[test]
+    fn stats_count_frames_and_light_connections() {
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &[0xde, 0xad, 0xbe, 0xef]));
+        let mut conn = connection(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+        conn.process_message().unwrap();
+        conn.process_message().unwrap();
+        conn.process_message().unwrap();
+
+        let stats = conn.stats();
+        assert_eq!(stats.transport.frames_received, 3);
+        assert_eq!(stats.transport.bytes_received, input.len() as u64 + 4);
+        assert_eq!(stats.transport.bytes_sent, conn.get_backend().output.len() as u64);
+        assert_eq!(stats.server_light_connections, 1);
+        assert_eq!(stats.client_light_connections, 1);
+
+        let metrics = conn.metrics_prometheus();
+        assert!(metrics.contains("# TYPE cardano_protocol_frames_total counter\n"));
+        assert!(metrics.contains("cardano_protocol_frames_total{direction=\"received\"} 3\n"));
+        assert!(metrics.contains(&format!("cardano_protocol_frames_total{{direction=\"sent\"}} {}\n", stats.transport.frames_sent)));
+        assert!(metrics.contains("cardano_protocol_light_connections{opened_by=\"client\"} 1\n"));
+    }
+
+    #[test]
+    fn accept_answers_establish() {
+        let hs = packet::Handshake::default();
+        let client_id = LightId::new(INITIAL_LIGHT_ID);
+
+        let mut input = vec![];
+        ntt::protocol::handshake(&mut input);
+        input.extend(create_frame(client_id));
+        input.extend(data_frame(client_id, &packet::send_handshake(&hs)));
+        input.extend(data_frame(client_id, ntt::protocol::NodeId::make_syn(5).as_ref()));
+
+        let server = Connection::accept(0, MockStream::new(input.clone()), &hs).unwrap();
+        assert!(server.server_cons.contains_key(&client_id));
+
+        // what the server answered is what a client expects
+        let output = server.get_backend().output.clone();
+        let client = Connection::establish(5, MockStream::new(output), &hs).unwrap();
+        assert_eq!(client.get_backend().output, input);
+    }
+
+    #[test]
+    fn accept_rejects_unsupported_version() {
+        let input = vec![0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        match Connection::accept(0, MockStream::new(input), &packet::Handshake::default()) {
+            Err(Error::NttError(ntt::Error::UnsupportedVersion)) => {},
+            Err(err) => panic!("expected an unsupported version error, got {:?}", err),
+            Ok(_) => panic!("expected an unsupported version error"),
+        }
+    }
+
+    #[test]
+    fn light_id_display_parse() {
+        let id = LightId::new(1025);
+        assert_eq!(id.to_string(), "1025");
+        assert_eq!(id.to_string().parse::<LightId>(), Ok(id));
+        assert_eq!(format!("{:?}", id), "LightId(1025)");
+
+        assert_eq!("12".parse::<LightId>(), Err(ParseLightIdError::ReservedId(12)));
+        assert!("light".parse::<LightId>().is_err());
+    }
+
+    #[test]
+    fn shutdown_closes_all_client_connections() {
+        let mut conn = connection(&[]);
+        let id1 = conn.get_free_light_id();
+        let id2 = conn.get_free_light_id();
+        conn.new_light_connection(id1).unwrap();
+        conn.new_light_connection(id2).unwrap();
+
+        let before = conn.get_backend().output.len();
+        conn.shutdown().unwrap();
+        let out = &conn.get_backend().output[before..];
+
+        let mut expected = close_frame(id1);
+        expected.extend(close_frame(id2));
+        assert_eq!(out, &expected[..]);
+        assert!(conn.client_cons.is_empty());
+
+        // nothing left to close the second time
+        conn.shutdown().unwrap();
+        assert_eq!(conn.get_backend().output.len(), before + expected.len());
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn execute_with_timeout_closes_light_connection() {
+        use self::command::{Command, GetBlockHeader};
+        use std::time::Duration;
+
+        let mut conn = connection(&[]);
+        let id = conn.next_light_id;
+
+        match GetBlockHeader::tip().execute_with_timeout(&mut conn, Some(Duration::from_secs(0))) {
+            Err(Error::CommandTimedOut(timed_out)) => assert_eq!(timed_out, id),
+            r => panic!("expected a timeout, got {:?}", r),
+        }
+        assert!(!conn.client_cons.contains_key(&id));
+        assert!(conn.get_backend().output.ends_with(&close_frame(id)));
+        assert!(conn.deadline.is_none());
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn execute_with_timeout_returns_when_the_peer_hangs() {
+        use self::command::{Command, GetBlockHeader};
+        use ntt::ReadTimeout;
+        use std::time::Duration;
+
+        // the peer acknowledges the light connection, then never answers:
+        // without a read timeout the transport would block, or here fail
+        // with the end of the input, on the next read
+        let server_id = LightId::new(0x500);
+        let mut conn = connection(&ack_frames(server_id, 0));
+        let id = conn.next_light_id;
+        assert_eq!(conn.get_backend().read_timeout().unwrap(), None);
+
+        match GetBlockHeader::tip().execute_with_timeout(&mut conn, Some(Duration::from_secs(10))) {
+            Err(Error::CommandTimedOut(timed_out)) => assert_eq!(timed_out, id),
+            r => panic!("expected a timeout, got {:?}", r),
+        }
+        assert!(!conn.client_cons.contains_key(&id));
+        assert!(conn.get_backend().output.ends_with(&close_frame(id)));
+        assert_eq!(conn.get_backend().read_timeout().unwrap(), None);
+        assert!(conn.deadline.is_none() && conn.recv_within.is_none());
+        assert!(! conn.failed);
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn ping_waits_for_ack() {
+        use self::command::{Command, Ping};
+        use std::time::Duration;
+
+        let server_id = LightId::new(0x500);
+        let mut conn = connection(&ack_frames(server_id, 0));
+        let id = conn.next_light_id;
+
+        let rtt = Ping.execute_with_timeout(&mut conn, Some(Duration::from_secs(10))).unwrap();
+        assert!(rtt < Duration::from_secs(10));
+        assert!(conn.is_acknowledged(id));
+        assert!(!conn.client_cons.contains_key(&id));
+    }
+
+    #[test]
+    fn wait_ack_of_sent_bytes() {
+        let server_id = LightId::new(0x500);
+        let mut conn = connection(&ack_frames(server_id, 0));
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        let node_id = conn.send_bytes_ack(id, b"hello").unwrap();
+        assert!(!conn.is_acknowledged(id));
+        conn.wait_ack(&node_id).unwrap();
+        assert!(conn.is_acknowledged(id));
+
+        conn.close_light_connection(id).unwrap();
+        match conn.wait_ack(&node_id) {
+            Err(Error::NodeIdNotFound(n)) => assert_eq!(n, node_id),
+            r => panic!("expected an unknown node id, got {:?}", r),
+        }
+    }
+
+    #[test]
+    fn ack_matching_multiple_connections() {
+        let server_id = LightId::new(0x500);
+        let mut conn = connection(&ack_frames(server_id, 7));
+        let id1 = conn.get_free_light_id();
+        let id2 = conn.get_free_light_id();
+        conn.client_cons.insert(id1, LightConnection::new_with_nodeid(id1, 7));
+        conn.client_cons.insert(id2, LightConnection::new_with_nodeid(id2, 7));
+
+        conn.process_message().unwrap();
+        match conn.process_message() {
+            Err(Error::AmbiguousNodeId(_, ids)) => assert_eq!(ids, vec![id1, id2]),
+            r => panic!("expected an ambiguous node id, got {:?}", r),
+        }
+        assert!(conn.map_to_client.is_empty());
+    }
+
+    #[test]
+    fn process_message_stops_at_high_water_mark() {
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &[1; 8]));
+        input.extend(data_frame(server_id, &[2; 8]));
+
+        let mut conn = connection(&input);
+        conn.set_received_high_water_mark(8);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        // create, ack and the first data frame
+        for _ in 0..3 { conn.process_message().unwrap(); }
+        assert_eq!(conn.received_len(), 8);
+        match conn.process_message() {
+            Err(Error::ReceiveBufferFull(8)) => {},
+            r => panic!("expected ReceiveBufferFull, got {:?}", r),
+        }
+
+        // draining the buffer allows to read the next frame
+        assert_eq!(conn.wait_msg(id).unwrap(), vec![1; 8]);
+        assert_eq!(conn.wait_msg(id).unwrap(), vec![2; 8]);
+        assert_eq!(conn.received_len(), 0);
+    }
+
+    #[test]
+    fn wait_msg_starved_by_other_connections() {
+        let server_id = LightId::new(0x500);
+        let other_id = LightId::new(0x700);
+        let mut input = ack_frames(server_id, 0);
+        for _ in 0..4 { input.extend(data_frame(other_id, &[1; 8])); }
+        input.extend(data_frame(server_id, &[2; 8]));
+
+        let mut conn = connection(&input);
+        conn.set_max_wait_frames(4);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        match conn.wait_msg(id) {
+            Err(Error::LightConnectionStarved(starved, 4)) => assert_eq!(starved, id),
+            r => panic!("expected LightConnectionStarved, got {:?}", r),
+        }
+
+        // the counter is reset on every call
+        assert_eq!(conn.wait_msg(id).unwrap(), vec![2; 8]);
+    }
+
+    #[test]
+    fn send_bytes_split_in_frames() {
+        let mut conn = connection(&[]);
+        conn.ntt.set_max_send_frame_size(4);
+        // the receive limit does not apply to what we send
+        conn.ntt.set_max_payload_size(2);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        let before = conn.get_backend().output.len();
+        conn.send_bytes(id, &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10]).unwrap();
+        let out = &conn.get_backend().output[before..];
+
+        let mut expected = data_frame(id, &[1, 2, 3, 4]);
+        expected.extend(data_frame(id, &[5, 6, 7, 8]));
+        expected.extend(data_frame(id, &[9, 10]));
+        assert_eq!(out, &expected[..]);
+    }
+
+    #[test]
+    fn send_message_in_a_single_write() {
+        let mut conn = connection(&[]);
+        let id = conn.get_free_light_id();
+        let writes = conn.get_backend().writes.len();
+        conn.new_light_connection(id).unwrap();
+        // the creation of the light connection and its node id
+        assert_eq!(conn.get_backend().writes.len(), writes + 1);
+
+        // the message id, then the message, in a single data frame
+        let msg = packet::send_msg_subscribe(false);
+        conn.send_message(id, &msg).unwrap();
+        let mut payload = se::Serializer::new_vec().serialize(&msg.0.as_u8()).unwrap().finalize();
+        payload.extend(&msg.1[..]);
+        assert_eq!(&conn.get_backend().writes[writes + 1..], &[data_frame(id, &payload)]);
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_block_cancelled_then_reused() {
+        use self::command::{Command, GetBlock, Ping};
+        use cardano::block::HeaderHash;
+
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &[0x82, 0x00, 0x01]));
+        input.extend(data_frame(server_id, &[0x82, 0x00, 0x02]));
+        input.extend(data_frame(server_id, &[0x82, 0x00, 0x03]));
+        input.extend(close_frame(server_id));
+        input.extend(ack_frames(server_id.next(), 1));
+
+        let mut conn = connection(&input);
+        let id = conn.next_light_id;
+
+        let hh = HeaderHash::new(&[]);
+        let blocks = GetBlock::only(&hh).until(|blocks| blocks.len() == 2).execute(&mut conn).unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert!(!conn.client_cons.contains_key(&id));
+        assert_eq!(conn.received_len(), 0);
+
+        Ping.execute(&mut conn).unwrap();
+        assert!(conn.is_acknowledged(id.next()));
+
+        // the cancelled light connection has been closed only once
+        let close = close_frame(id);
+        let out = &conn.get_backend().output;
+        assert_eq!(out.windows(close.len()).filter(|w| *w == &close[..]).count(), 1);
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_block_progress() {
+        use self::command::{Command, GetBlock};
+        use cardano::block::HeaderHash;
+
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &[0x82, 0x00, 0x01]));
+        input.extend(data_frame(server_id, &[0x82, 0x00, 0x02]));
+        input.extend(close_frame(server_id));
+
+        let mut conn = connection(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        let mut progress = Vec::new();
+        let blocks = {
+            let hh = HeaderHash::new(&[]);
+            let cmd = GetBlock::only(&hh).with_progress(|n| progress.push(n));
+            cmd.result(&mut conn, id).unwrap()
+        };
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(progress, vec![1, 2]);
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_block_header_fragmented_response() {
+        use self::command::{Command, GetBlockHeader};
+        use packet::tests::GET_BLOCK_HEADER_BYTES;
+
+        let server_id = LightId::new(0x500);
+        let third = GET_BLOCK_HEADER_BYTES.len() / 3;
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &GET_BLOCK_HEADER_BYTES[..third]));
+        input.extend(data_frame(server_id, &GET_BLOCK_HEADER_BYTES[third..2 * third]));
+        input.extend(data_frame(server_id, &GET_BLOCK_HEADER_BYTES[2 * third..]));
+
+        let mut conn = connection(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        let headers = GetBlockHeader::tip().result(&mut conn, id).unwrap();
+        assert_eq!(headers.decode().unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_block_header_empty_response() {
+        use self::command::{Command, GetBlockHeader};
+
+        const EMPTY_OK : &'static [u8] = &[0x82, 0x00, 0x80];
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, EMPTY_OK));
+        input.extend(data_frame(server_id, EMPTY_OK));
+
+        let mut conn = connection(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        let to = cardano::block::HeaderHash::new(&[]);
+        let headers = GetBlockHeader::range(&[to.clone()], to).result(&mut conn, id).unwrap();
+        assert!(headers.decode().unwrap().is_empty());
+
+        match GetBlockHeader::tip().result(&mut conn, id) {
+            Err(Error::NoTip) => {},
+            r => panic!("expected NoTip, got {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn execute_detailed_reports_the_light_connection() {
+        use self::command::{Command, GetBlockHeader};
+        use packet::tests::GET_BLOCK_HEADER_BYTES;
+
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, GET_BLOCK_HEADER_BYTES));
+
+        let mut conn = connection(&input);
+        let expected_id = conn.next_light_id;
+        let sent_before = conn.get_backend().output.len();
+
+        let (headers, id, stats) = GetBlockHeader::tip().execute_detailed(&mut conn).unwrap();
+        assert_eq!(headers.decode().unwrap().len(), 1);
+        assert_eq!(id, expected_id);
+        assert!(conn.get_backend().output.ends_with(&close_frame(id)));
+
+        // create, node id, request and close sent; create, ack and response received
+        assert_eq!(stats.frames_sent, 4);
+        assert_eq!(stats.frames_received, 3);
+        assert_eq!(stats.bytes_sent, (conn.get_backend().output.len() - sent_before) as u64);
+        assert_eq!(stats.bytes_received, input.len() as u64);
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_block_header_verify_signatures() {
+        use self::command::{Command, GetBlockHeader};
+        use packet::tests::GET_BLOCK_HEADER_BYTES;
+
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, GET_BLOCK_HEADER_BYTES));
+
+        let mut conn = connection(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        let headers = GetBlockHeader::tip().verify_signatures().result(&mut conn, id).unwrap();
+        assert_eq!(headers.decode().unwrap().len(), 1);
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_block_header_epoch_boundary() {
+        use self::command::{Command, GetBlockHeader};
+        use cardano::block::{BlockHeader, BlockDate};
+
+        // epoch boundary block header of epoch 1
+        let mut header = vec![0x82, 0x00, 0x85, 0x1a, 0x2d, 0x96, 0x4a, 0x09, 0x58, 0x20];
+        header.extend_from_slice(&[1; 32]); // previous header
+        header.extend_from_slice(&[0x58, 0x20]);
+        header.extend_from_slice(&[2; 32]); // body proof
+        header.extend_from_slice(&[0x82, 0x01, 0x81, 0x18, 0x2a]); // epoch 1, difficulty 42
+        header.push(0xa0); // attributes
+        let mut response = vec![0x82, 0x00, 0x81];
+        response.extend(header);
+
+        let server_id = LightId::new(0x500);
+        let mut input = ack_frames(server_id, 0);
+        input.extend(data_frame(server_id, &response));
+
+        let mut conn = connection(&input);
+        let id = conn.get_free_light_id();
+        conn.new_light_connection(id).unwrap();
+
+        let headers = GetBlockHeader::tip().verify_signatures().result(&mut conn, id).unwrap()
+            .decode().unwrap();
+        assert_eq!(headers.len(), 1);
+        match &headers[0] {
+            BlockHeader::GenesisBlockHeader(gbh) => assert_eq!(gbh.consensus.epoch, 1),
+            hdr => panic!("expected an epoch boundary header, got {}", hdr),
+        }
+        assert_eq!(headers[0].get_blockdate(), BlockDate::Genesis(1));
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_blocks_by_slot_skips_empty_slots() {
+        use self::command::GetBlocksBySlot;
+        use cardano::block::HeaderHash;
+
+        // the tip is the epoch boundary block of epoch 1, child of the checkpoint
+        let mut header = vec![0x82, 0x00, 0x81, 0x82, 0x00, 0x85, 0x1a, 0x2d, 0x96, 0x4a, 0x09, 0x58, 0x20];
+        header.extend_from_slice(&[1; 32]); // previous header
+        header.extend_from_slice(&[0x58, 0x20]);
+        header.extend_from_slice(&[2; 32]); // body proof
+        header.extend_from_slice(&[0x82, 0x01, 0x81, 0x18, 0x2a]); // epoch 1, difficulty 42
+        header.push(0xa0); // attributes
+        let checkpoint = HeaderHash::from([1; 32]);
+
+        let (tip_id, blocks_id) = (LightId::new(0x500), LightId::new(0x501));
+        let mut input = ack_frames(tip_id, 0);
+        input.extend(data_frame(tip_id, &header));
+        input.extend(ack_frames(blocks_id, 1));
+        input.extend(data_frame(blocks_id, &[0x82, 0x00, 0x01]));
+        input.extend(close_frame(blocks_id));
+
+        // the range starts in the empty slots before the boundary block
+        let mut conn = connection(&input);
+        let blocks = GetBlocksBySlot::new(&checkpoint, 0, 10, 1, 5).execute(&mut conn).unwrap();
+        assert_eq!(blocks.len(), 1);
+
+        // nothing but empty slots before the boundary block
+        let mut conn = connection(&input);
+        let blocks = GetBlocksBySlot::new(&checkpoint, 0, 10, 0, 20).execute(&mut conn).unwrap();
+        assert!(blocks.is_empty());
+
+        // an empty range does not need the peer
+        let mut conn = connection(&[]);
+        let blocks = GetBlocksBySlot::new(&checkpoint, 1, 5, 0, 20).execute(&mut conn).unwrap();
+        assert!(blocks.is_empty());
+    }
+
+    // epoch boundary block header of the given epoch and difficulty
+    #[cfg(feature = "commands")]
+    fn boundary_header(previous: &cardano::block::HeaderHash, epoch: u8, difficulty: u8) -> Vec<u8> {
+        let mut header = vec![0x82, 0x00, 0x85, 0x1a, 0x2d, 0x96, 0x4a, 0x09, 0x58, 0x20];
+        header.extend_from_slice(previous.as_ref());
+        header.extend_from_slice(&[0x58, 0x20]);
+        header.extend_from_slice(&[2; 32]); // body proof
+        header.extend_from_slice(&[0x82, epoch, 0x81, 0x18, difficulty]);
+        header.push(0xa0); // attributes
+        header
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_block_by_height_walks_the_headers() {
+        use self::command::GetBlockByHeight;
+        use cardano::block::{HeaderHash, RawBlockHeader};
+
+        // the checkpoint, then the blocks of height 41 and 42, the tip
+        let checkpoint = HeaderHash::from([1; 32]);
+        let first = boundary_header(&checkpoint, 1, 41);
+        let first_hash = RawBlockHeader::from_dat(first.clone()).decode().unwrap().compute_hash();
+        let tip = boundary_header(&first_hash, 2, 42);
+
+        let (tip_id, headers_id, blocks_id) = (LightId::new(0x500), LightId::new(0x501), LightId::new(0x502));
+        let mut tip_input = ack_frames(tip_id, 0);
+        tip_input.extend(data_frame(tip_id, &[&[0x82, 0x00, 0x81][..], &tip[..]].concat()));
+
+        let mut input = tip_input.clone();
+        input.extend(ack_frames(headers_id, 1));
+        input.extend(data_frame(headers_id, &[&[0x82, 0x00, 0x82][..], &tip[..], &first[..]].concat()));
+        input.extend(ack_frames(blocks_id, 2));
+        input.extend(data_frame(blocks_id, &[0x82, 0x00, 0x01]));
+        input.extend(close_frame(blocks_id));
+
+        let mut conn = connection(&input);
+        assert_eq!(GetBlockByHeight::new(&checkpoint, 41).resolve(&mut conn).unwrap(), first_hash);
+
+        let mut conn = connection(&input);
+        let block = GetBlockByHeight::new(&checkpoint, 41).execute(&mut conn).unwrap();
+        assert_eq!(block.as_ref(), &[0x01]);
+        // the block was requested by the hash of the header of height 41
+        let request = packet::send_msg_getblocks(&first_hash, &first_hash);
+        assert!(conn.get_backend().output.windows(request.1.len()).any(|w| w == &request.1[..]));
+
+        // the checkpoint is already above the height
+        let mut conn = connection(&input);
+        match GetBlockByHeight::new(&checkpoint, 40).resolve(&mut conn) {
+            Err(Error::HeightNotFound(40)) => {},
+            r => panic!("expected HeightNotFound, got {:?}", r),
+        }
+
+        // the chain of the peer is not that high
+        let mut conn = connection(&tip_input);
+        match GetBlockByHeight::new(&checkpoint, 43).execute(&mut conn) {
+            Err(Error::HeightAboveTip(43, 42)) => {},
+            r => panic!("expected HeightAboveTip, got {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_epoch_boundary_walks_the_headers() {
+        use self::command::GetEpochBoundary;
+        use cardano::block::{HeaderHash, RawBlockHeader};
+
+        // the checkpoint, then the boundary blocks of the epochs 1 and 2, the tip
+        let checkpoint = HeaderHash::from([1; 32]);
+        let first = boundary_header(&checkpoint, 1, 41);
+        let first_hash = RawBlockHeader::from_dat(first.clone()).decode().unwrap().compute_hash();
+        let tip = boundary_header(&first_hash, 2, 41);
+        let tip_hash = RawBlockHeader::from_dat(tip.clone()).decode().unwrap().compute_hash();
+
+        let (tip_id, headers_id) = (LightId::new(0x500), LightId::new(0x501));
+        let mut tip_input = ack_frames(tip_id, 0);
+        tip_input.extend(data_frame(tip_id, &[&[0x82, 0x00, 0x81][..], &tip[..]].concat()));
+
+        let mut input = tip_input.clone();
+        input.extend(ack_frames(headers_id, 1));
+        input.extend(data_frame(headers_id, &[&[0x82, 0x00, 0x82][..], &tip[..], &first[..]].concat()));
+
+        assert_eq!(GetEpochBoundary::new(&checkpoint, 1).execute(&mut connection(&input)).unwrap(), first_hash);
+        assert_eq!(GetEpochBoundary::new(&checkpoint, 2).execute(&mut connection(&input)).unwrap(), tip_hash);
+
+        // the checkpoint is already past the epoch boundary
+        match GetEpochBoundary::new(&checkpoint, 0).execute(&mut connection(&input)) {
+            Err(Error::EpochBoundaryNotFound(0)) => {},
+            r => panic!("expected EpochBoundaryNotFound, got {:?}", r),
+        }
+
+        // the chain of the peer has not reached the epoch
+        match GetEpochBoundary::new(&checkpoint, 3).execute(&mut connection(&tip_input)) {
+            Err(Error::EpochAboveTip(3, 2)) => {},
+            r => panic!("expected EpochAboveTip, got {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn resumable_subscription_resumes_after_disconnect() {
+        use self::command::ResumableSubscription;
+        use cardano::block::{HeaderHash, RawBlockHeader};
+
+        let hash = |header: &[u8]| RawBlockHeader::from_dat(header.to_vec()).decode().unwrap().compute_hash();
+        let checkpoint = HeaderHash::from([1; 32]);
+        let h1 = boundary_header(&checkpoint, 1, 1);
+        let h2 = boundary_header(&hash(&h1), 2, 2);
+        let h3 = boundary_header(&hash(&h2), 3, 3);
+        let headers = |headers: &[&[u8]]| {
+            let mut dat = vec![0x82, 0x00, 0x80 + headers.len() as u8];
+            for header in headers { dat.extend_from_slice(header) }
+            dat
+        };
+        let (tip_id, range_id, async_id) = (LightId::new(0x500), LightId::new(0x501), LightId::new(0x600));
+
+        // the tip is h1, then the peer announces h2 and the connection drops
+        let mut first = ack_frames(tip_id, 0);
+        first.extend(data_frame(tip_id, &headers(&[&h1])));
+        first.extend(create_frame(async_id));
+        first.extend(data_frame(async_id, ntt::protocol::NodeId::make_syn(5).as_ref()));
+        first.extend(data_frame(async_id, &[&[packet::MessageId::Headers.as_u8()][..], &headers(&[&h2])[..]].concat()));
+        first.extend(close_frame(async_id));
+
+        // on the new connection the tip is h3, h2 is received again
+        let mut second = ack_frames(tip_id, 0);
+        second.extend(data_frame(tip_id, &headers(&[&h3])));
+        second.extend(ack_frames(range_id, 1));
+        second.extend(data_frame(range_id, &headers(&[&h3, &h2])));
+
+        let mut inputs = vec![second, first];
+        let mut subscription = ResumableSubscription::new(|| {
+            match inputs.pop() {
+                Some(input) => Ok(connection(&input)),
+                None => Err(Error::UnexpectedResponse()),
+            }
+        }, Some(&checkpoint));
+
+        assert_eq!(subscription.next().unwrap().compute_hash(), hash(&h1));
+        subscription.ack(&hash(&h1));
+        // h2 is returned but not acknowledged before the disconnection
+        assert_eq!(subscription.next().unwrap().compute_hash(), hash(&h2));
+        assert_eq!(subscription.next().unwrap().compute_hash(), hash(&h3));
+        assert_eq!(subscription.acked(), Some(&hash(&h1)));
+
+        // the reconnection fails, the error is returned
+        match subscription.next() {
+            Err(Error::UnexpectedResponse()) => {},
+            r => panic!("expected the error of the reconnection, got {:?}", r),
+        }
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn get_block_header_after_skips_the_parent_interval() {
+        use self::command::GetBlockHeader;
+        use cardano::block::HeaderHash;
+
+        let (a, b, c) = (HeaderHash::from([1; 32]), HeaderHash::from([2; 32]), HeaderHash::from([3; 32]));
+        // (c, c] is empty and (b, c] only holds c: nothing to request
+        assert!(GetBlockHeader::after(&c, &c, &b).is_none());
+        assert!(GetBlockHeader::after(&b, &c, &b).is_none());
+        assert!(GetBlockHeader::after(&a, &c, &b).is_some());
+    }
+
+    #[cfg(feature = "commands")]
+    #[test]
+    fn pipelined_get_blocks_requests_headers_ahead() {
+        use self::command::PipelinedGetBlocks;
+        use cardano::block::{HeaderHash, RawBlockHeader};
+
+        let hash = |header: &[u8]| RawBlockHeader::from_dat(header.to_vec()).decode().unwrap().compute_hash();
+        let checkpoint = HeaderHash::from([1; 32]);
+        let h1 = boundary_header(&checkpoint, 1, 1);
+        let h2 = boundary_header(&hash(&h1), 2, 2);
+        let h3 = boundary_header(&hash(&h2), 3, 3);
+        let (h1_hash, h2_hash, h3_hash) = (hash(&h1), hash(&h2), hash(&h3));
+
+        let ids : Vec<LightId> = (0x500..0x504).map(LightId::new).collect();
+        // the peer returns the headers in two batches
+        let mut input = ack_frames(ids[0], 0);
+        input.extend(data_frame(ids[0], &[&[0x82, 0x00, 0x81][..], &h1[..]].concat()));
+        input.extend(ack_frames(ids[1], 1));
+        input.extend(ack_frames(ids[2], 2));
+        // the blocks of the first batch arrive before the second batch
+        input.extend(data_frame(ids[2], &[0x82, 0x00, 0x01]));
+        input.extend(close_frame(ids[2]));
+        input.extend(data_frame(ids[1], &[&[0x82, 0x00, 0x82][..], &h3[..], &h2[..]].concat()));
+        input.extend(ack_frames(ids[3], 3));
+        input.extend(data_frame(ids[3], &[0x82, 0x00, 0x02]));
+        input.extend(data_frame(ids[3], &[0x82, 0x00, 0x03]));
+        input.extend(close_frame(ids[3]));
+
+        let mut conn = connection(&input);
+        let mut received = Vec::new();
+        PipelinedGetBlocks::new(&checkpoint, false, &h3_hash, &h2_hash)
+            .execute(&mut conn, |blocks| {
+                received.push(blocks.iter().map(|b| b.as_ref().to_vec()).collect::<Vec<_>>())
+            }).unwrap();
+        assert_eq!(received, vec![vec![vec![0x01]], vec![vec![0x02], vec![0x03]]]);
+
+        // the second headers were requested before the first blocks
+        let output = &conn.get_backend().output;
+        let position = |msg: packet::Message| output.windows(msg.1.len()).position(|w| w == &msg.1[..]).unwrap();
+        let second_headers = position(packet::send_msg_getheaders(&[h1_hash.clone()], &Some(h3_hash.clone())));
+        let first_blocks = position(packet::send_msg_getblocks(&h1_hash, &h1_hash));
+        let second_blocks = position(packet::send_msg_getblocks(&h2_hash, &h3_hash));
+        assert!(second_headers < first_blocks);
+        assert!(first_blocks < second_blocks);
+
+        // the range ends at the parent of the tip: only the tip is requested
+        let mut input = ack_frames(ids[0], 0);
+        input.extend(data_frame(ids[0], &[0x82, 0x00, 0x03]));
+        input.extend(close_frame(ids[0]));
+        let mut conn = connection(&input);
+        let mut received = Vec::new();
+        PipelinedGetBlocks::new(&h2_hash, false, &h3_hash, &h2_hash).depth(4)
+            .execute(&mut conn, |blocks| received.extend(blocks)).unwrap();
+        assert_eq!(received.len(), 1);
+        let request = packet::send_msg_getblocks(&h3_hash, &h3_hash);
+        assert!(conn.get_backend().output.windows(request.1.len()).any(|w| w == &request.1[..]));
+
+        // nothing past the tip
+        let mut conn = connection(&[]);
+        PipelinedGetBlocks::new(&h3_hash, false, &h3_hash, &h2_hash)
+            .execute(&mut conn, |_| panic!("no blocks expected")).unwrap();
+
+        // the headers returned do not follow the start of the range
+        let mut input = ack_frames(ids[0], 0);
+        input.extend(data_frame(ids[0], &[&[0x82, 0x00, 0x81][..], &h2[..]].concat()));
+        let mut conn = connection(&input);
+        match PipelinedGetBlocks::new(&checkpoint, false, &h3_hash, &h2_hash).execute(&mut conn, |_| panic!("no blocks expected")) {
+            Err(Error::UnlinkedHeaders(ref start, ref parent)) if *start == checkpoint && *parent == h1_hash => {},
+            r => panic!("expected UnlinkedHeaders, got {:?}", r),
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg(feature = "with-bench")]
+mod bench {
+    use super::*;
+    use super::test::{connection, ack_frames, data_frame};
+    use test;
+
+    // number and size of the block frames received in each iteration
+    const BLOCKS : usize = 100;
+    const BLOCK_SIZE : usize = 2048;
+
+    /// the frames acknowledging `lights` light connections, followed by
+    /// `BLOCKS` block frames for each of them, interleaved
+    fn block_frames(lights: u32) -> Vec<u8> {
+        let block = vec![0x42; BLOCK_SIZE];
+        let mut input = Vec::new();
+        for i in 0..lights {
+            input.extend(ack_frames(LightId::new(0x500 + i), i as u64));
+        }
+        for _ in 0..BLOCKS {
+            for i in 0..lights {
+                input.extend(data_frame(LightId::new(0x500 + i), &block));
+            }
+        }
+        input
+    }
+
+    fn receive_blocks(b: &mut test::Bencher, lights: u32) {
+        let input = block_frames(lights);
+        b.bytes = (BLOCKS * BLOCK_SIZE) as u64;
+        b.iter(|| {
+            let mut conn = connection(&input);
+            let ids : Vec<LightId> = (0..lights).map(|_| conn.get_free_light_id()).collect();
+            for id in ids.iter() {
+                conn.new_light_connection(*id).unwrap();
+                conn.wait_light_ack(*id).unwrap();
+            }
+            // only the first one is consumed, the frames of the others
+            // are dispatched to their buffers
+            for _ in 0..BLOCKS {
+                test::black_box(conn.wait_msg(ids[0]).unwrap());
+            }
+        })
+    }
+
+    #[bench]
+    fn wait_msg_blocks(b: &mut test::Bencher) {
+        receive_blocks(b, 1)
+    }
+
+    #[bench]
+    fn wait_msg_blocks_multiplexed(b: &mut test::Bencher) {
+        receive_blocks(b, 4)
+    }
+}
@@ -1,7 +1,7 @@
 use types::{BlockHash};
 use std::{result, io};
 use cardano::{hash};
-use cardano::block::BlockDate;
+use cardano::block::{BlockDate, BlockDecodeError};
 use cbor_event;
 
 #[derive(Debug)]
@@ -9,6 +9,7 @@ pub enum Error {
     NoTagHead,
     IoError(io::Error),
     BlockEncodingError(cbor_event::Error),
+    BlockDecodeError(BlockDecodeError),
     InvalidHeaderHash(hash::Error),
     HashNotFound(BlockHash),
     DateNotAvailable(BlockDate),
@@ -22,5 +23,8 @@ impl From<io::Error> for Error {
 impl From<cbor_event::Error> for Error {
     fn from(e: cbor_event::Error) -> Self { Error::BlockEncodingError(e) }
 }
+impl From<BlockDecodeError> for Error {
+    fn from(e: BlockDecodeError) -> Self { Error::BlockDecodeError(e) }
+}
 
 pub type Result<T> = result::Result<T, Error>;
@@ -2,13 +2,13 @@
 //!
 
 use super::super::{Storage, StorageConfig, block_location, block_read, block_read_location, header_to_blockhash, packreader_init};
-use super::super::{blob};
+use super::super::{blob, tag};
 use super::super::epoch::{epoch_read_pack, epoch_open_packref};
 use super::super::containers::packfile;
 use super::super::types::{BlockHash, PackHash};
-use cardano::block::{HeaderHash, Block, RawBlock, BlockDate};
+use cardano::block::{HeaderHash, Block, RawBlock, BlockDate, EpochId};
 
-use std::{iter, fs, mem};
+use std::{iter, fs, mem, io, fmt};
 use std::cmp::Ordering;
 
 use super::error::{Error, Result};
@@ -256,6 +256,10 @@ impl<'a> iter::Iterator for Iter {
 }
 
 /// reverse iterator over the block chain
+///
+/// the iteration ends with the first block stored: the walk stops at a
+/// block that is not in the storage (the genesis' previous block, or a
+/// block of an epoch deleted by `Storage::prune_epochs_before`).
 pub struct ReverseIter<'a> {
     storage: &'a Storage,
     current_block: Option<HeaderHash>
@@ -281,15 +285,125 @@ impl<'a> iter::Iterator for ReverseIter<'a> {
             &Some(ref hh) => hh.clone(),
         };
 
-        let loc = block_location(&self.storage, hh.bytes()).expect("block location");
+        let loc = match block_location(&self.storage, hh.bytes()) {
+            None => {
+                debug!("block {} not in the storage, end of the reverse iteration", hh);
+                self.current_block = None;
+                return None;
+            },
+            Some(loc) => loc,
+        };
         match block_read_location(&self.storage, &loc, hh.bytes()) {
             None        => panic!("error while reading block {}", hh),
             Some(blk) => {
                 let block = blk.decode().unwrap();
                 let hdr = block.get_header();
+                if hdr.get_blockdate().get_epochid() < self.storage.first_epoch() {
+                    debug!("block {} in a pruned epoch, end of the reverse iteration", hh);
+                    self.current_block = None;
+                    return None;
+                }
                 self.current_block = Some(hdr.get_previous_header());
                 Some(block)
             }
         }
     }
 }
+
+/// iterator over all the stored blocks, from the first packed epoch to
+/// the `HEAD` tag
+///
+/// the epoch packs are streamed one block at a time, then the blocks
+/// stored after the last packed epoch are read one by one. Only the
+/// hashes of the latter are kept in memory.
+pub struct ChainIter<'a> {
+    storage: &'a Storage,
+    state: ChainIterState,
+}
+
+enum ChainIterState {
+    Packs(EpochId, Option<(PackHash, packfile::Reader<fs::File>)>),
+    Loose(Vec<HeaderHash>),
+    Done,
+}
+
+fn invalid_data<E: fmt::Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+impl<'a> ChainIter<'a> {
+    pub fn new(storage: &'a Storage) -> Self {
+        ChainIter { storage: storage, state: ChainIterState::Packs(storage.first_epoch(), None) }
+    }
+
+    /// collect the hashes of the blocks from the `HEAD` tag down to the
+    /// first block of the given epoch, in reverse order.
+    fn loose_hashes(&self, epoch: EpochId) -> io::Result<Vec<HeaderHash>> {
+        let mut hashes = Vec::new();
+        let mut current = match tag::read_hash(self.storage, &tag::HEAD) {
+            None => return Ok(hashes),
+            Some(hh) => hh,
+        };
+        while let Some(rblk) = block_read(self.storage, current.bytes()) {
+            let blk = rblk.decode().map_err(invalid_data)?;
+            let hdr = blk.get_header();
+            if hdr.get_blockdate().get_epochid() < epoch { break; }
+            hashes.push(current);
+            current = hdr.get_previous_header();
+        }
+        Ok(hashes)
+    }
+
+    fn next_block(&mut self) -> io::Result<Option<Block>> {
+        loop {
+            let next_state = match self.state {
+                ChainIterState::Done => return Ok(None),
+                ChainIterState::Loose(ref mut hashes) => {
+                    return match hashes.pop() {
+                        None => Ok(None),
+                        Some(hh) => match block_read(self.storage, hh.bytes()) {
+                            None => Err(io::Error::new(io::ErrorKind::NotFound, format!("block {} not found", hh))),
+                            Some(rblk) => rblk.decode().map(Some).map_err(invalid_data),
+                        }
+                    };
+                },
+                ChainIterState::Packs(epoch, Some((ref packhash, ref mut reader))) => {
+                    match reader.next_block()? {
+                        Some(data) => return RawBlock(data).decode().map(Some).map_err(invalid_data),
+                        None => {
+                            if &reader.finalize() != packhash {
+                                return Err(invalid_data(format!("pack of epoch {} is corrupted", epoch)));
+                            }
+                            ChainIterState::Packs(epoch + 1, None)
+                        },
+                    }
+                },
+                ChainIterState::Packs(epoch, None) => {
+                    match epoch_read_pack(&self.storage.config, epoch) {
+                        Ok(packhash) => {
+                            let reader = packfile::Reader::init(self.storage.config.get_pack_filepath(&packhash))?;
+                            ChainIterState::Packs(epoch, Some((packhash, reader)))
+                        },
+                        Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                            ChainIterState::Loose(self.loose_hashes(epoch)?)
+                        },
+                        Err(err) => return Err(err),
+                    }
+                },
+            };
+            self.state = next_state;
+        }
+    }
+}
+
+impl<'a> iter::Iterator for ChainIter<'a> {
+    type Item = io::Result<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.next_block() {
+            Ok(None) => { self.state = ChainIterState::Done; None },
+            Ok(Some(blk)) => Some(Ok(blk)),
+            Err(err) => { self.state = ChainIterState::Done; Some(Err(err)) },
+        }
+    }
+}
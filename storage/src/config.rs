@@ -27,6 +27,7 @@ impl StorageConfig {
             StorageFileType::Blob => p.push("blob/"),
             StorageFileType::Tag => p.push("tag/"),
             StorageFileType::Epoch => p.push("epoch/"),
+            StorageFileType::Utxo => p.push("utxo/"),
         }
         p
     }
@@ -37,12 +38,12 @@ impl StorageConfig {
     }
     pub fn get_pack_filepath(&self, packhash: &PackHash) -> PathBuf {
         let mut p = self.get_filetype_dir(StorageFileType::Pack);
-        p.push(hex::encode(packhash));
+        p.push(packhash.to_string());
         p
     }
     pub fn get_index_filepath(&self, packhash: &PackHash) -> PathBuf {
         let mut p = self.get_filetype_dir(StorageFileType::Index);
-        p.push(hex::encode(packhash));
+        p.push(packhash.to_string());
         p
     }
     pub fn get_blob_filepath(&self, blockhash: &BlockHash) -> PathBuf {
@@ -71,11 +72,23 @@ impl StorageConfig {
         p.push("pack");
         p
     }
+    /// the name of the pack of the given epoch, next to the pack file
+    /// named after its hash
+    pub fn get_epoch_named_pack_filepath(&self, epoch: EpochId) -> PathBuf {
+        let mut p = self.get_filetype_dir(StorageFileType::Pack);
+        p.push(format!("epoch-{}", epoch));
+        p
+    }
     pub fn get_epoch_refpack_filepath(&self, epoch: EpochId) -> PathBuf {
         let mut p = self.get_epoch_dir(epoch);
         p.push("refpack");
         p
     }
+    pub fn get_utxo_snapshot_filepath(&self, epoch: EpochId) -> PathBuf {
+        let mut p = self.get_filetype_dir(StorageFileType::Utxo);
+        p.push(epoch.to_string());
+        p
+    }
 
     pub fn list_indexes(&self) -> Vec<PackHash> {
         let mut packs = Vec::new();
@@ -85,10 +98,23 @@ impl StorageConfig {
             if entry.file_type().unwrap().is_file() {
                 if let Ok(s) = entry.file_name().into_string() {
                     if s.len() == 64 {
-                        let v = hex::decode(s.as_ref()).unwrap();
-                        let mut packref = [0;HASH_SIZE];
-                        packref.clone_from_slice(&v[..]);
-                        packs.push(packref);
+                        packs.push(s.parse().unwrap());
+                    }
+                }
+            }
+        }
+        packs
+    }
+
+    pub fn list_packs(&self) -> Vec<PackHash> {
+        let mut packs = Vec::new();
+        let p = self.get_filetype_dir(StorageFileType::Pack);
+        for entry in fs::read_dir(p).unwrap() {
+            let entry = entry.unwrap();
+            if entry.file_type().unwrap().is_file() {
+                if let Ok(s) = entry.file_name().into_string() {
+                    if s.len() == 64 {
+                        packs.push(s.parse().unwrap());
                     }
                 }
             }
@@ -90,7 +90,13 @@ impl<R: Read> Reader<R> {
     /// note: any IO error raise runtime exception for now. will be changed soon.
     pub fn get_next(&mut self) -> Option<Vec<u8>> {
         // TODO: remove unwrap()
-        let mdata = read_next_block_or_eof(&mut self.reader).unwrap();
+        self.next_block().unwrap()
+    }
+
+    /// Return the next data block, or `None` once the end of the pack
+    /// is reached.
+    pub fn next_block(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mdata = read_next_block_or_eof(&mut self.reader)?;
         match mdata {
             None => {},
             Some(ref data) => {
@@ -98,7 +104,7 @@ impl<R: Read> Reader<R> {
                 self.pos += 4 + offset_align4(data.len() as u64);
             }
         };
-        mdata
+        Ok(mdata)
     }
 }
 
@@ -120,7 +126,7 @@ impl<R> Reader<R> {
     pub fn finalize(&mut self) -> PackHash {
         let mut packhash = [0u8;HASH_SIZE];
         self.hash_context.result(&mut packhash);
-        packhash
+        PackHash::from(packhash)
     }
 }
 
@@ -168,8 +174,8 @@ impl Writer {
     }
 
     pub fn finalize(mut self) -> io::Result<(TmpFile, PackHash, indexfile::Index)> {
-        let mut packhash : PackHash = [0u8;HASH_SIZE];
+        let mut packhash = [0u8;HASH_SIZE];
         self.hash_context.result(&mut packhash);
-        Ok((self.tmpfile, packhash, self.index))
+        Ok((self.tmpfile, PackHash::from(packhash), self.index))
     }
 }
\ No newline at end of file
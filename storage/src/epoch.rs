@@ -1,7 +1,6 @@
 use std::fs;
 use std::io;
 use std::io::{Read};
-use cardano::util::{hex};
 
 use cardano;
 
@@ -15,7 +14,8 @@ pub fn epoch_create_with_refpack(config: &StorageConfig, packref: &PackHash, ref
     fs::create_dir_all(dir).unwrap();
 
     let pack_filepath = config.get_epoch_pack_filepath(epochid);
-    tmpfile::atomic_write_simple(&pack_filepath, hex::encode(packref).as_bytes()).unwrap();
+    tmpfile::atomic_write_simple(&pack_filepath, packref.to_string().as_bytes()).unwrap();
+    epoch_name_pack(config, packref, epochid);
 
     let mut tmpfile = TmpFile::create(config.get_epoch_dir(epochid)).unwrap();
     refpack.write(&mut tmpfile).unwrap();
@@ -56,7 +56,22 @@ pub fn epoch_create(config: &StorageConfig, packref: &PackHash, epochid: cardano
 
     // write the pack pointer
     let pack_filepath = config.get_epoch_pack_filepath(epochid);
-    tmpfile::atomic_write_simple(&pack_filepath, hex::encode(packref).as_bytes()).unwrap();
+    tmpfile::atomic_write_simple(&pack_filepath, packref.to_string().as_bytes()).unwrap();
+    epoch_name_pack(config, packref, epochid);
+}
+
+/// name the pack of the given epoch `pack/epoch-<epoch>`: a hard link to
+/// the pack file, which keeps being named after its hash
+///
+/// The name is only there to find an epoch's pack on the disk, failing to
+/// create the link (e.g. on a file system without hard links) is not an
+/// error.
+pub fn epoch_name_pack(config: &StorageConfig, packref: &PackHash, epochid: cardano::block::EpochId) {
+    let name = config.get_epoch_named_pack_filepath(epochid);
+    let _ = fs::remove_file(&name);
+    if let Err(err) = fs::hard_link(config.get_pack_filepath(packref), &name) {
+        warn!("cannot name the pack {} of the epoch {}: {}", packref, epochid, err);
+    }
 }
 
 pub fn epoch_read_pack(config: &StorageConfig, epochid: cardano::block::EpochId) -> io::Result<PackHash> {
@@ -66,9 +81,7 @@ pub fn epoch_read_pack(config: &StorageConfig, epochid: cardano::block::EpochId)
     let mut file = fs::File::open(&pack_filepath)?;
     let _read = file.read_to_end(&mut content).unwrap();
 
-    let p = String::from_utf8(content.clone()).ok().and_then(|r| hex::decode(&r).ok()).unwrap();
-    let mut ph = [0u8; super::HASH_SIZE];
-    ph.clone_from_slice(&p[..]);
+    let ph = String::from_utf8(content.clone()).ok().and_then(|r| r.parse().ok()).unwrap();
 
     Ok(ph)
 }
@@ -4,6 +4,7 @@ extern crate cryptoxide;
 extern crate cbor_event;
 extern crate cardano;
 extern crate rand;
+extern crate serde;
 
 pub mod block;
 pub mod types;
@@ -14,7 +15,8 @@ pub mod epoch;
 pub mod refpack;
 pub mod utils;
 pub mod containers;
-use std::{fs, io, result};
+pub mod utxo;
+use std::{fs, io, result, path::Path};
 
 pub use config::StorageConfig;
 
@@ -32,6 +34,7 @@ pub enum Error {
     IoError(io::Error),
     BlockError(block::Error),
     CborBlockError(cbor_event::Error),
+    BlockDecodeError(cardano::block::BlockDecodeError),
     // ** RefPack creation errors
     RefPackError(refpack::Error),
     RefPackUnexpectedGenesis(SlotId),
@@ -40,6 +43,9 @@ pub enum Error {
     EpochError(EpochId, EpochId),
     EpochSlotRewind(EpochId, SlotId),
     EpochChainInvalid(BlockDate, HeaderHash, HeaderHash),
+    // ** Pruning errors
+    EpochNotPacked(EpochId),
+    BlockNotStored(HeaderHash),
     NoSuchTag
 }
 impl From<io::Error> for Error {
@@ -54,12 +60,16 @@ impl From<refpack::Error> for Error {
 impl From<cbor_event::Error> for Error {
     fn from(e: cbor_event::Error) -> Self { Error::CborBlockError(e) }
 }
+impl From<cardano::block::BlockDecodeError> for Error {
+    fn from(e: cardano::block::BlockDecodeError) -> Self { Error::BlockDecodeError(e) }
+}
 
 pub type Result<T> = result::Result<T, Error>;
 
 pub struct Storage {
     pub config: StorageConfig,
     lookups: BTreeMap<PackHash, indexfile::Lookup>,
+    first_epoch: EpochId,
 }
 
 impl Storage {
@@ -72,6 +82,7 @@ impl Storage {
         fs::create_dir_all(cfg.get_filetype_dir(StorageFileType::Tag))?;
         fs::create_dir_all(cfg.get_filetype_dir(StorageFileType::Epoch))?;
         fs::create_dir_all(cfg.get_filetype_dir(StorageFileType::RefPack))?;
+        fs::create_dir_all(cfg.get_filetype_dir(StorageFileType::Utxo))?;
 
         let packhashes = cfg.list_indexes();
         for p in packhashes.iter() {
@@ -83,10 +94,55 @@ impl Storage {
             }
         }
 
-        let storage = Storage { config: cfg.clone(), lookups: lookups };
+        let first_epoch = utxo::snapshot_epochs(cfg)?.pop().unwrap_or(0);
+
+        let storage = Storage { config: cfg.clone(), lookups: lookups, first_epoch: first_epoch };
+        storage.migrate_epoch_packs()?;
         Ok(storage)
     }
 
+    // move the epoch packs of the older storages to the per-epoch layout:
+    // the epochs only tagged `EPOCH_<epoch>` get their `epoch/<epoch>`
+    // directory, and every epoch pack gets its `pack/epoch-<epoch>` name.
+    // Nothing is done on a storage already migrated.
+    fn migrate_epoch_packs(&self) -> Result<()> {
+        for entry in fs::read_dir(self.config.get_filetype_dir(StorageFileType::Tag))? {
+            let name = match entry?.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            let epoch = match tag::parse_epoch_tag(&name) {
+                Some(epoch) => epoch,
+                None => continue,
+            };
+            let packhash = match tag::read(self, &name).and_then(|v| PackHash::from_slice(&v).ok()) {
+                Some(packhash) => packhash,
+                None => { warn!("tag {} does not refer to a pack, not migrating it", name); continue },
+            };
+            if self.pack_for_epoch(epoch)?.is_none() {
+                if ! self.config.get_pack_filepath(&packhash).exists() {
+                    warn!("pack {} of the epoch {} is missing, not migrating it", packhash, epoch);
+                    continue;
+                }
+                epoch::epoch_create(&self.config, &packhash, epoch);
+                info!("epoch {} migrated to its own directory", epoch);
+            }
+            tag::remove_tag(self, &name);
+        }
+
+        for entry in fs::read_dir(self.config.get_filetype_dir(StorageFileType::Epoch))? {
+            let epoch = match entry?.file_name().into_string().ok().and_then(|name| name.parse().ok()) {
+                Some(epoch) => epoch,
+                None => continue,
+            };
+            if self.config.get_epoch_named_pack_filepath(epoch).exists() { continue }
+            if let Some(packhash) = self.pack_for_epoch(epoch)? {
+                epoch::epoch_name_pack(&self.config, &packhash, epoch);
+            }
+        }
+        Ok(())
+    }
+
     /// create a reverse iterator over the stored blocks
     ///
     /// it will iterate from the tag `HEAD` until there is no more
@@ -100,11 +156,190 @@ impl Storage {
     //    Ok(block::Iter::new(&self.config, from)?)
     //}
 
+    /// iterate over all the stored blocks in chain order
+    ///
+    /// the epoch packs are read sequentially, starting from `first_epoch`,
+    /// then the blocks that are not packed yet up to the tag `HEAD`. Blocks
+    /// are read one at a time; the iterator stops after yielding an error.
+    pub fn blocks_iter<'a>(&'a self) -> impl Iterator<Item = io::Result<Block>> + 'a {
+        block::ChainIter::new(self)
+    }
+
+    /// compute the UTxO set from scratch by replaying all the blocks of
+    /// `blocks_iter`, e.g. to check a UTxO set maintained incrementally
+    /// with `utxo::apply_block`.
+    ///
+    /// If the oldest epochs were pruned, this starts from the UTxO set
+    /// saved at the end of these epochs.
+    pub fn rebuild_utxo(&self) -> Result<utxo::UtxoSet> {
+        self.utxo_before(None)
+    }
+
+    /// compute the UTxO set right after the given block, replaying the
+    /// blocks of `blocks_iter` as `rebuild_utxo` does.
+    ///
+    /// Fails with `Error::BlockNotStored` if the block is not one of the
+    /// blocks of `blocks_iter`, e.g. it is in a pruned epoch.
+    pub fn utxo_after(&self, hash: &HeaderHash) -> Result<utxo::UtxoSet> {
+        let mut utxos = self.first_utxo()?;
+        for block in self.blocks_iter() {
+            let block = block?;
+            utxo::apply_block(&mut utxos, &block);
+            if block.get_header().compute_hash() == *hash { return Ok(utxos) }
+        }
+        Err(Error::BlockNotStored(hash.clone()))
+    }
+
+    // the UTxO set at the start of `first_epoch`
+    fn first_utxo(&self) -> Result<utxo::UtxoSet> {
+        if self.first_epoch == 0 {
+            Ok(utxo::UtxoSet::new())
+        } else {
+            Ok(utxo::read_snapshot(&self.config, self.first_epoch)?)
+        }
+    }
+
+    // the UTxO set at the start of the given epoch, or at the tag `HEAD`
+    fn utxo_before(&self, epoch: Option<EpochId>) -> Result<utxo::UtxoSet> {
+        let mut utxos = self.first_utxo()?;
+        for block in self.blocks_iter() {
+            let block = block?;
+            if let Some(epoch) = epoch {
+                if block.get_header().get_blockdate().get_epochid() >= epoch { break }
+            }
+            utxo::apply_block(&mut utxos, &block);
+        }
+        Ok(utxos)
+    }
+
+    /// the first epoch whose blocks are stored, the blocks of the epochs
+    /// before it having been deleted by `prune_epochs_before`
+    pub fn first_epoch(&self) -> EpochId {
+        self.first_epoch
+    }
+
+    /// delete the blocks of the epochs before the given one, to bound the
+    /// disk usage. Returns the number of epochs deleted.
+    ///
+    /// The UTxO set at the start of the given epoch is saved first, so
+    /// `rebuild_utxo` remains valid. Only the packed epochs can be deleted:
+    /// the blocks that are not packed yet may still be rolled back, this
+    /// fails with `Error::EpochNotPacked` before deleting anything if one
+    /// of the epochs is not packed.
+    ///
+    /// The wallets synced with the storage can not sync the deleted epochs
+    /// anymore.
+    pub fn prune_epochs_before(&mut self, epoch: EpochId) -> Result<usize> {
+        if epoch > self.first_epoch {
+            for e in self.first_epoch..epoch {
+                if self.pack_for_epoch(e)?.is_none() { return Err(Error::EpochNotPacked(e)) }
+            }
+            let utxos = self.utxo_before(Some(epoch))?;
+            utxo::write_snapshot(&self.config, epoch, &utxos)?;
+            self.first_epoch = epoch;
+        }
+
+        // the epochs left over by an interrupted pruning are deleted too
+        let mut pruned = 0;
+        for e in 0..epoch {
+            let packhash = match self.pack_for_epoch(e)? {
+                None => continue,
+                Some(packhash) => packhash,
+            };
+            fs::remove_dir_all(self.config.get_epoch_dir(e))?;
+            remove_file_if_exists(&self.config.get_epoch_named_pack_filepath(e))?;
+            remove_file_if_exists(&self.config.get_pack_filepath(&packhash))?;
+            remove_file_if_exists(&self.config.get_index_filepath(&packhash))?;
+            self.lookups.remove(&packhash);
+            debug!("epoch {} pruned", e);
+            pruned += 1;
+        }
+        for e in utxo::snapshot_epochs(&self.config)? {
+            if e < self.first_epoch {
+                remove_file_if_exists(&self.config.get_utxo_snapshot_filepath(e))?;
+            }
+        }
+        Ok(pruned)
+    }
+
+    /// the hash of the pack holding the blocks of the given epoch, `None`
+    /// if the epoch has not been packed yet
+    ///
+    /// each packed epoch has its own pack, the pack file is named after
+    /// its hash and `epoch/<epoch>/pack` points to it. The pack is also
+    /// named `pack/epoch-<epoch>`, a hard link to the pack file. Deleting
+    /// a single epoch's data is deleting this directory, the pack file,
+    /// its name and its index.
+    pub fn pack_for_epoch(&self, epoch: EpochId) -> Result<Option<PackHash>> {
+        match epoch::epoch_read_pack(&self.config, epoch) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+            Ok(packhash) => Ok(Some(packhash)),
+        }
+    }
+
+    /// the space, in bytes, taken on disk by the blocks: the sum of the
+    /// sizes of the pack files, of their indexes and of the blocks not
+    /// packed yet
+    ///
+    /// Only the metadata of the files named after a hash are read, the
+    /// epoch names of the packs are links to the pack files and are not
+    /// counted twice. The files removed while computing the sum (a blob
+    /// being packed) are skipped, the result is then only an
+    /// approximation of the usage while the storage is being written.
+    pub fn disk_usage(&self) -> io::Result<u64> {
+        let mut usage = 0;
+        for filetype in &[StorageFileType::Pack, StorageFileType::Index, StorageFileType::Blob] {
+            usage += files_size(&self.config.get_filetype_dir(*filetype))?;
+        }
+        Ok(usage)
+    }
+
     /// construct a range between the given hash
     pub fn range(&self, from: BlockHash, to: BlockHash) -> Result<block::Range> {
         block::Range::new(self, from, to).map_err(|err| Error::BlockError(err))
     }
 
+    /// rebuild the index of every pack file from the content of the pack
+    ///
+    /// This is useful if the indexes are missing or inconsistent (e.g. after
+    /// a crash). The packs are streamed one block at a time, only the hashes
+    /// and offsets of the blocks of the current pack are kept in memory.
+    ///
+    /// A pack whose content does not match its hash is left untouched, and
+    /// its index is not loaded. Returns the list of reindexed packs.
+    pub fn reindex(&mut self) -> Result<Vec<PackHash>> {
+        let mut reindexed = Vec::new();
+        for packhash in self.config.list_packs() {
+            let mut reader = packfile::Reader::init(self.config.get_pack_filepath(&packhash))?;
+            let mut index = indexfile::Index::new();
+
+            loop {
+                let offset = reader.pos;
+                match reader.get_next() {
+                    None => break,
+                    Some(data) => {
+                        let hash = RawBlock(data).to_header()?.compute_hash();
+                        index.append(hash.bytes(), offset);
+                    }
+                }
+            }
+
+            if reader.finalize() != packhash {
+                error!("pack {} is corrupted, not reindexing it", packhash);
+                self.lookups.remove(&packhash);
+                continue;
+            }
+
+            let (lookup, tmpfile) = pack::create_index(self, &index);
+            tmpfile.render_permanent(&self.config.get_index_filepath(&packhash))?;
+            self.lookups.insert(packhash, lookup);
+            debug!("pack {} reindexed", packhash);
+            reindexed.push(packhash);
+        }
+        Ok(reindexed)
+    }
+
     pub fn get_block_from_tag(&self, tag: &str) -> Result<Block> {
         match tag::read_hash(&self, &tag) {
             None => Err(Error::NoSuchTag),
@@ -121,6 +356,33 @@ impl Storage {
     }
 }
 
+fn remove_file_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        r => r,
+    }
+}
+
+// sum of the sizes of the files of the given directory named after a
+// hash, skipping the ones that disappear before their metadata could be
+// read
+fn files_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = match entry {
+            Ok(ref entry) if entry.file_name().len() != 64 => continue,
+            entry => entry,
+        };
+        let metadata = match entry.and_then(|entry| entry.metadata()) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+            Ok(metadata) => metadata,
+        };
+        if metadata.is_file() { size += metadata.len() }
+    }
+    Ok(size)
+}
+
 fn tmpfile_create_type(storage: &Storage, filetype: StorageFileType) -> TmpFile {
     TmpFile::create(storage.config.get_filetype_dir(filetype)).unwrap()
 }
@@ -293,8 +555,7 @@ pub fn pack_blobs(storage: &mut Storage, params: &PackParameters) -> PackHash {
 pub fn refpack_epoch_pack<S: AsRef<str>>(storage: &Storage, tag: &S) -> Result<()> {
     let mut rp = reffile::Lookup::new();
     let packhash_vec = tag::read(storage, tag).expect("EPOCH not found");
-    let mut packhash = [0;HASH_SIZE];
-    packhash[..].clone_from_slice(packhash_vec.as_slice());
+    let packhash = PackHash::from_slice(&packhash_vec).unwrap();
     let mut pack = packreader_init(&storage.config, &packhash);
 
     let mut current_state = None;
@@ -356,9 +617,10 @@ pub fn integrity_check(storage: &Storage, genesis_hash: HeaderHash, count: Epoch
 }
 
 fn epoch_integrity_check(storage: &Storage, epochid: EpochId, last_known_hash: HeaderHash) -> Result<HeaderHash> {
-    let packhash_vec = tag::read(storage, &format!("EPOCH_{}", epochid)).expect("EPOCH not found");
-    let mut packhash = [0;HASH_SIZE];
-    packhash[..].clone_from_slice(packhash_vec.as_slice());
+    let packhash = match storage.pack_for_epoch(epochid)? {
+        None => return Err(Error::EpochNotPacked(epochid)),
+        Some(packhash) => packhash,
+    };
     let mut pack = packreader_init(&storage.config, &packhash);
 
     let mut current_state = None;
@@ -415,3 +677,321 @@ fn epoch_integrity_check(storage: &Storage, epochid: EpochId, last_known_hash: H
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{address::ExtendedAddr, coin::Coin, config::ProtocolMagic, hash::Blake2b256, hdwallet, tx::{TxId, TxIn, TxOut}};
+    use cbor_event::{self, se::Serializer};
+    use std::{env, path::PathBuf};
+
+    /// an epoch boundary block: magic, previous, body proof, consensus
+    /// (epoch, difficulty), attributes; no slot leaders; extra
+    pub fn boundary_block(epoch: EpochId, previous: &HeaderHash) -> RawBlock {
+        let bytes = Serializer::new_vec()
+            .write_array(cbor_event::Len::Len(2)).unwrap()
+            .write_unsigned_integer(0).unwrap()
+            .write_array(cbor_event::Len::Len(3)).unwrap()
+            .write_array(cbor_event::Len::Len(5)).unwrap()
+            .serialize(&ProtocolMagic::default()).unwrap()
+            .serialize(previous).unwrap()
+            .serialize(&Blake2b256::new(&[])).unwrap()
+            .write_array(cbor_event::Len::Len(2)).unwrap()
+            .write_unsigned_integer(epoch).unwrap()
+            .write_array(cbor_event::Len::Len(1)).unwrap()
+            .write_unsigned_integer(0).unwrap()
+            .write_map(cbor_event::Len::Len(0)).unwrap()
+            .write_array(cbor_event::Len::Indefinite).unwrap()
+            .write_special(cbor_event::Special::Break).unwrap()
+            .write_array(cbor_event::Len::Len(1)).unwrap()
+            .write_map(cbor_event::Len::Len(0)).unwrap()
+            .finalize();
+        RawBlock::from_dat(bytes)
+    }
+
+    pub fn txout(value: u64) -> TxOut {
+        let xpub = hdwallet::XPrv::normalize_bytes([7;hdwallet::XPRV_SIZE]).public();
+        TxOut::new(ExtendedAddr::new_simple(xpub), Coin::new(value).unwrap())
+    }
+
+    fn hash(raw: &RawBlock) -> HeaderHash {
+        raw.to_header().unwrap().compute_hash()
+    }
+
+    /// the boundary blocks of the given number of epochs, one per epoch
+    fn boundary_chain(epochs: EpochId) -> Vec<RawBlock> {
+        let mut chain : Vec<RawBlock> = Vec::new();
+        for epoch in 0..epochs {
+            let previous = chain.last().map(hash).unwrap_or(HeaderHash::new(b"genesis-1"));
+            chain.push(boundary_block(epoch, &previous));
+        }
+        chain
+    }
+
+    /// a new storage in the temporary directory holding the given chain
+    /// of one block per epoch: the blocks of the epochs before `packed`
+    /// in their epoch pack, the others as blobs up to the tag `HEAD`
+    fn storage_of(name: &str, chain: &[RawBlock], packed: EpochId) -> (PathBuf, StorageConfig) {
+        let dir = env::temp_dir().join(format!("storage-{}-{}", name, ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let storage_cfg = StorageConfig::new(&dir);
+        let storage = Storage::init(&storage_cfg).unwrap();
+        for (epoch, raw) in chain.iter().enumerate() {
+            let epoch = epoch as EpochId;
+            let blockhash = header_to_blockhash(&hash(raw));
+            if epoch < packed {
+                let mut writer = pack::packwriter_init(&storage_cfg);
+                writer.append(&blockhash, raw.as_ref()).unwrap();
+                let (packhash, index) = pack::packwriter_finalize(&storage_cfg, writer);
+                let (_, tmpfile) = pack::create_index(&storage, &index);
+                tmpfile.render_permanent(&storage_cfg.get_index_filepath(&packhash)).unwrap();
+                epoch::epoch_create(&storage_cfg, &packhash, epoch);
+            } else {
+                blob::write(&storage, &blockhash, raw.as_ref()).unwrap();
+            }
+        }
+        tag::write_hash(&storage, &tag::HEAD, &hash(chain.last().unwrap()));
+        (dir, storage_cfg)
+    }
+
+    #[test]
+    fn blocks_iter_in_chain_order() {
+        let chain = boundary_chain(4);
+        let (dir, storage_cfg) = storage_of("blocks-iter", &chain, 2);
+
+        // the packed epochs 0 and 1, then the blobs of the epochs 2 and 3
+        let storage = Storage::init(&storage_cfg).unwrap();
+        let iterated : Vec<HeaderHash> = storage.blocks_iter()
+            .map(|block| block.unwrap().get_header().compute_hash())
+            .collect();
+        let expected : Vec<HeaderHash> = chain.iter().map(hash).collect();
+        assert_eq!(iterated, expected);
+
+        // nothing is packed: only the blobs
+        let (other_dir, storage_cfg) = storage_of("blocks-iter-blobs", &chain, 0);
+        let storage = Storage::init(&storage_cfg).unwrap();
+        assert_eq!(storage.blocks_iter().map(|block| block.unwrap().get_header().compute_hash()).collect::<Vec<_>>(), expected);
+
+        fs::remove_dir_all(dir).unwrap();
+        fs::remove_dir_all(other_dir).unwrap();
+    }
+
+    #[test]
+    fn blocks_iter_of_an_empty_storage() {
+        let dir = env::temp_dir().join(format!("storage-blocks-iter-empty-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let storage = Storage::init(&StorageConfig::new(&dir)).unwrap();
+        assert_eq!(storage.blocks_iter().count(), 0);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn prune_packed_epochs() {
+        let chain = boundary_chain(3);
+        let (dir, storage_cfg) = storage_of("prune", &chain, 2);
+        let mut storage = Storage::init(&storage_cfg).unwrap();
+        assert_eq!(storage.first_epoch(), 0);
+
+        // the epoch 2 is not packed yet
+        match storage.prune_epochs_before(3) {
+            Err(Error::EpochNotPacked(2)) => {},
+            r => panic!("expected EpochNotPacked, got {:?}", r),
+        }
+        assert!(storage.pack_for_epoch(0).unwrap().is_some());
+
+        assert_eq!(storage.prune_epochs_before(1).unwrap(), 1);
+        assert!(storage.pack_for_epoch(0).unwrap().is_none());
+        assert!(!storage_cfg.get_epoch_named_pack_filepath(0).exists());
+        assert!(block_read(&storage, hash(&chain[0]).bytes()).is_none());
+        assert_eq!(storage.first_epoch(), 1);
+        assert_eq!(storage.blocks_iter().count(), 2);
+
+        // walking back from the tip ends with the first block left
+        let walked : Vec<_> = block::iter::ReverseIter::from(&storage, hash(chain.last().unwrap())).unwrap()
+            .map(|block| block.get_header().compute_hash())
+            .collect();
+        let expected : Vec<_> = chain[1..].iter().rev().map(hash).collect();
+        assert_eq!(walked, expected);
+        assert_eq!(utxo::snapshot_epochs(&storage_cfg).unwrap(), vec![1]);
+
+        // pruning again does nothing, the state survives reopening
+        assert_eq!(storage.prune_epochs_before(1).unwrap(), 0);
+        let storage = Storage::init(&storage_cfg).unwrap();
+        assert_eq!(storage.first_epoch(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rebuild_utxo_after_pruning_starts_from_the_snapshot() {
+        let chain = boundary_chain(3);
+        let (dir, storage_cfg) = storage_of("prune-utxo", &chain, 2);
+        let mut storage = Storage::init(&storage_cfg).unwrap();
+        assert_eq!(storage.prune_epochs_before(1).unwrap(), 1);
+
+        // the boundary blocks have no transactions: the UTxO set rebuilt
+        // is the one of the snapshot
+        let mut snapshot = utxo::UtxoSet::new();
+        snapshot.insert(TxIn::new(TxId::new(b"pruned"), 0), txout(10));
+        utxo::write_snapshot(&storage_cfg, 1, &snapshot).unwrap();
+        assert_eq!(storage.rebuild_utxo().unwrap(), snapshot);
+        assert_eq!(Storage::init(&storage_cfg).unwrap().rebuild_utxo().unwrap(), snapshot);
+
+        // the next pruning saves the UTxO set at its start, from the
+        // previous snapshot, and deletes the previous one
+        assert_eq!(storage.prune_epochs_before(2).unwrap(), 1);
+        assert_eq!(utxo::snapshot_epochs(&storage_cfg).unwrap(), vec![2]);
+        assert_eq!(utxo::read_snapshot(&storage_cfg, 2).unwrap(), snapshot);
+        assert_eq!(storage.rebuild_utxo().unwrap(), snapshot);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn utxo_after_a_block_of_a_pruned_storage() {
+        let chain = boundary_chain(3);
+        let (dir, storage_cfg) = storage_of("utxo-after", &chain, 2);
+        let mut storage = Storage::init(&storage_cfg).unwrap();
+        assert_eq!(storage.utxo_after(&hash(&chain[1])).unwrap(), utxo::UtxoSet::new());
+
+        assert_eq!(storage.prune_epochs_before(1).unwrap(), 1);
+        let mut snapshot = utxo::UtxoSet::new();
+        snapshot.insert(TxIn::new(TxId::new(b"pruned"), 0), txout(10));
+        utxo::write_snapshot(&storage_cfg, 1, &snapshot).unwrap();
+        assert_eq!(storage.utxo_after(&hash(&chain[1])).unwrap(), snapshot);
+        assert_eq!(storage.utxo_after(&hash(&chain[2])).unwrap(), snapshot);
+
+        // the block of the pruned epoch is not replayed anymore
+        match storage.utxo_after(&hash(&chain[0])) {
+            Err(Error::BlockNotStored(ref h)) if *h == hash(&chain[0]) => {},
+            r => panic!("expected BlockNotStored, got {:?}", r),
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reindex_restores_the_lookups() {
+        let chain = boundary_chain(3);
+        let (dir, storage_cfg) = storage_of("reindex", &chain, 2);
+
+        // the index of the pack of the epoch 0 is lost, its block
+        // cannot be found anymore
+        let storage = Storage::init(&storage_cfg).unwrap();
+        let packhash = storage.pack_for_epoch(0).unwrap().unwrap();
+        fs::remove_file(storage_cfg.get_index_filepath(&packhash)).unwrap();
+        let mut storage = Storage::init(&storage_cfg).unwrap();
+        assert!(block_read(&storage, hash(&chain[0]).bytes()).is_none());
+
+        let reindexed = storage.reindex().unwrap();
+        assert_eq!(reindexed.len(), 2);
+        assert!(reindexed.contains(&packhash));
+        for raw in chain.iter() {
+            assert_eq!(block_read(&storage, hash(raw).bytes()).unwrap().as_ref(), raw.as_ref());
+        }
+
+        // the index is written back: the blocks are found after reopening
+        let storage = Storage::init(&storage_cfg).unwrap();
+        for raw in chain.iter() {
+            assert_eq!(block_read(&storage, hash(raw).bytes()).unwrap().as_ref(), raw.as_ref());
+        }
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn reindex_skips_a_corrupted_pack() {
+        let chain = boundary_chain(2);
+        let (dir, storage_cfg) = storage_of("reindex-corrupted", &chain, 2);
+
+        let mut storage = Storage::init(&storage_cfg).unwrap();
+        let packhash = storage.pack_for_epoch(1).unwrap().unwrap();
+        let packpath = storage_cfg.get_pack_filepath(&packhash);
+        let mut content = fs::read(&packpath).unwrap();
+        let middle = content.len() / 2;
+        content[middle] ^= 0xff;
+        fs::write(&packpath, content).unwrap();
+
+        let reindexed = storage.reindex().unwrap();
+        assert_eq!(reindexed, vec![storage.pack_for_epoch(0).unwrap().unwrap()]);
+        assert!(block_read(&storage, hash(&chain[0]).bytes()).is_some());
+        assert!(block_read(&storage, hash(&chain[1]).bytes()).is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn epoch_packs_named_by_epoch() {
+        let chain = boundary_chain(2);
+        let (dir, storage_cfg) = storage_of("epoch-names", &chain, 2);
+        let storage = Storage::init(&storage_cfg).unwrap();
+        for epoch in 0..2 {
+            let packhash = storage.pack_for_epoch(epoch).unwrap().unwrap();
+            let named = fs::read(storage_cfg.get_epoch_named_pack_filepath(epoch)).unwrap();
+            assert_eq!(named, fs::read(storage_cfg.get_pack_filepath(&packhash)).unwrap());
+        }
+        assert_eq!(storage_cfg.list_packs().len(), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn migrate_tagged_epoch_packs() {
+        let chain = boundary_chain(3);
+        let (dir, storage_cfg) = storage_of("migrate", &chain, 2);
+
+        // the older layout: the epoch packs are only tagged `EPOCH_<epoch>`
+        let storage = Storage::init(&storage_cfg).unwrap();
+        let packhashes : Vec<PackHash> = (0..2).map(|epoch| storage.pack_for_epoch(epoch).unwrap().unwrap()).collect();
+        for (epoch, packhash) in packhashes.iter().enumerate() {
+            let epoch = epoch as EpochId;
+            fs::remove_dir_all(storage_cfg.get_epoch_dir(epoch)).unwrap();
+            fs::remove_file(storage_cfg.get_epoch_named_pack_filepath(epoch)).unwrap();
+            tag::write(&storage, &tag::get_epoch_tag(epoch), packhash.as_ref());
+        }
+
+        let storage = Storage::init(&storage_cfg).unwrap();
+        for (epoch, packhash) in packhashes.iter().enumerate() {
+            let epoch = epoch as EpochId;
+            assert_eq!(storage.pack_for_epoch(epoch).unwrap().as_ref(), Some(packhash));
+            assert!(storage_cfg.get_epoch_named_pack_filepath(epoch).exists());
+            assert!(!tag::exist(&storage, &tag::get_epoch_tag(epoch)));
+        }
+        assert_eq!(storage.blocks_iter().count(), 3);
+        integrity_check(&storage, HeaderHash::new(b"genesis-1"), 2);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn disk_usage_of_packs_and_blobs() {
+        let dir = env::temp_dir().join(format!("storage-disk-usage-{}", ::std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let storage_cfg = StorageConfig::new(&dir);
+        let storage = Storage::init(&storage_cfg).unwrap();
+        assert_eq!(storage.disk_usage().unwrap(), 0);
+
+        // each block of a pack is prefixed by its 4 bytes length and
+        // padded to 4 bytes: 4 + 5 + 3, then 4 + 8
+        let mut writer = pack::packwriter_init(&storage_cfg);
+        writer.append(&[1; 32], &[1; 5]).unwrap();
+        writer.append(&[2; 32], &[2; 8]).unwrap();
+        pack::packwriter_finalize(&storage_cfg, writer);
+        assert_eq!(storage.disk_usage().unwrap(), 24);
+
+        // the indexes and the blocks not packed yet are counted too
+        let mut writer = pack::packwriter_init(&storage_cfg);
+        writer.append(&[4; 32], &[4; 4]).unwrap();
+        let (packhash, index) = pack::packwriter_finalize(&storage_cfg, writer);
+        let (_, tmpfile) = pack::create_index(&storage, &index);
+        tmpfile.render_permanent(&storage_cfg.get_index_filepath(&packhash)).unwrap();
+        let index_len = fs::metadata(storage_cfg.get_index_filepath(&packhash)).unwrap().len();
+        assert_eq!(storage.disk_usage().unwrap(), 24 + 8 + index_len);
+
+        blob::write(&storage, &[3; 32], &[3; 10]).unwrap();
+        assert_eq!(storage.disk_usage().unwrap(), 24 + 8 + index_len + 10);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
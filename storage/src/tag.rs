@@ -11,6 +11,11 @@ pub fn get_epoch_tag(epoch: block::EpochId) -> String {
     format!("EPOCH_{}", epoch)
 }
 
+/// the epoch of a tag named by `get_epoch_tag`
+pub fn parse_epoch_tag(name: &str) -> Option<block::EpochId> {
+    if name.starts_with("EPOCH_") { name["EPOCH_".len()..].parse().ok() } else { None }
+}
+
 pub fn write<S: AsRef<str>>(storage: &super::Storage, name: &S, content: &[u8]) {
     let mut tmp_file = super::tmpfile_create_type(storage, super::StorageFileType::Tag);
     tmp_file.write_all(hex::encode(content).as_bytes()).unwrap();
@@ -1,9 +1,62 @@
+use std::{fmt, result, str};
 use cardano::block::HeaderHash;
+use cardano::{hash, util::hex};
+use serde;
 
 pub const HASH_SIZE : usize = 32;
 
 pub type BlockHash = [u8;HASH_SIZE];
-pub type PackHash = [u8;HASH_SIZE];
+
+/// hash of the content of a pack file, identifying the pack and its index
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+pub struct PackHash([u8;HASH_SIZE]);
+impl PackHash {
+    pub fn bytes<'a>(&'a self) -> &'a [u8;HASH_SIZE] { &self.0 }
+
+    pub fn from_slice(bytes: &[u8]) -> hash::Result<Self> {
+        if bytes.len() != HASH_SIZE { return Err(hash::Error::InvalidHashSize(bytes.len())); }
+        let mut buf = [0;HASH_SIZE];
+        buf.clone_from_slice(bytes);
+        Ok(PackHash(buf))
+    }
+}
+impl From<[u8;HASH_SIZE]> for PackHash {
+    fn from(bytes: [u8;HASH_SIZE]) -> Self { PackHash(bytes) }
+}
+impl AsRef<[u8]> for PackHash {
+    fn as_ref(&self) -> &[u8] { self.0.as_ref() }
+}
+impl fmt::Debug for PackHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.0[..]))
+    }
+}
+impl fmt::Display for PackHash {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", hex::encode(&self.0[..]))
+    }
+}
+impl str::FromStr for PackHash {
+    type Err = hash::Error;
+    fn from_str(s: &str) -> result::Result<Self, Self::Err> {
+        let bytes = hex::decode(s)?;
+        Self::from_slice(&bytes)
+    }
+}
+impl serde::Serialize for PackHash {
+    fn serialize<S>(&self, serializer: S) -> result::Result<S::Ok, S::Error>
+        where S: serde::Serializer
+    {
+        hash::Blake2b256::from(self.0).serialize(serializer)
+    }
+}
+impl<'de> serde::Deserialize<'de> for PackHash {
+    fn deserialize<D>(deserializer: D) -> result::Result<Self, D::Error>
+        where D: serde::Deserializer<'de>
+    {
+        hash::Blake2b256::deserialize(deserializer).map(|h| PackHash(h.into_bytes()))
+    }
+}
 
 pub fn header_to_blockhash(header_hash: &HeaderHash) -> BlockHash {
     let mut bh = [0u8;HASH_SIZE];
@@ -19,4 +72,5 @@ pub enum StorageFileType {
     Tag,
     RefPack,
     Epoch,
+    Utxo,
 }
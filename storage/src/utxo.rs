@@ -0,0 +1,158 @@
+//! the unspent transaction outputs (UTxO) of the stored blocks
+//!
+//! The UTxO set is computed by applying the transactions of the blocks
+//! in chain order. The funds distributed in the genesis data are not in
+//! any block: spending them removes nothing from the set.
+//!
+//! Once the blocks of the oldest epochs are deleted, the UTxO set at the
+//! end of these epochs is kept in a snapshot the computation starts from.
+
+use std::{fmt, fs, io::{self, Read}, collections::BTreeMap};
+use cardano::{block::{Block, EpochId}, tx::{Tx, TxIn, TxOut}};
+use cbor_event::{self, se, de::RawCbor};
+
+use config::StorageConfig;
+use types::StorageFileType;
+use utils::tmpfile;
+
+/// the unspent outputs, by the input that would spend them
+pub type UtxoSet = BTreeMap<TxIn, TxOut>;
+
+/// remove the outputs spent by the transaction and add the ones it creates
+pub fn apply_tx(utxos: &mut UtxoSet, tx: &Tx) {
+    for txin in tx.inputs.iter() {
+        utxos.remove(txin);
+    }
+    let id = tx.id();
+    for (index, txout) in tx.outputs.iter().enumerate() {
+        utxos.insert(TxIn::new(id, index as u32), txout.clone());
+    }
+}
+
+/// apply the transactions of the block, in order
+pub fn apply_block(utxos: &mut UtxoSet, block: &Block) {
+    if let Some(txs) = block.get_transactions() {
+        for txaux in txs.iter() {
+            apply_tx(utxos, &txaux.tx);
+        }
+    }
+}
+
+/// save the UTxO set at the start of the given epoch, when the blocks of the
+/// previous epochs are deleted (see `Storage::prune_epochs_before`)
+pub fn write_snapshot(config: &StorageConfig, epoch: EpochId, utxos: &UtxoSet) -> io::Result<()> {
+    let mut serializer = se::Serializer::new_vec().write_array(cbor_event::Len::Len(utxos.len() as u64)).map_err(invalid_data)?;
+    for (txin, txout) in utxos.iter() {
+        serializer = serializer.write_array(cbor_event::Len::Len(2))
+            .and_then(|s| s.serialize(txin))
+            .and_then(|s| s.serialize(txout))
+            .map_err(invalid_data)?;
+    }
+    tmpfile::atomic_write_simple(&config.get_utxo_snapshot_filepath(epoch), &serializer.finalize())
+}
+
+/// read the UTxO set saved by `write_snapshot` for the given epoch
+pub fn read_snapshot(config: &StorageConfig, epoch: EpochId) -> io::Result<UtxoSet> {
+    let mut content = Vec::new();
+    fs::File::open(config.get_utxo_snapshot_filepath(epoch))?.read_to_end(&mut content)?;
+
+    let mut raw = RawCbor::from(&content);
+    let len = match raw.array().map_err(invalid_data)? {
+        cbor_event::Len::Len(len) => len,
+        cbor_event::Len::Indefinite => return Err(invalid_data("indefinite UTxO snapshot")),
+    };
+    let mut utxos = UtxoSet::new();
+    for _ in 0..len {
+        raw.tuple(2, "utxo").map_err(invalid_data)?;
+        let txin : TxIn = raw.deserialize().map_err(invalid_data)?;
+        let txout : TxOut = raw.deserialize().map_err(invalid_data)?;
+        utxos.insert(txin, txout);
+    }
+    Ok(utxos)
+}
+
+/// the epochs a UTxO snapshot was saved for, in increasing order
+pub fn snapshot_epochs(config: &StorageConfig) -> io::Result<Vec<EpochId>> {
+    let mut epochs = Vec::new();
+    for entry in fs::read_dir(config.get_filetype_dir(StorageFileType::Utxo))? {
+        if let Some(epoch) = entry?.file_name().to_str().and_then(|name| name.parse().ok()) {
+            epochs.push(epoch);
+        }
+    }
+    epochs.sort();
+    Ok(epochs)
+}
+
+fn invalid_data<E: fmt::Display>(e: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cardano::{block::HeaderHash, tx::TxId};
+    use test::{boundary_block, txout};
+
+    fn tx(inputs: Vec<TxIn>, values: &[u64]) -> Tx {
+        Tx::new_with(inputs, values.iter().map(|value| txout(*value)).collect())
+    }
+
+    #[test]
+    fn apply_tx_spends_its_inputs() {
+        let mut utxos = UtxoSet::new();
+        let funding = tx(vec![], &[10, 20]);
+        apply_tx(&mut utxos, &funding);
+        assert_eq!(utxos.len(), 2);
+        assert_eq!(utxos.get(&TxIn::new(funding.id(), 1)), Some(&txout(20)));
+
+        let spending = tx(vec![TxIn::new(funding.id(), 0)], &[5]);
+        apply_tx(&mut utxos, &spending);
+        assert_eq!(utxos.len(), 2);
+        assert_eq!(utxos.get(&TxIn::new(funding.id(), 0)), None);
+        assert_eq!(utxos.get(&TxIn::new(funding.id(), 1)), Some(&txout(20)));
+        assert_eq!(utxos.get(&TxIn::new(spending.id(), 0)), Some(&txout(5)));
+    }
+
+    #[test]
+    fn apply_tx_double_spend() {
+        let mut utxos = UtxoSet::new();
+        let funding = tx(vec![], &[10]);
+        apply_tx(&mut utxos, &funding);
+        apply_tx(&mut utxos, &tx(vec![TxIn::new(funding.id(), 0)], &[4]));
+        let after_spend = utxos.clone();
+
+        // the output is already spent: nothing is removed, the outputs
+        // of the second transaction are still added
+        let double_spend = tx(vec![TxIn::new(funding.id(), 0)], &[3]);
+        apply_tx(&mut utxos, &double_spend);
+        assert_eq!(utxos.len(), after_spend.len() + 1);
+        assert!(after_spend.iter().all(|(txin, txout)| utxos.get(txin) == Some(txout)));
+        assert_eq!(utxos.get(&TxIn::new(double_spend.id(), 0)), Some(&txout(3)));
+    }
+
+    #[test]
+    fn apply_tx_unknown_input() {
+        // e.g. the funds of the genesis data, that are not in any block
+        let mut utxos = UtxoSet::new();
+        let known = tx(vec![], &[10]);
+        apply_tx(&mut utxos, &known);
+
+        let unknown = TxIn::new(TxId::new(b"not in the set"), 0);
+        let spending = tx(vec![unknown], &[7]);
+        apply_tx(&mut utxos, &spending);
+        assert_eq!(utxos.len(), 2);
+        assert_eq!(utxos.get(&TxIn::new(known.id(), 0)), Some(&txout(10)));
+        assert_eq!(utxos.get(&TxIn::new(spending.id(), 0)), Some(&txout(7)));
+    }
+
+    #[test]
+    fn apply_block_without_transactions() {
+        let block = boundary_block(1, &HeaderHash::new(b"previous")).decode().unwrap();
+
+        let mut utxos = UtxoSet::new();
+        apply_tx(&mut utxos, &tx(vec![], &[10]));
+        let before = utxos.clone();
+        apply_block(&mut utxos, &block);
+        assert_eq!(utxos, before);
+    }
+}